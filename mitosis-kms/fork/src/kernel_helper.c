@@ -4,3 +4,11 @@
 
 long mac_id = 0;
 module_param(mac_id, long, DEFAULT_PERMISSION);
+
+// shared by the machines of the cluster, 0 to accept any RPC connection
+unsigned long rpc_auth_token = 0;
+module_param(rpc_auth_token, ulong, S_IRUSR);
+
+// the transport of the RPC sessions, 0 for UD and 1 for RC, the same among the machines
+unsigned long rpc_transport = 0;
+module_param(rpc_transport, ulong, S_IRUSR);
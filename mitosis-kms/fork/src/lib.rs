@@ -13,6 +13,8 @@ use mitosis::syscalls::*;
 use mitosis_macros::declare_module_param; 
 
 declare_module_param!(mac_id, u64);
+declare_module_param!(rpc_auth_token, u64);
+declare_module_param!(rpc_transport, u64);
 
 /// The module corresponding to the kernel module lifetime
 #[allow(dead_code)]
@@ -30,12 +32,18 @@ impl linux_kernel_module::KernelModule for Module {
 
         // Currently, we use a default configuration of MITOSIS
         let mut config: mitosis::Config = Default::default();
+        let transport = match rpc_transport::read() {
+            1 => os_network::rpc::impls::transport::RPCTransport::RC,
+            _ => os_network::rpc::impls::transport::RPCTransport::UD,
+        };
 
         config
             .set_num_nics_used(1)
             .set_rpc_threads(2)
             .set_init_dc_targets(12)
-            .set_machine_id(id as usize);
+            .set_machine_id(id as usize)
+            .set_rpc_auth_token(rpc_auth_token::read())
+            .set_rpc_transport(transport);
 
         assert!(start_instance(config.clone()).is_some());
 
@@ -4,6 +4,8 @@ use proc_macro2::{Ident, Span};
 
 use quote::quote;
 
+mod rpc;
+
 /// Create a getter to an extern variable in C (used for passing parameters)
 ///
 /// ```
@@ -90,3 +92,54 @@ pub fn declare_global(args: TokenStream) -> TokenStream {
         }
     }.into()
 }
+
+/// Generate typed RPCs from a trait, whose methods are annotated with their RPC IDs.
+/// The arguments must be `Copy`, and the return type must implement `os_network::serialize::Serialize`.
+///
+/// The reply buffer is checked before the method is called, against the size of the return type
+/// or the `max_reply = ..` option if the reply is serialized larger (e.g., with a variable size).
+/// The hook replies `ReplyStatus::Malformed` to a request that cannot be parsed,
+/// and `ReplyStatus::TooLarge` if the reply cannot fit.
+///
+/// ```ignore
+/// #[rpc_service]
+/// pub trait Mitosis {
+///     #[rpc(id = RPCId::Nil, idempotent)]
+///     fn nil();
+///     #[rpc(id = RPCId::Query, idempotent)]
+///     fn query(key: usize) -> DescriptorLookupReply;
+/// }
+/// ```
+///
+/// For each method (e.g., `query`), the module `mitosis` contains:
+/// - `QUERY`, the RPC ID
+/// - `QueryRequest` and `QueryReply`, the request and reply types
+/// - `decode_query`, which decodes the reply payload
+///
+/// The server registers all the methods implemented by `T: Mitosis` via:
+/// ```ignore
+/// mitosis::register::<MyImpl>(&mut service);
+/// ```
+///
+/// And the client calls them through the `mitosis::Client` trait of `Caller`:
+/// ```ignore
+/// use mitosis::Client;
+/// let id = caller.call_query(session_id, my_session_id, key)?;
+/// let (msg, reply) = block_on(&mut TimeoutWRef::new(&mut caller.get_reply(id), timeout))?;
+/// let reply = mitosis::decode_query(&reply);
+/// caller.register_recv_buf(msg)?;
+/// ```
+///
+#[proc_macro_attribute]
+pub fn rpc_service(args: TokenStream, input: TokenStream) -> TokenStream {
+    if !args.is_empty() {
+        return syn::Error::new(Span::call_site(), "rpc_service takes no arguments")
+            .to_compile_error()
+            .into();
+    }
+    let item = syn::parse_macro_input!(input as syn::ItemTrait);
+    match rpc::expand(item) {
+        Ok(tokens) => tokens.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
@@ -0,0 +1,434 @@
+use proc_macro2::{Ident, Span, TokenStream};
+use quote::{format_ident, quote};
+use syn::parse::{Parse, ParseStream};
+use syn::punctuated::Punctuated;
+use syn::{Expr, FnArg, ItemTrait, Pat, ReturnType, Token, TraitItem, Type};
+
+/// The options of a RPC method, e.g., `#[rpc(id = 3, idempotent, max_reply = 4096)]`
+struct RPCAttr {
+    id: Expr,
+    idempotent: bool,
+    // the max serialized size of the reply, if it is larger than the reply type
+    max_reply: Option<Expr>,
+}
+
+enum RPCOption {
+    Id(Box<Expr>),
+    Idempotent,
+    MaxReply(Box<Expr>),
+}
+
+impl Parse for RPCOption {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let name: Ident = input.parse()?;
+        if name == "id" {
+            input.parse::<Token![=]>()?;
+            return Ok(RPCOption::Id(Box::new(input.parse()?)));
+        }
+        if name == "idempotent" {
+            return Ok(RPCOption::Idempotent);
+        }
+        if name == "max_reply" {
+            input.parse::<Token![=]>()?;
+            return Ok(RPCOption::MaxReply(Box::new(input.parse()?)));
+        }
+        Err(syn::Error::new(
+            name.span(),
+            "unknown rpc option, expect `id = ..`, `idempotent` or `max_reply = ..`",
+        ))
+    }
+}
+
+impl RPCAttr {
+    fn parse(attr: &syn::Attribute) -> syn::Result<Self> {
+        let options = attr.parse_args_with(Punctuated::<RPCOption, Token![,]>::parse_terminated)?;
+        let mut id = None;
+        let mut idempotent = false;
+        let mut max_reply = None;
+        for option in options {
+            match option {
+                RPCOption::Id(expr) => id = Some(*expr),
+                RPCOption::Idempotent => idempotent = true,
+                RPCOption::MaxReply(expr) => max_reply = Some(*expr),
+            }
+        }
+        let id = id.ok_or_else(|| syn::Error::new_spanned(attr, "missing the rpc id"))?;
+        Ok(Self {
+            id,
+            idempotent,
+            max_reply,
+        })
+    }
+}
+
+/// A method of the annotated trait
+struct RPCMethod {
+    name: Ident,
+    attr: RPCAttr,
+    args: Vec<(Ident, Type)>,
+    // None if the method returns nothing
+    reply: Option<Type>,
+}
+
+impl RPCMethod {
+    fn parse(method: &mut syn::TraitItemMethod) -> syn::Result<Self> {
+        let pos = method
+            .attrs
+            .iter()
+            .position(|a| a.path.is_ident("rpc"))
+            .ok_or_else(|| syn::Error::new_spanned(&method.sig, "missing #[rpc(id = ..)]"))?;
+        let attr = RPCAttr::parse(&method.attrs.remove(pos))?;
+
+        let mut args = Vec::new();
+        for arg in method.sig.inputs.iter() {
+            match arg {
+                FnArg::Typed(arg) => match &*arg.pat {
+                    Pat::Ident(pat) => args.push((pat.ident.clone(), (*arg.ty).clone())),
+                    _ => return Err(syn::Error::new_spanned(&arg.pat, "rpc arguments must be plain identifiers")),
+                },
+                FnArg::Receiver(r) => {
+                    return Err(syn::Error::new_spanned(r, "rpc methods are called without an instance, remove `self`"))
+                }
+            }
+        }
+
+        let reply = match &method.sig.output {
+            ReturnType::Default => None,
+            ReturnType::Type(_, ty) => match &**ty {
+                Type::Tuple(t) if t.elems.is_empty() => None,
+                ty => Some(ty.clone()),
+            },
+        };
+        if reply.is_none() && attr.max_reply.is_some() {
+            return Err(syn::Error::new_spanned(&method.sig, "`max_reply` is given but the rpc replies nothing"));
+        }
+
+        Ok(Self {
+            name: method.sig.ident.clone(),
+            attr,
+            args,
+            reply,
+        })
+    }
+}
+
+fn to_camel(name: &str) -> String {
+    name.split('_')
+        .map(|w| {
+            let mut c = w.chars();
+            match c.next() {
+                Some(f) => f.to_uppercase().chain(c).collect(),
+                None => String::new(),
+            }
+        })
+        .collect()
+}
+
+fn to_snake(name: &str) -> String {
+    let mut res = String::new();
+    let chars: Vec<char> = name.chars().collect();
+    for (i, c) in chars.iter().enumerate() {
+        if c.is_uppercase() {
+            // split "FooBar" and "RPCService" as "foo_bar" and "rpc_service"
+            let prev_lower = i > 0 && chars[i - 1].is_lowercase();
+            let next_lower = i > 0 && matches!(chars.get(i + 1), Some(n) if n.is_lowercase()) && chars[i - 1].is_uppercase();
+            if prev_lower || next_lower {
+                res.push('_');
+            }
+            res.extend(c.to_lowercase());
+        } else {
+            res.push(*c);
+        }
+    }
+    res
+}
+
+pub(crate) fn expand(mut item: ItemTrait) -> syn::Result<TokenStream> {
+    let mut methods = Vec::new();
+    for it in item.items.iter_mut() {
+        match it {
+            TraitItem::Method(m) => methods.push(RPCMethod::parse(m)?),
+            other => return Err(syn::Error::new_spanned(other, "only rpc methods are allowed in a rpc service")),
+        }
+    }
+
+    let trait_name = &item.ident;
+    let vis = &item.vis;
+    let mod_name = Ident::new(&to_snake(&trait_name.to_string()), Span::call_site());
+
+    let mut defs = Vec::new();
+    let mut registers = Vec::new();
+    let mut client_sigs = Vec::new();
+    let mut client_impls = Vec::new();
+
+    for m in methods.iter() {
+        let name = &m.name;
+        let camel = to_camel(&name.to_string());
+        let id_name = Ident::new(&name.to_string().to_uppercase(), name.span());
+        let req_name = format_ident!("{}Request", camel);
+        let reply_name = format_ident!("{}Reply", camel);
+        let decode_name = format_ident!("decode_{}", name);
+        let call_name = format_ident!("call_{}", name);
+        let id = &m.attr.id;
+
+        let arg_names: Vec<&Ident> = m.args.iter().map(|(n, _)| n).collect();
+        let arg_types: Vec<&Type> = m.args.iter().map(|(_, t)| t).collect();
+
+        let id_doc = format!("The RPC ID of `{}::{}`", trait_name, name);
+        let req_doc = format!("The arguments of `{}`, sent by memory copy", name);
+        defs.push(quote! {
+            #[doc = #id_doc]
+            pub const #id_name: usize = (#id) as usize;
+
+            #[doc = #req_doc]
+            #[repr(C)]
+            #[derive(Clone, Copy)]
+            pub struct #req_name {
+                #(pub #arg_names: #arg_types,)*
+            }
+
+            impl ::os_network::serialize::Serialize for #req_name {}
+        });
+
+        // the max size of the reply, which is checked before the call
+        let max_reply = match (&m.attr.max_reply, &m.reply) {
+            (Some(sz), _) => quote! { ((#sz) as usize) },
+            (None, Some(ty)) => quote! { core::mem::size_of::<#ty>() },
+            (None, None) => quote! { 0usize },
+        };
+
+        // the reply: a serializable type, or nothing
+        let (reply_def, encode, decode) = match &m.reply {
+            Some(ty) => (
+                quote! { pub type #reply_name = #ty; },
+                quote! {
+                    // checked before the call, so a reply never fails after the call's side effects
+                    if #max_reply > output.len() {
+                        return ::os_network::rpc::service::REPLY_TOO_LARGE;
+                    }
+                    let reply = <T as super::#trait_name>::#name(#(req.#arg_names),*);
+                    let sz = reply.serialization_buf_len();
+                    if sz > #max_reply || !reply.serialize(output) {
+                        return ::os_network::rpc::service::REPLY_TOO_LARGE;
+                    }
+                    sz
+                },
+                quote! { <#reply_name as ::os_network::serialize::Serialize>::deserialize(reply) },
+            ),
+            None => (
+                quote! { pub type #reply_name = (); },
+                quote! {
+                    <T as super::#trait_name>::#name(#(req.#arg_names),*);
+                    0
+                },
+                quote! {
+                    let _ = reply;
+                    Some(())
+                },
+            ),
+        };
+        let decode_doc = format!("Decode the reply of `{}`, return None if it is malformed", name);
+        defs.push(quote! {
+            #reply_def
+
+            #[doc = #decode_doc]
+            pub fn #decode_name(reply: &::os_network::bytes::BytesMut) -> core::option::Option<#reply_name> {
+                #decode
+            }
+        });
+
+        let register_fn = if m.attr.idempotent {
+            quote! { register_idempotent }
+        } else {
+            quote! { register }
+        };
+        registers.push(quote! {
+            ok &= service.#register_fn(
+                #id_name,
+                |input: &::os_network::bytes::BytesMut, output: &mut ::os_network::bytes::BytesMut| -> usize {
+                    #[allow(unused_imports)]
+                    use ::os_network::serialize::Serialize;
+                    let req = match <#req_name as Serialize>::deserialize(input) {
+                        Some(req) => req,
+                        None => return ::os_network::rpc::service::MALFORMED_REQ,
+                    };
+                    #encode
+                },
+            );
+        });
+
+        client_sigs.push(quote! {
+            fn #call_name(
+                &mut self,
+                session_id: usize,
+                my_session_id: usize,
+                #(#arg_names: #arg_types),*
+            ) -> Result<::os_network::rpc::ReqId, ::os_network::rpc::CallError<Self::Error>>;
+        });
+        client_impls.push(quote! {
+            #[inline]
+            fn #call_name(
+                &mut self,
+                session_id: usize,
+                my_session_id: usize,
+                #(#arg_names: #arg_types),*
+            ) -> Result<::os_network::rpc::ReqId, ::os_network::rpc::CallError<Self::Error>> {
+                self.call_async(session_id, my_session_id, #id_name, #req_name { #(#arg_names),* })
+            }
+        });
+    }
+
+    let mod_doc = format!("The typed RPCs of `{}`, generated by `#[rpc_service]`", trait_name);
+    let register_doc = format!(
+        "Register all the RPCs of `{}` implemented by T.\nReturn false if any of the IDs is already registered.",
+        trait_name
+    );
+
+    Ok(quote! {
+        #item
+
+        #[doc = #mod_doc]
+        #vis mod #mod_name {
+            #[allow(unused_imports)]
+            use super::*;
+
+            #(#defs)*
+
+            #[doc = #register_doc]
+            #[allow(unused_mut)]
+            pub fn register<'a, T: super::#trait_name>(service: &mut ::os_network::rpc::Service<'a>) -> bool {
+                let mut ok = true;
+                #(#registers)*
+                ok
+            }
+
+            /// The client stubs, each call returns the request ID to wait its reply
+            pub trait Client {
+                type Error;
+
+                #(#client_sigs)*
+            }
+
+            impl<R, SS> Client for ::os_network::rpc::Caller<R, SS>
+            where
+                R: ::os_network::Receiver,
+                SS: ::os_network::rpc::RPCConn,
+                R: ::os_network::rpc::GetContext<
+                    Context = <<SS as ::os_network::rpc::RPCConn>::ReqPayload as ::os_network::rpc::AllocMsgBuf>::Context,
+                >,
+                SS::ReqPayload: ::os_network::bytes::ToBytes,
+            {
+                type Error = SS::IOResult;
+
+                #(#client_impls)*
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use syn::parse_quote;
+
+    fn expand_err(item: ItemTrait) -> String {
+        match expand(item) {
+            Ok(_) => panic!("the rpc service should be rejected"),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_to_snake() {
+        assert_eq!(to_snake("FooBar"), "foo_bar");
+        assert_eq!(to_snake("RPCService"), "rpc_service");
+        assert_eq!(to_snake("DescriptorRPC"), "descriptor_rpc");
+        assert_eq!(to_snake("ForkRPCService"), "fork_rpc_service");
+        assert_eq!(to_snake("A"), "a");
+        assert_eq!(to_snake("already_snake"), "already_snake");
+    }
+
+    #[test]
+    fn test_to_camel() {
+        assert_eq!(to_camel("push_pages"), "PushPages");
+        assert_eq!(to_camel("lookup_rpc_id"), "LookupRpcId");
+        assert_eq!(to_camel("nil"), "Nil");
+        assert_eq!(to_camel("_leading__double"), "LeadingDouble");
+    }
+
+    #[test]
+    fn test_expand() {
+        let item: ItemTrait = parse_quote! {
+            pub trait ForkRPCService {
+                #[rpc(id = 3, idempotent, max_reply = 4096)]
+                fn lookup(key: usize) -> u64;
+                #[rpc(id = 4)]
+                fn ping();
+            }
+        };
+        let out = expand(item).unwrap().to_string();
+        assert!(out.contains("mod fork_rpc_service"));
+        assert!(out.contains("LookupRequest"));
+        assert!(out.contains("register_idempotent"));
+        assert!(out.contains("call_ping"));
+    }
+
+    #[test]
+    fn test_attr_errors() {
+        let err = expand_err(parse_quote! {
+            trait S {
+                #[rpc(idempotent)]
+                fn f();
+            }
+        });
+        assert!(err.contains("missing the rpc id"), "{}", err);
+
+        let err = expand_err(parse_quote! {
+            trait S {
+                #[rpc(id = 1, retry)]
+                fn f();
+            }
+        });
+        assert!(err.contains("unknown rpc option"), "{}", err);
+
+        let err = expand_err(parse_quote! {
+            trait S {
+                #[rpc(id = 1, max_reply = 64)]
+                fn f();
+            }
+        });
+        assert!(err.contains("replies nothing"), "{}", err);
+
+        let err = expand_err(parse_quote! {
+            trait S {
+                fn f();
+            }
+        });
+        assert!(err.contains("missing #[rpc(id = ..)]"), "{}", err);
+
+        let err = expand_err(parse_quote! {
+            trait S {
+                #[rpc(id = 1)]
+                fn f(&self);
+            }
+        });
+        assert!(err.contains("remove `self`"), "{}", err);
+
+        let err = expand_err(parse_quote! {
+            trait S {
+                #[rpc(id = 1)]
+                fn f((a, b): (u32, u32));
+            }
+        });
+        assert!(err.contains("plain identifiers"), "{}", err);
+
+        let err = expand_err(parse_quote! {
+            trait S {
+                #[rpc(id = 1)]
+                fn f();
+                const C: usize;
+            }
+        });
+        assert!(err.contains("only rpc methods"), "{}", err);
+    }
+}
@@ -8,8 +8,81 @@ enum LibMITOSISCmd {
     ResumeRemote = 6, // resume to another process of remote via RPC
     PreparePing = 7, // Prepare the memory mapping of this process (and ping it in kernel)
     NilRPC = 8,     // Call the nil RPC function
+    FdPolicy = 9,   // set how the parent's fds are restored by the later resumes
+    FdReport = 10,  // query the fds that the last resume failed to restore
+    PrepareSecret = 11,      // like Prepare, yet the resumes must present the secret of the image
+    ResumeRemoteSecret = 12, // like ResumeRemote, presenting the secret of the image
+    RpcStats = 13,           // query the statistics of the RPC callers and handlers
+    RpcThreads = 14,         // start or stop the RPC handler threads, return the number running
+    Disconnect = 15,         // close the sessions to a remote machine, e.g., before it leaves
+};
+
+enum MITOSISFdPolicy {
+    FdSamePath = 0, // reopen the files at the parent's paths (default)
+    FdRedirect = 1, // like FdSamePath, but the written pipes and streams go to `redirect_path`
+    FdDevNull = 2,  // open all the fds to /dev/null
+};
+
+enum MITOSISPageTableKind {
+    PtDefault = 0, // the backend given when the module is loaded
+    PtRadix = 1,   // four-level tables like x86, fast to walk
+    PtExtent = 2,  // runs of consecutive pages, compact for sparse address spaces
 };
 
+typedef struct {
+    unsigned int policy;       // MITOSISFdPolicy
+    const char *redirect_path; // only used by FdRedirect
+} fd_policy_req_t;
+
+#define MITOSIS_MAX_FD_REPORT 64
+
+/*
+  The fds failed to restore are opened to /dev/null, or closed if it fails as well.
+ */
+typedef struct {
+    unsigned int count; // may exceed MITOSIS_MAX_FD_REPORT, the rest are not listed
+    int fds[MITOSIS_MAX_FD_REPORT];
+    int errs[MITOSIS_MAX_FD_REPORT]; // the negative errno of reopening the fd
+} fd_report_t;
+
+#define MITOSIS_MAX_RPC_ID 8
+#define MITOSIS_LATENCY_BUCKETS 16
+
+/*
+  Bucket 0 counts the latencies less than 1us, bucket i counts the ones in [2^(i-1), 2^i) us,
+  and the last bucket also counts all the larger ones.
+ */
+typedef struct {
+    unsigned long count;
+    unsigned long avg_usec;
+    unsigned long max_usec;
+    unsigned long buckets[MITOSIS_LATENCY_BUCKETS];
+} rpc_latency_t;
+
+typedef struct {
+    unsigned long ncalls;
+    unsigned long timeouts;
+    unsigned long no_ids;    // calls to the RPC IDs not registered
+    unsigned long corrupted; // malformed messages
+    unsigned long other_errors;
+    unsigned long bytes_in;  // payload bytes received
+    unsigned long bytes_out; // payload bytes sent
+    unsigned long queue_depth;
+    unsigned long max_queue_depth;
+    unsigned long sessions;
+    rpc_latency_t latencies[MITOSIS_MAX_RPC_ID]; // indexed by the RPC ID
+} rpc_side_stats_t;
+
+/*
+  The latencies of the callers are from the calls to their replies,
+  and those of the handlers are the execution of the requests.
+  The queue depth is the in-flight calls of the callers, and the pending replies of the handlers.
+ */
+typedef struct {
+    rpc_side_stats_t callers;
+    rpc_side_stats_t handlers;
+} rpc_stats_t;
+
 typedef struct {
     unsigned int machine_id; // should not be zero!
     unsigned int nic_id; // nic idx according to gid
@@ -19,4 +92,17 @@ typedef struct {
 typedef struct {
     unsigned int machine_id;    // keep `machine_id` the same as that in `connect_req_t`
     unsigned int handler_id;
-} resume_remote_req_t;
\ No newline at end of file
+} resume_remote_req_t;
+
+typedef struct {
+    unsigned long key;
+    unsigned long secret; // 0 if any can resume, as Prepare
+    unsigned int ping;    // ping the image in kernel, as PreparePing
+} prepare_req_t;
+
+typedef struct {
+    unsigned int machine_id;
+    unsigned int handler_id;
+    unsigned long secret; // the secret given at prepare
+    unsigned int pt_kind; // MITOSISPageTableKind of the child's page table
+} resume_secret_req_t;
\ No newline at end of file
@@ -34,6 +34,15 @@ call_connect(int sd, const char *addr, unsigned int mac_id, unsigned int nic_id)
     return 0;
 }
 
+static inline int
+call_disconnect(int sd, unsigned long mac_id) {
+    if (ioctl(sd, Disconnect, mac_id) == -1) {
+        return -1;
+    }
+
+    return 0;
+}
+
 /*
   Dump myself as an image to the kernel,
   so that later process can swap to it.
@@ -56,6 +65,26 @@ fork_prepare_ping(int sd, unsigned long key) {
     return 0;
 }
 
+/*
+  Like fork_prepare (or fork_prepare_ping),
+  yet only the resumes presenting the secret can fetch the image.
+  The secret is sent in plaintext, so it guards against resuming the wrong image
+  by mistake, not against an attacker who can observe the network.
+ */
+static inline int
+fork_prepare_secret(int sd, unsigned long key, unsigned long secret, int ping) {
+    prepare_req_t req;
+    req.key = key;
+    req.secret = secret;
+    req.ping = ping;
+
+    if (ioctl(sd, PrepareSecret, &req) == -1) {
+        return -1;
+    }
+
+    return 0;
+}
+
 static inline int
 fork_resume_local(int sd, unsigned long key) {
     if (ioctl(sd, ResumeLocal, key) == -1) {
@@ -79,6 +108,30 @@ fork_resume_remote(int sd, unsigned long mac_id, unsigned long handler_id) {
     return 0;
 }
 
+/*
+  Like fork_resume_remote_secret, and the child's page table is backed by `pt_kind` (MITOSISPageTableKind).
+ */
+static inline int
+fork_resume_remote_with_pt(int sd, unsigned long mac_id, unsigned long handler_id, unsigned long secret,
+                           unsigned int pt_kind) {
+    resume_secret_req_t req;
+    req.machine_id = mac_id;
+    req.handler_id = handler_id;
+    req.secret = secret;
+    req.pt_kind = pt_kind;
+
+    if (ioctl(sd, ResumeRemoteSecret, &req) == -1) {
+        return -1;
+    }
+
+    return 0;
+}
+
+static inline int
+fork_resume_remote_secret(int sd, unsigned long mac_id, unsigned long handler_id, unsigned long secret) {
+    return fork_resume_remote_with_pt(sd, mac_id, handler_id, secret, PtDefault);
+}
+
 static inline int
 nil_rpc(int sd, unsigned long mac_id, unsigned long handler_id) {
     resume_remote_req_t req;
@@ -91,3 +144,46 @@ nil_rpc(int sd, unsigned long mac_id, unsigned long handler_id) {
 
     return 0;
 }
+
+static inline int
+fork_set_fd_policy(int sd, unsigned int policy, const char *redirect_path) {
+    fd_policy_req_t req;
+    req.policy = policy;
+    req.redirect_path = redirect_path;
+
+    if (ioctl(sd, FdPolicy, &req) == -1) {
+        return -1;
+    }
+
+    return 0;
+}
+
+/*
+  Called by the child after the resume, with the `sd` of the parent.
+ */
+static inline int
+fork_fd_report(int sd, fd_report_t *report) {
+    if (ioctl(sd, FdReport, report) == -1) {
+        return -1;
+    }
+
+    return 0;
+}
+
+/*
+  Return the number of the running RPC handler threads, which is clamped
+  to [1, the max threads], or -1 on error (e.g., without CAP_SYS_ADMIN).
+ */
+static inline int
+fork_set_rpc_threads(int sd, unsigned long num) {
+    return ioctl(sd, RpcThreads, num);
+}
+
+static inline int
+fork_rpc_stats(int sd, rpc_stats_t *stats) {
+    if (ioctl(sd, RpcStats, stats) == -1) {
+        return -1;
+    }
+
+    return 0;
+}
@@ -3,12 +3,19 @@ use std::path::PathBuf;
 
 // types from customized syscalls
 const INCLUDED_ENUMS: &[&str] = &[
-    "LibMITOSISCmd"
+    "LibMITOSISCmd",
+    "MITOSISFdPolicy",
+    "MITOSISPageTableKind"
 ];
 
 const INCLUDED_TYPES: &[&str] = &[
     "connect_req_t",
-    "resume_remote_req_t"
+    "resume_remote_req_t",
+    "prepare_req_t",
+    "resume_secret_req_t",
+    "fd_policy_req_t",
+    "fd_report_t",
+    "rpc_stats_t"
 ];
 
 // types from kernel
@@ -22,6 +29,12 @@ const INCLUDED_KERNEL_TYPES: &[&str] = &[
     "vm_flags_t",
     "pgprot_t",
     "file_system_type",
+    "shrinker",
+    "shrink_control",
+    "mutex",
+    "semaphore",
+    "pmem_cq_waiter",
+    "pmem_task_state",
 ];
 const INCLUDED_KERNEL_FUNCS: &[&str] = &[
     "print_file_path",
@@ -67,9 +80,41 @@ const INCLUDED_KERNEL_FUNCS: &[&str] = &[
     "pmem_get_current_cpu",
     "pmem_get_cpu",
     "pmem_put_cpu",
+    "pmem_sleep_usecs",
+    "pmem_get_random_u64",
+    "pmem_cond_resched",
+    "pmem_mutex_init",
+    "pmem_mutex_lock",
+    "pmem_mutex_trylock",
+    "pmem_mutex_unlock",
+    "pmem_sema_init",
+    "pmem_sema_up",
+    "pmem_sema_down_timeout",
+    "pmem_cq_waiter_init",
+    "pmem_cq_waiter_attach",
+    "pmem_cq_waiter_arm",
+    "pmem_cq_waiter_events",
+    "pmem_cq_waiter_wait",
     "pmem_filemap_fault",
     "pmem_get_file",
     "pmem_put_file",
+    "pmem_mm_write_lock",
+    "pmem_mm_write_unlock",
+    "pmem_get_mm_exe_path",
+    "pmem_set_mm_exe_path",
+    "pmem_get_vma_special_name",
+    "pmem_get_mm_vdso",
+    "pmem_arch_map_vdso",
+    "pmem_get_max_fds",
+    "pmem_strncpy_from_user",
+    "pmem_get_fd_info",
+    "pmem_fd_is_file",
+    "pmem_install_fd_at",
+    "pmem_open_fd_at",
+    "pmem_get_task_state",
+    "pmem_check_task_creds",
+    "pmem_capable_admin",
+    "pmem_set_task_state",
     "schedule",
     // vmalloc, vfree
     "vmalloc",
@@ -88,6 +133,22 @@ const INCLUDED_KERNEL_FUNCS: &[&str] = &[
 
 const INCLUDED_VARS: &[&str] = &[
     "O_NONBLOCK",
+    "O_ACCMODE",
+    "O_RDONLY",
+    "O_WRONLY",
+    "O_RDWR",
+    "O_CREAT",
+    "O_EXCL",
+    "O_TRUNC",
+    "O_APPEND",
+    "O_CLOEXEC",
+    "PMEM_S_IFMT",
+    "PMEM_S_IFREG",
+    "PMEM_S_IFIFO",
+    "PMEM_S_IFCHR",
+    "MITOSIS_MAX_FD_REPORT",
+    "MITOSIS_MAX_RPC_ID",
+    "MITOSIS_LATENCY_BUCKETS",
     "PMEM_PAGE_PRESENT",
     "PMEM_PAGE_RW",
     "PMEM_PAGE_USER",
@@ -110,8 +171,18 @@ const INCLUDED_VARS: &[&str] = &[
     "PMEM_PROT_EXEC",
     "PMEM_PROT_GROWSUP",
     "PMEM_VM_FAULT_SIGSEGV",
+    "PMEM_VM_FAULT_RETRY",
+    "PMEM_VM_FAULT_NOPAGE",
+    "PMEM_VM_FAULT_SIGBUS",
+    "PMEM_FAULT_FLAG_ALLOW_RETRY",
+    "PMEM_FAULT_FLAG_RETRY_NOWAIT",
     "PMEM_GFP_HIGHUSER",
-    "PMEM_GFP_USER"
+    "PMEM_GFP_USER",
+    "PMEM_GFP_HIGHUSER_ZERO",
+    "PMEM_SHRINK_STOP",
+    "PMEM_AT_VECTOR_SIZE",
+    "PMEM_AT_NULL",
+    "PMEM_AT_SYSINFO_EHDR"
 ];
 
 // Takes the CFLAGS from the kernel Makefile and changes all the include paths to be absolute
@@ -92,6 +92,15 @@ impl vm_area_struct {
 bitflags::bitflags! {
     pub struct FileFlags: crate::linux_kernel_module::c_types::c_uint {
         const NONBLOCK = O_NONBLOCK;
+        const ACCMODE = O_ACCMODE;
+        const RDONLY = O_RDONLY;
+        const WRONLY = O_WRONLY;
+        const RDWR = O_RDWR;
+        const CREAT = O_CREAT;
+        const EXCL = O_EXCL;
+        const TRUNC = O_TRUNC;
+        const APPEND = O_APPEND;
+        const CLOEXEC = O_CLOEXEC;
     }
 }
 
@@ -125,6 +134,17 @@ bitflags::bitflags! {
 bitflags::bitflags! {
     pub struct FaultFlags : crate::linux_kernel_module::c_types::c_uint {
         const SIGSEGV = PMEM_VM_FAULT_SIGSEGV;
+        const RETRY = PMEM_VM_FAULT_RETRY;
+        const NOPAGE = PMEM_VM_FAULT_NOPAGE;
+        const SIGBUS = PMEM_VM_FAULT_SIGBUS;
+    }
+}
+
+bitflags::bitflags! {
+    /// The flags of a `vm_fault`
+    pub struct FaultReqFlags : crate::linux_kernel_module::c_types::c_uint {
+        const ALLOW_RETRY = PMEM_FAULT_FLAG_ALLOW_RETRY;
+        const RETRY_NOWAIT = PMEM_FAULT_FLAG_RETRY_NOWAIT;
     }
 }
 
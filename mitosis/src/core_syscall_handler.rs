@@ -6,8 +6,10 @@ use rust_kernel_linux_util::kthread;
 
 #[allow(unused_imports)]
 use crate::descriptors::{ChildDescriptor, ParentDescriptor};
+use crate::descriptors::{FdRestorePolicy, FdRestoreReport};
 
 use crate::linux_kernel_module::c_types::*;
+use crate::remote_mapping::{FaultLocks, PteState};
 use crate::remote_paging::{AccessInfo, RemotePagingService};
 use crate::syscalls::FileOperations;
 
@@ -25,12 +27,43 @@ use crate::startup::probe_remote_rpc_end;
 
 const TIMEOUT_USEC: i64 = 1000_000; // 1s
 
+/// The read of an async fault is taken as lost after this long, see `wait_async_fault`
+#[cfg(feature = "async-fault")]
+const ASYNC_FAULT_TIMEOUT_USEC: i64 = 10 * TIMEOUT_USEC;
+
+/// The page in flight of another fault is waited for at most this long, see `wait_in_flight`
+const IN_FLIGHT_TIMEOUT_USEC: i64 = 10 * TIMEOUT_USEC;
+
+/// How a fault is resolved under the lock of its table
+enum FaultStep {
+    /// The fault is finished with the returned flags
+    Done(c_int),
+    /// The remote page is claimed to be read without the lock
+    Read(crate::remote_mapping::RemotePage),
+    /// The page is in flight by others
+    Wait,
+}
+
 #[allow(dead_code)]
 struct ResumeDataStruct {
     handler_id: usize,
     remote_mac_id: usize,
+    // of the image, see `shared_page_cache`
+    #[cfg_attr(not(feature = "page-sharing"), allow(dead_code))]
+    generation: u64,
     descriptor: crate::descriptors::ChildDescriptor,
     access_info: crate::remote_paging::AccessInfo,
+    // the threads of the child fault concurrently
+    fault_locks: FaultLocks,
+    // some read of an async fault is lost, see `wait_async_fault`
+    #[cfg(feature = "async-fault")]
+    lost_read: core::sync::atomic::AtomicBool,
+    // the parent's fds failed to restore
+    fd_report: FdRestoreReport,
+    // the hot pages being pushed by the parent,
+    // its lock is acquired after a table lock and before the descriptor's `FaultState`
+    #[cfg(feature = "push-resume")]
+    push: crate::lock_bundler::BoxedLockBundler<Option<crate::push_resume::PushRegion>>,
 }
 
 impl ResumeDataStruct {
@@ -44,8 +77,10 @@ impl ResumeDataStruct {
 struct CallerData {
     ping_img: bool,
     prepared_key: Option<usize>,
-    fault_page_cnt: usize,
+    fault_page_cnt: AtomicUsize,
     resume_related: Option<ResumeDataStruct>,
+    // how the parent's fds are restored by the next resume
+    fd_policy: FdRestorePolicy,
 }
 
 impl Default for CallerData {
@@ -53,14 +88,15 @@ impl Default for CallerData {
         Self {
             ping_img: false,
             prepared_key: None,
-            fault_page_cnt: 0,
+            fault_page_cnt: AtomicUsize::new(0),
             resume_related: None,
+            fd_policy: Default::default(),
         }
     }
 }
 
 use crate::rdma_context::SERVICE_ID_BASE;
-use core::sync::atomic::AtomicUsize;
+use core::sync::atomic::{AtomicUsize, Ordering};
 
 /// The MitosisSysCallService has the following two jobs:
 ///  1. handle up parent/child system calls
@@ -85,7 +121,16 @@ impl Drop for MitosisSysCallHandler {
                 "workingset size {} KB, page fault size {} KB, fetch page size {} KB",
                 meta_workingset_sz,
                 pg_fault_sz,
-                fetch_page_sz
+                fetch_page_sz,
+            );
+        }
+        if let Some(resume_related) = self.caller_status.resume_related.as_ref() {
+            let pt = &resume_related.descriptor.page_table;
+            crate::log::info!(
+                "page table of {} entries uses {} KB ({:?})",
+                pt.len(),
+                pt.memory_usage() / 1024,
+                pt.kind()
             );
         }
         self.cache_my_pt();
@@ -95,14 +140,14 @@ impl Drop for MitosisSysCallHandler {
             let res = self
                 .caller_status
                 .resume_related
-                .as_mut()
+                .as_ref()
                 .unwrap()
                 .descriptor
-                .prefetcher
-                .drain_connections();
-            if res.is_ok() {
+                .fault_state
+                .lock(|s| s.prefetcher.drain_connections());
+            if let Some(conn) = res {
                 unsafe {
-                    crate::get_dc_pool_async_service_ref().lock(|p| p.push_one_qp(res.unwrap()))
+                    crate::get_dc_pool_async_service_ref().lock(|p| p.push_one_qp(conn))
                 };
             }
         }
@@ -124,6 +169,14 @@ impl Drop for MitosisSysCallHandler {
                 }
             }
         }
+        #[cfg(feature = "dedup-pages")]
+        if let Some(des) = self.caller_status.resume_related.as_ref() {
+            des.descriptor.fault_state.lock(|s| {
+                for page_p in s.dedup_pages.values() {
+                    unsafe { crate::bindings::pmem_put_page(*page_p) };
+                }
+            });
+        }
     }
 }
 
@@ -156,31 +209,59 @@ impl FileOperations for MitosisSysCallHandler {
     #[allow(non_snake_case)]
     #[inline]
     fn ioctrl(&mut self, cmd: c_uint, arg: c_ulong) -> c_long {
-        use crate::bindings::{connect_req_t, resume_remote_req_t, LibMITOSISCmd};
-        use linux_kernel_module::bindings::_copy_from_user;
+        use crate::bindings::{
+            connect_req_t, fd_policy_req_t, prepare_req_t, resume_remote_req_t,
+            resume_secret_req_t, LibMITOSISCmd,
+        };
+        use linux_kernel_module::bindings::{_copy_from_user, _copy_to_user};
         match cmd {
             LibMITOSISCmd::Nil => 0, // a nill core do nothing
-            LibMITOSISCmd::Prepare => self.syscall_prepare(arg, false),
+            LibMITOSISCmd::Prepare => self.syscall_prepare(arg, false, 0),
             LibMITOSISCmd::ResumeLocal => unimplemented!(),
-            LibMITOSISCmd::ResumeRemote => {
-                let mut req: resume_remote_req_t = Default::default();
-                unsafe {
-                    _copy_from_user(
-                        (&mut req as *mut resume_remote_req_t).cast::<c_void>(),
-                        arg as *mut c_void,
-                        core::mem::size_of_val(&req) as u64,
-                    )
+            LibMITOSISCmd::ResumeRemote | LibMITOSISCmd::ResumeRemoteSecret => {
+                use crate::bindings::MITOSISPageTableKind;
+                use crate::remote_mapping::PageTableKind;
+
+                let (mac_id, handler_id, secret, pt_kind) = if cmd == LibMITOSISCmd::ResumeRemote {
+                    let mut req: resume_remote_req_t = Default::default();
+                    unsafe {
+                        _copy_from_user(
+                            (&mut req as *mut resume_remote_req_t).cast::<c_void>(),
+                            arg as *mut c_void,
+                            core::mem::size_of_val(&req) as u64,
+                        )
+                    };
+                    (req.machine_id, req.handler_id, 0, None)
+                } else {
+                    let mut req: resume_secret_req_t = Default::default();
+                    unsafe {
+                        _copy_from_user(
+                            (&mut req as *mut resume_secret_req_t).cast::<c_void>(),
+                            arg as *mut c_void,
+                            core::mem::size_of_val(&req) as u64,
+                        )
+                    };
+                    let pt_kind = match req.pt_kind {
+                        MITOSISPageTableKind::PtDefault => None,
+                        MITOSISPageTableKind::PtRadix => Some(PageTableKind::Radix),
+                        MITOSISPageTableKind::PtExtent => Some(PageTableKind::Extent),
+                        kind => {
+                            crate::log::error!("unknown page table kind {}", kind);
+                            return -1;
+                        }
+                    };
+                    (req.machine_id, req.handler_id, req.secret as u64, pt_kind)
                 };
-                let (mac_id, handler_id) = (req.machine_id, req.handler_id);
                 if cfg!(feature = "resume-profile") {
                     let mut profile = crate::KRdmaKit::Profile::new();
-                    let res = self.syscall_resume_w_rpc(mac_id as _, handler_id as _);
+                    let res =
+                        self.syscall_resume_w_rpc(mac_id as _, handler_id as _, secret, pt_kind);
                     profile.tick_record(0);
                     profile.increase_op(1);
                     profile.report(1);
                     res
                 } else {
-                    self.syscall_resume_w_rpc(mac_id as _, handler_id as _)
+                    self.syscall_resume_w_rpc(mac_id as _, handler_id as _, secret, pt_kind)
                 }
             }
             LibMITOSISCmd::Connect => {
@@ -217,7 +298,19 @@ impl FileOperations for MitosisSysCallHandler {
                     self.syscall_connect_rc(machine_id as _, &gid, nic_id as _) | self.syscall_connect_session(machine_id as _, &gid, nic_id as _)
                 }
             }
-            LibMITOSISCmd::PreparePing => self.syscall_prepare(arg, true),
+            LibMITOSISCmd::Disconnect => self.syscall_disconnect_session(arg as _),
+            LibMITOSISCmd::PreparePing => self.syscall_prepare(arg, true, 0),
+            LibMITOSISCmd::PrepareSecret => {
+                let mut req: prepare_req_t = Default::default();
+                unsafe {
+                    _copy_from_user(
+                        (&mut req as *mut prepare_req_t).cast::<c_void>(),
+                        arg as *mut c_void,
+                        core::mem::size_of_val(&req) as u64,
+                    )
+                };
+                self.syscall_prepare(req.key, req.ping != 0, req.secret as u64)
+            }
             LibMITOSISCmd::NilRPC => {
                 let mut req: resume_remote_req_t = Default::default();
                 unsafe {
@@ -230,6 +323,66 @@ impl FileOperations for MitosisSysCallHandler {
                 let (mac_id, handler_id) = (req.machine_id, req.handler_id);
                 self.syscall_nil_rpc(mac_id as _, handler_id as _)
             }
+            LibMITOSISCmd::FdPolicy => {
+                let mut req: fd_policy_req_t = Default::default();
+                unsafe {
+                    _copy_from_user(
+                        (&mut req as *mut fd_policy_req_t).cast::<c_void>(),
+                        arg as *mut c_void,
+                        core::mem::size_of_val(&req) as u64,
+                    )
+                };
+                self.syscall_set_fd_policy(&req)
+            }
+            LibMITOSISCmd::FdReport => {
+                let report = match self.caller_status.resume_related.as_ref() {
+                    Some(resume_related) => resume_related.fd_report.to_user(),
+                    None => {
+                        crate::log::error!("no resume to report the fds");
+                        return -1;
+                    }
+                };
+                let uncopied = unsafe {
+                    _copy_to_user(
+                        arg as *mut c_void,
+                        (&report as *const crate::bindings::fd_report_t).cast::<c_void>(),
+                        core::mem::size_of_val(&report) as u64,
+                    )
+                };
+                if uncopied != 0 {
+                    return -1;
+                }
+                0
+            }
+            LibMITOSISCmd::RpcStats => {
+                // too large for the kernel stack
+                let mut stats: alloc::boxed::Box<crate::bindings::rpc_stats_t> = Default::default();
+                stats.callers = crate::rpc_service::analysis_to_user(
+                    &unsafe { crate::get_rpc_caller_pool_ref() }.get_analysis(),
+                );
+                stats.handlers = crate::rpc_service::analysis_to_user(
+                    &unsafe { crate::service_rpc::get_ref() }.get_analysis(),
+                );
+                let uncopied = unsafe {
+                    _copy_to_user(
+                        arg as *mut c_void,
+                        (&*stats as *const crate::bindings::rpc_stats_t).cast::<c_void>(),
+                        core::mem::size_of_val(&*stats) as u64,
+                    )
+                };
+                if uncopied != 0 {
+                    return -1;
+                }
+                0
+            }
+            LibMITOSISCmd::RpcThreads => {
+                // the handlers are shared by all the users of the machine
+                if unsafe { crate::bindings::pmem_capable_admin() } == 0 {
+                    crate::log::warn!("reject setting the RPC handler threads: not an admin");
+                    return -1;
+                }
+                unsafe { crate::service_rpc::get_ref() }.set_handlers(arg as _) as _
+            }
             _ => {
                 crate::log::error!("unknown system call command ID {}", cmd);
                 -1
@@ -252,8 +405,9 @@ impl FileOperations for MitosisSysCallHandler {
 
 /// The system call parts
 impl MitosisSysCallHandler {
+    /// Prepare the image of myself, the resumes of which must present the secret (if not 0)
     #[inline]
-    fn syscall_prepare(&mut self, key: c_ulong, ping_img: bool) -> c_long {
+    fn syscall_prepare(&mut self, key: c_ulong, ping_img: bool, secret: u64) -> c_long {
         if self.caller_status.prepared_key.is_some() {
             crate::log::error!("This version doesn't support multiple fork yet. ");
             return -1;
@@ -262,9 +416,9 @@ impl MitosisSysCallHandler {
 
         let process_service = unsafe { crate::get_sps_mut() };
         let res = if cfg!(feature = "cow") {
-            process_service.add_myself_cow(key as _)
+            process_service.add_myself_cow(key as _, secret, self.my_file)
         } else {
-            process_service.add_myself_copy(key as _)
+            process_service.add_myself_copy(key as _, secret, self.my_file)
         };
 
         if res.is_none() {
@@ -342,8 +496,16 @@ impl MitosisSysCallHandler {
     }
 
     /// This is just a sample test function
+    ///
+    /// The secret of the image is checked by the parent, see `rpc_handlers::authorize`
     #[inline]
-    fn syscall_resume_w_rpc(&mut self, machine_id: c_ulong, handler_id: c_ulong) -> c_long {
+    fn syscall_resume_w_rpc(
+        &mut self,
+        machine_id: c_ulong,
+        handler_id: c_ulong,
+        secret: u64,
+        pt_kind: Option<crate::remote_mapping::PageTableKind>,
+    ) -> c_long {
         if self.caller_status.resume_related.is_some() {
             crate::log::error!("We don't support multiple resume yet. ");
             return -1;
@@ -379,23 +541,35 @@ impl MitosisSysCallHandler {
                 .expect("the caller should be properly initialized")
         };
         caller.lock(|caller| {
-            let res = caller.sync_call::<usize>(
+            let res = caller.call_async(
                 remote_session_id,
                 my_session_id,
                 crate::rpc_handlers::RPCId::Query as _,
-                handler_id as _,
+                crate::rpc_handlers::DescriptorQueryReq {
+                    key: handler_id as _,
+                    secret: secret,
+                },
             );
     
-            if res.is_err() {
-                crate::log::error!("failed to call {:?}", res);
-                crate::log::info!(
-                    "sanity check pending reqs {:?}",
-                    caller.get_pending_reqs(remote_session_id)
-                );
-                return -1;
+            let req_id = match res {
+                Ok(id) => id,
+                // the session is removed, e.g., the remote machine is down
+                Err(e) if e.is_peer_down() => {
+                    crate::log::error!("the session to machine {} is not connected", machine_id);
+                    return -(crate::linux_kernel_module::bindings::EHOSTDOWN as c_long);
+                }
+                Err(e) => {
+                    crate::log::error!("failed to call {:?}", e);
+                    crate::log::info!(
+                        "sanity check pending reqs {:?}",
+                        caller.get_pending_reqs(remote_session_id)
+                    );
+                    return -1;
+                }
             };
     
-            let mut timeout_caller = TimeoutWRef::new(caller, 10 * TIMEOUT_USEC);
+            let mut reply_future = caller.get_reply(req_id);
+            let mut timeout_caller = TimeoutWRef::new(&mut reply_future, 10 * TIMEOUT_USEC);
     
             use crate::rpc_handlers::DescriptorLookupReply;
             use os_network::serialize::Serialize;
@@ -412,68 +586,41 @@ impl MitosisSysCallHandler {
     
                             if !d.ready {
                                 crate::log::error!("failed to lookup handler id: {:?}", handler_id);
+                                // the image may have been unregistered
+                                #[cfg(feature = "page-sharing")]
+                                unsafe {
+                                    crate::get_shared_page_cache_ref()
+                                        .lock(|c| c.evict_image(machine_id as _, handler_id as _))
+                                };
                                 return -1;
                             }
+                            // the pages of a previous image with the same handler ID are stale
+                            #[cfg(feature = "page-sharing")]
+                            unsafe {
+                                crate::get_shared_page_cache_ref().lock(|c| {
+                                    c.check_image(machine_id as _, handler_id as _, d.generation)
+                                })
+                            };
                             #[cfg(feature = "resume-profile")]
                             crate::log::info!("meta descriptor size:{} KB", d.sz / 1024);
-    
-                            // fetch the descriptor with one-sided RDMA
-                            let desc_buf = RemotePagingService::remote_descriptor_fetch(
-                                d,
+
+                            // let the parent push the hot pages, overlapped with the descriptor fetch
+                            #[cfg(feature = "push-resume")]
+                            let push_region = Self::request_push(
                                 caller,
-                                machine_id,
+                                remote_session_id,
+                                my_session_id,
+                                handler_id as _,
+                                secret,
                             );
 
-                            crate::log::debug!("sanity check fetched desc_buf {:?}", desc_buf.is_ok());
-                            if desc_buf.is_err() {
-                                crate::log::error!("failed to fetch descriptor {:?}", desc_buf.err());
-                                return -1;
-                            }
-    
-                            // deserialize
-                            let des = {
-                                // optimized version
-                                ChildDescriptor::deserialize(desc_buf.unwrap().get_bytes())
-                            };
-    
-                            if des.is_none() {
-                                // crate::log::error!("failed to deserialize the child descriptor");
-                                return -1;
-                            }
-    
-                            let mut des = des.unwrap();
-    
-                            let access_info = AccessInfo::new(&des.machine_info);
-                            //let access_info =
-                            //AccessInfo::new_from_cache(des.machine_info.mac_id, &des.machine_info);
-                            if access_info.is_none() {
-                                crate::log::error!("failed to create access info");
-                                return -1;
-                            }
-    
-                            des.apply_to(self.my_file);
-    
-                            #[cfg(feature = "page-cache")]
-                            // Read the cache from kernel cache
-                            if let Some(cached_pg_table) = unsafe {
-                                crate::get_pt_cache_ref().lookup(machine_id as _, handler_id as _)
-                            } {
-                                crate::log::debug!(
-                                    "Find one cached page cache with mac id: {}, handler id: {}",
-                                    machine_id,
-                                    handler_id
-                                );
-                                des.page_table = cached_pg_table.copy();
+                            let res = self.resume_from_descriptor(d, caller, machine_id, handler_id, pt_kind);
+
+                            #[cfg(feature = "push-resume")]
+                            if let Some((region, push_id)) = push_region {
+                                self.start_push(caller, region, push_id);
                             }
-    
-                            self.caller_status.resume_related = Some(ResumeDataStruct {
-                                handler_id: handler_id as _,
-                                remote_mac_id: machine_id as _,
-                                descriptor: des,
-                                // access info cannot failed to create
-                                access_info: access_info.unwrap(),
-                            });
-                            return 0;
+                            return res;
                         }
                         None => {
                             crate::log::error!("Deserialize error");
@@ -483,12 +630,231 @@ impl MitosisSysCallHandler {
                 }
                 Err(e) => {
                     crate::log::error!("client receiver reply err {:?}", e);
+                    // the late reply is dropped on arrival
+                    caller.cancel(req_id);
                     return -1;
                 }
             };
         })
     }
 
+    /// Set the policy to restore the parent's fds, see `FdRestorePolicy`
+    fn syscall_set_fd_policy(&mut self, req: &crate::bindings::fd_policy_req_t) -> c_long {
+        use crate::bindings::MITOSISFdPolicy;
+        use crate::descriptors::FILE_PATH_LEN;
+
+        self.caller_status.fd_policy = match req.policy {
+            MITOSISFdPolicy::FdSamePath => FdRestorePolicy::SamePath,
+            MITOSISFdPolicy::FdDevNull => FdRestorePolicy::DevNull,
+            MITOSISFdPolicy::FdRedirect => {
+                let mut path_buf = [0 as u8; FILE_PATH_LEN];
+                let len = unsafe {
+                    crate::bindings::pmem_strncpy_from_user(
+                        path_buf.as_mut_ptr() as _,
+                        req.redirect_path as _,
+                        FILE_PATH_LEN as _,
+                    )
+                };
+                // a faulting or too long path
+                if len < 0 || len as usize >= FILE_PATH_LEN {
+                    crate::log::error!("failed to copy the redirect path of the fds: {}", len);
+                    return -1;
+                }
+                match core::str::from_utf8(&path_buf[..len as usize]) {
+                    Ok(path) if !path.is_empty() => FdRestorePolicy::Redirect(String::from(path)),
+                    _ => {
+                        crate::log::error!("invalid redirect path of the fds");
+                        return -1;
+                    }
+                }
+            }
+            _ => {
+                crate::log::error!("unknown fd policy {}", req.policy);
+                return -1;
+            }
+        };
+        0
+    }
+
+    /// Fetch the descriptor replied by the parent, and apply it to myself
+    #[inline]
+    fn resume_from_descriptor(
+        &mut self,
+        d: crate::rpc_handlers::DescriptorLookupReply,
+        caller: &mut crate::rpc_caller_pool::RPCCaller,
+        machine_id: c_ulong,
+        handler_id: c_ulong,
+        pt_kind: Option<crate::remote_mapping::PageTableKind>,
+    ) -> c_long {
+        use os_network::serialize::Serialize;
+        let generation = d.generation;
+
+        // fetch the descriptor with one-sided RDMA
+        let desc_buf = RemotePagingService::remote_descriptor_fetch(
+            d,
+            caller,
+            machine_id,
+        );
+
+        crate::log::debug!("sanity check fetched desc_buf {:?}", desc_buf.is_ok());
+        if desc_buf.is_err() {
+            crate::log::error!("failed to fetch descriptor {:?}", desc_buf.err());
+            return -1;
+        }
+
+        // deserialize
+        let des = {
+            // optimized version
+            // the module's backend unless the resume asks for one
+            let kind = pt_kind.unwrap_or(unsafe { *crate::page_table_kind::get_ref() });
+            ChildDescriptor::deserialize_with(desc_buf.unwrap().get_bytes(), kind)
+        };
+
+        if des.is_none() {
+            // crate::log::error!("failed to deserialize the child descriptor");
+            return -1;
+        }
+
+        let mut des = des.unwrap();
+
+        // checked before the task is modified, so a denied resume leaves it intact
+        if !crate::kern_wrappers::task::Task::new().check_task_state(&des.task_state) {
+            crate::log::error!(
+                "not allowed to assume the credentials of the parent {:?}",
+                des.task_state
+            );
+            return -1;
+        }
+
+        let access_info = AccessInfo::new(&des.machine_info);
+        //let access_info =
+        //AccessInfo::new_from_cache(des.machine_info.mac_id, &des.machine_info);
+        if access_info.is_none() {
+            crate::log::error!("failed to create access info");
+            return -1;
+        }
+
+        des.apply_to(self.my_file);
+        let fd_report = crate::kern_wrappers::task::Task::new().restore_files(
+            &des.files,
+            &self.caller_status.fd_policy,
+            self.my_file,
+        );
+        if !fd_report.is_empty() {
+            crate::log::warn!("failed to restore {} fds of the parent", fd_report.failed.len());
+        }
+
+        #[cfg(feature = "page-cache")]
+        // Read the cache from kernel cache
+        if let Some(cached_pg_table) = unsafe {
+            crate::get_pt_cache_ref().lock(|c| {
+                c.lookup(machine_id as _, handler_id as _).map(|pt| pt.copy())
+            })
+        } {
+            crate::log::debug!(
+                "Find one cached page cache with mac id: {}, handler id: {}",
+                machine_id,
+                handler_id
+            );
+            des.page_table = cached_pg_table;
+        }
+
+        self.caller_status.resume_related = Some(ResumeDataStruct {
+            handler_id: handler_id as _,
+            remote_mac_id: machine_id as _,
+            generation: generation,
+            descriptor: des,
+            // access info cannot failed to create
+            access_info: access_info.unwrap(),
+            fault_locks: FaultLocks::new(),
+            #[cfg(feature = "async-fault")]
+            lost_read: core::sync::atomic::AtomicBool::new(false),
+            fd_report,
+            #[cfg(feature = "push-resume")]
+            push: crate::lock_bundler::LockBundler::new(None),
+        });
+        return 0;
+    }
+
+    /// Ask the parent to push the hot pages into a newly allocated region.
+    /// The reply is not waited here, see `start_push`.
+    #[cfg(feature = "push-resume")]
+    fn request_push(
+        caller: &mut crate::rpc_caller_pool::RPCCaller,
+        remote_session_id: usize,
+        my_session_id: usize,
+        handler_id: usize,
+        secret: u64,
+    ) -> Option<(crate::push_resume::PushRegion, os_network::rpc::ReqId)> {
+        use crate::push_resume::{PushRegion, MAX_PUSH_PAGES};
+
+        let region = PushRegion::new(MAX_PUSH_PAGES)?;
+        let req = region.gen_request(handler_id, secret);
+        let res = caller.call_async_bytes(
+            remote_session_id,
+            my_session_id,
+            crate::rpc_handlers::RPCId::Push as _,
+            req.as_payload(),
+        );
+        match res {
+            Ok(req_id) => Some((region, req_id)),
+            Err(e) => {
+                // the parent will never write the region, so it is safe to free it
+                crate::log::error!("failed to request push {:?}", e);
+                region.cancel();
+                None
+            }
+        }
+    }
+
+    /// Receive the parent's plan of the push, whose pages are installed by the faults
+    /// as they arrive, see `install_pushed_pages`.
+    /// The parent replies before writing the pages, so the child never waits for them.
+    #[cfg(feature = "push-resume")]
+    fn start_push(
+        &mut self,
+        caller: &mut crate::rpc_caller_pool::RPCCaller,
+        mut region: crate::push_resume::PushRegion,
+        req_id: os_network::rpc::ReqId,
+    ) {
+        use crate::push_resume::PushPagesReply;
+
+        // other calls may be issued before, so the reply is matched by its ID
+        let mut reply_future = caller.get_reply(req_id);
+        let mut timeout_caller = TimeoutWRef::new(&mut reply_future, TIMEOUT_USEC);
+        let reply = match block_on(&mut timeout_caller) {
+            Ok((msg, reply)) => {
+                caller
+                    .register_recv_buf(msg)
+                    .expect("register msg buffer cannot fail");
+                crate::push_resume::deserialize_boxed::<PushPagesReply>(&reply)
+            }
+            Err(e) => {
+                crate::log::error!("failed to receive the push reply {:?}", e);
+                caller.cancel(req_id);
+                None
+            }
+        };
+
+        // without a reply, the parent may still write the region, so it is freed later on drop
+        let reply = match reply {
+            Some(reply) => reply,
+            None => return,
+        };
+        if !region.start(&reply) {
+            return;
+        }
+        match self.caller_status.resume_related.as_ref() {
+            Some(resume_related) => resume_related.push.lock(|push| {
+                if !region.install_ready(&resume_related.descriptor) {
+                    *push = Some(region);
+                }
+            }),
+            // the parent may still write the region of the failed resume, so it is freed later
+            None => core::mem::drop(region),
+        }
+    }
+
     #[inline]
     fn syscall_connect_session(
         &mut self,
@@ -510,6 +876,13 @@ impl MitosisSysCallHandler {
         }
     }
 
+    #[inline]
+    fn syscall_disconnect_session(&mut self, machine_id: usize) -> c_long {
+        crate::log::debug!("disconnect remote machine id: {}", machine_id);
+        crate::startup::disconnect_remote_rpc_end(machine_id);
+        0
+    }
+
     #[cfg(feature = "use_rc")]
     #[inline]
     fn syscall_connect_rc(
@@ -563,22 +936,31 @@ impl MitosisSysCallHandler {
                 .expect("the caller should be properly initialized")
         };
         caller.lock(|caller| {
-            let res = caller.sync_call::<usize>(
+            let res = caller.call_async::<usize>(
                 remote_session_id,
                 my_session_id,
                 crate::rpc_handlers::RPCId::Nil as _,
                 handler_id as _,
             );
-            if res.is_err() {
-                crate::log::error!("failed to call {:?}", res);
-                crate::log::info!(
-                    "sanity check pending reqs {:?}",
-                    caller.get_pending_reqs(remote_session_id)
-                );
-                return -1;
+            let req_id = match res {
+                Ok(id) => id,
+                // the session is removed, e.g., the remote machine is down
+                Err(e) if e.is_peer_down() => {
+                    crate::log::error!("the session to machine {} is not connected", machine_id);
+                    return -(crate::linux_kernel_module::bindings::EHOSTDOWN as c_long);
+                }
+                Err(e) => {
+                    crate::log::error!("failed to call {:?}", e);
+                    crate::log::info!(
+                        "sanity check pending reqs {:?}",
+                        caller.get_pending_reqs(remote_session_id)
+                    );
+                    return -1;
+                }
             };
     
-            let mut timeout_caller = TimeoutWRef::new(caller, 10 * TIMEOUT_USEC);
+            let mut reply_future = caller.get_reply(req_id);
+            let mut timeout_caller = TimeoutWRef::new(&mut reply_future, 10 * TIMEOUT_USEC);
     
             use os_network::serialize::Serialize;
             let _reply = match block_on(&mut timeout_caller) {
@@ -591,6 +973,8 @@ impl MitosisSysCallHandler {
                 }
                 Err(e) => {
                     crate::log::error!("client receiver reply err {:?}", e);
+                    // the late reply is dropped on arrival
+                    caller.cancel(req_id);
                     return -1;
                 }
             };
@@ -608,42 +992,144 @@ unsafe extern "C" fn open_handler(_area: *mut crate::bindings::vm_area_struct) {
 
 #[allow(dead_code)]
 unsafe extern "C" fn page_fault_handler(vmf: *mut crate::bindings::vm_fault) -> c_int {
-    let handler: *mut MitosisSysCallHandler = (*(*vmf).vma).vm_private_data as *mut _;
+    // the threads of the child fault concurrently, so the handler is only shared
+    let handler: *const MitosisSysCallHandler = (*(*vmf).vma).vm_private_data as *const _;
     (*handler).handle_page_fault(vmf)
 }
 
 impl MitosisSysCallHandler {
     /// Core logic of handling the page faults
+    ///
+    /// The threads of a multi-threaded child fault concurrently,
+    /// see `FaultLocks` for how they are synchronized.
+    /// The lock of the table is never held during the network round trip:
+    /// the read of a remote page is claimed under the lock, done without it,
+    /// and the page is installed with the lock taken again.
     #[inline(always)]
-    unsafe fn handle_page_fault(&mut self, vmf: *mut crate::bindings::vm_fault) -> c_int {
+    unsafe fn handle_page_fault(&self, vmf: *mut crate::bindings::vm_fault) -> c_int {
         let fault_addr = (*vmf).address;
         #[cfg(feature = "resume-profile")]
         self.incr_fault_page_cnt();
 
-        let resume_related = self.caller_status.resume_related.as_mut().unwrap();
-        // #[cfg(feature = "page-cache")]
-        // let resume_related = self.caller_status.resume_related.as_ref().unwrap();
+        // The resumed descriptor is never replaced while the child runs
+        let resume_related = self.caller_status.resume_related.as_ref().unwrap();
+        let fault_locks = &resume_related.fault_locks;
+
+        loop {
+            let step = fault_locks.lock_table(fault_addr, || {
+                if Self::page_is_mapped(vmf) {
+                    // a concurrent fault on the same page has finished
+                    return FaultStep::Done(crate::bindings::FaultFlags::NOPAGE.bits() as c_int);
+                }
+                Self::resolve_page_fault(vmf, resume_related)
+            });
+
+            match step {
+                FaultStep::Done(ret) => {
+                    // the posted read is waited without any lock
+                    #[cfg(feature = "async-fault")]
+                    if ret == crate::bindings::FaultFlags::RETRY.bits() as c_int {
+                        Self::wait_async_fault(vmf, resume_related, fault_addr);
+                    }
+                    return ret;
+                }
+                FaultStep::Read(remote) => {
+                    let new_page = Self::read_remote_page_at(resume_related, fault_addr, remote);
+                    return fault_locks.lock_table(fault_addr, || {
+                        Self::install_read_page(vmf, resume_related, remote, new_page)
+                    });
+                }
+                FaultStep::Wait => {
+                    if !Self::wait_in_flight(resume_related, fault_addr) {
+                        return crate::bindings::FaultFlags::SIGBUS.bits() as c_int;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Wait for the page read by others, e.g., another fault or the prefetcher, without the lock.
+    /// Return false if the wait times out.
+    unsafe fn wait_in_flight(
+        resume_related: &ResumeDataStruct,
+        fault_addr: crate::kern_wrappers::mm::VirtAddrType,
+    ) -> bool {
+        let timer = rust_kernel_linux_util::timer::KTimer::new();
+        while !resume_related.descriptor.poll_in_flight(fault_addr) {
+            if timer.get_passed_usec() > IN_FLIGHT_TIMEOUT_USEC {
+                crate::log::error!(
+                    "[handle_page_fault] The page is in flight for too long, fault addr: 0x{:x}",
+                    fault_addr
+                );
+                return false;
+            }
+            crate::bindings::pmem_cond_resched();
+        }
+        true
+    }
+
+    /// Whether the faulted page has been mapped, e.g., by a concurrent fault
+    #[inline(always)]
+    unsafe fn page_is_mapped(vmf: *mut crate::bindings::vm_fault) -> bool {
+        let pte = crate::bindings::pmem_get_pte((*(*vmf).vma).vm_mm, (*vmf).address);
+        !pte.is_null() && crate::bindings::pmem_check_pte_present(pte) != 0
+    }
+
+    /// Find the page of the fault, with the lock of its table held.
+    /// A remote page is only claimed here, and read by `handle_page_fault` without the lock.
+    #[inline(always)]
+    unsafe fn resolve_page_fault(
+        vmf: *mut crate::bindings::vm_fault,
+        resume_related: &ResumeDataStruct,
+    ) -> FaultStep {
+        let fault_addr = (*vmf).address;
+
+        #[cfg(feature = "push-resume")]
+        Self::install_pushed_pages(resume_related);
 
         #[cfg(feature = "page-cache")]
         let mut miss_page_cache = false;
-        let phy_addr = resume_related.descriptor.lookup_pg_table(fault_addr);
+        #[cfg(not(feature = "page-cache"))]
+        let miss_page_cache = false;
+        let pte = resume_related.descriptor.lookup_pte(fault_addr);
+
+        // the retried fault of a lost read, whose entry is never resolved
+        #[cfg(feature = "async-fault")]
+        if pte == Some(PteState::InFlight)
+            && resume_related.lost_read.load(core::sync::atomic::Ordering::Acquire)
+        {
+            crate::log::error!(
+                "[handle_page_fault] The read of the page is lost, fault addr: 0x{:x}",
+                fault_addr
+            );
+            return FaultStep::Done(crate::bindings::FaultFlags::SIGBUS.bits() as c_int);
+        }
+
+        #[cfg(feature = "async-fault")]
+        if let Some(ret) = Self::try_async_fault(vmf, resume_related, fault_addr, pte) {
+            return FaultStep::Done(ret);
+        }
+
+        // read by others, e.g., a concurrent fault on the same page
+        if pte == Some(PteState::InFlight) {
+            return FaultStep::Wait;
+        }
 
         let new_page = {
-            if phy_addr.is_none() {
+            if pte.is_none() {
                 None
+            } else if let Some(page) = Self::read_eliminated_page(resume_related, fault_addr) {
+                // zero or duplicated pages
+                Some(page)
             } else {
                 #[cfg(feature = "page-cache")]
                 {
-                    use crate::remote_mapping::PhysAddr;
-                    let phy_addr = phy_addr.unwrap();
-                    let phys_addr = PhysAddr::new(phy_addr);
+                    let pte = pte.unwrap();
                     // if cache hit
-                    if phys_addr.is_cache() {
-                        let mut page = crate::kern_wrappers::Page::new_from_raw(
-                            phys_addr.convert_to_page() as *mut crate::bindings::page,
-                        );
+                    if let PteState::Cached { page, ro } = pte {
+                        let mut page = crate::kern_wrappers::Page::new_from_raw(page);
 
-                        if phys_addr.is_ro() {
+                        if ro {
                             // Read only, mark it as COW directly
                             page.increase_ref_count();
                             Some(page.get_inner())
@@ -663,54 +1149,128 @@ impl MitosisSysCallHandler {
                     } else {
                         // Cache miss, fallback into RDMA read
                         miss_page_cache = true;
-                        resume_related
-                            .descriptor
-                            .read_remote_page(fault_addr, 
-                                &resume_related.access_info,
-                            )
+                        match Self::lookup_local_or_shared_page(resume_related, fault_addr, pte) {
+                            Some(page) => Some(page),
+                            None => match resume_related.descriptor.claim_remote_read(fault_addr) {
+                                Some(remote) => return FaultStep::Read(remote),
+                                // e.g., a huge page
+                                None => Self::read_remote_page(resume_related, fault_addr),
+                            },
+                        }
                     }
                 }
                 #[cfg(not(feature = "page-cache"))]
                 {
-                    resume_related
-                        .descriptor
-                        .read_remote_page(fault_addr, 
-                            &resume_related.access_info,
-                        )
+                    let pte = pte.unwrap();
+                    match Self::lookup_local_or_shared_page(resume_related, fault_addr, pte) {
+                        Some(page) => Some(page),
+                        None => match resume_related.descriptor.claim_remote_read(fault_addr) {
+                            Some(remote) => return FaultStep::Read(remote),
+                            // e.g., a huge page
+                            None => Self::read_remote_page(resume_related, fault_addr),
+                        },
+                    }
                 }
             }
         };
+        FaultStep::Done(Self::install_page(vmf, resume_related, pte, new_page, miss_page_cache))
+    }
+
+    /// Install the page read without the lock of its table, which is held again.
+    /// The claimed entry is restored first, see `ChildDescriptor::claim_remote_read`.
+    #[inline(always)]
+    unsafe fn install_read_page(
+        vmf: *mut crate::bindings::vm_fault,
+        resume_related: &ResumeDataStruct,
+        remote: crate::remote_mapping::RemotePage,
+        new_page: Option<*mut crate::bindings::page>,
+    ) -> c_int {
+        let fault_addr = (*vmf).address;
+        resume_related.descriptor.finish_remote_read(fault_addr, remote);
+
+        // share the fetched page with the siblings
+        #[cfg(feature = "page-sharing")]
+        if let Some(new_page_p) = new_page.filter(|_| remote.ro) {
+            let (mac_id, handler_id, generation) = (
+                resume_related.remote_mac_id,
+                resume_related.handler_id,
+                resume_related.generation,
+            );
+            crate::get_shared_page_cache_ref()
+                .lock(|c| c.insert(mac_id, handler_id, generation, remote.pa, new_page_p));
+        }
+
+        Self::install_page(
+            vmf,
+            resume_related,
+            Some(PteState::Remote(remote)),
+            new_page,
+            cfg!(feature = "page-cache"),
+        )
+    }
+
+    /// Hand the page of the fault to the kernel, or fail the fault without one.
+    /// `miss_page_cache` tells whether the page should be kept by the page cache.
+    #[inline(always)]
+    #[allow(unused_variables)]
+    unsafe fn install_page(
+        vmf: *mut crate::bindings::vm_fault,
+        resume_related: &ResumeDataStruct,
+        pte: Option<PteState>,
+        new_page: Option<*mut crate::bindings::page>,
+        miss_page_cache: bool,
+    ) -> c_int {
+        let fault_addr = (*vmf).address;
         match new_page {
             Some(new_page_p) => {
                 (*vmf).page = new_page_p as *mut _;
                 // update cache
                 #[cfg(feature = "page-cache")]
-                if miss_page_cache && phy_addr.is_some() {
-                    use crate::remote_mapping::{PhysAddr, PhysAddrBitFlag};
-
-                    // let phy_addr = phy_addr.unwrap();
+                if miss_page_cache {
                     // Caching up this page. Just mark as CoW.
-                    // We leave the Cache bit setting process to function `caching_pg_table`
-                    crate::kern_wrappers::Page::new_from_raw(new_page_p).increase_ref_count();
-
-                    let kernel_va = PhysAddr::encode(
-                        new_page_p as crate::kern_wrappers::mm::VirtAddrType,
-                        PhysAddrBitFlag::Cache as _,
-                    );
-                    resume_related
+                    // Only a remote entry can be cached, i.e., the read page is not eliminated.
+                    let slot = resume_related
                         .descriptor
                         .page_table
-                        .force_map(x86_64::VirtAddr::new(fault_addr), PhysAddr::new(kernel_va));
+                        .find_entry(x86_64::VirtAddr::new(fault_addr));
+                    let cached = match slot.map(|slot| (slot, slot.state())) {
+                        Some((slot, PteState::Remote(remote))) => slot
+                            .transit(PteState::Cached {
+                                page: new_page_p,
+                                ro: remote.ro,
+                            })
+                            .is_ok(),
+                        _ => false,
+                    };
+                    if cached {
+                        crate::kern_wrappers::Page::new_from_raw(new_page_p).increase_ref_count();
+                    }
                 }
                 0
             }
             None => {
-                // check whether the page is anonymous
+                // the page can never be read from the down parent
+                if pte.is_some() && crate::peer_monitor::is_peer_down(resume_related.remote_mac_id) {
+                    crate::log::error!(
+                        "[handle_page_fault] The parent machine {} is down, fault addr: 0x{:x}",
+                        resume_related.remote_mac_id,
+                        fault_addr
+                    );
+                    return crate::bindings::FaultFlags::SIGBUS.bits() as linux_kernel_module::c_types::c_int;
+                }
+
+                // check whether the page is anonymous,
+                // the stack VMA may have grown down since it is mapped
                 let vma = crate::kern_wrappers::vma::VMA::new(&mut *((*vmf).vma));
                 for &vd in &resume_related.descriptor.vma {
-                    if vd.is_anonymous && (vma.get_start() == vd.get_start()) {
-                        let new_page_p =
-                            crate::bindings::pmem_alloc_page(crate::bindings::PMEM_GFP_HIGHUSER);
+                    if vd.is_anonymous
+                        && (vma.get_start() == vd.get_start()
+                            || (vd.is_stack() && vma.get_end() == vd.get_end()))
+                    {
+                        // never touched by the parent
+                        let new_page_p = crate::bindings::pmem_alloc_page(
+                            crate::bindings::PMEM_GFP_HIGHUSER_ZERO,
+                        );
 
                         (*vmf).page = new_page_p as *mut _;
                         return 0;
@@ -726,17 +1286,191 @@ impl MitosisSysCallHandler {
         }
     }
 
+    /// Install the pages pushed by the parent since the last fault
+    #[cfg(feature = "push-resume")]
+    #[inline(always)]
+    unsafe fn install_pushed_pages(resume_related: &ResumeDataStruct) {
+        let descriptor = &resume_related.descriptor;
+        resume_related.push.lock(|push| {
+            if push.as_mut().map(|p| p.install_ready(descriptor)).unwrap_or(false) {
+                *push = None;
+            }
+        });
+    }
+
+    /// Read the faulted page without holding the mmap_sem during the network round trip.
+    ///
+    /// The read is posted here, and waited by `wait_async_fault` after the table lock
+    /// is released. The fault then returns VM_FAULT_RETRY,
+    /// and the retried fault finds the page ready.
+    /// Return None if the fault should be handled synchronously.
+    #[cfg(feature = "async-fault")]
+    unsafe fn try_async_fault(
+        vmf: *mut crate::bindings::vm_fault,
+        resume_related: &ResumeDataStruct,
+        fault_addr: crate::kern_wrappers::mm::VirtAddrType,
+        pte: Option<PteState>,
+    ) -> Option<c_int> {
+        use crate::bindings::{FaultFlags, FaultReqFlags};
+
+        let flags = FaultReqFlags::from_bits_truncate((*vmf).flags);
+        if !flags.contains(FaultReqFlags::ALLOW_RETRY) {
+            // e.g., this is already the retried fault
+            return None;
+        }
+
+        match pte? {
+            // read-only pages are looked up in the shared page cache first
+            #[cfg(feature = "page-sharing")]
+            PteState::Remote(remote) if remote.ro => return None,
+            PteState::Remote(_) | PteState::InFlight | PteState::Local(_) => {}
+            // resolved locally
+            _ => return None,
+        }
+
+        // the sync path fails the fault of a down parent
+        if crate::peer_monitor::is_peer_down(resume_related.remote_mac_id) {
+            return None;
+        }
+
+        if resume_related.descriptor.start_remote_read(fault_addr) {
+            return None;
+        }
+        Some(FaultFlags::RETRY.bits() as c_int)
+    }
+
+    /// Wait for the read posted by `try_async_fault` with the mmap_sem released.
+    ///
+    /// No lock is held, so the other threads fault meanwhile,
+    /// including the ones on the same page, which find it in flight.
+    ///
+    /// The wait is bounded by `ASYNC_FAULT_TIMEOUT_USEC`, e.g., the completion is lost.
+    /// The mmap_sem has been released, so VM_FAULT_RETRY is still returned,
+    /// and the retried fault returns VM_FAULT_SIGBUS instead, see `lost_read`.
+    #[cfg(feature = "async-fault")]
+    unsafe fn wait_async_fault(
+        vmf: *mut crate::bindings::vm_fault,
+        resume_related: &ResumeDataStruct,
+        fault_addr: crate::kern_wrappers::mm::VirtAddrType,
+    ) {
+        use crate::bindings::FaultReqFlags;
+
+        let flags = FaultReqFlags::from_bits_truncate((*vmf).flags);
+        if flags.contains(FaultReqFlags::RETRY_NOWAIT) {
+            return;
+        }
+
+        // The VMA may be unmapped once the mmap_sem is released,
+        // so hold its file, which owns this handler, during the wait.
+        let file = (*(*vmf).vma).vm_file;
+        crate::bindings::pmem_get_file(file);
+        crate::bindings::pmem_fault_release_mmap_sem(vmf);
+
+        // The read of a down parent fails (after the transport timeout) rather than hangs,
+        // which restores the entry, so the retried fault fails without reading.
+        // It is waited even so, since an in-flight entry is never resolved by the faults.
+        let timer = rust_kernel_linux_util::timer::KTimer::new();
+        while !resume_related.descriptor.poll_in_flight(fault_addr) {
+            if timer.get_passed_usec() > ASYNC_FAULT_TIMEOUT_USEC {
+                crate::log::error!("the read of the async fault is lost, fault addr: 0x{:x}", fault_addr);
+                resume_related
+                    .lost_read
+                    .store(true, core::sync::atomic::Ordering::Release);
+                break;
+            }
+            crate::bindings::pmem_cond_resched();
+        }
+        crate::bindings::pmem_put_file(file);
+    }
+
+    /// The page of the fault found locally, without the network round trip.
+    ///
+    /// With the `page-sharing` feature, a read-only page is first looked up in
+    /// the host-wide shared page cache, which may have been filled by a sibling child
+    /// of the same image. A miss will share the fetched page with the others,
+    /// see `install_read_page`.
+    #[inline(always)]
+    #[allow(unused_variables)]
+    unsafe fn lookup_local_or_shared_page(
+        resume_related: &ResumeDataStruct,
+        fault_addr: crate::kern_wrappers::mm::VirtAddrType,
+        pte: PteState,
+    ) -> Option<*mut crate::bindings::page> {
+        #[cfg(feature = "page-sharing")]
+        if let PteState::Remote(remote) = pte {
+            if remote.ro {
+                let (mac_id, handler_id, generation) = (
+                    resume_related.remote_mac_id,
+                    resume_related.handler_id,
+                    resume_related.generation,
+                );
+                if let Some(page) = crate::get_shared_page_cache_ref()
+                    .lock(|c| c.lookup(mac_id, handler_id, generation, remote.pa))
+                {
+                    return Some(page);
+                }
+            }
+        }
+
+        resume_related.descriptor.take_local_page(fault_addr)
+    }
+
+    /// Read the page claimed by `resolve_page_fault`, with no lock held.
+    #[inline(always)]
+    unsafe fn read_remote_page_at(
+        resume_related: &ResumeDataStruct,
+        fault_addr: crate::kern_wrappers::mm::VirtAddrType,
+        remote: crate::remote_mapping::RemotePage,
+    ) -> Option<*mut crate::bindings::page> {
+        // fail fast instead of waiting for the read to time out
+        if crate::peer_monitor::is_peer_down(resume_related.remote_mac_id) {
+            return None;
+        }
+        resume_related
+            .descriptor
+            .read_page_at(fault_addr, remote.pa, &resume_related.access_info)
+    }
+
+    /// Read the entries that can not be claimed, e.g., huge pages, under the lock.
+    /// The prefetcher and the pushed pages shared by the whole descriptor are updated
+    /// behind its `FaultState` lock, which is never held during the reads.
+    #[inline(always)]
+    unsafe fn read_remote_page(
+        resume_related: &ResumeDataStruct,
+        fault_addr: crate::kern_wrappers::mm::VirtAddrType,
+    ) -> Option<*mut crate::bindings::page> {
+        // fail fast instead of waiting for the read to time out
+        if crate::peer_monitor::is_peer_down(resume_related.remote_mac_id) {
+            return None;
+        }
+        resume_related
+            .descriptor
+            .read_remote_page(fault_addr, &resume_related.access_info)
+    }
+
+    /// Zero pages are allocated in parallel,
+    /// while the copies of the duplicated pages are shared by the whole descriptor.
+    #[inline(always)]
+    unsafe fn read_eliminated_page(
+        resume_related: &ResumeDataStruct,
+        fault_addr: crate::kern_wrappers::mm::VirtAddrType,
+    ) -> Option<*mut crate::bindings::page> {
+        resume_related
+            .descriptor
+            .read_eliminated_page(fault_addr, &resume_related.access_info)
+    }
+
     #[allow(dead_code)]
     #[inline]
-    fn incr_fault_page_cnt(&mut self) {
-        self.caller_status.fault_page_cnt += 1;
+    fn incr_fault_page_cnt(&self) {
+        self.caller_status.fault_page_cnt.fetch_add(1, Ordering::Relaxed);
     }
 
     /// Page fault size (in Bytes)
     #[allow(dead_code)]
     #[inline]
     fn fault_page_size(&self) -> usize {
-        self.caller_status.fault_page_cnt * 4096 as usize
+        self.caller_status.fault_page_cnt.load(Ordering::Relaxed) * 4096 as usize
     }
 
     #[allow(dead_code)]
@@ -752,7 +1486,7 @@ impl MitosisSysCallHandler {
     #[cfg(feature = "resume-profile")]
     fn fetched_page_size(&self) -> usize {
         if let Some(meta) = self.caller_status.resume_related.as_ref() {
-            meta.descriptor.remote_fetched_page_count * 4096 as usize
+            meta.descriptor.remote_fetched_page_count.load(Ordering::Relaxed) * 4096 as usize
         } else {
             0
         }
@@ -766,13 +1500,15 @@ impl MitosisSysCallHandler {
         if let Some(resume_related) = self.caller_status.resume_related.as_ref() {
             // copy to the kernel cache
 
-            let pg_table = resume_related.descriptor.page_table.copy();
+            let pg_table = resume_related.descriptor.copy_page_table_for_cache();
             unsafe {
-                crate::get_pt_cache_mut().insert(
-                    resume_related.remote_mac_id,
-                    resume_related.handler_id,
-                    pg_table,
-                );
+                crate::get_pt_cache_ref().lock(|c| {
+                    c.insert(
+                        resume_related.remote_mac_id,
+                        resume_related.handler_id,
+                        pg_table,
+                    )
+                });
             }
         }
     }
@@ -16,8 +16,10 @@ pub struct DCPool {
     nic_idxs: Vec<usize>,
 }
 
+/// The per-core caches of the access info.
+/// Each is locked, since the peer monitor removes the entries of all the cores.
 pub struct AccessInfoPool {
-    pool: Vec<HashMap<usize, crate::remote_paging::AccessInfo>>,
+    pool: Vec<BoxedLockBundler<HashMap<usize, crate::remote_paging::AccessInfo>>>,
 }
 
 // TODO: currently the mapping is not done good
@@ -25,7 +27,7 @@ impl AccessInfoPool {
     pub fn new(sz: usize) -> Self {
         let mut res = Vec::new();
         for _ in 0..sz {
-            res.push(Default::default());
+            res.push(LockBundler::new(Default::default()));
         }
 
         Self { pool: res }
@@ -35,12 +37,19 @@ impl AccessInfoPool {
         &self,
         idx: usize,
         id: usize,
-    ) -> core::option::Option<&crate::remote_paging::AccessInfo> {
-        self.pool[idx].get(&id)
+    ) -> core::option::Option<crate::remote_paging::AccessInfo> {
+        self.pool[idx].lock(|pool| pool.get(&id).cloned())
     }
 
-    pub fn insert(&mut self, idx: usize, id: usize, access: crate::remote_paging::AccessInfo) {
-        self.pool[idx].insert(id, access);
+    pub fn insert(&self, idx: usize, id: usize, access: crate::remote_paging::AccessInfo) {
+        self.pool[idx].lock(|pool| pool.insert(id, access));
+    }
+
+    /// Remove the cached access info of all the cores, e.g., the remote is down
+    pub fn remove(&self, id: usize) {
+        for pool in self.pool.iter() {
+            pool.lock(|pool| pool.remove(&id));
+        }
     }
 }
 
@@ -1,9 +1,13 @@
 #[allow(unused_imports)]
 use crate::bindings::page;
 use crate::linux_kernel_module;
+use alloc::collections::VecDeque;
 use alloc::sync::Arc;
 use alloc::vec::Vec;
-use os_network::KRdmaKit::{MemoryRegion, DatapathError};
+use core::ops::Range;
+use os_network::KRdmaKit::MemoryRegion;
+#[allow(unused_imports)]
+use os_network::KRdmaKit::DatapathError;
 use os_network::rdma::payload::RDMAOp;
 use os_network::rdma::payload::dc::DCReqPayload;
 use os_network::timeout::TimeoutWRef;
@@ -16,6 +20,9 @@ use os_network::future::{Async, Future};
 use os_network::Conn;
 
 use super::rdma::RDMADescriptor;
+use super::files::FileTableDescriptor;
+use super::mm_layout::MMLayoutDescriptor;
+use super::task_state::TaskStateDescriptor;
 use super::reg::RegDescriptor;
 use super::vma::VMADescriptor;
 
@@ -23,39 +30,98 @@ use super::vma::VMADescriptor;
 use super::page_table::FlatPageTable;
 
 #[allow(unused_imports)]
-use crate::remote_mapping::{PageEntry, PhysAddr, RemotePageTable, RemotePageTableIter, VirtAddr};
+use crate::remote_mapping::{
+    ChildPageTable, PageEntry, PageTableKind, PhysAddr, PteSlot, PteState, RemotePage, VirtAddr,
+};
 
 #[allow(unused_imports)]
 use super::parent::{CompactPageTable, Offset, Value};
 
 use crate::kern_wrappers::mm::{PhyAddrType, VirtAddrType};
 use crate::kern_wrappers::task::Task;
+use crate::lock_bundler::{BoxedLockBundler, LockBundler};
 use crate::remote_paging::AccessInfo;
 
 #[cfg(feature = "prefetch")]
 use crate::prefetcher::{DCAsyncPrefetcher, StepPrefetcher};
 
+/// Max pages coalesced into one read of `batch_read_remote_pages`, one SGE for each
+const MAX_COALESCED_PAGES: usize = os_network::rdma::dc::MAX_DC_SGE;
+
+/// One signaled read for every `READS_PER_SIGNAL` reads of `batch_read_remote_pages`
+const READS_PER_SIGNAL: usize = 8;
+
+/// Max signaled reads in flight of `batch_read_remote_pages`
+const MAX_INFLIGHT_SIGNALS: usize = 4;
+
+/// Number of pages read in one batch by the eager resume
+#[cfg(feature = "eager-resume")]
+const EAGER_BATCH_PAGES: usize = 128;
+
+#[derive(Clone, Copy)]
+enum BatchReadResult {
+    Done,
+    Failed,
+    TimedOut,
+}
+
 /// The kernel-space process descriptor of MITOSIS
 /// The descriptors should be generate by the task
 #[allow(dead_code)]
 pub struct ChildDescriptor {
     pub regs: RegDescriptor,
+    pub mm_layout: MMLayoutDescriptor,
+    pub files: FileTableDescriptor,
+    pub task_state: TaskStateDescriptor,
 
     // #[cfg(not(feature = "prefetch"))]
     // pub page_table: FlatPageTable,
     //
     // #[cfg(feature = "prefetch")]
-    pub page_table: RemotePageTable,
+    pub page_table: ChildPageTable,
 
     pub vma: Vec<VMADescriptor>,
     pub machine_info: RDMADescriptor,
 
-    #[cfg(feature = "prefetch")]
-    pub prefetcher: DCAsyncPrefetcher,
     #[cfg(feature = "eager-resume")]
     pub eager_fetched_pages: hashbrown::HashSet<VirtAddrType>,
     #[cfg(feature = "resume-profile")]
-    pub remote_fetched_page_count: usize,
+    pub remote_fetched_page_count: core::sync::atomic::AtomicUsize,
+    // updated by the faults of all the threads of the child
+    pub fault_state: BoxedLockBundler<FaultState>,
+    // the pages carried by the descriptor, to be mapped in `apply_to`
+    #[cfg(feature = "piggyback-pages")]
+    pub piggybacked_pages: PiggybackedPages,
+}
+
+/// The states of a descriptor shared by all the faults, behind its own lock.
+///
+/// The lock is only held to update the states, never during a network round trip.
+/// It is always acquired after a table lock of `FaultLocks`.
+pub struct FaultState {
+    #[cfg(feature = "prefetch")]
+    pub prefetcher: DCAsyncPrefetcher,
+    // remote PA of the duplicated pages -> the local copy shared by them
+    #[cfg(feature = "dedup-pages")]
+    pub dedup_pages: hashbrown::HashMap<PhyAddrType, *mut page>,
+    // remote VA of the pages pushed by the parent -> their origin remote pages
+    #[cfg(feature = "push-resume")]
+    pub pushed_pages: hashbrown::HashMap<VirtAddrType, RemotePage>,
+}
+
+/// The pages carried by the descriptor.
+/// Those not mapped by `apply_to`, e.g., if the resume fails before it, are freed on drop.
+#[cfg(feature = "piggyback-pages")]
+#[derive(Default)]
+pub struct PiggybackedPages(pub Vec<(VirtAddrType, *mut page)>);
+
+#[cfg(feature = "piggyback-pages")]
+impl Drop for PiggybackedPages {
+    fn drop(&mut self) {
+        for (_, page_p) in self.0.drain(..) {
+            unsafe { crate::bindings::pmem_free_page(page_p) };
+        }
+    }
 }
 
 impl ChildDescriptor {
@@ -67,10 +133,8 @@ impl ChildDescriptor {
     }
 
     #[inline(always)]
-    pub fn lookup_pg_table(&self, virt: VirtAddrType) -> Option<PhyAddrType> {
-        self.page_table
-            .translate(VirtAddr::new(virt))
-            .map(|v| v.as_u64())
+    pub fn lookup_pte(&self, virt: VirtAddrType) -> Option<PteState> {
+        self.page_table.lookup(VirtAddr::new(virt))
     }
 
     /// Apply the descriptor into current process
@@ -84,180 +148,435 @@ impl ChildDescriptor {
         let access_info = AccessInfo::new(&self.machine_info).unwrap();
         // let access_info = AccessInfo::new_from_cache(self.machine_info.mac_id, &self.machine_info).unwrap();
 
-        // 2. Map new vma regions
-        #[cfg(not(feature = "eager-resume"))]
-        (&self.vma).into_iter().enumerate().for_each(|(i, m)| {
-            let vma = unsafe { task.map_one_region(file, &m, self.vma.get(i + 1)) };
-
-            #[allow(dead_code)]
-            let vma = vma.unwrap();
-
-            // tune the bits
-            let origin_vma_flags =
-                unsafe { crate::bindings::VMFlags::from_bits_unchecked(m.flags) };
-            // crate::log::info!("orign vma: {:?}", origin_vma_flags);
-            if origin_vma_flags.contains(crate::bindings::VMFlags::VM_ALLOC) {
-                // set the vma
-                crate::kern_wrappers::vma::VMA::new(vma).set_alloc();
+        // 2. Move the local vDSO to the parent's address, before its range is taken
+        if let Some(vdso) = task.restore_vdso(&self.vma) {
+            let ehdr = crate::bindings::PMEM_AT_SYSINFO_EHDR as u64;
+            if self.mm_layout.get_auxv(ehdr).map_or(false, |old| old != vdso) {
+                // only the later lookups of the vDSO find the relocated one
+                crate::log::warn!("the vDSO is relocated to 0x{:x}", vdso);
+                self.mm_layout.set_auxv(ehdr, vdso);
             }
-        });
+        }
+
+        // 3. Map new vma regions
+        (&self.vma)
+            .into_iter()
+            .filter(|m| !m.is_special())
+            .for_each(|m| {
+                let vma = unsafe { task.map_one_region(file, &m) };
+
+                #[allow(dead_code)]
+                let vma = vma.unwrap();
+
+                // tune the bits
+                let origin_vma_flags =
+                    unsafe { crate::bindings::VMFlags::from_bits_unchecked(m.flags) };
+                // crate::log::info!("orign vma: {:?}", origin_vma_flags);
+                if origin_vma_flags.contains(crate::bindings::VMFlags::VM_ALLOC) {
+                    // set the vma
+                    crate::kern_wrappers::vma::VMA::new(vma).set_alloc();
+                }
+            });
 
         #[cfg(feature = "eager-resume")]
-        self.vma.clone().into_iter().enumerate().for_each(|(i, m)| {
-            let vma = unsafe { task.map_one_region(file, &m, self.vma.get(i + 1)) };
+        self.eager_fetch_vmas(&task, &access_info);
 
-            #[allow(dead_code)]
-            let vma = vma.unwrap();
+        // 4. Map the piggybacked pages, so they never fault
+        #[cfg(feature = "piggyback-pages")]
+        self.map_piggybacked_pages(&task);
 
-            // tune the bits
-            let origin_vma_flags =
-                unsafe { crate::bindings::VMFlags::from_bits_unchecked(m.flags) };
+        // 5. Re-set states
+        task.set_mm_layout(&self.mm_layout);
+        task.set_task_state(&self.task_state);
+        task.set_mm_reg_states(&self.regs);
+    }
 
-            if origin_vma_flags.contains(crate::bindings::VMFlags::VM_ALLOC) {
-                // set the vma
-                crate::kern_wrappers::vma::VMA::new(vma).set_alloc();
+    #[cfg(feature = "piggyback-pages")]
+    fn map_piggybacked_pages(&mut self, task: &Task) {
+        let mm = task.get_memory_descriptor();
+        for (addr, page_p) in self.piggybacked_pages.0.drain(..) {
+            match mm.find_vma(addr) {
+                Some(vma) if vma.vm_start <= addr => {
+                    let res = unsafe { crate::bindings::pmem_vm_insert_page(vma, addr, page_p) };
+                    if res != 0 {
+                        // e.g., the page has been eagerly fetched
+                        crate::log::debug!("failed to map the piggybacked page 0x{:x}: {}", addr, res);
+                    }
+                }
+                _ => crate::log::debug!("no VMA for the piggybacked page 0x{:x}", addr),
             }
-            #[cfg(feature = "eager-resume")]
-            self.eager_fetch_vma(&m, vma, &access_info);
-        });
-
-        // 3. Re-set states
-        task.set_mm_reg_states(&self.regs);
+            // the mapping holds its own reference
+            unsafe { crate::bindings::pmem_free_page(page_p) };
+        }
     }
 }
 
 impl ChildDescriptor {
+    /// Fetch all the pages of the mapped VMAs with the eager resume workers,
+    /// and install them into the VMAs after all the workers finish.
     #[cfg(feature = "eager-resume")]
-    fn eager_fetch_vma(
-        &mut self,
-        vma_des: &VMADescriptor,
-        vma: &'static mut crate::bindings::vm_area_struct,
-        access_info: &AccessInfo,
-    ) {
-        let (size, start) = (vma_des.get_sz(), vma_des.get_start());
-        let len = 12;
-        let mut addr_buf: Vec<VirtAddrType> = Vec::with_capacity(len);
-        for addr in (start..start + size).step_by(4096) {
-            if addr_buf.len() < len {
-                addr_buf.push(addr);
-            }
-            if len == addr_buf.len() {
-                // batch
-                let page_list = self.batch_read_remote_pages(&addr_buf, access_info);
-
-                for (i, new_page_p) in page_list.iter().enumerate() {
-                    if let Some(new_page_p) = new_page_p {
-                        vma.vm_page_prot.pgprot =
-                            vma.vm_page_prot.pgprot | (((1 as u64) << 52) as u64); // present bit
-                        let _ = unsafe {
-                            crate::bindings::pmem_vm_insert_page(vma, addr_buf[i], *new_page_p)
-                        };
-                        self.eager_fetched_pages.insert(*new_page_p as VirtAddrType);
-                    }
-                }
-                addr_buf.clear();
+    fn eager_fetch_vmas(&mut self, task: &Task, access_info: &AccessInfo) {
+        let mut batches: Vec<Vec<VirtAddrType>> = Vec::new();
+        for vma_des in self.vma.iter().filter(|v| !v.is_special()) {
+            let (size, start) = (vma_des.get_sz(), vma_des.get_start());
+            let addrs: Vec<VirtAddrType> = (start..start + size).step_by(4096).collect();
+            for batch in addrs.chunks(EAGER_BATCH_PAGES) {
+                batches.push(batch.to_vec());
             }
         }
-        if !addr_buf.is_empty() {
-            // batch
-            let page_list = self.batch_read_remote_pages(&addr_buf, access_info);
 
-            for (i, new_page_p) in page_list.iter().enumerate() {
-                if let Some(new_page_p) = new_page_p {
+        let workers = unsafe { *crate::eager_resume_threads::get_ref() };
+        let fetched = crate::eager_resume::parallel_fetch(self, access_info, batches, workers);
+
+        let mm = task.get_memory_descriptor();
+        for (addr, new_page_p) in fetched {
+            match mm.find_vma(addr) {
+                Some(vma) if vma.vm_start <= addr => {
                     vma.vm_page_prot.pgprot = vma.vm_page_prot.pgprot | (((1 as u64) << 52) as u64); // present bit
-                    let _ = unsafe {
-                        crate::bindings::pmem_vm_insert_page(vma, addr_buf[i], *new_page_p)
-                    };
-                    self.eager_fetched_pages.insert(*new_page_p as VirtAddrType);
+                    let _ = unsafe { crate::bindings::pmem_vm_insert_page(vma, addr, new_page_p) };
                 }
+                _ => crate::log::debug!("no VMA for the eagerly fetched page 0x{:x}", addr),
             }
+            self.eager_fetched_pages.insert(new_page_p as VirtAddrType);
         }
     }
 
+    /// Read the pages in `addr_list` in a batch.
+    ///
+    /// The remote pages are sorted by their physical addresses, and the contiguous
+    /// ones are coalesced into one read, which scatters into independent local pages
+    /// with one SGE for each.
+    /// Only one read of every `READS_PER_SIGNAL` is signaled, and at most
+    /// `MAX_INFLIGHT_SIGNALS` signaled reads are in flight, to keep the link busy.
+    ///
+    /// The reads are posted to the DCQP `pool_idx` of the DC pool.
     #[inline]
     #[allow(dead_code)]
-    fn batch_read_remote_pages(
+    pub(crate) fn batch_read_remote_pages(
         &self,
         addr_list: &Vec<VirtAddrType>,
         access_info: &AccessInfo,
+        pool_idx: usize,
     ) -> Vec<Option<*mut crate::bindings::page>> {
-        let mut res: Vec<Option<*mut crate::bindings::page>> = Vec::with_capacity(addr_list.len());
+        let mut res: Vec<Option<*mut crate::bindings::page>> =
+            (0..addr_list.len()).map(|_| None).collect();
+
+        // (remote PA, index in the addr_list)
+        let mut remote_pages: Vec<(PhyAddrType, usize)> = Vec::with_capacity(addr_list.len());
         for (i, remote_va) in addr_list.iter().enumerate() {
-            let remote_pa = self.lookup_pg_table(*remote_va);
-            if remote_pa.is_none() {
-                res.push(None);
-                continue;
+            match self.lookup_pte(*remote_va) {
+                Some(PteState::Zero) => {
+                    res[i] = Some(unsafe {
+                        crate::bindings::pmem_alloc_page(crate::bindings::PMEM_GFP_HIGHUSER_ZERO)
+                    });
+                }
+                Some(PteState::Remote(remote)) | Some(PteState::Duplicate(remote)) => {
+                    remote_pages.push((remote.pa, i))
+                }
+                // the local pages are taken by the faults
+                _ => {}
             }
-
-            let new_page_p =
-                unsafe { crate::bindings::pmem_alloc_page(crate::bindings::PMEM_GFP_HIGHUSER) };
-            let new_page_va = unsafe { crate::bindings::pmem_page_to_virt(new_page_p) } as u64;
-
-            let result = {
-                use rust_kernel_rdma_base::bindings::*;
-                let (src, sz) = (remote_pa.unwrap(), 4096);
-                let signaled = i == (addr_list.len() - 1);
-                let pool_idx = unsafe { crate::bindings::pmem_get_current_cpu() } as usize;
-                let dc_qp =
-                    unsafe { crate::get_dc_pool_service_mut().get_dc_qp(pool_idx) }
-                        .expect("failed to get DCQP");
-                dc_qp.lock(|dc_qp| {
-                    // now sending the RDMA request
-                    let payload = DCReqPayload::new(
-                        unsafe{ Arc::new(MemoryRegion::new_from_raw(dc_qp.get_qp().ctx().clone(), new_page_va as _, sz).unwrap()) },
-                        0..sz as u64,
-                        signaled,
-                        RDMAOp::READ,
-                        access_info.rkey,
-                        src,
-                        access_info.access_handler.clone()
-                    );
-                    let res = dc_qp.post(&payload);
-                    if res.is_err() {
-                        crate::log::error!("failed to batch read pages {:?}", res);
+        }
+        remote_pages.sort_unstable_by_key(|(pa, _)| *pa);
+
+        // allocate the local pages of each read, the failed ones are left to the page faults
+        let reads: Vec<(Range<usize>, Vec<*mut crate::bindings::page>)> =
+            Self::coalesce_remote_pages(&remote_pages)
+                .into_iter()
+                .filter_map(|read| {
+                    let mut pages = Vec::with_capacity(read.len());
+                    for _ in read.clone() {
+                        let page_p = unsafe {
+                            crate::bindings::pmem_alloc_page(crate::bindings::PMEM_GFP_HIGHUSER)
+                        };
+                        if page_p.is_null() {
+                            pages
+                                .into_iter()
+                                .for_each(|p| unsafe { crate::bindings::pmem_free_page(p) });
+                            return None;
+                        }
+                        pages.push(page_p);
                     }
-
-                    if i == addr_list.len() - 1 {
-                        // wait for the request to complete
-                        let mut timeout_dc = TimeoutWRef::new(
-                            dc_qp,
-                            crate::remote_paging::TIMEOUT_USEC,
+                    Some((read, pages))
+                })
+                .collect();
+
+        let dc_qp = unsafe { crate::get_dc_pool_service_mut().get_dc_qp(pool_idx) }
+            .expect("failed to get DCQP");
+        dc_qp.lock(|dc_qp| {
+            // the reads posted after the last signaled one
+            let mut group = Vec::new();
+            let mut inflight = VecDeque::new();
+
+            for (i, (read, pages)) in reads.iter().enumerate() {
+                let signaled = group.len() + 1 == READS_PER_SIGNAL || i == reads.len() - 1;
+                let mrs: Vec<MemoryRegion> = pages
+                    .iter()
+                    .map(|page_p| unsafe {
+                        let local_va = crate::bindings::pmem_page_to_virt(*page_p) as u64;
+                        MemoryRegion::new_from_raw(dc_qp.get_qp().ctx().clone(), local_va as _, 4096)
+                            .unwrap()
+                    })
+                    .collect();
+                let sges: Vec<(&MemoryRegion, Range<u64>)> =
+                    mrs.iter().map(|mr| (mr, 0..4096)).collect();
+
+                if let Err(e) = dc_qp.post_sg(
+                    RDMAOp::READ,
+                    access_info.access_handler.as_ref(),
+                    &sges,
+                    signaled,
+                    remote_pages[read.start].0,
+                    access_info.rkey,
+                ) {
+                    crate::log::error!("failed to batch read pages {:?}", e);
+                    // the pages of the reads not posted are never written
+                    for (read, pages) in reads.iter().skip(i) {
+                        Self::finish_batch_read(
+                            read.clone(),
+                            pages,
+                            BatchReadResult::Failed,
+                            &remote_pages,
+                            &mut res,
+                        );
+                    }
+                    // yet we cannot tell when the unsignaled ones complete
+                    for (read, pages) in group.drain(..) {
+                        Self::finish_batch_read(
+                            read,
+                            pages,
+                            BatchReadResult::TimedOut,
+                            &remote_pages,
+                            &mut res,
                         );
-                        match os_network::block_on(&mut timeout_dc) {
-                            Ok(_) => Ok(()),
-                            Err(e) => {
-                                if e.is_elapsed() {
-                                    crate::log::error!("fatal, timeout on reading the DC QP");
-                                    Err(DatapathError::TimeoutError)
-                                } else {
-                                    Err(e.into_inner().unwrap())
-                                }
-                            }
+                    }
+                    break;
+                }
+
+                group.push((read.clone(), pages));
+                if signaled {
+                    inflight.push_back(core::mem::take(&mut group));
+                    if inflight.len() >= MAX_INFLIGHT_SIGNALS {
+                        let result = Self::wait_batch_read(dc_qp);
+                        for (read, pages) in inflight.pop_front().unwrap() {
+                            Self::finish_batch_read(read, pages, result, &remote_pages, &mut res);
                         }
-                    } else {
-                        Ok(())
                     }
-                })
-            };
+                }
+            }
 
-            match result {
-                Ok(_) => res.push(Some(new_page_p)),
-                Err(e) => {
-                    crate::log::error!(
-                        "[batch_read_remote_pages] Failed to read the remote page {:?}",
-                        e
-                    );
-                    unsafe { crate::bindings::pmem_free_page(new_page_p) };
-                    res.push(None)
+            while let Some(group) = inflight.pop_front() {
+                let result = Self::wait_batch_read(dc_qp);
+                for (read, pages) in group {
+                    Self::finish_batch_read(read, pages, result, &remote_pages, &mut res);
                 }
             }
-        }
+        });
 
         return res;
     }
+
+    /// Split the sorted remote pages into reads of physically contiguous pages,
+    /// each of at most `MAX_COALESCED_PAGES` pages.
+    fn coalesce_remote_pages(remote_pages: &[(PhyAddrType, usize)]) -> Vec<Range<usize>> {
+        let mut reads = Vec::new();
+        let mut start = 0;
+        while start < remote_pages.len() {
+            let mut end = start + 1;
+            while end < remote_pages.len()
+                && end - start < MAX_COALESCED_PAGES
+                && remote_pages[end].0 == remote_pages[end - 1].0 + 4096
+            {
+                end += 1;
+            }
+            reads.push(start..end);
+            start = end;
+        }
+        reads
+    }
+
+    /// Wait for the next signaled read
+    fn wait_batch_read(dc_qp: &mut os_network::rdma::dc::DCConn) -> BatchReadResult {
+        let mut timeout_dc = TimeoutWRef::new(dc_qp, crate::remote_paging::TIMEOUT_USEC);
+        match os_network::block_on(&mut timeout_dc) {
+            Ok(wc) if wc.status == rust_kernel_rdma_base::ib_wc_status::IB_WC_SUCCESS => {
+                BatchReadResult::Done
+            }
+            Ok(wc) => {
+                crate::log::error!("failed to batch read pages, status {:?}", wc.status);
+                BatchReadResult::Failed
+            }
+            Err(e) => {
+                if e.is_elapsed() {
+                    crate::log::error!("fatal, timeout on reading the DC QP");
+                    BatchReadResult::TimedOut
+                } else {
+                    crate::log::error!("failed to batch read pages {:?}", e.into_inner());
+                    BatchReadResult::Failed
+                }
+            }
+        }
+    }
+
+    #[inline]
+    fn finish_batch_read(
+        read: Range<usize>,
+        pages: &[*mut crate::bindings::page],
+        result: BatchReadResult,
+        remote_pages: &[(PhyAddrType, usize)],
+        res: &mut Vec<Option<*mut crate::bindings::page>>,
+    ) {
+        for ((_, idx), page_p) in remote_pages[read].iter().zip(pages.iter().copied()) {
+            match result {
+                BatchReadResult::Done => res[*idx] = Some(page_p),
+                BatchReadResult::Failed => unsafe { crate::bindings::pmem_free_page(page_p) },
+                // the NIC may still write the page, so it is leaked
+                BatchReadResult::TimedOut => {}
+            }
+        }
+    }
 }
 
 impl ChildDescriptor {
+    /// Resume the pages eliminated by the parent at prepare time, without reading them
+    /// (except the first access to a duplicated page).
+    ///
+    /// - A zero page is allocated locally.
+    /// - The duplicated pages share one local copy, which is COW-ed by the kernel upon write.
+    ///
+    /// Return None if the page is not eliminated, or failed to read.
+    #[inline]
+    #[allow(unused_variables)]
+    pub unsafe fn read_eliminated_page(
+        &self,
+        remote_va: VirtAddrType,
+        access_info: &AccessInfo,
+    ) -> Option<*mut crate::bindings::page> {
+        let state = self.lookup_pte(remote_va)?;
+        if state == PteState::Zero {
+            return Some(crate::bindings::pmem_alloc_page(
+                crate::bindings::PMEM_GFP_HIGHUSER_ZERO,
+            ));
+        }
+
+        #[cfg(feature = "dedup-pages")]
+        if let PteState::Duplicate(remote) = state {
+            let remote_pa = remote.pa;
+            let shared = |state: &mut FaultState| {
+                let page_p = state.dedup_pages.get(&remote_pa).copied()?;
+                // the reference for the mapping
+                crate::kern_wrappers::Page::new_from_raw(page_p).increase_ref_count();
+                Some(page_p)
+            };
+            if let Some(page_p) = self.fault_state.lock(|state| shared(state)) {
+                return Some(page_p);
+            }
+
+            // read without the lock, so a concurrent fault may read the same copy
+            let page_p = self.read_remote_pa(remote_pa, access_info)?;
+            return Some(self.fault_state.lock(|state| {
+                if let Some(shared_p) = shared(state) {
+                    crate::bindings::pmem_free_page(page_p);
+                    return shared_p;
+                }
+                // the reference is held by `dedup_pages`
+                state.dedup_pages.insert(remote_pa, page_p);
+                crate::kern_wrappers::Page::new_from_raw(page_p).increase_ref_count();
+                page_p
+            }));
+        }
+        None
+    }
+
+    /// Read the remote page at `remote_pa` into a new page,
+    /// not touching the page table
+    #[cfg(feature = "dedup-pages")]
+    #[inline]
+    unsafe fn read_remote_pa(
+        &self,
+        remote_pa: PhyAddrType,
+        access_info: &AccessInfo,
+    ) -> Option<*mut crate::bindings::page> {
+        let new_page_p = crate::bindings::pmem_alloc_page(crate::bindings::PMEM_GFP_HIGHUSER);
+        let new_page_pa = crate::bindings::pmem_page_to_phy(new_page_p) as u64;
+        let res = crate::remote_paging::RemotePagingService::remote_read(
+            new_page_pa,
+            remote_pa,
+            4096,
+            access_info,
+        );
+        #[cfg(feature = "resume-profile")]
+        self.incr_fetched_remote_count(1);
+        match res {
+            Ok(_) => Some(new_page_p),
+            Err(e) => {
+                crate::log::error!("Failed to read the duplicated page {:?}", e);
+                crate::bindings::pmem_free_page(new_page_p);
+                None
+            }
+        }
+    }
+
+    /// Mark the page at `remote_va` as present locally, whose content has been
+    /// pushed by the parent into `page_p`.
+    ///
+    /// Return false if the entry doesn't point to a remote page, e.g., it is cached.
+    #[cfg(feature = "push-resume")]
+    pub fn install_pushed_page(&self, remote_va: VirtAddrType, page_p: *mut page) -> bool {
+        let slot = match self.page_table.find_entry(VirtAddr::new(remote_va)) {
+            Some(slot) => slot,
+            None => return false,
+        };
+        // the origin is recorded before a fault can take the page
+        self.fault_state.lock(|state| {
+            let origin = match slot.state() {
+                PteState::Remote(origin) => origin,
+                _ => return false,
+            };
+            if slot.transit(PteState::Local(page_p)).is_err() {
+                return false;
+            }
+            state.pushed_pages.insert(remote_va, origin);
+            true
+        })
+    }
+
+    /// Take the local page pushed by the parent, if any.
+    /// The entry is restored to the remote one, since the OS is responsible for reclaiming the page.
+    #[cfg(all(feature = "push-resume", not(feature = "prefetch")))]
+    #[inline]
+    unsafe fn take_pushed_page(&self, remote_va: VirtAddrType) -> Option<*mut page> {
+        let slot = self.page_table.find_entry(VirtAddr::new(remote_va))?;
+        self.fault_state.lock(|state| {
+            let page_p = match slot.state() {
+                PteState::Local(page_p) => page_p,
+                _ => return None,
+            };
+            let origin = state.pushed_pages.remove(&remote_va)?;
+            slot.transit(PteState::Remote(origin)).ok()?;
+            Some(page_p)
+        })
+    }
+
+    /// Copy the page table for the `RemotePageTableCache`.
+    ///
+    /// The pushed pages not faulted yet belong to this child only,
+    /// so their entries are restored to the remote ones in the copy.
+    pub fn copy_page_table_for_cache(&self) -> ChildPageTable {
+        #[allow(unused_mut)]
+        let mut pt = self.page_table.copy();
+        #[cfg(feature = "push-resume")]
+        self.fault_state.lock(|state| {
+            for (va, origin) in state.pushed_pages.iter() {
+                let va = VirtAddr::new(*va);
+                if let Some(PteState::Local(_)) = pt.lookup(va) {
+                    let _ = pt.transit(va, PteState::Remote(*origin));
+                }
+            }
+        });
+        pt
+    }
+
     #[cfg(not(feature = "prefetch"))]
     /// Resume one page at remote side
     ///
@@ -265,15 +584,39 @@ impl ChildDescriptor {
     /// @param access_info: remote network meta info
     #[inline]
     pub unsafe fn read_remote_page(
-        &mut self,
+        &self,
         remote_va: PhyAddrType,
         access_info: &AccessInfo,
     ) -> Option<*mut crate::bindings::page> {
-        let remote_pa = self.lookup_pg_table(remote_va);
-        if remote_pa.is_none() {
-            return None;
+        if let Some(page_p) = self.take_local_page(remote_va) {
+            return Some(page_p);
         }
-        let remote_pa = remote_pa.unwrap();
+        let remote_pa = self.lookup_pte(remote_va)?.remote()?.pa;
+        self.read_page_at(remote_va, remote_pa, access_info)
+    }
+
+    #[cfg(not(feature = "prefetch"))]
+    /// The local page of the entry, i.e., pushed by the parent.
+    /// The entry is restored to the remote one.
+    #[inline]
+    #[allow(unused_variables)]
+    pub unsafe fn take_local_page(&self, remote_va: VirtAddrType) -> Option<*mut page> {
+        #[cfg(feature = "push-resume")]
+        return self.take_pushed_page(remote_va);
+        #[cfg(not(feature = "push-resume"))]
+        None
+    }
+
+    #[cfg(not(feature = "prefetch"))]
+    /// Read the remote page at `remote_pa` into a new page, e.g., claimed by `claim_remote_read`
+    #[inline]
+    #[allow(unused_variables)]
+    pub unsafe fn read_page_at(
+        &self,
+        remote_va: VirtAddrType,
+        remote_pa: PhyAddrType,
+        access_info: &AccessInfo,
+    ) -> Option<*mut crate::bindings::page> {
         let new_page_p = crate::bindings::pmem_alloc_page(crate::bindings::PMEM_GFP_HIGHUSER);
         let new_page_pa = crate::bindings::pmem_page_to_phy(new_page_p) as u64;
         let res = crate::remote_paging::RemotePagingService::remote_read(
@@ -301,20 +644,17 @@ impl ChildDescriptor {
     /// @param access_info: remote network meta info
     #[inline]
     pub unsafe fn read_remote_page_wo_prefetch(
-        &mut self,
+        &self,
         remote_va: VirtAddrType,
         access_info: &AccessInfo,
     ) -> Option<*mut crate::bindings::page> {
-        let remote_pa = self.lookup_pg_table(remote_va);
-        if remote_pa.is_none() {
-            return None;
-        }
+        let remote_pa = self.lookup_pte(remote_va)?.remote()?.pa;
 
         let new_page_p = crate::bindings::pmem_alloc_page(crate::bindings::PMEM_GFP_HIGHUSER);
         let new_page_pa = crate::bindings::pmem_page_to_phy(new_page_p) as u64;
         let res = crate::remote_paging::RemotePagingService::remote_read(
             new_page_pa,
-            remote_pa.unwrap(),
+            remote_pa,
             4096,
             access_info,
         );
@@ -334,55 +674,75 @@ impl ChildDescriptor {
     /// Resume one page at remote side
     /// It will also prefetch adjacent pages if necessary
     ///
-    /// This function is a little complex.
-    /// It will handle prefetch stuffs during the polling process
+    /// The prefetches are issued with the lock of the fault states,
+    /// which is released before waiting for the faulted page.
     ///
     /// @param remote_va: remote virt-addr
     /// @param access_info: remote network meta info
     #[inline]
     pub unsafe fn read_remote_page(
-        &mut self,
+        &self,
         remote_va: VirtAddrType,
         access_info: &AccessInfo,
     ) -> Option<*mut crate::bindings::page> {
-        let (pt, idx) = self.page_table.find_l1_page_idx(VirtAddr::new(remote_va))?;
-        let l1_page = &mut (*pt);
-
-        let mut remote_pa = l1_page[idx];
+        let slot = self.page_table.find_entry(VirtAddr::new(remote_va))?;
 
         // check whether it has been prefetched to local
-        #[cfg(feature = "prefetch")]
-        {
-            // we need to do the prefetch
-            if PhysAddr::new(remote_pa).is_prefetch() {
-                /*
-                Two cases.
-                    1. the page is prefetched. then we can directly return
-                    2. the page is prefetched, but the content has not ready.
-                    In this case, we need to poll the connection to wait for it ready.
-
-                FIXME:
-                - Currently, we don't handle the timeout here
-                 */
-                while remote_pa == crate::remote_mapping::K_MAGIC_IN_PREFETCH {
-                    // poll the prefetcher
-                    self.poll_prefetcher();
-                    remote_pa = l1_page[idx];
-                    compiler_fence(SeqCst);
-                }
-
-                // The remote page is encoded in the page table as
-                //     *mut addr | 1
-                let page = PhysAddr::decode(remote_pa as _) as *mut page;
+        /*
+        Two cases.
+            1. the page is prefetched. then we can directly return
+            2. the page is prefetched, but the content has not ready.
+            In this case, we need to poll the connection to wait for it ready.
+
+        FIXME:
+        - Currently, we don't handle the timeout here
+         */
+        let mut state = slot.state();
+        while state == PteState::InFlight {
+            // poll the prefetcher
+            if !self.fault_state.lock(|s| Self::poll_prefetcher(s)) {
+                return None;
+            }
+            state = slot.state();
+        }
 
-                // clean the entry in the page table, since
-                // the OS is responsible for reclaiming this page
-                l1_page[idx] = 0;
+        if let Some(page) = self.take_local_page(remote_va) {
+            return Some(page);
+        }
+        match slot.state() {
+            PteState::Remote(remote) => self.read_page_at(remote_va, remote.pa, access_info),
+            _ => None,
+        }
+    }
 
-                return Some(page);
+    #[cfg(feature = "prefetch")]
+    /// The local page of the entry, i.e., prefetched or pushed by the parent.
+    /// The entry is restored to the remote one, since the OS is responsible for
+    /// reclaiming this page.
+    #[inline]
+    pub unsafe fn take_local_page(&self, remote_va: VirtAddrType) -> Option<*mut page> {
+        let slot = self.page_table.find_entry(VirtAddr::new(remote_va))?;
+        match slot.state() {
+            PteState::Local(page) => {
+                let origin = self.fault_state.lock(|s| Self::take_local_origin(s, slot, remote_va));
+                slot.transit(PteState::Remote(origin))
+                    .expect("a local page is only taken by its faulting thread");
+                Some(page)
             }
+            _ => None,
         }
+    }
 
+    #[cfg(feature = "prefetch")]
+    /// Read the remote page at `remote_pa` into a new page, e.g., claimed by `claim_remote_read`.
+    /// The pages after it are prefetched meanwhile.
+    #[inline]
+    pub unsafe fn read_page_at(
+        &self,
+        remote_va: VirtAddrType,
+        remote_pa: PhyAddrType,
+        access_info: &AccessInfo,
+    ) -> Option<*mut crate::bindings::page> {
         let new_page_p = crate::bindings::pmem_alloc_page(crate::bindings::PMEM_GFP_HIGHUSER);
         let new_page_va = crate::bindings::pmem_page_to_virt(new_page_p) as u64;
 
@@ -406,7 +766,7 @@ impl ChildDescriptor {
                     true,
                     RDMAOp::READ,
                     access_info.rkey,
-                    PhysAddr::decode(remote_pa), // copy remote to local
+                    remote_pa, // copy remote to local
                     access_info.access_handler.clone(),
                 );
     
@@ -415,12 +775,14 @@ impl ChildDescriptor {
                 // Note, we do the prefetch things here
                 // This can overlap with the networking requests latency
                 // find prefetch pages
-                let pte_iter = RemotePageTableIter::new_from_l1(pt, idx);
-                self.prefetcher.execute_reqs(
-                    pte_iter,
-                    StepPrefetcher::<PageEntry, { crate::PREFETCH_STEP }>::new(),
-                );
-                self.poll_prefetcher();
+                self.fault_state.lock(|s| {
+                    let pte_iter = self.page_table.iter_after(VirtAddr::new(remote_va));
+                    s.prefetcher.execute_reqs(
+                        pte_iter,
+                        StepPrefetcher::<PageEntry, { crate::PREFETCH_STEP }>::new(),
+                    );
+                    Self::poll_prefetcher(s);
+                });
     
                 // wait for the request to complete
                 let mut timeout_dc = TimeoutWRef::new(dc_qp, TIMEOUT_USEC);
@@ -452,42 +814,169 @@ impl ChildDescriptor {
         };
     }
 
+    /// Start reading the faulted page without waiting for it.
+    /// The read is issued through the prefetcher, so a page already in flight
+    /// (prefetched, or read by a concurrent fault) is never read twice.
+    ///
+    /// Return true if the page is ready, i.e., `read_remote_page` will not block.
+    #[cfg(feature = "async-fault")]
+    pub unsafe fn start_remote_read(&self, remote_va: VirtAddrType) -> bool {
+        let slot = match self.page_table.find_entry(VirtAddr::new(remote_va)) {
+            Some(slot) => slot,
+            None => return true,
+        };
+        if slot.state() == PteState::Empty {
+            return true;
+        }
+
+        self.fault_state.lock(|s| {
+            if s.prefetcher.submit(slot) {
+                // overlap the prefetch with the read of the faulted page
+                let pte_iter = self.page_table.iter_after(VirtAddr::new(remote_va));
+                s.prefetcher.execute_reqs(
+                    pte_iter,
+                    StepPrefetcher::<PageEntry, { crate::PREFETCH_STEP }>::new(),
+                );
+                #[cfg(feature = "resume-profile")]
+                self.incr_fetched_remote_count(crate::PREFETCH_STEP + 1);
+            }
+            Self::poll_prefetcher(s);
+        });
+        slot.state() != PteState::InFlight
+    }
+
+    /// Poll the page in flight once, e.g., read by `start_remote_read`,
+    /// the prefetcher or another fault (see `claim_remote_read`).
+    ///
+    /// Return true if the read is finished (or failed), or can never finish.
+    pub unsafe fn poll_in_flight(&self, remote_va: VirtAddrType) -> bool {
+        match self.page_table.find_entry(VirtAddr::new(remote_va)) {
+            Some(slot) => {
+                // another fault may be polling, so the lock is not waited
+                #[cfg(feature = "prefetch")]
+                if self.fault_state.try_lock(|s| Self::poll_prefetcher(s)) == Some(false) {
+                    return true;
+                }
+                slot.state() != PteState::InFlight
+            }
+            None => true,
+        }
+    }
+
+    /// Claim the read of the remote page, so that it is read without the lock of its table:
+    /// the entry is in flight until `finish_remote_read`, and the other faults on it wait.
+    ///
+    /// Return None if the entry is not a remote one, e.g., the page has been prefetched.
+    pub fn claim_remote_read(&self, remote_va: VirtAddrType) -> Option<RemotePage> {
+        let slot = self.page_table.find_entry(VirtAddr::new(remote_va))?;
+        match slot.state() {
+            PteState::Remote(remote) => slot.transit(PteState::InFlight).ok().map(|_| remote),
+            _ => None,
+        }
+    }
+
+    /// Restore the entry claimed by `claim_remote_read`, whether the read succeeded or not,
+    /// since the OS is responsible for reclaiming the read page.
+    pub fn finish_remote_read(&self, remote_va: VirtAddrType, remote: RemotePage) {
+        if let Some(slot) = self.page_table.find_entry(VirtAddr::new(remote_va)) {
+            slot.transit(PteState::Remote(remote))
+                .expect("a claimed entry is only restored by its faulting thread");
+        }
+    }
+
+    /// The remote page of a local entry, recorded when the page was prefetched or pushed
+    #[cfg(feature = "prefetch")]
+    #[allow(unused_variables)]
+    fn take_local_origin(
+        state: &mut FaultState,
+        slot: PteSlot,
+        remote_va: VirtAddrType,
+    ) -> RemotePage {
+        let origin = state.prefetcher.take_origin(slot);
+        #[cfg(feature = "push-resume")]
+        let origin = origin.or_else(|| state.pushed_pages.remove(&remote_va));
+        origin.expect("the origin of a local page is not recorded")
+    }
+
+    /// Complete the finished prefetches, including the failed ones (see `DCAsyncPrefetcher`).
+    ///
+    /// Return false if the completions cannot be polled, so the in-flight entries never complete.
     #[cfg(feature = "prefetch")]
-    fn poll_prefetcher(&mut self) {
+    fn poll_prefetcher(state: &mut FaultState) -> bool {
         loop {
             #[allow(non_snake_case)]
-            match self.prefetcher.poll() {
+            match state.prefetcher.poll() {
                 Ok(Async::Ready(_)) => {
                     // The second poll is likely to succeed
                     // so just continue
                 }
                 Ok(_NotReady) => {
-                    return;
+                    return true;
+                }
+                Err(e) => {
+                    crate::log::error!("failed to poll the prefetcher: {:?}", e);
+                    return false;
                 }
-                Err(_e) => panic!("not implemented"),
             }
         }
     }
 
     #[cfg(feature = "resume-profile")]
-    fn incr_fetched_remote_count(&mut self, page_cnt: usize) {
-        self.remote_fetched_page_count += page_cnt;
+    fn incr_fetched_remote_count(&self, page_cnt: usize) {
+        self.remote_fetched_page_count.fetch_add(page_cnt, SeqCst);
     }
 }
 
-impl os_network::serialize::Serialize for ChildDescriptor {
-    fn serialize(&self, _bytes: &mut BytesMut) -> bool {
-        // Note, since we currently don't support multi-fork, so child serialize is not implemented
-        unimplemented!();
+impl ChildDescriptor {
+    /// Copy the piggybacked pages out of the descriptor buffer,
+    /// which is released after the resume.
+    #[cfg(feature = "piggyback-pages")]
+    fn deserialize_piggybacked_pages(
+        bytes: &BytesMut,
+    ) -> core::option::Option<PiggybackedPages> {
+        let mut count: usize = 0;
+        let off = unsafe { bytes.memcpy_deserialize(&mut count)? };
+        let mut cur = unsafe { bytes.truncate_header(off)? };
+
+        // the count is from the wire, so it is checked before any allocation
+        let entry_sz = core::mem::size_of::<VirtAddrType>() + 4096;
+        if count > crate::PIGGYBACK_PAGES || count * entry_sz > cur.len() {
+            crate::log::error!("invalid number of the piggybacked pages: {}", count);
+            return None;
+        }
+
+        let mut addrs = Vec::with_capacity(count);
+        for _ in 0..count {
+            let addr: VirtAddrType = unsafe { cur.read_unaligned_at_head() };
+            cur = unsafe { cur.truncate_header(core::mem::size_of::<VirtAddrType>())? };
+            addrs.push(addr);
+        }
+
+        // the pages copied so far are freed if the buffer turns out to be truncated
+        let mut res = PiggybackedPages(Vec::with_capacity(count));
+        for addr in addrs {
+            let content = unsafe { cur.clone_and_resize(4096)? };
+            cur = unsafe { cur.truncate_header(4096)? };
+
+            let page_p = unsafe { crate::bindings::pmem_alloc_page(crate::bindings::PMEM_GFP_HIGHUSER) };
+            let mut dst = unsafe {
+                BytesMut::from_raw(crate::bindings::pmem_page_to_virt(page_p) as *mut u8, 4096)
+            };
+            dst.copy(&content, 0);
+            res.0.push((addr, page_p));
+        }
+        Some(res)
     }
+}
 
-    /// De-serialize from a message buffer
+impl ChildDescriptor {
+    /// De-serialize from a message buffer, whose page table is backed by `kind`.
     /// **Warning**
     /// - The buffer to be serialized must be generated from the ParentDescriptor.
     ///
     /// **TODO**
     /// - Currently, we don't check the buf len, so this function is **unsafe**
-    fn deserialize(bytes: &BytesMut) -> core::option::Option<Self> {
+    pub fn deserialize_with(bytes: &BytesMut, kind: PageTableKind) -> core::option::Option<Self> {
         // FIXME: check buf len
 
         let mut cur = unsafe { bytes.truncate_header(0).unwrap() };
@@ -496,6 +985,18 @@ impl os_network::serialize::Serialize for ChildDescriptor {
         let regs = RegDescriptor::deserialize(&cur)?;
         cur = unsafe { cur.truncate_header(regs.serialization_buf_len())? };
 
+        // mm layout
+        let mm_layout = MMLayoutDescriptor::deserialize(&cur)?;
+        cur = unsafe { cur.truncate_header(mm_layout.serialization_buf_len())? };
+
+        // opened files
+        let files = FileTableDescriptor::deserialize(&cur)?;
+        cur = unsafe { cur.truncate_header(files.serialization_buf_len())? };
+
+        // signals, rlimits, credentials, etc.
+        let task_state = TaskStateDescriptor::deserialize(&cur)?;
+        cur = unsafe { cur.truncate_header(task_state.serialization_buf_len())? };
+
         // VMA page counts
         let mut count: usize = 0;
         let off = unsafe { cur.memcpy_deserialize(&mut count)? };
@@ -504,7 +1005,7 @@ impl os_network::serialize::Serialize for ChildDescriptor {
         crate::log::debug!("!!!!! start to deserialize vma, count: {}", count);
 
         // VMA & its corresponding page table
-        let mut pt = RemotePageTable::new();
+        let mut pt = ChildPageTable::new(kind);
 
         let mut vmas = Vec::new();
 
@@ -551,8 +1052,22 @@ impl os_network::serialize::Serialize for ChildDescriptor {
             }
         }
 
+        pt.finish_mapping();
+        crate::log::info!(
+            "page table of {} entries uses {} KB ({:?})",
+            pt.len(),
+            pt.memory_usage() / 1024,
+            pt.kind()
+        );
+
         let machine_info = RDMADescriptor::deserialize(&cur)?;
 
+        #[cfg(feature = "piggyback-pages")]
+        let piggybacked_pages = {
+            let cur = unsafe { cur.truncate_header(machine_info.serialization_buf_len())? };
+            Self::deserialize_piggybacked_pages(&cur)?
+        };
+
         // TODO: `LinuxMutex` should needs to have `into_inner` to get the underlying data.
         #[cfg(feature = "prefetch")]
         let prefetch_conn =
@@ -568,18 +1083,42 @@ impl os_network::serialize::Serialize for ChildDescriptor {
         }
         Some(Self {
             regs: regs,
+            mm_layout: mm_layout,
+            files: files,
+            task_state: task_state,
             page_table: pt,
             vma: vmas,
             machine_info: machine_info,
 
-            #[cfg(feature = "prefetch")]
-            prefetcher: DCAsyncPrefetcher::new_from_raw(prefetch_conn, access_info.unwrap()),
             #[cfg(feature = "eager-resume")]
             eager_fetched_pages: Default::default(),
             #[cfg(feature = "resume-profile")]
-            remote_fetched_page_count: 0
+            remote_fetched_page_count: Default::default(),
+            fault_state: LockBundler::new(FaultState {
+                #[cfg(feature = "prefetch")]
+                prefetcher: DCAsyncPrefetcher::new_from_raw(prefetch_conn, access_info.unwrap()),
+                #[cfg(feature = "dedup-pages")]
+                dedup_pages: Default::default(),
+                #[cfg(feature = "push-resume")]
+                pushed_pages: Default::default(),
+            }),
+            #[cfg(feature = "piggyback-pages")]
+            piggybacked_pages,
         })
     }
+}
+
+impl os_network::serialize::Serialize for ChildDescriptor {
+    fn serialize(&self, _bytes: &mut BytesMut) -> bool {
+        // Note, since we currently don't support multi-fork, so child serialize is not implemented
+        unimplemented!();
+    }
+
+    /// De-serialize from a message buffer, with the default page table backend.
+    /// See `ChildDescriptor::deserialize_with`.
+    fn deserialize(bytes: &BytesMut) -> core::option::Option<Self> {
+        Self::deserialize_with(bytes, Default::default())
+    }
 
     fn serialization_buf_len(&self) -> usize {
         unimplemented!();
@@ -0,0 +1,196 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use os_network::bytes::BytesMut;
+use os_network::serialize::Serialize;
+
+/// Max length of a file path, including the tailing NUL
+pub const FILE_PATH_LEN: usize = 256;
+
+/// What an fd of the parent refers to, which decides how it is restored
+#[repr(u8)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum FileKind {
+    Regular,
+    Pipe,
+    /// Character devices, e.g., the terminal of the standard streams
+    Stream,
+    /// The mitosis device, which is replaced by the one that resumes
+    Mitosis,
+    /// Sockets, eventfds, etc., which cannot be reopened
+    Other,
+}
+
+/// One opened fd of the parent
+#[derive(Clone, Copy)]
+pub struct FileDescriptor {
+    pub(crate) fd: u32,
+    pub(crate) kind: FileKind,
+    // the open flags, including O_CLOEXEC
+    pub(crate) flags: u32,
+    pub(crate) pos: i64,
+    // NUL-terminated, e.g., "pipe:[1234]" for a pipe
+    pub(crate) path: [u8; FILE_PATH_LEN],
+}
+
+impl FileDescriptor {
+    #[inline]
+    pub fn get_fd(&self) -> u32 {
+        self.fd
+    }
+
+    #[inline]
+    pub fn get_kind(&self) -> FileKind {
+        self.kind
+    }
+
+    #[inline]
+    pub fn get_flags(&self) -> crate::bindings::FileFlags {
+        crate::bindings::FileFlags::from_bits_truncate(self.flags)
+    }
+
+    #[inline]
+    pub fn get_pos(&self) -> i64 {
+        self.pos
+    }
+
+    #[inline]
+    pub fn is_writable(&self) -> bool {
+        use crate::bindings::FileFlags;
+        let mode = self.get_flags() & FileFlags::ACCMODE;
+        mode == FileFlags::WRONLY || mode == FileFlags::RDWR
+    }
+
+    pub fn get_path(&self) -> Option<&str> {
+        let len = self.path.iter().position(|c| *c == 0)?;
+        core::str::from_utf8(&self.path[..len]).ok()
+    }
+}
+
+impl core::fmt::Debug for FileDescriptor {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        f.debug_struct("FileDescriptor")
+            .field("fd", &self.fd)
+            .field("kind", &self.kind)
+            .field("flags", &format_args!("{:#o}", self.flags))
+            .field("pos", &self.pos)
+            .field("path", &self.get_path())
+            .finish()
+    }
+}
+
+impl Serialize for FileDescriptor {}
+
+/// The opened fds of the parent
+#[derive(Clone, Default, Debug)]
+pub struct FileTableDescriptor {
+    pub(crate) files: Vec<FileDescriptor>,
+}
+
+impl FileTableDescriptor {
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    pub fn iter(&self) -> core::slice::Iter<FileDescriptor> {
+        self.files.iter()
+    }
+}
+
+impl Serialize for FileTableDescriptor {
+    /// Serialization format:
+    /// ```
+    /// | fd count <-8 bytes-> | FileDescriptor * count |
+    /// ```
+    fn serialize(&self, bytes: &mut BytesMut) -> bool {
+        if bytes.len() < self.serialization_buf_len() {
+            return false;
+        }
+        let sz = unsafe { bytes.memcpy_serialize_at(0, &self.files.len()).unwrap() };
+        let mut cur = unsafe { bytes.truncate_header(sz).unwrap() };
+        for file in self.files.iter() {
+            file.serialize(&mut cur);
+            cur = unsafe { cur.truncate_header(file.serialization_buf_len()).unwrap() };
+        }
+        true
+    }
+
+    fn deserialize(bytes: &BytesMut) -> core::option::Option<Self> {
+        let mut count: usize = 0;
+        let off = unsafe { bytes.memcpy_deserialize(&mut count)? };
+        let mut cur = unsafe { bytes.truncate_header(off)? };
+
+        // the count is from the wire, so it is checked before any allocation
+        if count > cur.len() / core::mem::size_of::<FileDescriptor>() {
+            crate::log::error!("invalid number of the fds: {}", count);
+            return None;
+        }
+        let mut files = Vec::with_capacity(count);
+        for _ in 0..count {
+            let file = FileDescriptor::deserialize(&cur)?;
+            cur = unsafe { cur.truncate_header(file.serialization_buf_len())? };
+            files.push(file);
+        }
+        Some(Self { files })
+    }
+
+    fn serialization_buf_len(&self) -> usize {
+        core::mem::size_of::<usize>() + self.files.len() * core::mem::size_of::<FileDescriptor>()
+    }
+}
+
+/// How the child restores the fds of the parent, set by user space before the resume.
+///
+/// With all the policies, an fd failed to restore is opened to /dev/null,
+/// and reported in the `FdRestoreReport`.
+#[derive(Clone, Debug)]
+pub enum FdRestorePolicy {
+    /// Reopen the files at the parent's paths, with the same flags and offsets
+    SamePath,
+    /// Like `SamePath`, but the written pipes and streams are appended to the file,
+    /// and the read ones are opened to /dev/null
+    Redirect(String),
+    /// Open all the fds to /dev/null
+    DevNull,
+}
+
+impl Default for FdRestorePolicy {
+    fn default() -> Self {
+        FdRestorePolicy::SamePath
+    }
+}
+
+/// The fds failed to restore by a resume
+#[derive(Clone, Default, Debug)]
+pub struct FdRestoreReport {
+    // (fd, the negative errno)
+    pub(crate) failed: Vec<(u32, i32)>,
+}
+
+impl FdRestoreReport {
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.failed.is_empty()
+    }
+
+    pub fn to_user(&self) -> crate::bindings::fd_report_t {
+        let mut report: crate::bindings::fd_report_t = Default::default();
+        report.count = self.failed.len() as _;
+        for (i, (fd, err)) in self
+            .failed
+            .iter()
+            .take(crate::bindings::MITOSIS_MAX_FD_REPORT as usize)
+            .enumerate()
+        {
+            report.fds[i] = *fd as _;
+            report.errs[i] = *err as _;
+        }
+        report
+    }
+}
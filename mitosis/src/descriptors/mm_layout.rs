@@ -0,0 +1,92 @@
+use crate::bindings::{PMEM_AT_NULL, PMEM_AT_VECTOR_SIZE};
+
+/// Max length of the executable path, including the tailing NUL
+pub const EXE_PATH_LEN: usize = 256;
+
+/// The layout of the parent's address space beyond its VMAs,
+/// e.g., the heap used by brk(), and the ranges read by /proc/pid/{cmdline,environ,auxv}
+#[allow(dead_code)]
+#[derive(Clone, Copy)]
+pub struct MMLayoutDescriptor {
+    pub(crate) start_code: u64,
+    pub(crate) end_code: u64,
+    pub(crate) start_data: u64,
+    pub(crate) end_data: u64,
+    pub(crate) start_brk: u64,
+    pub(crate) brk: u64,
+    pub(crate) start_stack: u64,
+    pub(crate) arg_start: u64,
+    pub(crate) arg_end: u64,
+    pub(crate) env_start: u64,
+    pub(crate) env_end: u64,
+    pub(crate) saved_auxv: [u64; PMEM_AT_VECTOR_SIZE as usize],
+    // NUL-terminated, empty if the parent has no executable
+    pub(crate) exe_path: [u8; EXE_PATH_LEN],
+}
+
+impl Default for MMLayoutDescriptor {
+    fn default() -> Self {
+        unsafe { core::mem::zeroed() }
+    }
+}
+
+impl MMLayoutDescriptor {
+    #[inline]
+    pub fn get_brk(&self) -> (u64, u64) {
+        (self.start_brk, self.brk)
+    }
+
+    #[inline]
+    pub fn get_start_stack(&self) -> u64 {
+        self.start_stack
+    }
+
+    /// Lookup the value of an auxiliary vector entry, e.g., `PMEM_AT_SYSINFO_EHDR`
+    pub fn get_auxv(&self, key: u64) -> Option<u64> {
+        self.saved_auxv
+            .chunks_exact(2)
+            .take_while(|kv| kv[0] != PMEM_AT_NULL)
+            .find(|kv| kv[0] == key)
+            .map(|kv| kv[1])
+    }
+
+    /// Update the value of an existing auxiliary vector entry.
+    /// Return false if the entry is not found.
+    pub fn set_auxv(&mut self, key: u64, value: u64) -> bool {
+        match self
+            .saved_auxv
+            .chunks_exact_mut(2)
+            .take_while(|kv| kv[0] != PMEM_AT_NULL)
+            .find(|kv| kv[0] == key)
+        {
+            Some(kv) => {
+                kv[1] = value;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// The path of the executable, None if unknown
+    pub fn get_exe_path(&self) -> Option<&str> {
+        let len = self.exe_path.iter().position(|c| *c == 0)?;
+        if len == 0 {
+            return None;
+        }
+        core::str::from_utf8(&self.exe_path[..len]).ok()
+    }
+}
+
+impl core::fmt::Debug for MMLayoutDescriptor {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        f.debug_struct("MMLayoutDescriptor")
+            .field("brk", &format_args!("{:#x}-{:#x}", self.start_brk, self.brk))
+            .field("start_stack", &format_args!("{:#x}", self.start_stack))
+            .field("arg", &format_args!("{:#x}-{:#x}", self.arg_start, self.arg_end))
+            .field("env", &format_args!("{:#x}-{:#x}", self.env_start, self.env_end))
+            .field("exe", &self.get_exe_path())
+            .finish()
+    }
+}
+
+impl os_network::serialize::Serialize for MMLayoutDescriptor {}
@@ -1,8 +1,11 @@
 pub use reg::*;
+pub use mm_layout::MMLayoutDescriptor;
+pub use files::*;
+pub use task_state::TaskStateDescriptor;
 pub use page_table::*;
 pub use rdma::RDMADescriptor;
 pub use parent::{CompactPageTable, ParentDescriptor};
-pub use child::ChildDescriptor;
+pub use child::{ChildDescriptor, FaultState};
 
 pub use vma::*;
 pub use pair::*;
@@ -10,6 +13,9 @@ pub use pair::*;
 pub mod parent;
 pub mod child;
 pub mod reg;
+pub mod mm_layout;
+pub mod files;
+pub mod task_state;
 pub mod page_table;
 pub mod vma;
 pub mod pair;
@@ -1,5 +1,9 @@
-use crate::descriptors::{ChildDescriptor, RDMADescriptor, RegDescriptor, VMADescriptor};
+use crate::descriptors::{
+    ChildDescriptor, FaultState, FileTableDescriptor, MMLayoutDescriptor, RDMADescriptor, RegDescriptor,
+    TaskStateDescriptor, VMADescriptor,
+};
 use crate::kern_wrappers::mm::{PhyAddrType, VirtAddrType};
+use crate::remote_mapping::PteState;
 use crate::{linux_kernel_module, VmallocAllocator};
 use alloc::vec::Vec;
 use os_network::bytes::BytesMut;
@@ -24,19 +28,30 @@ pub struct CompactPageTable {
 #[derive(Clone)]
 pub struct ParentDescriptor {
     pub regs: RegDescriptor,
+    pub mm_layout: MMLayoutDescriptor,
+    pub files: FileTableDescriptor,
+    pub task_state: TaskStateDescriptor,
     // 2-dimension matrix, each row means one page-table according to one VMA
     pub page_table: Vec<CompactPageTable, VmallocAllocator>,
     pub vma: Vec<VMADescriptor>,
     pub machine_info: RDMADescriptor,
+    // the hot pages carried by the descriptor, picked once by `pick_piggybacked_pages`
+    #[cfg(feature = "piggyback-pages")]
+    pub piggybacked: Vec<(VirtAddrType, PhyAddrType)>,
 }
 
 impl Default for ParentDescriptor {
     fn default() -> Self {
         Self {
             regs: Default::default(),
+            mm_layout: Default::default(),
+            files: Default::default(),
+            task_state: Default::default(),
             page_table: Vec::new_in(VmallocAllocator),
             vma: Vec::new(),
             machine_info: Default::default(),
+            #[cfg(feature = "piggyback-pages")]
+            piggybacked: Vec::new(),
         }
     }
 }
@@ -49,7 +64,7 @@ impl ParentDescriptor {
     /// DCQP in the current kernel.
     #[inline]
     pub fn to_descriptor(&self) -> ChildDescriptor {
-        let mut page_table = crate::remote_mapping::RemotePageTable::new();
+        let mut page_table = crate::remote_mapping::ChildPageTable::new(Default::default());
 
         for (vma_idx, vma_pg_table) in self.page_table.iter().enumerate() {
             let start = self.vma[vma_idx].get_start();
@@ -77,21 +92,93 @@ impl ParentDescriptor {
 
         ChildDescriptor {
             regs: self.regs.clone(),
+            mm_layout: self.mm_layout,
+            files: self.files.clone(),
+            task_state: self.task_state,
             page_table,
             vma: self.vma.clone(),
             machine_info: self.machine_info.clone(),
 
-            #[cfg(feature = "prefetch")]
-            prefetcher: DCAsyncPrefetcher::new_from_raw(prefetch_conn, access_info),
             #[cfg(feature = "eager-resume")]
             eager_fetched_pages: Default::default(),
             #[cfg(feature = "resume-profile")]
-            remote_fetched_page_count: 0,
+            remote_fetched_page_count: Default::default(),
+            fault_state: crate::lock_bundler::LockBundler::new(FaultState {
+                #[cfg(feature = "prefetch")]
+                prefetcher: DCAsyncPrefetcher::new_from_raw(prefetch_conn, access_info),
+                #[cfg(feature = "dedup-pages")]
+                dedup_pages: Default::default(),
+                #[cfg(feature = "push-resume")]
+                pushed_pages: Default::default(),
+            }),
+            #[cfg(feature = "piggyback-pages")]
+            piggybacked_pages: Default::default(),
         }
     }
 }
 
+impl ParentDescriptor {
+    /// Pick at most `num` pages that the child is likely to touch first:
+    /// the code page at `ip`, the stack pages from `sp` upward,
+    /// and then the remaining pages in the VMA order.
+    ///
+    /// Zero and duplicated pages are skipped, since the child resolves them locally.
+    pub fn hot_pages(&self, num: usize) -> Vec<(VirtAddrType, PhyAddrType)> {
+        const HOT_STACK_PAGES: u64 = 8;
+        let ip = self.regs.others.ip as VirtAddrType & !4095;
+        let sp = self.regs.others.sp as VirtAddrType & !4095;
+        let is_hot = |va: VirtAddrType| va == ip || (va >= sp && va < sp + HOT_STACK_PAGES * 4096);
+
+        let pages = self
+            .page_table
+            .iter()
+            .enumerate()
+            .flat_map(|(vma_idx, vma_pg_table)| {
+                let start = self.vma[vma_idx].get_start();
+                vma_pg_table
+                    .inner_pg_table
+                    .iter()
+                    .filter_map(move |(offset, entry)| {
+                        match PteState::decode(*entry) {
+                            PteState::Remote(remote) => {
+                                Some((*offset as VirtAddrType + start, remote.pa))
+                            }
+                            _ => None,
+                        }
+                    })
+            });
+
+        let mut res: Vec<(VirtAddrType, PhyAddrType)> = pages
+            .clone()
+            .filter(|(va, _)| is_hot(*va))
+            .take(num)
+            .collect();
+        let left = num - res.len();
+        res.extend(pages.filter(|(va, _)| !is_hot(*va)).take(left));
+        res
+    }
+
+    /// Pick the pages carried by the descriptor.
+    /// Must be called after the page table is generated, and before the serialization.
+    #[cfg(feature = "piggyback-pages")]
+    pub fn pick_piggybacked_pages(&mut self) {
+        self.piggybacked = self.hot_pages(crate::PIGGYBACK_PAGES);
+    }
+}
+
 impl ParentDescriptor {
+    #[inline]
+    fn piggyback_serialization_buf_len(&self) -> usize {
+        #[allow(unused_mut)]
+        let mut len = 0;
+        #[cfg(feature = "piggyback-pages")]
+        {
+            len = core::mem::size_of::<usize>()
+                + self.piggybacked.len() * (core::mem::size_of::<VirtAddrType>() + 4096);
+        }
+        len
+    }
+
     #[inline]
     fn vma_pg_table_serialization_buf_len(&self) -> usize {
         let mut result = core::mem::size_of::<usize>();
@@ -190,10 +277,15 @@ impl os_network::serialize::Serialize for ParentDescriptor {
     /// Serialization format:
     /// ```
     /// | RegDescriptor <-sizeof(RegDescriptor)->
+    /// | MMLayoutDescriptor <-sizeof(MMLayoutDescriptor)->
+    /// | FileTableDescriptor |
+    /// | TaskStateDescriptor <-sizeof(TaskStateDescriptor)->
     /// | VMA page table length in bytes <-8 bytes-> | VMAPageMap
     /// | VMA descriptor length in bytes <-8 bytes-> | VMA descriptor
     /// | RDMADescriptor |
+    /// | piggybacked page count <-8 bytes-> | VAs <-8 bytes each-> | page contents <-4KB each-> |
     /// ```
+    /// The piggybacked pages are only serialized with the `piggyback-pages` feature.
     fn serialize(&self, bytes: &mut BytesMut) -> bool {
         if bytes.len() < self.serialization_buf_len() {
             crate::log::error!(
@@ -212,6 +304,21 @@ impl os_network::serialize::Serialize for ParentDescriptor {
             cur.truncate_header(self.regs.serialization_buf_len())
                 .unwrap()
         };
+        self.mm_layout.serialize(&mut cur);
+        let mut cur = unsafe {
+            cur.truncate_header(self.mm_layout.serialization_buf_len())
+                .unwrap()
+        };
+        self.files.serialize(&mut cur);
+        let mut cur = unsafe {
+            cur.truncate_header(self.files.serialization_buf_len())
+                .unwrap()
+        };
+        self.task_state.serialize(&mut cur);
+        let mut cur = unsafe {
+            cur.truncate_header(self.task_state.serialization_buf_len())
+                .unwrap()
+        };
 
         // 2. vmas & page table (size)
         let sz = unsafe { cur.memcpy_serialize_at(0, &self.page_table.len()).unwrap() };
@@ -231,9 +338,34 @@ impl os_network::serialize::Serialize for ParentDescriptor {
             };
         }
 
-        // 3. finally, machine info
+        // 3. machine info
         self.machine_info.serialize(&mut cur);
 
+        // 4. finally, the piggybacked pages
+        #[cfg(feature = "piggyback-pages")]
+        {
+            let mut cur = unsafe {
+                cur.truncate_header(self.machine_info.serialization_buf_len())
+                    .unwrap()
+            };
+            let pages = &self.piggybacked;
+            let sz = unsafe { cur.memcpy_serialize_at(0, &pages.len()).unwrap() };
+            cur = unsafe { cur.truncate_header(sz).unwrap() };
+
+            for (va, _) in pages.iter() {
+                let sz = unsafe { cur.write_unaligned_at_head(*va) };
+                cur = unsafe { cur.truncate_header(sz).unwrap() };
+            }
+            for (_, pa) in pages.iter() {
+                let kva = unsafe {
+                    crate::bindings::pmem_phys_to_virt(*pa)
+                };
+                let content = unsafe { BytesMut::from_raw(kva as *mut u8, 4096) };
+                cur.copy(&content, 0);
+                cur = unsafe { cur.truncate_header(4096).unwrap() };
+            }
+        }
+
         true
     }
 
@@ -244,6 +376,12 @@ impl os_network::serialize::Serialize for ParentDescriptor {
         // regs
         let regs = RegDescriptor::deserialize(&cur)?;
         cur = unsafe { cur.truncate_header(regs.serialization_buf_len())? };
+        let mm_layout = MMLayoutDescriptor::deserialize(&cur)?;
+        cur = unsafe { cur.truncate_header(mm_layout.serialization_buf_len())? };
+        let files = FileTableDescriptor::deserialize(&cur)?;
+        cur = unsafe { cur.truncate_header(files.serialization_buf_len())? };
+        let task_state = TaskStateDescriptor::deserialize(&cur)?;
+        cur = unsafe { cur.truncate_header(task_state.serialization_buf_len())? };
 
         // VMA page counts
         let mut count: usize = 0;
@@ -268,18 +406,28 @@ impl os_network::serialize::Serialize for ParentDescriptor {
 
         Some(Self {
             regs,
+            mm_layout,
+            files,
+            task_state,
             page_table: pt,
             vma: vmas,
             machine_info,
+            // the parent never reads the piggybacked pages back
+            #[cfg(feature = "piggyback-pages")]
+            piggybacked: Vec::new(),
         })
     }
 
     fn serialization_buf_len(&self) -> usize {
         self.regs.serialization_buf_len()
+            + self.mm_layout.serialization_buf_len()
+            + self.files.serialization_buf_len()
+            + self.task_state.serialization_buf_len()
             + self.vma_pg_table_serialization_buf_len()
             + core::mem::size_of::<usize>() // the number of VMA descriptors
             + self.vma.len() * core::mem::size_of::<VMADescriptor>()
             + self.machine_info.serialization_buf_len()
+            + self.piggyback_serialization_buf_len()
     }
 }
 
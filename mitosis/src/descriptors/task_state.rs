@@ -0,0 +1,55 @@
+use crate::bindings::pmem_task_state;
+
+/// The per-process states of the parent besides its memory and registers:
+/// signal handlers, the blocked mask, sigaltstack, rlimits, umask and credentials.
+///
+/// The credentials are only restored if the resumer is allowed to assume them,
+/// see `Task::check_task_state`.
+#[derive(Clone, Copy, Default)]
+pub struct TaskStateDescriptor {
+    pub(crate) inner: pmem_task_state,
+}
+
+impl TaskStateDescriptor {
+    /// (real, effective) uid
+    #[inline]
+    pub fn get_uid(&self) -> (u32, u32) {
+        (self.inner.uid, self.inner.euid)
+    }
+
+    /// (real, effective) gid
+    #[inline]
+    pub fn get_gid(&self) -> (u32, u32) {
+        (self.inner.gid, self.inner.egid)
+    }
+
+    #[inline]
+    pub fn get_blocked(&self) -> u64 {
+        self.inner.blocked
+    }
+
+    #[inline]
+    pub fn get_umask(&self) -> u32 {
+        self.inner.umask
+    }
+
+    /// The handler of the signal, e.g., SIG_DFL (0)
+    #[inline]
+    pub fn get_sig_handler(&self, sig: usize) -> Option<u64> {
+        self.inner.actions.get(sig.checked_sub(1)?).map(|a| a.handler)
+    }
+}
+
+impl core::fmt::Debug for TaskStateDescriptor {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        f.debug_struct("TaskStateDescriptor")
+            .field("uid", &self.get_uid())
+            .field("gid", &self.get_gid())
+            .field("cap_permitted", &format_args!("{:#x}", self.inner.cap_permitted))
+            .field("blocked", &format_args!("{:#x}", self.inner.blocked))
+            .field("umask", &format_args!("{:#o}", self.inner.umask))
+            .finish()
+    }
+}
+
+impl os_network::serialize::Serialize for TaskStateDescriptor {}
@@ -1,6 +1,24 @@
 use crate::bindings::VMFlags;
 use crate::kern_wrappers::mm::VirtAddrType;
 
+/// The special mappings installed by the kernel, whose content is not the parent's memory.
+///
+/// They are re-established with the local kernel on resume, instead of being fetched.
+/// Note that `[vsyscall]` is not a VMA of the mm, and it is at the same fixed address on all machines.
+#[repr(u8)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SpecialMapping {
+    None,
+    Vdso,
+    Vvar,
+}
+
+impl Default for SpecialMapping {
+    fn default() -> Self {
+        SpecialMapping::None
+    }
+}
+
 /// The VMA descriptors can generate from VMA in kern_wrappers
 #[derive(Copy, Clone, Default,Debug)]
 pub struct VMADescriptor {
@@ -12,6 +30,7 @@ pub struct VMADescriptor {
     pub flags: crate::bindings::vm_flags_t,
     pub prot: crate::bindings::pgprot_t,
     pub is_anonymous: bool,
+    pub special: SpecialMapping,
 }
 
 impl VMADescriptor {
@@ -35,6 +54,12 @@ impl VMADescriptor {
         self.is_anonymous
     }
 
+    /// Whether the VMA is the vDSO or vvar, see `SpecialMapping`
+    #[inline]
+    pub fn is_special(&self) -> bool {
+        self.special != SpecialMapping::None
+    }
+
     #[inline]
     pub fn get_sz(&self) -> u64 {
         self.range.1 - self.range.0
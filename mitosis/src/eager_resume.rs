@@ -0,0 +1,128 @@
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use rust_kernel_linux_util::kthread;
+use rust_kernel_linux_util::kthread::JoinHandler;
+use rust_kernel_linux_util::linux_kernel_module::c_types::{c_int, c_void};
+
+use crate::bindings::page;
+use crate::descriptors::ChildDescriptor;
+use crate::kern_wrappers::mm::VirtAddrType;
+use crate::remote_paging::AccessInfo;
+
+#[allow(unused_imports)]
+use crate::linux_kernel_module;
+
+/// The pages fetched by one worker, (remote VA, local page)
+pub(crate) type FetchedPages = Vec<(VirtAddrType, *mut page)>;
+
+/// The share of one eager fetch worker.
+///
+/// The worker only reads the descriptor, i.e., it looks up the remote page table,
+/// and the pages are installed by the thread that resumes the child,
+/// since only it can modify the child's address space.
+struct EagerFetchCTX {
+    id: usize,
+    descriptor: *const ChildDescriptor,
+    access_info: *const AccessInfo,
+    // the DCQP used by this worker
+    pool_idx: usize,
+    batches: Vec<Vec<VirtAddrType>>,
+    fetched: FetchedPages,
+    done: AtomicBool,
+}
+
+impl EagerFetchCTX {
+    fn run(&mut self) {
+        let descriptor = unsafe { &*self.descriptor };
+        let access_info = unsafe { &*self.access_info };
+        for batch in self.batches.iter() {
+            let page_list = descriptor.batch_read_remote_pages(batch, access_info, self.pool_idx);
+            for (addr, new_page_p) in batch.iter().zip(page_list) {
+                if let Some(new_page_p) = new_page_p {
+                    self.fetched.push((*addr, new_page_p));
+                }
+            }
+        }
+        self.done.store(true, Ordering::Release);
+    }
+}
+
+/// Fetch the pages in `batches` with `workers` threads, each with its own DCQP.
+/// The calling thread serves as the first worker.
+///
+/// The batches are dealt to the workers in a round-robin way, so that
+/// each worker gets a share of every large VMA.
+/// If a worker thread fails to start, its share is fetched by the calling thread.
+pub(crate) fn parallel_fetch(
+    descriptor: &ChildDescriptor,
+    access_info: &AccessInfo,
+    batches: Vec<Vec<VirtAddrType>>,
+    workers: usize,
+) -> FetchedPages {
+    let workers = core::cmp::max(1, core::cmp::min(workers, batches.len()));
+    let max_pool_idx = unsafe { *crate::max_caller_num::get_ref() };
+    let my_cpu = crate::get_calling_cpu_id();
+
+    let mut ctxs: Vec<Box<EagerFetchCTX>> = (0..workers)
+        .map(|i| {
+            Box::new(EagerFetchCTX {
+                id: i,
+                descriptor: descriptor as *const _,
+                access_info: access_info as *const _,
+                pool_idx: (my_cpu + i) % max_pool_idx,
+                batches: Vec::new(),
+                fetched: Vec::new(),
+                done: AtomicBool::new(false),
+            })
+        })
+        .collect();
+    for (i, batch) in batches.into_iter().enumerate() {
+        ctxs[i % workers].batches.push(batch);
+    }
+
+    let mut threads: Vec<JoinHandler> = Vec::new();
+    for ctx in ctxs.iter_mut().skip(1) {
+        // the ctx is owned by this function, which outlives the worker
+        let arg_ptr = ctx.as_mut() as *mut EagerFetchCTX;
+        let builder = kthread::Builder::new()
+            .set_name(alloc::format!("MITOSIS eager fetch Thread {}", ctx.id))
+            .set_parameter(arg_ptr as *mut c_void);
+        match builder.spawn(worker) {
+            Ok(handler) => threads.push(handler),
+            Err(_) => {
+                crate::log::warn!("failed to spawn eager fetch worker {}", ctx.id);
+                ctx.run();
+            }
+        }
+    }
+    ctxs[0].run();
+
+    for ctx in ctxs.iter() {
+        while !ctx.done.load(Ordering::Acquire) {
+            kthread::yield_now();
+        }
+    }
+    for handler in threads {
+        handler.join();
+    }
+
+    let mut res = Vec::new();
+    for ctx in ctxs.iter_mut() {
+        res.append(&mut ctx.fetched);
+    }
+    res
+}
+
+extern "C" fn worker(ctx: *mut c_void) -> c_int {
+    let ctx = unsafe { &mut *(ctx as *mut EagerFetchCTX) };
+    ctx.run();
+
+    // keep the thread alive until it is joined
+    while !kthread::should_stop() {
+        kthread::yield_now();
+    }
+    0
+}
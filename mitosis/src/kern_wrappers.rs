@@ -3,6 +3,8 @@
 /// * mm_struct - abstracted in mm::MemoryDescriptor 
 /// * task_struct - abstracted in task::Task
 /// * vma_struct - abstracted in vma::VMA
+/// * semaphore - abstracted in sema::Semaphore
+/// * completion notification of the CQs - abstracted in cq_waiter::CQWaiter
 /// 
 /// vma_iters module also includes useful code for iterating pages belonging to a VMA
 pub mod mm;
@@ -10,6 +12,8 @@ pub mod task;
 pub mod vma;
 pub mod vma_iters;
 pub mod page;
+pub mod sema;
+pub mod cq_waiter;
 
 pub use page::{Page, copy_page_content_4k};
 
@@ -0,0 +1,58 @@
+use alloc::boxed::Box;
+use core::cell::UnsafeCell;
+
+use os_network::KRdmaKit::QueuePair;
+
+use crate::bindings::{
+    pmem_cq_waiter, pmem_cq_waiter_arm, pmem_cq_waiter_attach, pmem_cq_waiter_events,
+    pmem_cq_waiter_init, pmem_cq_waiter_wait,
+};
+
+/// Sleep until the receive CQs of the attached QPs have completions,
+/// e.g., to let an idle kthread stop polling them.
+///
+/// The waiters should read `events` before arming the CQs, and wait for the ones after it,
+/// so the completions between the arm and the wait are not missed.
+pub struct CQWaiter {
+    inner: UnsafeCell<pmem_cq_waiter>,
+}
+
+unsafe impl Send for CQWaiter {}
+unsafe impl Sync for CQWaiter {}
+
+impl CQWaiter {
+    // boxed since the kernel wait queue cannot be moved after the initialization
+    pub fn new() -> Box<Self> {
+        let res = Box::new(Self {
+            inner: UnsafeCell::new(unsafe { core::mem::zeroed() }),
+        });
+        unsafe { pmem_cq_waiter_init(res.inner.get()) };
+        res
+    }
+
+    /// Wake up the waiters on the completions of the QP's receive CQ, once armed.
+    ///
+    /// # Safety
+    /// The waiter must outlive the QP, and the CQ must not be notified to others.
+    pub unsafe fn attach(&self, qp: &QueuePair) {
+        pmem_cq_waiter_attach(self.inner.get(), qp.raw_ptr().as_ptr() as _);
+    }
+
+    /// Arm the QP's receive CQ for its next completion.
+    /// Return false if some completions may be missed, so the CQ should be polled instead.
+    #[inline]
+    pub fn arm(qp: &QueuePair) -> bool {
+        unsafe { pmem_cq_waiter_arm(qp.raw_ptr().as_ptr() as _) == 0 }
+    }
+
+    #[inline]
+    pub fn events(&self) -> u32 {
+        unsafe { pmem_cq_waiter_events(self.inner.get()) }
+    }
+
+    /// Sleep until an event after the `seen` ones, the kthread should stop, or about `usecs` passed
+    #[inline]
+    pub fn wait(&self, seen: u32, usecs: u64) {
+        unsafe { pmem_cq_waiter_wait(self.inner.get(), seen, usecs as _) };
+    }
+}
@@ -0,0 +1,34 @@
+use alloc::boxed::Box;
+use core::cell::UnsafeCell;
+
+use crate::bindings::{pmem_sema_down_timeout, pmem_sema_init, pmem_sema_up, semaphore};
+
+/// A counting semaphore, e.g., to let a kthread sleep until there is work to do
+pub struct Semaphore {
+    inner: UnsafeCell<semaphore>,
+}
+
+unsafe impl Send for Semaphore {}
+unsafe impl Sync for Semaphore {}
+
+impl Semaphore {
+    // boxed since the kernel semaphore cannot be moved after the initialization
+    pub fn new(val: i32) -> Box<Self> {
+        let res = Box::new(Self {
+            inner: UnsafeCell::new(unsafe { core::mem::zeroed() }),
+        });
+        unsafe { pmem_sema_init(res.inner.get(), val) };
+        res
+    }
+
+    #[inline]
+    pub fn up(&self) {
+        unsafe { pmem_sema_up(self.inner.get()) };
+    }
+
+    /// Return false if not acquired after about `usecs`
+    #[inline]
+    pub fn down_timeout(&self, usecs: u64) -> bool {
+        unsafe { pmem_sema_down_timeout(self.inner.get(), usecs as _) == 0 }
+    }
+}
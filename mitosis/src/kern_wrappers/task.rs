@@ -18,61 +18,81 @@ impl Task {
 }
 
 use crate::descriptors::*;
+use crate::kern_wrappers::mm::VirtAddrType;
+use alloc::string::String;
 
 impl Task {
-    /// Unmap all of the VMA in the task
+    /// Unmap all of the VMA in the task, except the vDSO and vvar, see `restore_vdso`
     #[inline]
     pub fn unmap_self(&self) {
         let mut md = self.get_memory_descriptor();
-        self.get_memory_descriptor().get_vma_iter().for_each(|m| {
-            md.unmap_region(m.get_start() as _, m.get_sz() as _);
-        });
+        self.get_memory_descriptor()
+            .get_vma_iter()
+            .filter(|m| m.get_special_mapping() == SpecialMapping::None)
+            .for_each(|m| {
+                md.unmap_region(m.get_start() as _, m.get_sz() as _);
+            });
     }
 
-    /// Map one region into current task
+    /// Move the vDSO (and vvar) of the local kernel to the address of the parent's one,
+    /// because the parent's code, e.g., libc, may hold pointers into it.
+    /// Should be called before the parent's VMAs are mapped.
+    ///
+    /// Return the start of the local vDSO text, which differs from the parent's
+    /// if its range is taken, or None if there is no vDSO.
+    pub fn restore_vdso(&self, parent_vmas: &[VMADescriptor]) -> Option<VirtAddrType> {
+        let mut md = self.get_memory_descriptor();
+        let find = |kind| parent_vmas.iter().find(|v| v.special == kind);
+
+        let local: alloc::vec::Vec<(VirtAddrType, u64)> = md
+            .get_vma_iter()
+            .filter(|m| m.get_special_mapping() != SpecialMapping::None)
+            .map(|m| (m.get_start(), m.get_sz()))
+            .collect();
+        let local_vdso = unsafe { crate::bindings::pmem_get_mm_vdso(self.task_inner.mm) };
+
+        let parent_vdso = find(SpecialMapping::Vdso).map(|v| v.get_start());
+        if parent_vdso.is_some() && parent_vdso == Some(local_vdso) {
+            return parent_vdso;
+        }
+
+        for (start, sz) in local {
+            md.unmap_region(start, sz as _);
+        }
+        // the parent runs without the vDSO, e.g., booted with vdso=0
+        let parent_vdso = parent_vdso?;
+
+        // the vvar is placed right before the vDSO text
+        let start = find(SpecialMapping::Vvar).map_or(parent_vdso, |v| v.get_start());
+        let res = unsafe { crate::bindings::pmem_arch_map_vdso(start) };
+        if res != 0 {
+            crate::log::error!("failed to map the vDSO at 0x{:x}: {}", start, res);
+            return None;
+        }
+        Some(unsafe { crate::bindings::pmem_get_mm_vdso(self.task_inner.mm) })
+    }
+
+    /// Map one region into current task.
+    ///
+    /// The region is mapped with exactly the parent's range. The heap grows
+    /// with brk(), which maps new anonymous VMAs after it (see `set_mm_layout`),
+    /// and the stack grows down on faults, like any other stack VMA.
     #[inline]
     pub unsafe fn map_one_region(
         &self,
         file: *mut crate::bindings::file,
         vma_meta: &VMADescriptor,
-        next_vma: core::option::Option<&VMADescriptor>,
     ) -> Option<&'static mut crate::bindings::vm_area_struct> {
         use crate::bindings::{pmem_vm_mmap, VMFlags};
 
-        let ret = {
-            // we need to extend the VMA of heap & stack to avoid corrupting
-            let mut extended_map_area_sz = 1024 * 1024 * 1024; // 1GB
-            // let mut extended_map_area_sz = 0;
-            if next_vma.is_some() {
-                assert!(next_vma.unwrap().get_start() >= vma_meta.get_end());
-                extended_map_area_sz = core::cmp::min(
-                    extended_map_area_sz,
-                    next_vma.unwrap().get_start() - vma_meta.get_end(),
-                );
-            } else { 
-                extended_map_area_sz = 0;
-            }
-
-            if vma_meta.is_anonymous() {
-                pmem_vm_mmap(
-                    file,
-                    vma_meta.get_start(),
-                    vma_meta.get_sz() + extended_map_area_sz,
-                    vma_meta.get_mmap_flags(),
-                    crate::kern_wrappers::mm::mmap_flags::MAP_PRIVATE,
-                    0,
-                )
-            } else {
-                pmem_vm_mmap(
-                    file,
-                    vma_meta.get_start(),
-                    vma_meta.get_sz(),
-                    vma_meta.get_mmap_flags(),
-                    crate::kern_wrappers::mm::mmap_flags::MAP_PRIVATE,
-                    0,
-                )
-            }
-        };
+        let ret = pmem_vm_mmap(
+            file,
+            vma_meta.get_start(),
+            vma_meta.get_sz(),
+            vma_meta.get_mmap_flags(),
+            crate::kern_wrappers::mm::mmap_flags::MAP_PRIVATE,
+            0,
+        );
         if ret != vma_meta.get_start() {
             return None;
         }
@@ -95,6 +115,73 @@ impl Task {
         return Some(vma);
     }
 
+    /// Restore the layout of the mm besides the VMAs, e.g., brk and the argv/envp ranges.
+    /// Should be called after the VMAs are mapped.
+    ///
+    /// The executable is re-opened with its path at the parent,
+    /// and the current one is kept if the path is not found on this machine.
+    pub fn set_mm_layout(&mut self, layout: &MMLayoutDescriptor) {
+        let mm = self.task_inner.mm;
+        unsafe {
+            crate::bindings::pmem_mm_write_lock(mm);
+            (*mm).start_code = layout.start_code;
+            (*mm).end_code = layout.end_code;
+            (*mm).start_data = layout.start_data;
+            (*mm).end_data = layout.end_data;
+            (*mm).start_brk = layout.start_brk;
+            (*mm).brk = layout.brk;
+            (*mm).start_stack = layout.start_stack;
+            (*mm).arg_start = layout.arg_start;
+            (*mm).arg_end = layout.arg_end;
+            (*mm).env_start = layout.env_start;
+            (*mm).env_end = layout.env_end;
+            (*mm).saved_auxv.copy_from_slice(&layout.saved_auxv);
+            crate::bindings::pmem_mm_write_unlock(mm);
+        }
+
+        if layout.get_exe_path().is_some() {
+            let res =
+                unsafe { crate::bindings::pmem_set_mm_exe_path(mm, layout.exe_path.as_ptr() as _) };
+            if res != 0 {
+                crate::log::warn!(
+                    "failed to restore the executable {:?}: {}",
+                    layout.get_exe_path(),
+                    res
+                );
+            }
+        }
+    }
+
+    pub fn generate_mm_layout_descriptor(&self) -> MMLayoutDescriptor {
+        let mm = self.task_inner.mm;
+        let mut layout: MMLayoutDescriptor = Default::default();
+        unsafe {
+            layout.start_code = (*mm).start_code;
+            layout.end_code = (*mm).end_code;
+            layout.start_data = (*mm).start_data;
+            layout.end_data = (*mm).end_data;
+            layout.start_brk = (*mm).start_brk;
+            layout.brk = (*mm).brk;
+            layout.start_stack = (*mm).start_stack;
+            layout.arg_start = (*mm).arg_start;
+            layout.arg_end = (*mm).arg_end;
+            layout.env_start = (*mm).env_start;
+            layout.env_end = (*mm).env_end;
+            layout.saved_auxv.copy_from_slice(&(*mm).saved_auxv);
+
+            let res = crate::bindings::pmem_get_mm_exe_path(
+                mm,
+                layout.exe_path.as_mut_ptr() as _,
+                layout.exe_path.len() as _,
+            );
+            if res != 0 {
+                crate::log::debug!("failed to get the executable path: {}", res);
+                layout.exe_path[0] = 0;
+            }
+        }
+        layout
+    }
+
     #[inline]
     pub fn set_mm_reg_states(&mut self, regs: &RegDescriptor) {
         self.get_memory_descriptor().flush_tlb_all();
@@ -111,6 +198,188 @@ impl Task {
         }
     }
 
+    pub fn generate_task_state_descriptor(&self) -> TaskStateDescriptor {
+        let mut state: TaskStateDescriptor = Default::default();
+        unsafe { crate::bindings::pmem_get_task_state(&mut state.inner) };
+        state
+    }
+
+    /// Whether the task is allowed to assume the credentials of the state,
+    /// e.g., an unprivileged resumer cannot become root
+    pub fn check_task_state(&self, state: &TaskStateDescriptor) -> bool {
+        unsafe { crate::bindings::pmem_check_task_creds(&state.inner) == 0 }
+    }
+
+    /// Restore the signal handlers, rlimits, credentials, etc.
+    /// The state must have been checked with `check_task_state`.
+    pub fn set_task_state(&mut self, state: &TaskStateDescriptor) {
+        let res = unsafe { crate::bindings::pmem_set_task_state(&state.inner) };
+        if res < 0 {
+            crate::log::error!("failed to restore the task state: {}", res);
+        } else if res > 0 {
+            crate::log::warn!("{} rlimits are clamped to the hard limits of the resumer", res);
+        }
+    }
+
+    /// Record the opened fds of the task, see `FileKind` for the kinds recorded.
+    ///
+    /// `mitosis_file` is the mitosis device that prepares the task.
+    pub fn generate_file_table_descriptor(
+        &self,
+        mitosis_file: *mut crate::bindings::file,
+    ) -> FileTableDescriptor {
+        use crate::bindings::{PMEM_S_IFCHR, PMEM_S_IFIFO, PMEM_S_IFMT, PMEM_S_IFREG};
+
+        let mut table: FileTableDescriptor = Default::default();
+        let max_fds = unsafe { crate::bindings::pmem_get_max_fds() };
+        for fd in 0..max_fds {
+            let mut file = FileDescriptor {
+                fd: fd,
+                kind: FileKind::Other,
+                flags: 0,
+                pos: 0,
+                path: [0; FILE_PATH_LEN],
+            };
+            let mut mode: crate::linux_kernel_module::c_types::c_uint = 0;
+            let res = unsafe {
+                crate::bindings::pmem_get_fd_info(
+                    fd,
+                    file.path.as_mut_ptr() as _,
+                    FILE_PATH_LEN as _,
+                    &mut file.flags,
+                    &mut file.pos,
+                    &mut mode,
+                )
+            };
+            if res == -(crate::linux_kernel_module::bindings::EBADF as i32) {
+                continue;
+            }
+            if res != 0 {
+                // e.g., the path is too long, then it cannot be reopened
+                crate::log::debug!("failed to get the path of fd {}: {}", fd, res);
+                file.path[0] = 0;
+            }
+
+            file.kind = if unsafe { crate::bindings::pmem_fd_is_file(fd, mitosis_file) } != 0 {
+                FileKind::Mitosis
+            } else {
+                match mode & PMEM_S_IFMT {
+                    m if m == PMEM_S_IFREG => FileKind::Regular,
+                    m if m == PMEM_S_IFIFO => FileKind::Pipe,
+                    m if m == PMEM_S_IFCHR => FileKind::Stream,
+                    _ => FileKind::Other,
+                }
+            };
+            table.files.push(file);
+        }
+        table
+    }
+
+    /// Replace the fds of the task with the parent's ones, following the policy.
+    /// The other fds of the task are closed, see `close_other_fds`.
+    ///
+    /// `mitosis_file` is the mitosis device that resumes the task,
+    /// which is installed at the fds of the parent's mitosis device.
+    pub fn restore_files(
+        &mut self,
+        table: &FileTableDescriptor,
+        policy: &FdRestorePolicy,
+        mitosis_file: *mut crate::bindings::file,
+    ) -> FdRestoreReport {
+        use crate::bindings::FileFlags;
+
+        const DEV_NULL: &str = "/dev/null\0";
+        let mut report: FdRestoreReport = Default::default();
+
+        for file in table.iter() {
+            let flags = file.get_flags();
+            let res = match (file.get_kind(), policy) {
+                (FileKind::Mitosis, _) => unsafe {
+                    crate::bindings::pmem_install_fd_at(file.fd, mitosis_file, flags.bits())
+                },
+                (_, FdRestorePolicy::DevNull) => Self::open_fd_at(file, DEV_NULL, flags, -1),
+                (FileKind::Pipe, FdRestorePolicy::Redirect(path))
+                | (FileKind::Stream, FdRestorePolicy::Redirect(path)) => {
+                    if file.is_writable() {
+                        let flags = FileFlags::WRONLY
+                            | FileFlags::CREAT
+                            | FileFlags::APPEND
+                            | (flags & FileFlags::CLOEXEC);
+                        let mut path = path.clone();
+                        path.push('\0');
+                        Self::open_fd_at(file, &path, flags, -1)
+                    } else {
+                        Self::open_fd_at(file, DEV_NULL, flags, -1)
+                    }
+                }
+                (FileKind::Regular, _) | (FileKind::Stream, _) => match file.get_path() {
+                    Some(path) if !path.is_empty() => {
+                        // the file is never re-created, nor truncated
+                        let flags = flags - (FileFlags::CREAT | FileFlags::EXCL | FileFlags::TRUNC);
+                        let mut path = String::from(path);
+                        path.push('\0');
+                        Self::open_fd_at(file, &path, flags, file.pos)
+                    }
+                    _ => -(crate::linux_kernel_module::bindings::ENOENT as i32),
+                },
+                // the other end of the pipe is left at the parent's machine
+                (FileKind::Pipe, _) | (FileKind::Other, _) => {
+                    -(crate::linux_kernel_module::bindings::EOPNOTSUPP as i32)
+                }
+            };
+
+            if res != 0 {
+                crate::log::debug!("failed to restore {:?}: {}", file, res);
+                report.failed.push((file.fd, res));
+                let fallback = Self::open_fd_at(file, DEV_NULL, flags, -1);
+                if fallback != 0 {
+                    // then the fd is closed, rather than left to the sacrificial's file
+                    unsafe {
+                        crate::bindings::pmem_install_fd_at(file.fd, core::ptr::null_mut(), 0)
+                    };
+                }
+            }
+        }
+
+        Self::close_other_fds(table, mitosis_file);
+        report
+    }
+
+    /// Close the fds of the sacrificial process that the parent never had.
+    ///
+    /// Its fd of the mitosis file is kept if the parent had none, since the file
+    /// may be only referenced by that fd during this ioctl.
+    fn close_other_fds(table: &FileTableDescriptor, mitosis_file: *mut crate::bindings::file) {
+        let restored: hashbrown::HashSet<u32> = table.iter().map(|f| f.fd).collect();
+        let keep_mitosis = !table.iter().any(|f| f.get_kind() == FileKind::Mitosis);
+
+        let max_fds = unsafe { crate::bindings::pmem_get_max_fds() };
+        for fd in (0..max_fds).filter(|fd| !restored.contains(fd)) {
+            if keep_mitosis && unsafe { crate::bindings::pmem_fd_is_file(fd, mitosis_file) } != 0 {
+                continue;
+            }
+            // closing an fd not opened is a no-op
+            unsafe { crate::bindings::pmem_install_fd_at(fd, core::ptr::null_mut(), 0) };
+        }
+    }
+
+    /// `path` must be NUL-terminated
+    fn open_fd_at(
+        file: &FileDescriptor,
+        path: &str,
+        flags: crate::bindings::FileFlags,
+        pos: i64,
+    ) -> crate::linux_kernel_module::c_types::c_int {
+        let mode = if flags.contains(crate::bindings::FileFlags::CREAT) {
+            0o644
+        } else {
+            0
+        };
+        unsafe {
+            crate::bindings::pmem_open_fd_at(file.fd, path.as_ptr() as _, flags.bits(), mode, pos)
+        }
+    }
+
     pub fn generate_mm(&self) -> (alloc::vec::Vec<VMADescriptor>, FlatPageTable) {
         use crate::kern_wrappers::vma_iters::VMADumpIter;
 
@@ -14,6 +14,7 @@ impl<'a> VMA<'a> {
             flags: self.get_raw_flags(),
             prot: self.get_prot(),
             is_anonymous: self.is_anonymous(),
+            special: self.get_special_mapping(),
         }
     }
 
@@ -42,6 +43,23 @@ impl<'a> VMA<'a> {
         self.vma_inner.vm_ops.is_null()
     }
 
+    /// Whether this VMA is the vDSO or vvar installed by the kernel
+    pub fn get_special_mapping(&self) -> crate::descriptors::SpecialMapping {
+        use crate::descriptors::SpecialMapping;
+
+        let name = unsafe {
+            crate::bindings::pmem_get_vma_special_name(self.vma_inner as *const _ as *mut _)
+        };
+        if name.is_null() {
+            return SpecialMapping::None;
+        }
+        match unsafe { ptr2string(name) }.as_str() {
+            "[vdso]" => SpecialMapping::Vdso,
+            "[vvar]" => SpecialMapping::Vvar,
+            _ => SpecialMapping::None,
+        }
+    }
+
     /// whether this VMA is a stack
     pub fn is_stack(&self) -> bool {
         self.get_flags().contains(VMFlags::STACK)
@@ -35,6 +35,9 @@ pub const MAX_RPC_THREADS_CNT: usize = 10;
 // Prefetch page count (fetch by async operation). The real fetched page count would be `PREFETCH_STEP`
 pub const PREFETCH_STEP: usize = 1;
 
+// Number of hot pages carried by the descriptor, with the `piggyback-pages` feature
+pub const PIGGYBACK_PAGES: usize = 4;
+
 pub fn get_calling_cpu_id() -> usize {
     unsafe { crate::bindings::pmem_get_current_cpu() as _ }
 }
@@ -46,6 +49,10 @@ declare_global!(global_locks, alloc::vec::Vec<crate::linux_kernel_module::mutex:
 
 declare_global!(max_cluster_size, usize);
 
+declare_global!(eager_resume_threads, usize);
+
+declare_global!(page_table_kind, crate::remote_mapping::PageTableKind);
+
 // FIXME: currently, we assume that all machines in the cluster has the
 // same number of RNIC attached to it.
 declare_global!(max_nics_used, usize);
@@ -58,6 +65,8 @@ pub struct Config {
 
     pub num_nics_used: usize,
 
+    // number of the RPC endpoints, and of the handler threads started with them,
+    // which can be changed at runtime by `LibMITOSISCmd::RpcThreads`
     pub rpc_threads_num: usize,
 
     // my machine ID
@@ -72,6 +81,23 @@ pub struct Config {
     pub max_cluster_size: usize,
 
     pub mem_pool_size: usize,
+
+    // number of threads (including the resuming one) fetching the pages, with the `eager-resume` feature
+    pub eager_resume_threads: usize,
+
+    // default backend of the page tables of the resumed children, a resume may ask for another
+    pub page_table_kind: crate::remote_mapping::PageTableKind,
+
+    // the token shared by the machines of the cluster to connect the RPC sessions, 0 for none.
+    // It is sent in plaintext, so it only keeps out the misconfigured machines,
+    // see `RPCHook::set_auth_token`
+    pub rpc_auth_token: u64,
+
+    // the transport of the RPC sessions, which should be the same among the machines
+    pub rpc_transport: os_network::rpc::impls::transport::RPCTransport,
+
+    // number of the remote callers that can connect to each RPC endpoint over RC
+    pub rpc_mailbox_lanes: usize,
 }
 
 impl Default for Config {
@@ -86,6 +112,11 @@ impl Default for Config {
             init_dc_targets: 256,
             max_cluster_size: 128,
             mem_pool_size: 20,
+            eager_resume_threads: 4,
+            page_table_kind: Default::default(),
+            rpc_auth_token: 0,
+            rpc_transport: Default::default(),
+            rpc_mailbox_lanes: 256,
         }
     }
 }
@@ -139,6 +170,33 @@ impl Config {
         self.mem_pool_size = sz;
         self
     }
+
+    pub fn set_eager_resume_threads(&mut self, num: usize) -> &mut Self {
+        assert!(num > 0);
+        self.eager_resume_threads = num;
+        self
+    }
+
+    pub fn set_page_table_kind(&mut self, kind: crate::remote_mapping::PageTableKind) -> &mut Self {
+        self.page_table_kind = kind;
+        self
+    }
+
+    pub fn set_rpc_auth_token(&mut self, token: u64) -> &mut Self {
+        self.rpc_auth_token = token;
+        self
+    }
+
+    pub fn set_rpc_transport(&mut self, transport: os_network::rpc::impls::transport::RPCTransport) -> &mut Self {
+        self.rpc_transport = transport;
+        self
+    }
+
+    pub fn set_rpc_mailbox_lanes(&mut self, num: usize) -> &mut Self {
+        assert!(num > 0);
+        self.rpc_mailbox_lanes = num;
+        self
+    }
 }
 
 // kernel-space global variables
@@ -194,13 +252,11 @@ declare_global!(
     alloc::vec::Vec<crate::KRdmaKit::services::DatagramMeta>
 );
 
-#[cfg(feature = "use_rc")]
 declare_global!(
     rc_service,
     alloc::vec::Vec<alloc::sync::Arc<crate::KRdmaKit::services::ReliableConnectionServer>>
 );
 
-#[cfg(feature = "use_rc")]
 #[inline]
 pub unsafe fn get_rc_service_ref(
     nic_idx: usize,
@@ -208,7 +264,6 @@ pub unsafe fn get_rc_service_ref(
     crate::rc_service::get_ref().get(nic_idx)
 }
 
-#[cfg(feature = "use_rc")]
 declare_global!(
     rc_cm_service,
     alloc::vec::Vec<crate::KRdmaKit::comm_manager::CMServer<crate::KRdmaKit::services::ReliableConnectionServer>>
@@ -301,6 +356,11 @@ pub unsafe fn set_service_rpc(arg: crate::rpc_service::Service)
     crate::service_rpc::init(arg);
 }
 
+#[inline]
+pub unsafe fn get_service_rpc_ref() -> &'static crate::rpc_service::Service {
+    crate::service_rpc::get_ref()
+}
+
 /// A pool of connected RPC clients
 pub mod rpc_caller_pool;
 
@@ -326,6 +386,11 @@ pub unsafe fn set_rpc_caller_pool(arg: crate::rpc_caller_pool::CallerPool<'stati
     crate::service_caller_pool::init(arg);
 }
 
+/// Detect the down machines by the heartbeats of the callers
+pub mod peer_monitor;
+
+declare_global!(peer_monitor_service, crate::peer_monitor::PeerMonitor);
+
 #[cfg(feature = "use_rc")]
 /// A pool of rc connection
 pub mod rc_conn_pool;
@@ -385,6 +450,11 @@ pub unsafe fn get_dc_pool_service_mut() -> &'static mut crate::dc_pool::DCPool {
     crate::dc_pool_service::get_mut()
 }
 
+#[inline]
+pub unsafe fn get_accessinfo_service_ref() -> &'static crate::dc_pool::AccessInfoPool {
+    crate::access_info_service::get_ref()
+}
+
 #[inline]
 pub unsafe fn get_accessinfo_service_mut() -> &'static mut crate::dc_pool::AccessInfoPool {
     crate::access_info_service::get_mut()
@@ -410,29 +480,37 @@ pub unsafe fn get_sps_mut() -> &'static mut crate::shadow_process_service::Shado
     crate::sp_service::get_mut()
 }
 
-declare_global!(mem_pool, crate::mem_pools::MemPool);
+// The memory pool and the page table cache are protected by locks,
+// because the shrinker may reclaim them concurrently
+type MemPool = lock_bundler::BoxedLockBundler<crate::mem_pools::MemPool>;
+type PageTableCache = lock_bundler::BoxedLockBundler<crate::remote_pt_cache::RemotePageTableCache>;
 
-#[inline]
-pub unsafe fn get_mem_pool_ref() -> &'static crate::mem_pools::MemPool {
-    crate::mem_pool::get_ref()
-}
+declare_global!(mem_pool, crate::MemPool);
 
 #[inline]
-pub unsafe fn get_mem_pool_mut() -> &'static mut crate::mem_pools::MemPool {
-    crate::mem_pool::get_mut()
+pub unsafe fn get_mem_pool_ref() -> &'static crate::MemPool {
+    crate::mem_pool::get_ref()
 }
 
-// TODO: need add locks
-declare_global!(global_pt_cache, crate::remote_pt_cache::RemotePageTableCache);
+declare_global!(global_pt_cache, crate::PageTableCache);
 
 #[inline]
-pub unsafe fn get_pt_cache_ref() -> &'static crate::remote_pt_cache::RemotePageTableCache {
+pub unsafe fn get_pt_cache_ref() -> &'static crate::PageTableCache {
     crate::global_pt_cache::get_ref()
 }
 
+#[cfg(feature = "page-sharing")]
+type SharedPageCache = lock_bundler::BoxedLockBundler<crate::shared_page_cache::SharedPageCache>;
+
+#[cfg(feature = "page-sharing")]
+declare_global!(global_shared_page_cache, crate::SharedPageCache);
+
+#[cfg(feature = "page-sharing")]
 #[inline]
-pub unsafe fn get_pt_cache_mut() -> &'static mut crate::remote_pt_cache::RemotePageTableCache {
-    crate::global_pt_cache::get_mut()
+/// The cache is concurrently accessed by the page faults of all the children,
+/// so it is protected by a lock
+pub unsafe fn get_shared_page_cache_ref() -> &'static crate::SharedPageCache {
+    crate::global_shared_page_cache::get_ref()
 }
 
 pub mod core_syscall_handler;
@@ -453,3 +531,22 @@ pub mod prefetcher;
 pub mod lock_bundler;
 
 pub mod remote_pt_cache;
+
+#[cfg(feature = "page-sharing")]
+pub mod shared_page_cache;
+
+pub mod push_resume;
+
+/// Writes the hot pages of the accepted pushes, see `push_resume::Pusher`
+declare_global!(push_service, crate::push_resume::Pusher);
+
+/// The push regions of the children waiting to be freed, see `push_resume::AbandonedRegions`
+#[cfg(feature = "push-resume")]
+declare_global!(abandoned_push_regions, crate::push_resume::AbandonedRegions);
+
+#[cfg(feature = "eager-resume")]
+pub mod eager_resume;
+
+pub mod shrinker;
+
+declare_global!(mitosis_shrinker, crate::shrinker::MitosisShrinker);
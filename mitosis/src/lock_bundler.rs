@@ -1,24 +1,32 @@
-use crate::linux_kernel_module::mutex::LinuxMutex;
 use alloc::boxed::Box;
-use rust_kernel_linux_util::linux_kernel_module::sync::Mutex;
+use core::cell::UnsafeCell;
+
+use crate::bindings::{
+    mutex, pmem_mutex_init, pmem_mutex_lock, pmem_mutex_trylock, pmem_mutex_unlock,
+};
 
 pub type BoxedLockBundler<T> = Box<LockBundler<T>>;
 
-/// A simple wrapper over LinuxMutex to simplifiy creation
+/// A simple wrapper over the kernel mutex to simplifiy creation
 pub struct LockBundler<T> {
-    inner: LinuxMutex<T>,
+    lock: UnsafeCell<mutex>,
+    inner: UnsafeCell<T>,
 }
 
+unsafe impl<T: Send> Send for LockBundler<T> {}
+unsafe impl<T: Send> Sync for LockBundler<T> {}
+
 impl<T> LockBundler<T> {
-    // `LinuxMutex` is wrapped in a `Box` because it is a self-referening struct that 
+    // The kernel mutex is wrapped in a `Box` because it is a self-referening struct that
     // cannot be moved.
     #[inline]
     pub fn new(inner: T) -> Box<Self> {
         let res = Box::new(Self {
-            inner: LinuxMutex::new(inner),
+            lock: UnsafeCell::new(unsafe { core::mem::zeroed() }),
+            inner: UnsafeCell::new(inner),
         });
 
-        res.inner.init();
+        unsafe { pmem_mutex_init(res.lock.get()) };
         res
     }
 }
@@ -27,6 +35,27 @@ impl<T> LockBundler<T> {
 impl<'a, T: 'a> LockBundler<T> {
     #[inline]
     pub fn lock<R>(&self, f: impl FnOnce(&'a mut T) -> R) -> R {
-        self.inner.lock_f(f)
+        unsafe {
+            pmem_mutex_lock(self.lock.get());
+            let res = f(&mut *self.inner.get());
+            pmem_mutex_unlock(self.lock.get());
+            res
+        }
+    }
+
+    /// Return None if the lock is held, instead of waiting for it.
+    ///
+    /// This is necessary for the callers that may re-enter the critical section
+    /// on the same thread, e.g., the shrinker called by an allocation with the lock held.
+    #[inline]
+    pub fn try_lock<R>(&self, f: impl FnOnce(&'a mut T) -> R) -> Option<R> {
+        unsafe {
+            if pmem_mutex_trylock(self.lock.get()) == 0 {
+                return None;
+            }
+            let res = f(&mut *self.inner.get());
+            pmem_mutex_unlock(self.lock.get());
+            Some(res)
+        }
     }
 }
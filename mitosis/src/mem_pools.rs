@@ -54,4 +54,19 @@ impl MemPool {
     pub fn capacity(&self) -> usize {
         self.capacity
     }
+
+    /// The buffers that can be released under memory pressure.
+    /// We keep half of the capacity, so the next prepare is unlikely to
+    /// pay for refilling the whole pool.
+    #[inline]
+    pub fn surplus(&self) -> usize {
+        self.len().saturating_sub(self.capacity / 2)
+    }
+
+    /// Release at most `num` surplus buffers, return the number of released ones
+    pub fn shrink(&mut self, num: usize) -> usize {
+        let num = core::cmp::min(num, self.surplus());
+        self.pool.truncate(self.len() - num);
+        num
+    }
 }
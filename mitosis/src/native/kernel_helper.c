@@ -7,6 +7,16 @@
 #include <linux/ptrace.h>
 #include <linux/cpumask.h>
 #include <linux/smp.h>
+#include <linux/highmem.h>
+#include <linux/fs.h>
+#include <linux/fdtable.h>
+#include <linux/cred.h>
+#include <linux/capability.h>
+#include <linux/fs_struct.h>
+#include <linux/sched/signal.h>
+#include <linux/string.h>
+#include <linux/delay.h>
+#include <linux/random.h>
 
 struct thread_info *
 pmem_get_current_thread_info(void)
@@ -280,12 +290,34 @@ pmem_put_cpu(void)
   return 0;
 }
 
+void pmem_sleep_usecs(unsigned long usecs)
+{
+  usleep_range(usecs, usecs + usecs / 2);
+}
+
+unsigned long long pmem_get_random_u64(void)
+{
+  unsigned long long res;
+  get_random_bytes(&res, sizeof(res));
+  return res;
+}
+
+void pmem_cond_resched(void)
+{
+  cond_resched();
+}
+
 unsigned int
 pmem_filemap_fault(struct vm_fault *vmf)
 {
   return filemap_fault(vmf);
 }
 
+void pmem_fault_release_mmap_sem(struct vm_fault *vmf)
+{
+  up_read(&vmf->vma->vm_mm->mmap_sem);
+}
+
 // file related
 #include <linux/file.h>
 
@@ -354,6 +386,53 @@ pmem_pte_to_page(pte_t *pte)
   return pte_page(*pte);
 }
 
+// Whether the page is the kernel's shared zero page, or its content is all zero
+unsigned int pmem_check_page_zero(struct page *page)
+{
+  unsigned int res;
+  void *va;
+
+  if (is_zero_pfn(page_to_pfn(page)))
+    return 1;
+
+  va = kmap_atomic(page);
+  res = memchr_inv(va, 0, PAGE_SIZE) == NULL;
+  kunmap_atomic(va);
+  return res;
+}
+
+// shrinker related
+#include <linux/slab.h>
+
+// The shrinker is allocated here, so the rust side only holds an opaque pointer
+struct shrinker *
+pmem_register_shrinker(unsigned long (*count)(struct shrinker *, struct shrink_control *),
+                       unsigned long (*scan)(struct shrinker *, struct shrink_control *))
+{
+  struct shrinker *s = kzalloc(sizeof(struct shrinker), GFP_KERNEL);
+  if (!s)
+    return NULL;
+
+  s->count_objects = count;
+  s->scan_objects = scan;
+  s->seeks = DEFAULT_SEEKS;
+
+  if (register_shrinker(s))
+  {
+    printk(KERN_ERR "kernel-helper error: "
+                    "failed to register the shrinker\n");
+    kfree(s);
+    return NULL;
+  }
+  return s;
+}
+
+void pmem_unregister_shrinker(struct shrinker *s)
+{
+  unregister_shrinker(s);
+  kfree(s);
+}
+
 // Credits:
 // From https://stackoverflow.com/questions/32175346/how-i-get-absolute-path-in-kernel-space-from-file-descriptor
 void print_file_path(struct file *file)
@@ -370,4 +449,426 @@ void print_file_path(struct file *file)
   printk("path: %s\n", path);
 out:
   free_page((unsigned long)tmp);
-}
\ No newline at end of file
+}
+void pmem_mm_write_lock(struct mm_struct *mm)
+{
+  down_write(&mm->mmap_sem);
+}
+
+void pmem_mm_write_unlock(struct mm_struct *mm)
+{
+  up_write(&mm->mmap_sem);
+}
+
+int pmem_get_mm_exe_path(struct mm_struct *mm, char *buf, int len)
+{
+  struct file *exe_file = get_mm_exe_file(mm);
+  char *path;
+  int ret = 0;
+
+  if (!exe_file)
+    return -ENOENT;
+
+  path = d_path(&exe_file->f_path, buf, len);
+  if (IS_ERR(path))
+    ret = PTR_ERR(path);
+  else
+    // d_path fills the buffer from its end
+    memmove(buf, path, strlen(path) + 1);
+
+  fput(exe_file);
+  return ret;
+}
+
+int pmem_set_mm_exe_path(struct mm_struct *mm, const char *path)
+{
+  struct file *old_exe;
+  // the reference is moved to the mm
+  struct file *new_exe = filp_open(path, O_RDONLY, 0);
+  if (IS_ERR(new_exe))
+    return PTR_ERR(new_exe);
+
+  // set_mm_exe_file is not exported, so do it the same way
+  down_write(&mm->mmap_sem);
+  old_exe = rcu_dereference_protected(mm->exe_file, 1);
+  rcu_assign_pointer(mm->exe_file, new_exe);
+  up_write(&mm->mmap_sem);
+
+  if (old_exe)
+    fput(old_exe);
+  return 0;
+}
+
+const char *pmem_get_vma_special_name(struct vm_area_struct *vma)
+{
+  if (vma->vm_ops && vma->vm_ops->name)
+    return vma->vm_ops->name(vma);
+  return NULL;
+}
+
+unsigned long pmem_get_mm_vdso(struct mm_struct *mm)
+{
+  return (unsigned long)mm->context.vdso;
+}
+
+long pmem_arch_map_vdso(unsigned long addr)
+{
+  return pmem_do_arch_prctl_64(current, ARCH_MAP_VDSO_64, addr);
+}
+
+unsigned int pmem_get_max_fds(void)
+{
+  unsigned int res;
+  rcu_read_lock();
+  res = files_fdtable(current->files)->max_fds;
+  rcu_read_unlock();
+  return res;
+}
+
+int pmem_get_fd_info(unsigned int fd,
+                     char *path,
+                     int len,
+                     unsigned int *flags,
+                     long long *pos,
+                     unsigned int *mode)
+{
+  char *p;
+  int ret = 0;
+  struct file *f = fget_raw(fd);
+  if (!f)
+    return -EBADF;
+
+  p = d_path(&f->f_path, path, len);
+  if (IS_ERR(p))
+    ret = PTR_ERR(p);
+  else
+    memmove(path, p, strlen(p) + 1);
+
+  *flags = f->f_flags;
+  *pos = f->f_pos;
+  *mode = file_inode(f)->i_mode;
+
+  // the close-on-exec flag is kept by the fd table
+  rcu_read_lock();
+  if (close_on_exec(fd, files_fdtable(current->files)))
+    *flags |= O_CLOEXEC;
+  rcu_read_unlock();
+
+  fput(f);
+  return ret;
+}
+
+unsigned int pmem_fd_is_file(unsigned int fd, struct file *file)
+{
+  unsigned int res;
+  struct file *f = fget_raw(fd);
+  res = (f == file);
+  if (f)
+    fput(f);
+  return res;
+}
+
+long pmem_strncpy_from_user(char *dst, const char *src, long count)
+{
+  return strncpy_from_user(dst, (const char __user *)src, count);
+}
+
+int pmem_install_fd_at(unsigned int fd, struct file *file, unsigned int flags)
+{
+  static int (*k_replace_fd)(unsigned fd, struct file * file, unsigned flags) = NULL;
+  int ret;
+  if (!k_replace_fd)
+    k_replace_fd = (void *)kallsyms_lookup_name("replace_fd");
+  if (!k_replace_fd)
+  {
+    printk(KERN_ERR "kernel-helper error: "
+                    "can't find kernel function replace_fd\n");
+    return -ENOSYS;
+  }
+  // the fd table takes its own reference
+  ret = (*k_replace_fd)(fd, file, flags & O_CLOEXEC);
+  return ret < 0 ? ret : 0;
+}
+
+int pmem_open_fd_at(unsigned int fd,
+                    const char *path,
+                    unsigned int flags,
+                    umode_t mode,
+                    long long pos)
+{
+  int ret;
+  struct file *f = filp_open(path, flags & ~O_CLOEXEC, mode);
+  if (IS_ERR(f))
+    return PTR_ERR(f);
+
+  if (pos > 0 && (f->f_mode & FMODE_LSEEK))
+  {
+    loff_t res = vfs_llseek(f, pos, SEEK_SET);
+    if (res < 0)
+    {
+      fput(f);
+      return res;
+    }
+  }
+
+  ret = pmem_install_fd_at(fd, f, flags);
+  fput(f);
+  return ret;
+}
+
+static u64 pmem_cap_to_u64(kernel_cap_t cap)
+{
+  return ((u64)cap.cap[1] << 32) | cap.cap[0];
+}
+
+static kernel_cap_t pmem_u64_to_cap(u64 v)
+{
+  kernel_cap_t cap = CAP_EMPTY_SET;
+  cap.cap[0] = (u32)v;
+  cap.cap[1] = (u32)(v >> 32);
+  return cap;
+}
+
+void pmem_get_task_state(struct pmem_task_state *state)
+{
+  int i;
+  const struct cred *cred = current_cred();
+  struct sighand_struct *sighand = current->sighand;
+
+  spin_lock_irq(&sighand->siglock);
+  for (i = 0; i < _NSIG; ++i)
+  {
+    struct sigaction *sa = &sighand->action[i].sa;
+    state->actions[i].handler = (unsigned long)sa->sa_handler;
+    state->actions[i].flags = sa->sa_flags;
+#ifdef __ARCH_HAS_SA_RESTORER
+    state->actions[i].restorer = (unsigned long)sa->sa_restorer;
+#endif
+    state->actions[i].mask = sa->sa_mask.sig[0];
+  }
+  state->blocked = current->blocked.sig[0];
+  spin_unlock_irq(&sighand->siglock);
+
+  state->altstack_sp = current->sas_ss_sp;
+  state->altstack_size = current->sas_ss_size;
+  state->altstack_flags = current->sas_ss_flags;
+
+  task_lock(current->group_leader);
+  for (i = 0; i < RLIM_NLIMITS; ++i)
+  {
+    state->rlimits[i].cur = current->signal->rlim[i].rlim_cur;
+    state->rlimits[i].max = current->signal->rlim[i].rlim_max;
+  }
+  task_unlock(current->group_leader);
+
+  state->umask = current_umask();
+
+  state->uid = from_kuid(&init_user_ns, cred->uid);
+  state->gid = from_kgid(&init_user_ns, cred->gid);
+  state->euid = from_kuid(&init_user_ns, cred->euid);
+  state->egid = from_kgid(&init_user_ns, cred->egid);
+  state->suid = from_kuid(&init_user_ns, cred->suid);
+  state->sgid = from_kgid(&init_user_ns, cred->sgid);
+  state->fsuid = from_kuid(&init_user_ns, cred->fsuid);
+  state->fsgid = from_kgid(&init_user_ns, cred->fsgid);
+
+  state->cap_inheritable = pmem_cap_to_u64(cred->cap_inheritable);
+  state->cap_permitted = pmem_cap_to_u64(cred->cap_permitted);
+  state->cap_effective = pmem_cap_to_u64(cred->cap_effective);
+  state->cap_bset = pmem_cap_to_u64(cred->cap_bset);
+  state->cap_ambient = pmem_cap_to_u64(cred->cap_ambient);
+}
+
+// an unprivileged task can only switch among its real, effective and saved ids
+static bool pmem_uid_allowed(const struct cred *cred, unsigned int id)
+{
+  kuid_t kid = make_kuid(&init_user_ns, id);
+  return uid_valid(kid) &&
+         (uid_eq(kid, cred->uid) || uid_eq(kid, cred->euid) || uid_eq(kid, cred->suid));
+}
+
+static bool pmem_gid_allowed(const struct cred *cred, unsigned int id)
+{
+  kgid_t kid = make_kgid(&init_user_ns, id);
+  return gid_valid(kid) &&
+         (gid_eq(kid, cred->gid) || gid_eq(kid, cred->egid) || gid_eq(kid, cred->sgid));
+}
+
+int pmem_check_task_creds(const struct pmem_task_state *state)
+{
+  const struct cred *cred = current_cred();
+
+  if (!capable(CAP_SETUID) &&
+      !(pmem_uid_allowed(cred, state->uid) && pmem_uid_allowed(cred, state->euid) &&
+        pmem_uid_allowed(cred, state->suid) && pmem_uid_allowed(cred, state->fsuid)))
+    return -EPERM;
+
+  if (!capable(CAP_SETGID) &&
+      !(pmem_gid_allowed(cred, state->gid) && pmem_gid_allowed(cred, state->egid) &&
+        pmem_gid_allowed(cred, state->sgid) && pmem_gid_allowed(cred, state->fsgid)))
+    return -EPERM;
+
+  // the capabilities can never be raised, like capset()
+  if (!cap_issubset(pmem_u64_to_cap(state->cap_permitted), cred->cap_permitted) ||
+      !cap_issubset(pmem_u64_to_cap(state->cap_bset), cred->cap_bset))
+    return -EPERM;
+
+  return 0;
+}
+
+int pmem_capable_admin(void)
+{
+  return capable(CAP_SYS_ADMIN) ? 1 : 0;
+}
+
+int pmem_set_task_state(const struct pmem_task_state *state)
+{
+  int i, clamped = 0;
+  sigset_t blocked;
+  struct cred *new;
+  struct sighand_struct *sighand = current->sighand;
+
+  spin_lock_irq(&sighand->siglock);
+  for (i = 0; i < _NSIG; ++i)
+  {
+    struct sigaction *sa = &sighand->action[i].sa;
+    // SIGKILL and SIGSTOP cannot be caught
+    if (sig_kernel_only(i + 1))
+      continue;
+    sa->sa_handler = (__sighandler_t)state->actions[i].handler;
+    sa->sa_flags = state->actions[i].flags;
+#ifdef __ARCH_HAS_SA_RESTORER
+    sa->sa_restorer = (__sigrestore_t)state->actions[i].restorer;
+#endif
+    siginitset(&sa->sa_mask, state->actions[i].mask);
+    sigdelsetmask(&sa->sa_mask, sigmask(SIGKILL) | sigmask(SIGSTOP));
+  }
+  spin_unlock_irq(&sighand->siglock);
+
+  siginitset(&blocked, state->blocked);
+  sigdelsetmask(&blocked, sigmask(SIGKILL) | sigmask(SIGSTOP));
+  sigprocmask(SIG_SETMASK, &blocked, NULL);
+
+  current->sas_ss_sp = state->altstack_sp;
+  current->sas_ss_size = state->altstack_size;
+  current->sas_ss_flags = state->altstack_flags;
+
+  task_lock(current->group_leader);
+  for (i = 0; i < RLIM_NLIMITS; ++i)
+  {
+    struct rlimit *rlim = &current->signal->rlim[i];
+    struct rlimit new_rlim = {state->rlimits[i].cur, state->rlimits[i].max};
+
+    if (new_rlim.rlim_max > rlim->rlim_max && !capable(CAP_SYS_RESOURCE))
+    {
+      new_rlim.rlim_max = rlim->rlim_max;
+      clamped++;
+    }
+    new_rlim.rlim_cur = min(new_rlim.rlim_cur, new_rlim.rlim_max);
+    *rlim = new_rlim;
+  }
+  task_unlock(current->group_leader);
+
+  xchg(&current->fs->umask, state->umask & S_IRWXUGO);
+
+  new = prepare_creds();
+  if (!new)
+    return -ENOMEM;
+  new->uid = make_kuid(&init_user_ns, state->uid);
+  new->gid = make_kgid(&init_user_ns, state->gid);
+  new->euid = make_kuid(&init_user_ns, state->euid);
+  new->egid = make_kgid(&init_user_ns, state->egid);
+  new->suid = make_kuid(&init_user_ns, state->suid);
+  new->sgid = make_kgid(&init_user_ns, state->sgid);
+  new->fsuid = make_kuid(&init_user_ns, state->fsuid);
+  new->fsgid = make_kgid(&init_user_ns, state->fsgid);
+
+  new->cap_permitted = pmem_u64_to_cap(state->cap_permitted);
+  new->cap_bset = pmem_u64_to_cap(state->cap_bset);
+  new->cap_inheritable = cap_intersect(pmem_u64_to_cap(state->cap_inheritable), new->cap_permitted);
+  new->cap_effective = cap_intersect(pmem_u64_to_cap(state->cap_effective), new->cap_permitted);
+  new->cap_ambient = cap_intersect(pmem_u64_to_cap(state->cap_ambient), new->cap_inheritable);
+  commit_creds(new);
+
+  return clamped;
+}
+
+void pmem_mutex_init(struct mutex *lock)
+{
+  mutex_init(lock);
+}
+
+void pmem_mutex_lock(struct mutex *lock)
+{
+  mutex_lock(lock);
+}
+
+int pmem_mutex_trylock(struct mutex *lock)
+{
+  return mutex_trylock(lock);
+}
+
+void pmem_mutex_unlock(struct mutex *lock)
+{
+  mutex_unlock(lock);
+}
+
+void pmem_sema_init(struct semaphore *sem, int val)
+{
+  sema_init(sem, val);
+}
+
+void pmem_sema_up(struct semaphore *sem)
+{
+  up(sem);
+}
+
+int pmem_sema_down_timeout(struct semaphore *sem, unsigned long usecs)
+{
+  return down_timeout(sem, usecs_to_jiffies(usecs));
+}
+
+#include <linux/kthread.h>
+#include <rdma/ib_verbs.h>
+
+static void pmem_cq_waiter_comp(struct ib_cq *cq, void *ctx)
+{
+  struct pmem_cq_waiter *w = ctx;
+
+  atomic_inc(&w->events);
+  wake_up_all(&w->wq);
+}
+
+void pmem_cq_waiter_init(struct pmem_cq_waiter *w)
+{
+  init_waitqueue_head(&w->wq);
+  atomic_set(&w->events, 0);
+}
+
+void pmem_cq_waiter_attach(struct pmem_cq_waiter *w, void *qp)
+{
+  struct ib_cq *cq = ((struct ib_qp *)qp)->recv_cq;
+
+  // the handler is only called once the CQ is armed
+  cq->cq_context = w;
+  smp_wmb();
+  cq->comp_handler = pmem_cq_waiter_comp;
+}
+
+int pmem_cq_waiter_arm(void *qp)
+{
+  return ib_req_notify_cq(((struct ib_qp *)qp)->recv_cq,
+                          IB_CQ_NEXT_COMP | IB_CQ_REPORT_MISSED_EVENTS);
+}
+
+unsigned int pmem_cq_waiter_events(struct pmem_cq_waiter *w)
+{
+  return atomic_read(&w->events);
+}
+
+void pmem_cq_waiter_wait(struct pmem_cq_waiter *w, unsigned int seen, unsigned long usecs)
+{
+  wait_event_interruptible_timeout(w->wq,
+                                   atomic_read(&w->events) != seen || kthread_should_stop(),
+                                   usecs_to_jiffies(usecs));
+}
@@ -22,6 +22,9 @@
 #ifndef ARCH_SET_FS
 #define ARCH_SET_FS 0x1002
 #endif
+#ifndef ARCH_MAP_VDSO_64
+#define ARCH_MAP_VDSO_64 0x2003
+#endif
 
 // handles inline functions
 struct thread_info *
@@ -90,6 +93,12 @@ pmem_phys_to_virt(u64 p);
 
 unsigned int
 pmem_filemap_fault(struct vm_fault *vmf);
+
+/*
+ * Release the mmap_sem held by the fault handler, before returning VM_FAULT_RETRY.
+ * The vmf->vma must not be touched afterwards.
+ */
+void pmem_fault_release_mmap_sem(struct vm_fault *vmf);
 /*
  Page protection flags
  */
@@ -129,12 +138,19 @@ const unsigned long PMEM_PROT_GROWSDOWN = PROT_GROWSDOWN;
  Page fault flags
  */
 const unsigned int PMEM_VM_FAULT_SIGSEGV = VM_FAULT_SIGSEGV;
+const unsigned int PMEM_VM_FAULT_RETRY = VM_FAULT_RETRY;
+const unsigned int PMEM_VM_FAULT_NOPAGE = VM_FAULT_NOPAGE;
+const unsigned int PMEM_VM_FAULT_SIGBUS = VM_FAULT_SIGBUS;
+
+const unsigned int PMEM_FAULT_FLAG_ALLOW_RETRY = FAULT_FLAG_ALLOW_RETRY;
+const unsigned int PMEM_FAULT_FLAG_RETRY_NOWAIT = FAULT_FLAG_RETRY_NOWAIT;
 
 /*
  gfp related
  */
 const gfp_t PMEM_GFP_HIGHUSER = GFP_HIGHUSER;
 const gfp_t PMEM_GFP_USER = GFP_USER;
+const gfp_t PMEM_GFP_HIGHUSER_ZERO = GFP_HIGHUSER | __GFP_ZERO;
 
 /*
  About fs and gs
@@ -154,6 +170,23 @@ unsigned int pmem_get_current_cpu(void);
 unsigned int pmem_get_cpu(void);
 unsigned int pmem_put_cpu(void);
 
+/*
+ * Sleep for usecs to 1.5 * usecs, with the hrtimers.
+ * Must not be called in the atomic context.
+ */
+void pmem_sleep_usecs(unsigned long usecs);
+
+/*
+ * A random number from the kernel's entropy pool, e.g., to seed a per-boot counter.
+ */
+unsigned long long pmem_get_random_u64(void);
+
+/*
+ * Give up the CPU if a reschedule is due, e.g., in a polling loop.
+ * Must not be called in the atomic context.
+ */
+void pmem_cond_resched(void);
+
 /*
  file related
  */
@@ -164,6 +197,158 @@ void pmem_put_file(struct file *f);
 
 void print_file_path(struct file *file);
 
+/*
+ mm layout related
+ */
+#include <linux/auxvec.h>
+
+const unsigned int PMEM_AT_VECTOR_SIZE = AT_VECTOR_SIZE;
+const unsigned long PMEM_AT_NULL = AT_NULL;
+const unsigned long PMEM_AT_SYSINFO_EHDR = AT_SYSINFO_EHDR;
+
+void pmem_mm_write_lock(struct mm_struct *mm);
+void pmem_mm_write_unlock(struct mm_struct *mm);
+
+/*
+ * Copy the (NUL-terminated) path of the mm's executable into buf.
+ * Return 0 on success, or a negative errno.
+ */
+int pmem_get_mm_exe_path(struct mm_struct *mm, char *buf, int len);
+
+/*
+ * Replace the mm's executable with the file at path, e.g., for /proc/pid/exe.
+ * Return 0 on success, or a negative errno.
+ */
+int pmem_set_mm_exe_path(struct mm_struct *mm, const char *path);
+
+/*
+ * The name of a special mapping installed by the kernel, e.g., "[vdso]",
+ * or NULL if the VMA is not one.
+ */
+const char *pmem_get_vma_special_name(struct vm_area_struct *vma);
+
+// The start of the vDSO text of the mm, 0 if not mapped
+unsigned long pmem_get_mm_vdso(struct mm_struct *mm);
+
+/*
+ * Map the vDSO (and its vvar) of the local kernel into current task, starting at addr if
+ * the range is free. The current one must be unmapped first.
+ * Require CONFIG_CHECKPOINT_RESTORE, like arch_prctl(ARCH_MAP_VDSO_64).
+ */
+long pmem_arch_map_vdso(unsigned long addr);
+
+/*
+ file descriptor table related
+ */
+const unsigned int PMEM_S_IFMT = S_IFMT;
+const unsigned int PMEM_S_IFREG = S_IFREG;
+const unsigned int PMEM_S_IFIFO = S_IFIFO;
+const unsigned int PMEM_S_IFCHR = S_IFCHR;
+
+// The number of fd slots of current task
+unsigned int pmem_get_max_fds(void);
+
+/*
+ * Copy the NUL-terminated string from the user, see strncpy_from_user.
+ * Return its length, `count` if it is not terminated within `count` bytes,
+ * or -EFAULT.
+ */
+long pmem_strncpy_from_user(char *dst, const char *src, long count);
+
+/*
+ * Get the path, open flags (with O_CLOEXEC), offset and inode mode of the fd of current task.
+ * Return 0 on success, or a negative errno, e.g., -EBADF if the fd is not opened.
+ */
+int pmem_get_fd_info(unsigned int fd,
+                     char *path,
+                     int len,
+                     unsigned int *flags,
+                     long long *pos,
+                     unsigned int *mode);
+
+// Whether the fd of current task refers to the file
+unsigned int pmem_fd_is_file(unsigned int fd, struct file *file);
+
+/*
+ * Install the file at the fd of current task, replacing the existing one.
+ * Only the O_CLOEXEC of flags is used.
+ * Return 0 on success, or a negative errno.
+ */
+int pmem_install_fd_at(unsigned int fd, struct file *file, unsigned int flags);
+
+/*
+ * Open the path and install it at the fd of current task, replacing the existing one.
+ * The offset is restored if pos is positive and the file is seekable.
+ * Return 0 on success, or a negative errno.
+ */
+int pmem_open_fd_at(unsigned int fd,
+                    const char *path,
+                    unsigned int flags,
+                    umode_t mode,
+                    long long pos);
+
+/*
+ signal, rlimit and credential related
+ */
+#include <linux/signal.h>
+#include <linux/resource.h>
+
+struct pmem_sigaction
+{
+  unsigned long handler;
+  unsigned long flags;
+  unsigned long restorer;
+  unsigned long mask;
+};
+
+struct pmem_rlimit
+{
+  unsigned long cur;
+  unsigned long max;
+};
+
+/*
+ * The per-process states besides the memory and registers.
+ * The ids are the ones seen from the initial user namespace.
+ */
+struct pmem_task_state
+{
+  struct pmem_sigaction actions[_NSIG];
+  unsigned long blocked;
+
+  unsigned long altstack_sp;
+  unsigned long altstack_size;
+  unsigned int altstack_flags;
+
+  struct pmem_rlimit rlimits[RLIM_NLIMITS];
+  unsigned int umask;
+
+  unsigned int uid, gid, euid, egid, suid, sgid, fsuid, fsgid;
+  u64 cap_inheritable, cap_permitted, cap_effective, cap_bset, cap_ambient;
+};
+
+void pmem_get_task_state(struct pmem_task_state *state);
+
+/*
+ * Check whether current task is allowed to assume the credentials of the state,
+ * following the rules of setresuid(), setresgid() and capset().
+ * Return 0 if allowed, or -EPERM.
+ */
+int pmem_check_task_creds(const struct pmem_task_state *state);
+
+/*
+ * Whether current task may administrate the MITOSIS instance, e.g., its RPC handlers.
+ * Return 1 if allowed (with CAP_SYS_ADMIN), otherwise 0.
+ */
+int pmem_capable_admin(void);
+
+/*
+ * Restore the state into current task, the credentials must be checked first.
+ * The rlimits above the current hard limits are clamped without CAP_SYS_RESOURCE.
+ * Return the number of clamped rlimits, or a negative errno.
+ */
+int pmem_set_task_state(const struct pmem_task_state *state);
+
 /*
   page related
 */
@@ -181,4 +366,63 @@ unsigned int pmem_check_pte_write(pte_t *pte);
 struct page *
 pmem_pte_to_page(pte_t *pte);
 
+unsigned int pmem_check_page_zero(struct page *page);
+
+/*
+  mutex related, wrapped since some of them are macros (e.g., with lockdep)
+*/
+#include <linux/mutex.h>
+
+void pmem_mutex_init(struct mutex *lock);
+void pmem_mutex_lock(struct mutex *lock);
+// Return 1 if acquired, 0 if the mutex is held, even by current task
+int pmem_mutex_trylock(struct mutex *lock);
+void pmem_mutex_unlock(struct mutex *lock);
+
+/*
+  semaphore related
+*/
+#include <linux/semaphore.h>
+
+void pmem_sema_init(struct semaphore *sem, int val);
+void pmem_sema_up(struct semaphore *sem);
+// Return 0 if acquired, or a negative errno if timed out after about usecs
+int pmem_sema_down_timeout(struct semaphore *sem, unsigned long usecs);
+
+/*
+  completion notification related, to sleep until the receive CQs have completions.
+  The QPs are passed as `struct ib_qp *`.
+*/
+#include <linux/wait.h>
+
+struct pmem_cq_waiter {
+  wait_queue_head_t wq;
+  // increased on each completion event
+  atomic_t events;
+};
+
+void pmem_cq_waiter_init(struct pmem_cq_waiter *w);
+// Wake up the waiter on the completion events of the QP's receive CQ, which must not be armed yet.
+// The waiter must outlive the CQ.
+void pmem_cq_waiter_attach(struct pmem_cq_waiter *w, void *qp);
+// Arm the QP's receive CQ for its next completion.
+// Return 0 if armed, 1 if some completions may be missed (so the CQ should be polled), or a negative errno
+int pmem_cq_waiter_arm(void *qp);
+unsigned int pmem_cq_waiter_events(struct pmem_cq_waiter *w);
+// Sleep until an event after the seen ones, the kthread should stop, or about usecs passed
+void pmem_cq_waiter_wait(struct pmem_cq_waiter *w, unsigned int seen, unsigned long usecs);
+
+/*
+  shrinker related
+*/
+#include <linux/shrinker.h>
+
+const unsigned long PMEM_SHRINK_STOP = SHRINK_STOP;
+
+struct shrinker *
+pmem_register_shrinker(unsigned long (*count)(struct shrinker *, struct shrink_control *),
+                       unsigned long (*scan)(struct shrinker *, struct shrink_control *));
+
+void pmem_unregister_shrinker(struct shrinker *s);
+
 #endif
@@ -0,0 +1,213 @@
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use os_network::rpc::HeartbeatConfig;
+use rust_kernel_linux_util::kthread;
+use rust_kernel_linux_util::kthread::JoinHandler;
+use rust_kernel_linux_util::linux_kernel_module::c_types::{c_int, c_void};
+use rust_kernel_linux_util::timer::KTimer;
+
+#[allow(unused_imports)]
+use crate::linux_kernel_module;
+
+// how often the sessions are moved onto the endpoints added by the remote machines
+const REBALANCE_INTERVAL_USEC: i64 = 5_000_000; // 5s
+
+/// The peer monitor detects the down machines (e.g., crashed or rebooted)
+/// by the heartbeats of the RPC callers, and releases what depends on them:
+///  1. the RPC sessions (failing their in-flight calls) and RC connections
+///  2. the cached access info, remote page tables and shared pages
+///
+/// The in-flight page faults of the children resumed from a down machine fail (SIGBUS)
+/// instead of waiting for their reads to time out.
+///
+/// It also moves the idle sessions onto the endpoints added by the (alive) remote machines.
+pub struct PeerMonitor {
+    // indexed by the machine ID
+    down: Vec<AtomicBool>,
+    thread: Option<JoinHandler>,
+    heartbeat: HeartbeatConfig,
+}
+
+impl PeerMonitor {
+    pub fn new(config: &crate::Config) -> Self {
+        Self {
+            down: (0..config.max_cluster_size)
+                .map(|_| AtomicBool::new(false))
+                .collect(),
+            thread: None,
+            heartbeat: Default::default(),
+        }
+    }
+
+    /// Start the monitor thread.
+    /// It must be called after the monitor is installed as the global one.
+    pub fn start(&mut self) -> core::option::Option<()> {
+        let builder = kthread::Builder::new()
+            .set_name(alloc::format!("MITOSIS peer monitor"))
+            .set_parameter(core::ptr::null_mut());
+        self.thread = Some(builder.spawn(Self::worker).ok()?);
+        Some(())
+    }
+
+    #[inline]
+    pub fn is_down(&self, mac_id: usize) -> bool {
+        self.down
+            .get(mac_id)
+            .map(|d| d.load(Ordering::Acquire))
+            .unwrap_or(false)
+    }
+
+    /// The machine is connected again, e.g., after its reboot
+    #[inline]
+    pub fn mark_up(&self, mac_id: usize) {
+        if let Some(d) = self.down.get(mac_id) {
+            d.store(false, Ordering::Release);
+        }
+    }
+
+    /// Release everything that depends on the down machine
+    pub fn on_peer_down(&self, mac_id: usize) {
+        match self.down.get(mac_id) {
+            // handled by the others
+            Some(d) if d.swap(true, Ordering::AcqRel) => return,
+            Some(_) => {}
+            None => {
+                crate::log::error!("fatal: error machine ID: {}", mac_id);
+                return;
+            }
+        };
+
+        let len = unsafe { crate::get_rpc_caller_pool_ref().len() };
+        let mut failed = 0;
+        for idx in 0..len {
+            let session_id = crate::startup::calculate_session_id(mac_id, idx, len);
+            // the other callers may have not noticed it yet
+            failed += unsafe { crate::get_rpc_caller_pool_mut() }
+                .remove_session_at(idx, session_id)
+                .unwrap_or(0);
+
+            #[cfg(feature = "use_rc")]
+            if let Some(pool) = unsafe { crate::get_rc_conn_pool_mut(idx) } {
+                pool.remove_rc_connection(session_id);
+            }
+        }
+
+        unsafe { crate::get_accessinfo_service_ref().remove(mac_id) };
+        let evicted = unsafe { crate::get_pt_cache_ref() }.lock(|c| c.evict_machine(mac_id));
+        #[cfg(feature = "page-sharing")]
+        unsafe { crate::get_shared_page_cache_ref() }.lock(|c| c.evict_machine(mac_id));
+
+        crate::log::warn!(
+            "machine {} is down, fail {} in-flight calls, evict {} cached page tables",
+            mac_id,
+            failed,
+            evicted
+        );
+    }
+
+    /// Let the callers check their peers, and handle the down ones
+    fn check_callers(&self) {
+        let len = unsafe { crate::get_rpc_caller_pool_ref().len() };
+        let mut down: Vec<usize> = Vec::new();
+        for idx in 0..len {
+            let caller = match unsafe { crate::rpc_caller_pool::CallerPool::get_global_caller(idx) } {
+                Some(c) => c,
+                None => continue,
+            };
+            match caller.lock(|caller| caller.check_peers()) {
+                Ok(sessions) => {
+                    for session_id in sessions {
+                        // see `startup::calculate_session_id`
+                        let mac_id = session_id / len;
+                        if !down.contains(&mac_id) {
+                            down.push(mac_id);
+                        }
+                    }
+                }
+                Err(e) => crate::log::error!("caller {} failed to check its peers: {:?}", idx, e),
+            }
+        }
+
+        for mac_id in down {
+            self.on_peer_down(mac_id);
+        }
+    }
+
+    /// Move the idle sessions onto the endpoints added by the remote machines
+    fn rebalance_peers(&self) {
+        let pool = unsafe { crate::get_rpc_caller_pool_ref() };
+        let len = pool.len();
+        // the first caller is connected to all the machines, see `startup::probe_remote_rpc_end`
+        for session_id in pool.get_sessions_at(0) {
+            // see `startup::calculate_session_id`
+            let mac_id = session_id / len;
+            if self.is_down(mac_id) {
+                continue;
+            }
+            match crate::startup::rebalance_remote_rpc_end(mac_id) {
+                Some(0) => {}
+                Some(moved) => crate::log::info!(
+                    "move {} sessions onto the new endpoints of machine {}",
+                    moved,
+                    mac_id
+                ),
+                None => crate::log::warn!("failed to rebalance the sessions to machine {}", mac_id),
+            }
+        }
+    }
+
+    extern "C" fn worker(_ctx: *mut c_void) -> c_int {
+        let monitor = unsafe { crate::peer_monitor_service::get_ref() };
+        let mut timer = KTimer::new();
+        let mut rebalance_timer = KTimer::new();
+
+        while !kthread::should_stop() {
+            if timer.get_passed_usec() < monitor.heartbeat.interval_usec {
+                kthread::yield_now();
+                continue;
+            }
+            timer.reset();
+            monitor.check_callers();
+
+            // the regions of the abandoned pushes, which the parents no longer write
+            #[cfg(feature = "push-resume")]
+            {
+                let freed = unsafe { crate::abandoned_push_regions::get_ref() }.reclaim();
+                if freed > 0 {
+                    crate::log::debug!("free {} abandoned push regions", freed);
+                }
+            }
+
+            if rebalance_timer.get_passed_usec() >= REBALANCE_INTERVAL_USEC {
+                rebalance_timer.reset();
+                monitor.rebalance_peers();
+            }
+        }
+        0
+    }
+}
+
+impl Drop for PeerMonitor {
+    fn drop(&mut self) {
+        self.thread.take().map(|handler| handler.join());
+    }
+}
+
+/// Whether the remote machine is known to be down
+#[inline]
+pub fn is_peer_down(mac_id: usize) -> bool {
+    // the monitor may be absent, e.g., before the RPC is initialized
+    match unsafe { crate::peer_monitor_service.as_ref() } {
+        Some(monitor) => monitor.is_down(mac_id),
+        None => false,
+    }
+}
+
+/// The remote machine is connected (again)
+#[inline]
+pub fn mark_peer_up(mac_id: usize) {
+    if let Some(monitor) = unsafe { crate::peer_monitor_service.as_ref() } {
+        monitor.mark_up(mac_id);
+    }
+}
@@ -3,7 +3,6 @@ use alloc::{collections::VecDeque, sync::Arc};
 #[allow(unused_imports)]
 use crate::{
     linux_kernel_module,
-    remote_mapping::{PageTable, PhysAddr},
     remote_paging::{AccessInfo},
 };
 use os_network::{Conn, KRdmaKit::{MemoryRegion, ControlpathError}, rdma::{payload::{RDMAOp, dc::DCReqPayload}, DCCreationMeta}};
@@ -15,7 +14,7 @@ use os_network::{
     Factory, Future,
 };
 
-use crate::remote_mapping::{PhysAddrBitFlag, RemotePageTableIter, K_MAGIC_IN_PREFETCH};
+use crate::remote_mapping::{PageEntry, PteSlot, PteState, RemotePage};
 
 use crate::bindings::page;
 use rust_kernel_rdma_base::bindings::*;
@@ -26,8 +25,7 @@ use super::Prefetch;
 /// Since I currently don't know how to do it right in rust
 /// I will come back to this issue later
 pub struct ReplyEntry {
-    pt: *mut PageTable, // page table to update
-    idx: usize,
+    slot: PteSlot, // page table entry to update
     user_page: *mut page, // user page to hold the requests
 }
 
@@ -36,9 +34,13 @@ pub struct DCAsyncPrefetcher {
     conn: DCConn,
     pending_queues: VecDeque<ReplyEntry>,
     access_info: AccessInfo,
+    // address of the entry -> the remote page it stored, until the page is taken by a fault
+    origins: hashbrown::HashMap<u64, RemotePage>,
+    // a read has failed, so the QP is in the error state
+    failed: bool,
 }
 
-type PrefetchReq = <RemotePageTableIter as Iterator>::Item;
+type PrefetchReq = PageEntry;
 
 impl DCAsyncPrefetcher {
     pub fn new(fact: &DCFactory, remote_info: AccessInfo) -> Result<Self, ControlpathError> {
@@ -52,20 +54,30 @@ impl DCAsyncPrefetcher {
             conn: conn,
             pending_queues: Default::default(),
             access_info: remote_info,
+            origins: Default::default(),
+            failed: false,
         })
     }
 
     /// Clean my prefetch requests
     /// This call is necessary to drain pending RDMA requests related to this QP.
     /// After call drain_conenctions, another container can use this QP for the prefetch. 
-    pub fn drain_connections(&mut self) -> Result<DCConn, <Self as Future>::Error> {
+    ///
+    /// Return None if any read has failed, since the QP is no longer usable.
+    pub fn drain_connections(&mut self) -> Option<DCConn> {
         while !self.pending_queues.is_empty() {
             // let pt = self.pending_queues.front().unwrap().pt;
             // let idx = self.pending_queues.front().unwrap().idx;
 
             // we don't need to clear this page now, 
             // because upon page table drop, we will free the pages
-            let page = self.poll()?;
+            let page = match self.poll() {
+                Ok(page) => page,
+                Err(e) => {
+                    crate::log::error!("failed to drain the prefetcher: {:?}", e);
+                    return None;
+                }
+            };
             match page {
                 Async::Ready(_p) => {
                     //unsafe {                    
@@ -78,7 +90,10 @@ impl DCAsyncPrefetcher {
                 _ => {}
             };
         }
-        Ok(self.conn.clone())
+        if self.failed {
+            return None;
+        }
+        Some(self.conn.clone())
     }
 
     #[inline]
@@ -87,6 +102,8 @@ impl DCAsyncPrefetcher {
             conn: conn,
             pending_queues: Default::default(),
             access_info: access_info,
+            origins: Default::default(),
+            failed: false,
         }
     }
 
@@ -96,87 +113,118 @@ impl DCAsyncPrefetcher {
     }
 
     /// Submit requests to the prefetcher executor, and execute
-    /// PhysAddr format:
-    /// - 0x00000000001 => during prefetch
-    /// - 0xdeadbeafde1 => prefetched
-    /// - 0xdeadbeafde0 => remote PA
-    ///
-    /// TODO:
-    /// Currently, we assume that the remote PA will not change.
-    /// If this is not the case, we need to 2 bits to identify
-    /// whether the remote page is in the prefetch state.
+    /// The entry of a submitted page goes Remote -> InFlight -> Local,
+    /// see `PteState`.
     #[inline]
-    pub fn execute_reqs<P, const NUM : usize>(&mut self, mut iter: RemotePageTableIter, strategy: P)
+    pub fn execute_reqs<I, P, const NUM : usize>(&mut self, mut iter: I, strategy: P)
     where
+        I: Iterator<Item = PrefetchReq>,
         P: Prefetch<NUM, Item = PrefetchReq>,
     {
         let reqs = strategy.generate_request(&mut iter);
         for i in 0..reqs.len() {
-            // process this entry
-            let pte_p = reqs[i].page;
-            let pte_page = unsafe { &mut (*pte_p) };
-            let phyaddr = PhysAddr::new(pte_page[reqs[i].index]);
-
-            if phyaddr.bottom_bit() {
-                // this page has been prefetched, or at least in the list
-                continue;
-            }
+            self.submit(reqs[i].slot);
+        }
+    }
 
-            // 1. set the page table entry's bottom bit to 1 to prevent future prefetch
-            let remote_pa = phyaddr.real_addr();
-            // let remote_pa = pte_page[reqs[i].index];
-
-            // FIXME: this code assumes the remote PA never changes for this children
-            // To fix this, we need to instrumnet another bits in the address
-            pte_page[reqs[i].index] = K_MAGIC_IN_PREFETCH;
-
-            // 2. submit the RDMA request to read the page
-            let user_page =
-                unsafe { crate::bindings::pmem_alloc_page(crate::bindings::PMEM_GFP_HIGHUSER) };
-            let new_page_va = unsafe { crate::bindings::pmem_page_to_virt(user_page) as u64 };
-
-            // TODO: doorbell optimization
-            let payload = DCReqPayload::new(
-                unsafe { Arc::new(MemoryRegion::new_from_raw(self.conn.get_qp().ctx().clone(), new_page_va as _, 4096).unwrap()) },
-                0..4096,
-                true,
-                RDMAOp::READ,
-                self.access_info.rkey,
-                remote_pa,
-                self.access_info.access_handler.clone(),
-            );
-
-            // crate::log::debug!("post reqs {}", self.access_info.dct_num);
-            // send the requests
-            self.conn.post(&payload).unwrap(); // FIXME: it should never fail
-
-            // 3. record the prefetch information here
-            self.pending_queues.push_back(ReplyEntry {
-                pt: reqs[i].page,
-                idx: reqs[i].index,
-                user_page: user_page,
-            });
+    /// Read the page of the entry `slot` asynchronously.
+    /// The page is filled into the entry upon `poll`.
+    ///
+    /// Return false if the entry is already read (or being read),
+    /// or the QP has failed, so the page should be read synchronously.
+    #[inline]
+    pub fn submit(&mut self, slot: PteSlot) -> bool {
+        if self.failed {
+            return false;
         }
+
+        // 1. mark the entry in flight to prevent future prefetch.
+        // Only a remote page can be in flight, others are prefetched,
+        // at least in the list, or resolved locally.
+        let remote = match slot.transit(PteState::InFlight) {
+            Ok(PteState::Remote(remote)) => remote,
+            _ => return false,
+        };
+        let remote_pa = remote.pa;
+        self.origins.insert(slot.as_u64(), remote);
+
+        // 2. submit the RDMA request to read the page
+        let user_page =
+            unsafe { crate::bindings::pmem_alloc_page(crate::bindings::PMEM_GFP_HIGHUSER) };
+        let new_page_va = unsafe { crate::bindings::pmem_page_to_virt(user_page) as u64 };
+
+        // TODO: doorbell optimization
+        let payload = DCReqPayload::new(
+            unsafe { Arc::new(MemoryRegion::new_from_raw(self.conn.get_qp().ctx().clone(), new_page_va as _, 4096).unwrap()) },
+            0..4096,
+            true,
+            RDMAOp::READ,
+            self.access_info.rkey,
+            remote_pa,
+            self.access_info.access_handler.clone(),
+        );
+
+        // crate::log::debug!("post reqs {}", self.access_info.dct_num);
+        // send the requests
+        if let Err(e) = self.conn.post(&payload) {
+            crate::log::error!("failed to post the prefetch: {:?}", e);
+            self.failed = true;
+            unsafe { crate::bindings::pmem_free_page(user_page) };
+            self.origins.remove(&slot.as_u64());
+            slot.transit(PteState::Remote(remote))
+                .expect("only the prefetcher completes an in-flight entry");
+            return false;
+        }
+
+        // 3. record the prefetch information here
+        self.pending_queues.push_back(ReplyEntry {
+            slot: slot,
+            user_page: user_page,
+        });
+        true
+    }
+
+    /// The remote page stored in the entry before the page was prefetched.
+    ///
+    /// A fault that takes the prefetched page restores the entry with it,
+    /// so a concurrent fault on the same page reads it again instead of
+    /// finding an empty entry.
+    #[inline]
+    pub fn take_origin(&mut self, slot: PteSlot) -> Option<RemotePage> {
+        self.origins.remove(&slot.as_u64())
     }
 }
 
 impl Future for DCAsyncPrefetcher {
-    type Output = *mut page;
+    /// The prefetched page, or None if the read fails (e.g., the remote is down)
+    type Output = Option<*mut page>;
     type Error = <DCConn as Future>::Error;
 
+    /// Complete the oldest read.
+    /// A failed read restores its entry to the remote page, so the page is read again (or
+    /// the fault fails if the remote is down). The later reads are flushed with errors.
     fn poll(&mut self) -> Poll<Self::Output, Self::Error> {
         match self.conn.poll() {
-            Ok(Async::Ready(_wc)) => {
+            Ok(Async::Ready(wc)) => {
                 // must have one
                 let v = self.pending_queues.pop_front().unwrap();
-                let pte_p = v.pt;
-                let pte_page = unsafe { &mut (*pte_p) };
-
-                assert!(PhysAddr::new(v.user_page as _).bottom_bit() == false);
-                pte_page[v.idx] =
-                    PhysAddr::encode(v.user_page as u64, PhysAddrBitFlag::Prefetch as _);
+                if wc.status != ib_wc_status::IB_WC_SUCCESS {
+                    crate::log::error!("failed to prefetch the page, status {:?}", wc.status);
+                    self.failed = true;
+                    unsafe { crate::bindings::pmem_free_page(v.user_page) };
+                    let origin = self
+                        .take_origin(v.slot)
+                        .expect("the origin of an in-flight entry is recorded");
+                    v.slot
+                        .transit(PteState::Remote(origin))
+                        .expect("only the prefetcher completes an in-flight entry");
+                    return Ok(Async::Ready(None));
+                }
+                v.slot
+                    .transit(PteState::Local(v.user_page))
+                    .expect("only the prefetcher completes an in-flight entry");
 
-                return Ok(Async::Ready(v.user_page));
+                return Ok(Async::Ready(Some(v.user_page)));
             }
             Ok(Async::NotReady) => Ok(Async::NotReady),
             Err(e) => return Err(e),
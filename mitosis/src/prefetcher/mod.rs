@@ -12,9 +12,8 @@ pub const K_MAX_PREFETCH_NUM: usize = crate::PREFETCH_STEP;
 /// Check whether myself needs to be prefetch.
 ///
 /// This trait is used to define when we need prefetch a request.
-/// In MITOSIS, we physical address with the 1st bottom bit as 0
-/// need to be prefetch.
-/// see [`crate::remote_mapping::PhysAddr`],
+/// In MITOSIS, only the entries in the remote state need to be prefetch.
+/// see [`crate::remote_mapping::PteState`],
 /// [`crate::remote_mapping::PageEntry`]
 pub trait NeedPrefetch {
     /// Return whether myself needs prefetch
@@ -0,0 +1,556 @@
+use alloc::boxed::Box;
+use alloc::collections::VecDeque;
+#[cfg(feature = "push-resume")]
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+#[cfg(feature = "push-resume")]
+use os_network::rdma::dc::DCTarget;
+use os_network::rdma::dc::DCConn;
+use os_network::remote_memory::rdma::{DCKeys, DCRemoteDevice};
+use os_network::remote_memory::Device;
+use os_network::block_on;
+use os_network::timeout::TimeoutWRef;
+use rust_kernel_linux_util::kthread;
+use rust_kernel_linux_util::kthread::JoinHandler;
+use rust_kernel_linux_util::linux_kernel_module::c_types::{c_int, c_void};
+use rust_kernel_linux_util::timer::KTimer;
+
+#[cfg(feature = "push-resume")]
+use crate::bindings::page;
+#[cfg(feature = "push-resume")]
+use crate::descriptors::ChildDescriptor;
+use crate::descriptors::RDMADescriptor;
+use crate::kern_wrappers::mm::{PhyAddrType, VirtAddrType};
+use crate::kern_wrappers::sema::Semaphore;
+use crate::lock_bundler::{BoxedLockBundler, LockBundler};
+use crate::remote_paging::{AccessInfo, TIMEOUT_USEC};
+
+#[allow(unused_imports)]
+use crate::linux_kernel_module;
+
+/// Max pages pushed in one resume.
+/// Both the request and the reply must fit in one UD message.
+pub const MAX_PUSH_PAGES: usize = 128;
+
+/// Number of RDMA writes posted before waiting for their completions
+const PUSH_BATCH: usize = 16;
+
+/// The parent starts no write of a push after this long since it is accepted
+const PUSH_DEADLINE_USEC: i64 = 5_000_000; // 5s
+
+/// By then the parent's writes of a push are over: the deadline,
+/// and the timeouts of the last batch and the status write
+#[cfg(feature = "push-resume")]
+const ABANDON_GRACE_USEC: i64 = PUSH_DEADLINE_USEC + (PUSH_BATCH as i64 + 2) * TIMEOUT_USEC;
+
+/// No push is requested while this many regions are waiting to be freed,
+/// see `AbandonedRegions`
+#[cfg(feature = "push-resume")]
+const MAX_ABANDONED_REGIONS: usize = 64;
+
+/// The requests and the replies (~1KB) are kept off the kernel stack
+fn alloc_zeroed_box<T: os_network::serialize::Serialize>() -> Box<T> {
+    let layout = core::alloc::Layout::new::<T>();
+    unsafe {
+        let ptr = alloc::alloc::alloc_zeroed(layout) as *mut T;
+        if ptr.is_null() {
+            alloc::alloc::handle_alloc_error(layout);
+        }
+        Box::from_raw(ptr)
+    }
+}
+
+/// Like `Serialize::deserialize`, into a boxed message
+pub(crate) fn deserialize_boxed<T: os_network::serialize::Serialize>(
+    bytes: &os_network::bytes::BytesMut,
+) -> core::option::Option<Box<T>> {
+    let mut res = alloc_zeroed_box::<T>();
+    unsafe { bytes.memcpy_deserialize(&mut *res)? };
+    Some(res)
+}
+
+/// The child advertises its receive region with this request.
+#[derive(Clone)]
+pub(crate) struct PushPagesReq {
+    pub(crate) handler_id: usize,
+    // the secret of the image given at prepare, in plaintext, see `rpc_handlers::authorize`
+    pub(crate) secret: u64,
+    // the DCT of the child, through which the parent writes the pages
+    pub(crate) target: RDMADescriptor,
+    pub(crate) slot_num: usize,
+    // the physical addresses of the (empty) pages at the child
+    pub(crate) slots: [PhyAddrType; MAX_PUSH_PAGES],
+    // the physical address of the child's `PushStatus`
+    pub(crate) status: PhyAddrType,
+}
+
+impl os_network::serialize::Serialize for PushPagesReq {}
+
+impl PushPagesReq {
+    /// The payload of the call, see `os_network::rpc::Caller::call_async_bytes`
+    pub(crate) fn as_payload(&self) -> &[u8] {
+        unsafe {
+            core::slice::from_raw_parts(
+                self as *const Self as *const u8,
+                core::mem::size_of::<Self>(),
+            )
+        }
+    }
+}
+
+/// The parent replies as soon as the push is planned, and writes
+/// `addrs[i]` into `slots[i]` for `i < planned` in the background.
+#[derive(Clone)]
+pub(crate) struct PushPagesReply {
+    pub(crate) planned: usize,
+    pub(crate) addrs: [VirtAddrType; MAX_PUSH_PAGES],
+}
+
+impl os_network::serialize::Serialize for PushPagesReply {}
+
+
+/// The progress of a push, written by the parent into the child
+/// after the pages it counts have been written.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct PushStatus {
+    pub(crate) pushed: u64,
+    pub(crate) state: u64,
+}
+
+pub(crate) const PUSH_RUNNING: u64 = 0;
+/// No more writes to the child
+pub(crate) const PUSH_DONE: u64 = 1;
+/// Some writes timed out, so the unused slots may still be written
+pub(crate) const PUSH_ABORTED: u64 = 2;
+
+/// Parent side: plan the push of the hot pages of the image into the child's region,
+/// and hand the writes to the `Pusher`.
+pub(crate) fn accept_push(req: &PushPagesReq) -> Box<PushPagesReply> {
+    let mut reply = alloc_zeroed_box::<PushPagesReply>();
+
+    if !crate::rpc_handlers::authorize(req.handler_id, req.secret) {
+        crate::log::error!("not authorized to push the handler, id: {}", req.handler_id);
+        return reply;
+    }
+
+    let process_service = unsafe { crate::get_sps_ref() };
+    let (descriptor, generation) = match (
+        process_service.query_descriptor(req.handler_id),
+        process_service.query_generation(req.handler_id),
+    ) {
+        (Some(d), Some(g)) => (d, g),
+        _ => {
+            crate::log::error!("failed to find the handler to push, id: {}", req.handler_id);
+            return reply;
+        }
+    };
+    let access_info = match AccessInfo::new(&req.target) {
+        Some(a) => a,
+        None => {
+            crate::log::error!("failed to create the access info of the child");
+            return reply;
+        }
+    };
+
+    let pages = descriptor.hot_pages(core::cmp::min(req.slot_num, MAX_PUSH_PAGES));
+    for (i, (va, _)) in pages.iter().enumerate() {
+        reply.addrs[i] = *va;
+    }
+    reply.planned = pages.len();
+
+    unsafe { crate::push_service::get_ref() }.submit(PushJob {
+        handler_id: req.handler_id,
+        generation: generation,
+        access_info: access_info,
+        slots: req.slots[..pages.len()].to_vec(),
+        status: req.status,
+        pages: pages,
+        accepted: KTimer::new(),
+    });
+    reply
+}
+
+/// The writes of one push, in the order planned in the reply
+struct PushJob {
+    handler_id: usize,
+    generation: u64,
+    access_info: AccessInfo,
+    slots: Vec<PhyAddrType>,
+    status: PhyAddrType,
+    pages: Vec<(VirtAddrType, PhyAddrType)>,
+    // the child frees the region after a grace period, see `PUSH_DEADLINE_USEC`
+    accepted: KTimer,
+}
+
+impl PushJob {
+    /// The pages are freed once the image is unregistered
+    fn is_alive(&self) -> bool {
+        unsafe { crate::get_sps_ref() }.query_generation(self.handler_id) == Some(self.generation)
+    }
+
+    /// Write the pages batch by batch, each followed by the progress.
+    /// If the status cannot be written, the child never frees its region.
+    fn run(&self) {
+        let status_page = unsafe { crate::bindings::pmem_alloc_page(crate::bindings::PMEM_GFP_HIGHUSER) };
+        if status_page.is_null() {
+            // the child frees its region after the grace period
+            crate::log::error!("failed to allocate the push status of handler {}", self.handler_id);
+            return;
+        }
+        let status = unsafe { &mut *(crate::bindings::pmem_page_to_virt(status_page) as *mut PushStatus) };
+        let status_pa = unsafe { crate::bindings::pmem_page_to_phy(status_page) } as PhyAddrType;
+        *status = Default::default();
+
+        let pool_idx = unsafe { crate::bindings::pmem_get_current_cpu() } as usize;
+        let dc_qp = unsafe { crate::get_dc_pool_service_mut().get_dc_qp(pool_idx) }
+            .expect("failed to get DCQP");
+
+        for batch in self.pages.chunks(PUSH_BATCH) {
+            if !self.is_alive() {
+                break;
+            }
+            if self.accepted.get_passed_usec() > PUSH_DEADLINE_USEC {
+                status.state = PUSH_ABORTED;
+                break;
+            }
+            let start = status.pushed as usize;
+            let res = dc_qp.lock(|dc_qp| self.write_batch(dc_qp, start, batch));
+            match res {
+                Some(posted) => status.pushed += posted as u64,
+                None => {
+                    status.state = PUSH_ABORTED;
+                    break;
+                }
+            }
+            if (status.pushed as usize) < start + batch.len() || status.pushed as usize == self.pages.len() {
+                break;
+            }
+            if !dc_qp.lock(|dc_qp| self.write_status(dc_qp, status_pa)) {
+                status.state = PUSH_ABORTED;
+                break;
+            }
+        }
+
+        if status.state == PUSH_RUNNING {
+            status.state = PUSH_DONE;
+        }
+        if !dc_qp.lock(|dc_qp| self.write_status(dc_qp, status_pa)) {
+            crate::log::error!("failed to write the push status of handler {}", self.handler_id);
+        }
+        crate::log::debug!("pushed {} pages for handler {}", status.pushed, self.handler_id);
+        unsafe { crate::bindings::pmem_free_page(status_page) };
+    }
+
+    /// Return the number of the pages written, or None if some writes timed out
+    fn write_batch(
+        &self,
+        dc_qp: &DCConn,
+        start: usize,
+        batch: &[(VirtAddrType, PhyAddrType)],
+    ) -> Option<usize> {
+        let mut remote_device = DCRemoteDevice::new(dc_qp.clone());
+        let mut posted = 0;
+        for (i, (_, pa)) in batch.iter().enumerate() {
+            let mut local_pa = *pa;
+            let res = unsafe {
+                remote_device.write(
+                    &self.access_info.access_handler,
+                    &self.slots[start + i],
+                    &DCKeys::new(self.access_info.rkey),
+                    &mut local_pa,
+                    &4096,
+                )
+            };
+            if res.is_err() {
+                crate::log::error!("failed to post the push write {:?}", res);
+                break;
+            }
+            posted += 1;
+        }
+
+        // each write is signaled, so wait for all of them
+        let mut timeout_device = TimeoutWRef::new(&mut remote_device, TIMEOUT_USEC);
+        for _ in 0..posted {
+            if let Err(e) = block_on(&mut timeout_device) {
+                crate::log::error!("failed to push the pages, timeout: {}", e.is_elapsed());
+                return None;
+            }
+        }
+        Some(posted)
+    }
+
+    fn write_status(&self, dc_qp: &DCConn, status_pa: PhyAddrType) -> bool {
+        let mut remote_device = DCRemoteDevice::new(dc_qp.clone());
+        let mut local_pa = status_pa;
+        let res = unsafe {
+            remote_device.write(
+                &self.access_info.access_handler,
+                &self.status,
+                &DCKeys::new(self.access_info.rkey),
+                &mut local_pa,
+                &core::mem::size_of::<PushStatus>(),
+            )
+        };
+        if res.is_err() {
+            return false;
+        }
+        let mut timeout_device = TimeoutWRef::new(&mut remote_device, TIMEOUT_USEC);
+        block_on(&mut timeout_device).is_ok()
+    }
+}
+
+/// Parent side: the thread writing the pages of the accepted pushes,
+/// so that the RPC handlers reply without waiting for them.
+pub struct Pusher {
+    jobs: BoxedLockBundler<VecDeque<PushJob>>,
+    // counts the jobs in the queue
+    ready: Box<Semaphore>,
+    thread: Option<JoinHandler>,
+}
+
+impl Pusher {
+    // wake up to check whether the thread should stop
+    const IDLE_USEC: u64 = 100_000;
+
+    pub fn new() -> Self {
+        Self {
+            jobs: LockBundler::new(VecDeque::new()),
+            ready: Semaphore::new(0),
+            thread: None,
+        }
+    }
+
+    /// Start the pusher thread.
+    /// It must be called after the pusher is installed as the global one.
+    pub fn start(&mut self) -> core::option::Option<()> {
+        let builder = kthread::Builder::new()
+            .set_name(alloc::format!("MITOSIS pusher"))
+            .set_parameter(core::ptr::null_mut());
+        self.thread = Some(builder.spawn(Self::worker).ok()?);
+        Some(())
+    }
+
+    fn submit(&self, job: PushJob) {
+        self.jobs.lock(|jobs| jobs.push_back(job));
+        self.ready.up();
+    }
+
+    extern "C" fn worker(_ctx: *mut c_void) -> c_int {
+        let pusher = unsafe { crate::push_service::get_ref() };
+        while !kthread::should_stop() {
+            if !pusher.ready.down_timeout(Self::IDLE_USEC) {
+                continue;
+            }
+            if let Some(job) = pusher.jobs.lock(|jobs| jobs.pop_front()) {
+                job.run();
+            }
+        }
+        0
+    }
+}
+
+impl Drop for Pusher {
+    fn drop(&mut self) {
+        // the children of the dropped jobs never free their regions
+        self.thread.take().map(|handler| handler.join());
+    }
+}
+
+/// The pages of a region not installed yet, freed on drop
+#[cfg(feature = "push-resume")]
+struct RegionPages {
+    pages: Vec<*mut page>,
+    // receives the `PushStatus`
+    status: *mut page,
+    // hold the DCT until the parent finishes writing
+    _target: Arc<DCTarget>,
+    // since the push is requested
+    requested: KTimer,
+}
+
+#[cfg(feature = "push-resume")]
+impl Drop for RegionPages {
+    fn drop(&mut self) {
+        for page_p in self.pages.iter().filter(|p| !p.is_null()) {
+            unsafe { crate::bindings::pmem_free_page(*page_p) };
+        }
+        if !self.status.is_null() {
+            unsafe { crate::bindings::pmem_free_page(self.status) };
+        }
+    }
+}
+
+/// Child side: the regions dropped before their pushes are over.
+///
+/// The parent may still write them, so they are freed by the peer monitor
+/// after `ABANDON_GRACE_USEC` since their requests, see `reclaim`.
+#[cfg(feature = "push-resume")]
+pub struct AbandonedRegions {
+    regions: BoxedLockBundler<VecDeque<RegionPages>>,
+}
+
+#[cfg(feature = "push-resume")]
+impl AbandonedRegions {
+    pub fn new() -> Self {
+        Self {
+            regions: LockBundler::new(VecDeque::new()),
+        }
+    }
+
+    /// The bound is checked before a push is requested,
+    /// so it is exceeded by at most the pushes in flight
+    fn is_full(&self) -> bool {
+        self.regions.lock(|r| r.len() >= MAX_ABANDONED_REGIONS)
+    }
+
+    fn push(&self, region: RegionPages) {
+        self.regions.lock(|r| r.push_back(region));
+    }
+
+    /// Free the regions that the parents no longer write.
+    /// Return the number of them.
+    pub fn reclaim(&self) -> usize {
+        let expired: Vec<RegionPages> = self.regions.lock(|r| {
+            let (expired, kept) = r
+                .drain(..)
+                .partition(|region| region.requested.get_passed_usec() >= ABANDON_GRACE_USEC);
+            *r = kept;
+            expired
+        });
+        expired.len()
+    }
+}
+
+/// Child side: the local pages that receive the pushed pages.
+///
+/// The parent writes them in the background, and the child installs them
+/// as its `PushStatus` advances, see `install_ready`.
+/// The pages (and the DCT) must live until the push is done,
+/// so a region dropped before that is handed to the `AbandonedRegions`.
+#[cfg(feature = "push-resume")]
+pub(crate) struct PushRegion {
+    mem: RegionPages,
+    target_info: RDMADescriptor,
+    // the remote VAs of the pages, in the order they are written
+    plan: Vec<VirtAddrType>,
+    installed: usize,
+    done: bool,
+}
+
+#[cfg(feature = "push-resume")]
+impl PushRegion {
+    pub(crate) fn new(num: usize) -> Option<Self> {
+        if unsafe { crate::abandoned_push_regions::get_ref() }.is_full() {
+            crate::log::warn!("too many abandoned push regions, skip the push");
+            return None;
+        }
+        let (target, target_info) = RDMADescriptor::new_from_dc_target_pool()?;
+        let num = core::cmp::min(num, MAX_PUSH_PAGES);
+        let mut mem = RegionPages {
+            pages: Vec::with_capacity(num),
+            status: unsafe {
+                crate::bindings::pmem_alloc_page(crate::bindings::PMEM_GFP_HIGHUSER_ZERO)
+            },
+            _target: target,
+            requested: KTimer::new(),
+        };
+        for _ in 0..num {
+            mem.pages.push(unsafe {
+                crate::bindings::pmem_alloc_page(crate::bindings::PMEM_GFP_HIGHUSER)
+            });
+        }
+        // the allocated ones are freed on drop
+        if mem.status.is_null() || mem.pages.iter().any(|p| p.is_null()) {
+            crate::log::error!("failed to allocate the push region");
+            return None;
+        }
+        Some(Self {
+            mem,
+            target_info,
+            plan: Vec::new(),
+            installed: 0,
+            done: false,
+        })
+    }
+
+    pub(crate) fn gen_request(&self, handler_id: usize, secret: u64) -> Box<PushPagesReq> {
+        let mut req = alloc_zeroed_box::<PushPagesReq>();
+        for (i, page_p) in self.mem.pages.iter().enumerate() {
+            req.slots[i] = unsafe { crate::bindings::pmem_page_to_phy(*page_p) } as PhyAddrType;
+        }
+        req.handler_id = handler_id;
+        req.secret = secret;
+        req.target = self.target_info.clone();
+        req.slot_num = self.mem.pages.len();
+        req.status = unsafe { crate::bindings::pmem_page_to_phy(self.mem.status) } as PhyAddrType;
+        req
+    }
+
+    /// Record the plan of the parent.
+    /// Return false if nothing will be pushed, so the region can be dropped.
+    pub(crate) fn start(&mut self, reply: &PushPagesReply) -> bool {
+        let planned = core::cmp::min(reply.planned, self.mem.pages.len());
+        self.plan = reply.addrs[..planned].to_vec();
+        if planned == 0 {
+            self.done = true;
+        }
+        !self.done
+    }
+
+    /// The request may have never reached the parent, or been rejected.
+    pub(crate) fn cancel(mut self) {
+        self.done = true;
+    }
+
+    #[inline]
+    fn status(&self) -> PushStatus {
+        unsafe {
+            core::ptr::read_volatile(
+                crate::bindings::pmem_page_to_virt(self.mem.status) as *const PushStatus
+            )
+        }
+    }
+
+    /// Mark the newly pushed pages as present in the child's page table.
+    /// The installed pages are owned by the page table afterwards.
+    ///
+    /// Return true if the push is over, so the region can be dropped.
+    pub(crate) fn install_ready(&mut self, des: &ChildDescriptor) -> bool {
+        let status = self.status();
+        let pushed = core::cmp::min(status.pushed as usize, self.plan.len());
+        for i in self.installed..pushed {
+            if des.install_pushed_page(self.plan[i], self.mem.pages[i]) {
+                self.mem.pages[i] = core::ptr::null_mut();
+            }
+        }
+        self.installed = core::cmp::max(self.installed, pushed);
+
+        match status.state {
+            PUSH_RUNNING => false,
+            state => {
+                self.done = state == PUSH_DONE;
+                true
+            }
+        }
+    }
+}
+
+#[cfg(feature = "push-resume")]
+impl Drop for PushRegion {
+    fn drop(&mut self) {
+        if self.done {
+            // the pages are freed with `mem`
+            return;
+        }
+        // the parent may still write them
+        let mem = RegionPages {
+            pages: core::mem::take(&mut self.mem.pages),
+            status: core::mem::replace(&mut self.mem.status, core::ptr::null_mut()),
+            _target: self.mem._target.clone(),
+            requested: core::mem::replace(&mut self.mem.requested, KTimer::new()),
+        };
+        unsafe { crate::abandoned_push_regions::get_ref() }.push(mem);
+    }
+}
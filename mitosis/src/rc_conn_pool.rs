@@ -78,6 +78,15 @@ impl<'a> RCPool {
         ret
     }
 
+    /// Remove the connection of the session, e.g., its remote is down.
+    /// Return false if it is not connected.
+    pub fn remove_rc_connection(&'a mut self, session_id: usize) -> bool {
+        self.mutex.lock();
+        let ret = self.pool.remove(&session_id).is_some();
+        self.mutex.unlock();
+        ret
+    }
+
     pub fn create_rc_connection(
         &'a mut self,
         idx: usize,
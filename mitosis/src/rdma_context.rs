@@ -69,7 +69,13 @@ pub fn start_rdma(config: &crate::Config) -> core::option::Option<()> {
         crate::rdma_cm_service::init(servers);
     };
 
-    #[cfg(feature = "use_rc")]
+    // the RPC sessions over RC are connected through the same services
+    let use_rc = cfg!(feature = "use_rc")
+        || config.rpc_transport == os_network::rpc::impls::transport::RPCTransport::RC;
+    if !use_rc {
+        return Some(());
+    }
+
     unsafe {
         let mut rc_services = Vec::new();
         for i in 0..config.num_nics_used {
@@ -83,7 +89,6 @@ pub fn start_rdma(config: &crate::Config) -> core::option::Option<()> {
         crate::rc_service::init(rc_services);
     };
 
-    #[cfg(feature = "use_rc")]
     unsafe {
         let mut servers = Vec::new();
         for i in 0..config.num_nics_used {
@@ -104,9 +109,8 @@ pub fn start_rdma(config: &crate::Config) -> core::option::Option<()> {
 pub fn end_rdma() {
     // Note: the **order** of drop is very important here
     unsafe {
-        #[cfg(feature = "use_rc")]
+        // no-ops if the RC services are not started
         crate::rc_cm_service::drop();
-        #[cfg(feature = "use_rc")]
         crate::rc_service::drop();
         crate::rdma_cm_service::drop();
         crate::ud_service::drop();
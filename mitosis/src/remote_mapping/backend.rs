@@ -0,0 +1,165 @@
+use super::extent_table::{ExtentPageTable, ExtentPageTableIter};
+use super::page_structures::*;
+use super::page_table::{PageEntry, RemotePageTable, RemotePageTableIter};
+use super::pte::{PteSlot, PteState};
+
+#[allow(unused_imports)]
+use crate::linux_kernel_module;
+
+/// The data structure backing the page table of a child
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PageTableKind {
+    /// Four-level tables like x86, see `RemotePageTable`
+    Radix,
+    /// Sorted runs of consecutive pages, see `ExtentPageTable`
+    Extent,
+}
+
+impl Default for PageTableKind {
+    fn default() -> Self {
+        PageTableKind::Radix
+    }
+}
+
+/// The (remote) page table of a child, whose backend is selected per descriptor.
+///
+/// The fault, prefetch and cache code address the entries with `PteSlot`s,
+/// so they work on both backends.
+#[derive(Debug)]
+pub enum ChildPageTable {
+    Radix(RemotePageTable),
+    Extent(ExtentPageTable),
+}
+
+macro_rules! dispatch {
+    ($self:expr, $pt:ident => $e:expr) => {
+        match $self {
+            ChildPageTable::Radix($pt) => $e,
+            ChildPageTable::Extent($pt) => $e,
+        }
+    };
+}
+
+impl ChildPageTable {
+    /// Create an empty page table
+    pub fn new(kind: PageTableKind) -> Self {
+        match kind {
+            PageTableKind::Radix => ChildPageTable::Radix(RemotePageTable::new()),
+            PageTableKind::Extent => ChildPageTable::Extent(ExtentPageTable::new()),
+        }
+    }
+
+    pub fn kind(&self) -> PageTableKind {
+        match self {
+            ChildPageTable::Radix(_) => PageTableKind::Radix,
+            ChildPageTable::Extent(_) => PageTableKind::Extent,
+        }
+    }
+
+    #[inline]
+    pub fn copy(&self) -> Self {
+        match self {
+            ChildPageTable::Radix(pt) => ChildPageTable::Radix(pt.copy()),
+            ChildPageTable::Extent(pt) => ChildPageTable::Extent(pt.copy()),
+        }
+    }
+
+    /// Return the number of mapped PTEs in the page table
+    pub fn len(&self) -> usize {
+        dispatch!(self, pt => pt.len())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        dispatch!(self, pt => pt.is_empty())
+    }
+
+    /// Bytes used by the page table itself, not including the pages it points to
+    pub fn memory_usage(&self) -> usize {
+        dispatch!(self, pt => pt.memory_usage())
+    }
+
+    /// Called once all the pages of the descriptor are mapped
+    pub fn finish_mapping(&mut self) {
+        if let ChildPageTable::Extent(pt) = self {
+            pt.shrink_to_fit();
+        }
+    }
+
+    #[inline(always)]
+    pub fn translate(&self, addr: VirtAddr) -> core::option::Option<PhysAddr> {
+        dispatch!(self, pt => pt.translate(addr))
+    }
+
+    #[inline(always)]
+    pub fn lookup(&self, addr: VirtAddr) -> core::option::Option<PteState> {
+        dispatch!(self, pt => pt.lookup(addr))
+    }
+
+    #[inline(always)]
+    pub fn find_entry(&self, addr: VirtAddr) -> core::option::Option<PteSlot> {
+        dispatch!(self, pt => pt.find_entry(addr))
+    }
+
+    #[inline]
+    pub fn map(&mut self, addr: VirtAddr, phy: PhysAddr) -> core::option::Option<PhysAddr> {
+        dispatch!(self, pt => pt.map(addr, phy))
+    }
+
+    #[inline]
+    pub fn transit(&mut self, addr: VirtAddr, next: PteState) -> Result<PteState, PteState> {
+        dispatch!(self, pt => pt.transit(addr, next))
+    }
+
+    /// Iterate all the mapped entries
+    #[inline]
+    pub fn iter(&self) -> ChildPageTableIter {
+        match self {
+            ChildPageTable::Radix(pt) => {
+                ChildPageTableIter::Radix(unsafe { RemotePageTableIter::new(pt) })
+            }
+            ChildPageTable::Extent(pt) => ChildPageTableIter::Extent(pt.iter()),
+        }
+    }
+
+    /// Iterate the mapped entries after the one of $addr$, e.g., to find the pages to prefetch
+    #[inline]
+    pub fn iter_after(&self, addr: VirtAddr) -> ChildPageTableIter {
+        match self {
+            ChildPageTable::Radix(pt) => ChildPageTableIter::Radix(pt.iter_after(addr)),
+            ChildPageTable::Extent(pt) => ChildPageTableIter::Extent(pt.iter_after(addr)),
+        }
+    }
+
+    pub fn cached_page_cnt(&self) -> usize {
+        dispatch!(self, pt => pt.cached_page_cnt())
+    }
+
+    pub fn release_cached_pages(&mut self) -> usize {
+        dispatch!(self, pt => pt.release_cached_pages())
+    }
+}
+
+impl core::fmt::Display for ChildPageTable {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        dispatch!(self, pt => core::fmt::Display::fmt(pt, f))
+    }
+}
+
+/// Iterator over the mapped entries of a `ChildPageTable`
+pub enum ChildPageTableIter<'a> {
+    // None if the table is empty
+    Radix(Option<RemotePageTableIter>),
+    Extent(ExtentPageTableIter<'a>),
+}
+
+impl Iterator for ChildPageTableIter<'_> {
+    type Item = PageEntry;
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            ChildPageTableIter::Radix(iter) => iter.as_mut()?.next(),
+            ChildPageTableIter::Extent(iter) => iter.next(),
+        }
+    }
+}
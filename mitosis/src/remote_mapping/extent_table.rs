@@ -0,0 +1,292 @@
+use alloc::collections::btree_map::{self, BTreeMap};
+use alloc::vec::Vec;
+use core::ops::Bound::{Excluded, Unbounded};
+
+use crate::kern_wrappers::mm::VirtAddrType;
+
+use super::page_structures::*;
+use super::page_table::PageEntry;
+use super::pte::{drop_entry, PteSlot, PteState};
+
+#[allow(unused_imports)]
+use crate::linux_kernel_module;
+
+/// Max pages of an extent. Its entries fit in one page,
+/// so they never need a high-order allocation.
+pub const EXTENT_MAX_PAGES: usize = 4096 / core::mem::size_of::<PageTableEntry>();
+
+const PAGE_SHIFT: usize = 12;
+
+/// Entries of consecutive 4KB pages
+#[derive(Debug)]
+struct Extent {
+    entries: Vec<PageTableEntry>,
+}
+
+impl Extent {
+    #[inline]
+    fn contains(&self, idx: usize) -> bool {
+        idx < self.entries.len()
+    }
+
+    #[inline]
+    fn slot(&self, idx: usize) -> PteSlot {
+        unsafe { PteSlot::new(&self.entries[idx]) }
+    }
+
+    fn copy(&self) -> Self {
+        let mut entries = Vec::with_capacity(self.entries.len());
+        entries.extend((0..self.entries.len()).map(|i| self.slot(i).load()));
+        Self { entries }
+    }
+}
+
+impl Drop for Extent {
+    fn drop(&mut self) {
+        for entry in self.entries.iter() {
+            drop_entry(*entry);
+        }
+    }
+}
+
+/// Maps (remote) virtual pages to their entries with a sorted set of extents,
+/// each of which covers a run of mapped consecutive pages.
+///
+/// Compared with `RemotePageTable`, which allocates a 4KB table at every level
+/// of each touched region, it costs about 8 bytes per mapped page,
+/// so it suits sparse address spaces.
+/// The lookup costs O(log #extents), instead of four memory accesses.
+///
+/// Limitation:
+/// - The extents may reallocate their entries when a page is mapped,
+///   so the pages must be mapped before the slots are handed out, i.e.,
+///   before the child is resumed. The later transitions never add an entry.
+#[derive(Debug, Default)]
+pub struct ExtentPageTable {
+    // start address of the extent -> the extent
+    extents: BTreeMap<VirtAddrType, Extent>,
+
+    // number of mapped PTE in the page table
+    cnt: usize,
+}
+
+impl ExtentPageTable {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    #[inline]
+    pub fn copy(&self) -> Self {
+        Self {
+            extents: self.extents.iter().map(|(k, v)| (*k, v.copy())).collect(),
+            cnt: self.cnt,
+        }
+    }
+
+    /// Return the number of mapped PTEs in the page table
+    pub fn len(&self) -> usize {
+        self.cnt
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cnt == 0
+    }
+
+    /// Number of extents, the more the sparser
+    pub fn extent_count(&self) -> usize {
+        self.extents.len()
+    }
+
+    /// Bytes used by the extents, including an estimation of the tree nodes
+    pub fn memory_usage(&self) -> usize {
+        core::mem::size_of::<Self>()
+            + self
+                .extents
+                .values()
+                .map(|e| {
+                    core::mem::size_of::<(VirtAddrType, Extent)>()
+                        + e.entries.capacity() * core::mem::size_of::<PageTableEntry>()
+                })
+                .sum::<usize>()
+    }
+
+    /// Release the spare capacity of the extents, once all the pages are mapped
+    pub fn shrink_to_fit(&mut self) {
+        for extent in self.extents.values_mut() {
+            extent.entries.shrink_to_fit();
+        }
+    }
+
+    /// Find the extent containing $addr$, and the index of its entry
+    #[inline]
+    fn find(&self, addr: VirtAddr) -> core::option::Option<(&Extent, usize)> {
+        let va = addr.as_u64() as VirtAddrType;
+        let (start, extent) = self.extents.range(..=va).next_back()?;
+        let idx = ((va - start) >> PAGE_SHIFT) as usize;
+        if extent.contains(idx) {
+            Some((extent, idx))
+        } else {
+            None
+        }
+    }
+
+    /// Lookup the entry of the requested address, which may be empty
+    #[inline(always)]
+    pub fn find_entry(&self, addr: VirtAddr) -> core::option::Option<PteSlot> {
+        self.find(addr).map(|(extent, idx)| extent.slot(idx))
+    }
+
+    /// Lookup the physical address using the $addr$
+    #[inline(always)]
+    pub fn translate(&self, addr: VirtAddr) -> core::option::Option<PhysAddr> {
+        match self.find_entry(addr)?.load() {
+            0 => None,
+            entry => Some(PhysAddr::new(entry)),
+        }
+    }
+
+    /// Lookup the state of the entry of $addr$, None if it is not mapped
+    #[inline(always)]
+    pub fn lookup(&self, addr: VirtAddr) -> core::option::Option<PteState> {
+        match self.find_entry(addr)?.state() {
+            PteState::Empty => None,
+            state => Some(state),
+        }
+    }
+
+    /// Add a (addr, phy) mapping to the page table.
+    /// Return Some(value) if there is an existing mapping.
+    /// Return None means the map is successful.
+    pub fn map(&mut self, addr: VirtAddr, phy: PhysAddr) -> core::option::Option<PhysAddr> {
+        let va = addr.align_down(4096u64).as_u64() as VirtAddrType;
+
+        if let Some((start, extent)) = self.extents.range_mut(..=va).next_back() {
+            let idx = ((va - start) >> PAGE_SHIFT) as usize;
+            if extent.contains(idx) {
+                let res = extent.entries[idx];
+                if res != 0 {
+                    return Some(PhysAddr::new(res));
+                }
+                extent.entries[idx] = phy.as_u64();
+                self.cnt += 1;
+                return None;
+            }
+            // the pages are mapped in order, so the extent usually grows at the end
+            if idx == extent.entries.len() && idx < EXTENT_MAX_PAGES {
+                extent.entries.push(phy.as_u64());
+                self.cnt += 1;
+                return None;
+            }
+        }
+
+        let mut entries = Vec::new();
+        entries.push(phy.as_u64());
+        self.extents.insert(va, Extent { entries });
+        self.cnt += 1;
+        None
+    }
+
+    /// Move the entry of $addr$ to `next`, see `PteState::can_transit_to`.
+    ///
+    /// Return the previous state, or the current one if the transition is illegal.
+    #[inline]
+    pub fn transit(&mut self, addr: VirtAddr, next: PteState) -> Result<PteState, PteState> {
+        let prev = match self.find_entry(addr) {
+            Some(slot) => slot.transit(next)?,
+            None => {
+                if !PteState::Empty.can_transit_to(&next) {
+                    return Err(PteState::Empty);
+                }
+                self.map(addr, PhysAddr::from(next));
+                return Ok(PteState::Empty);
+            }
+        };
+        if prev == PteState::Empty {
+            self.cnt += 1;
+        }
+        if next == PteState::Empty {
+            self.cnt -= 1;
+        }
+        Ok(prev)
+    }
+
+    /// Iterate all the mapped entries
+    #[inline]
+    pub fn iter(&self) -> ExtentPageTableIter {
+        ExtentPageTableIter {
+            extents: self.extents.range(..),
+            cur: None,
+            next_idx: 0,
+        }
+    }
+
+    /// Iterate the mapped entries after the one of $addr$
+    #[inline]
+    pub fn iter_after(&self, addr: VirtAddr) -> ExtentPageTableIter {
+        let va = addr.as_u64() as VirtAddrType;
+        match self.extents.range(..=va).next_back() {
+            Some((start, extent)) if extent.contains(((va - start) >> PAGE_SHIFT) as usize) => {
+                ExtentPageTableIter {
+                    extents: self.extents.range((Excluded(*start), Unbounded)),
+                    cur: Some(extent),
+                    next_idx: ((va - start) >> PAGE_SHIFT) as usize + 1,
+                }
+            }
+            _ => ExtentPageTableIter {
+                extents: self.extents.range((Excluded(va), Unbounded)),
+                cur: None,
+                next_idx: 0,
+            },
+        }
+    }
+
+    /// Count the entries that point to a local cached page
+    pub fn cached_page_cnt(&self) -> usize {
+        self.iter().filter(|e| e.is_cached()).count()
+    }
+
+    /// Drop the references of the local cached pages hold by this page table,
+    /// see `RemotePageTable::release_cached_pages`.
+    pub fn release_cached_pages(&mut self) -> usize {
+        let released = self.iter().filter(|e| e.release_cached_page()).count();
+        self.cnt -= released;
+        released
+    }
+}
+
+/// Iterator over the mapped entries of an `ExtentPageTable`
+pub struct ExtentPageTableIter<'a> {
+    extents: btree_map::Range<'a, VirtAddrType, Extent>,
+    cur: Option<&'a Extent>,
+    next_idx: usize,
+}
+
+impl Iterator for ExtentPageTableIter<'_> {
+    type Item = PageEntry;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(extent) = self.cur {
+                while extent.contains(self.next_idx) {
+                    let slot = extent.slot(self.next_idx);
+                    self.next_idx += 1;
+                    let entry = slot.load();
+                    if entry != 0 {
+                        return Some(PageEntry::new(slot, PhysAddr::new(entry)));
+                    }
+                }
+            }
+            self.cur = Some(self.extents.next()?.1);
+            self.next_idx = 0;
+        }
+    }
+}
+
+impl core::fmt::Display for ExtentPageTable {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        for item in self.iter().filter(|e| e.is_cached()) {
+            write!(f, "[table] entry:{:#x}, state:{:?}", item.slot.as_u64(), item.addr.state())?;
+        }
+        Ok(())
+    }
+}
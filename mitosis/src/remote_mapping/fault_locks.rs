@@ -0,0 +1,56 @@
+use alloc::vec::Vec;
+
+use crate::kern_wrappers::mm::VirtAddrType;
+use crate::lock_bundler::{BoxedLockBundler, LockBundler};
+
+#[allow(unused_imports)]
+use crate::linux_kernel_module;
+
+/// Number of the table locks of one child
+pub const FAULT_LOCK_STRIPES: usize = 64;
+
+/// Each last-level table of the remote page table maps 2MB
+const L1_TABLE_SHIFT: usize = 21;
+
+/// Locks that serialize the page faults of a multi-threaded child.
+///
+/// - A fault holds the lock of the last-level table of its address, and the locks
+///   are striped by the tables. So faults on different 2MB regions run in parallel,
+///   while faults on the same page are serialized, and the later ones find the page
+///   resolved by the first one.
+/// - No table lock is held during the remote reads: a fault claims the entry as in flight
+///   under the lock, reads the page without it, and takes the lock again to install the page.
+///   The other faults on that page wait for the entry without the lock,
+///   see `MitosisSysCallHandler::handle_page_fault`.
+/// - The states shared by the whole descriptor, i.e., the prefetcher and the maps of the
+///   deduplicated and pushed pages, are behind the lock of its `FaultState`,
+///   which is always acquired after a table lock.
+/// - No lock is held while waiting for the network with the mmap_sem released,
+///   see `MitosisSysCallHandler::try_async_fault`.
+///
+/// The entries are accessed atomically through `PteSlot`,
+/// since a fault may read an entry updated by the prefetcher of another fault.
+pub struct FaultLocks {
+    tables: Vec<BoxedLockBundler<()>>,
+}
+
+impl FaultLocks {
+    pub fn new() -> Self {
+        Self {
+            tables: (0..FAULT_LOCK_STRIPES).map(|_| LockBundler::new(())).collect(),
+        }
+    }
+
+    /// Run `f` with the lock of the table that maps `addr`
+    #[inline]
+    pub fn lock_table<R>(&self, addr: VirtAddrType, f: impl FnOnce() -> R) -> R {
+        let idx = (addr as usize >> L1_TABLE_SHIFT) % self.tables.len();
+        self.tables[idx].lock(|_| f())
+    }
+}
+
+impl Default for FaultLocks {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -4,8 +4,16 @@
 //!
 //! Credits: some code is taken from <https://github.com/rust-osdev/x86_64/blob/master/src/structures/paging/mod.rs>
 
+pub use backend::{ChildPageTable, ChildPageTableIter, PageTableKind};
+pub use extent_table::{ExtentPageTable, ExtentPageTableIter};
+pub use fault_locks::FaultLocks;
 pub use page_structures::*;
 pub use page_table::{PageEntry, RemotePageAddr, RemotePageTable, RemotePageTableIter};
+pub use pte::{PteSlot, PteState, RemotePage};
 
+pub mod backend;
+pub mod extent_table;
+pub mod fault_locks;
 pub mod page_structures;
-pub mod page_table;
\ No newline at end of file
+pub mod page_table;
+pub mod pte;
\ No newline at end of file
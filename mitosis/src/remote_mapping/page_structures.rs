@@ -7,15 +7,13 @@ use rust_kernel_rdma_base::VmallocAllocator;
 use core::fmt;
 use core::ops::{Index, IndexMut};
 
-use crate::remote_mapping::page_structures::PhysAddrBitFlag::{Cache, Prefetch, ReadOnly};
+use super::pte::{drop_entry, PteSlot, PteState};
 pub use x86_64::{
     align_down, align_up,
     structures::paging::{Page, Size4KiB},
     VirtAddr,
 };
 
-pub const K_MAGIC_IN_PREFETCH: u64 = 1;
-
 /// We cannot use the PhysAddr in x86_64
 /// This is because it will raise a
 /// "physical addresses must not have any bits in the range 52 to 64 set" error
@@ -58,15 +56,7 @@ impl Drop for PageTable {
 
             None => {
                 for entry in self.iter() {
-                    if *entry != 0 {
-                        let physaddr = PhysAddr::new(*entry);
-                        assert_ne!(*entry, K_MAGIC_IN_PREFETCH);
-                        if physaddr.is_prefetch() {
-                            // free the page
-                            let page = PhysAddr::decode(*entry as _) as *mut crate::bindings::page;
-                            unsafe { crate::bindings::pmem_free_page(page) };
-                        }
-                    }
+                    drop_entry(*entry);
                 }
             }
         }
@@ -168,6 +158,50 @@ impl PageTable {
         self.entries.iter_mut()
     }
 
+    /// The entry at `index`, see `PteSlot`
+    #[inline(always)]
+    pub fn slot(&self, index: usize) -> PteSlot {
+        unsafe { PteSlot::new(&self.entries[index]) }
+    }
+
+    /// Read the entry, which may be updated by a concurrent page fault
+    #[inline(always)]
+    pub fn load(&self, index: usize) -> PageTableEntry {
+        self.slot(index).load()
+    }
+
+    /// Update the entry, the concurrent faults see either the old or the new value
+    #[inline(always)]
+    pub fn store(&self, index: usize, value: PageTableEntry) {
+        self.slot(index).store(value)
+    }
+
+    /// The state of a last-level entry
+    #[inline(always)]
+    pub fn state(&self, index: usize) -> PteState {
+        self.slot(index).state()
+    }
+
+    /// Move a last-level entry to `next`, see `PteSlot::transit`
+    #[inline]
+    pub fn transit(&self, index: usize, next: PteState) -> Result<PteState, PteState> {
+        self.slot(index).transit(next)
+    }
+
+    /// Number of tables rooted at this one, including itself
+    pub fn table_count(&self) -> usize {
+        match self.level.next_lower_level() {
+            Some(_) => {
+                1 + self
+                    .iter()
+                    .filter(|e| **e != 0)
+                    .map(|e| unsafe { (*(*e as *const PageTable)).table_count() })
+                    .sum::<usize>()
+            }
+            None => 1,
+        }
+    }
+
     /// Return a valid non-null index
     #[inline]
     pub fn find_valid_entry(&self, start_idx: usize) -> core::option::Option<usize> {
@@ -362,33 +396,10 @@ impl PageTableLevel {
     }
 }
 
-pub enum PhysAddrBitFlag {
-    Empty = 0b0000,
-    Prefetch = 0b0001,
-    Cache = 0b0010,
-    ReadOnly = 0b0100,
-}
-
-impl PhysAddrBitFlag {
-    pub fn mask() -> u64 {
-        Prefetch as u64 | Cache as u64 | ReadOnly as u64
-    }
-}
-
 /// Credits: most code is from x86_64, just remove unnecessary checks
 /// If the crate updates, we can switch back to it
 ///
-/// Encoding formation:
-///
-/// |   *mut page   |   ro bit  |   cache bit   |   prefetch bit    |
-/// |   63          |       1   |       1       |       1           |
-///
-/// - The Prefetch flag is only set at child-side (DCAsyncPreFetcher). It means the
-///   page has already been async fetched.
-///
-/// - The Cache flag is only set at child-side (Child trigger the cache miss and set it as COW)
-///
-/// - The ReadOnly flag is only set at parent-side (walk the whole pte, and set read-only according to page flag)
+/// The entries of the last-level tables are typed by `PteState`.
 impl PhysAddr {
     /// Creates a new physical address.
     ///
@@ -398,18 +409,10 @@ impl PhysAddr {
         PhysAddr(addr)
     }
 
-    /// Get the bottom bit of this physical address.
-    /// Normally, it will always be zero.
-    ///
-    /// Thus, we will use it to encode additional information
-    pub fn bottom_bit(&self) -> bool {
-        self.is_prefetch()
-    }
-
-    /// Set the bottom bit of this physical address.
-    pub fn set_bottom_bit_as_one(&mut self) -> u64 {
-        self.0 = self.0 | 1;
-        self.0
+    /// The state of the page table entry holding this address.
+    #[inline(always)]
+    pub fn state(&self) -> PteState {
+        PteState::decode(self.0)
     }
 
     /// Tries to create a new physical address.
@@ -458,53 +461,10 @@ impl PhysAddr {
     }
 }
 
-impl PhysAddr {
-    /// Decode to get the real page address.
-    /// Set lower 3 bits into all zero.
+impl From<PteState> for PhysAddr {
     #[inline(always)]
-    pub fn decode(addr: u64) -> u64 {
-        addr & !PhysAddrBitFlag::mask()
-    }
-
-    /// Encode from the give page physical address.
-    /// Put the dedicated bits into 1
-    #[inline(always)]
-    pub fn encode(page_addr: u64, flag: u64) -> u64 {
-        // Validate the flags
-        page_addr | (flag & PhysAddrBitFlag::mask())
-    }
-
-    /// Get Prefetch bit value
-    #[inline(always)]
-    pub fn is_prefetch(&self) -> bool {
-        self.0 & Prefetch as u64 == Prefetch as u64
-    }
-
-    /// Get Cache bit value
-    #[inline(always)]
-    pub fn is_cache(&self) -> bool {
-        self.0 & Cache as u64 == Cache as u64
-    }
-
-    /// Get ReadOnly bit value
-    #[inline(always)]
-    pub fn is_ro(&self) -> bool {
-        self.0 & ReadOnly as u64 == ReadOnly as u64
-    }
-
-    #[inline(always)]
-    pub fn real_addr(&self) -> u64 {
-        Self::decode(self.0)
-    }
-
-    /// The address means:
-    /// - Remote physical address, if the prefetch and cache are both 0
-    /// - Local kernel virtual address, if either prefetch or cache is 1
-    #[inline(always)]
-    pub fn convert_to_page(&self) -> *mut crate::bindings::page {
-        let kernel_page_va = self.real_addr();
-
-        kernel_page_va as *mut crate::bindings::page
+    fn from(state: PteState) -> Self {
+        PhysAddr(state.encode())
     }
 }
 
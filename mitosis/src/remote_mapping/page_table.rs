@@ -7,6 +7,7 @@ pub use x86_64::{
 use alloc::boxed::Box;
 
 use super::page_structures::*;
+use super::pte::{PteSlot, PteState};
 
 #[allow(unused_imports)]
 use crate::linux_kernel_module;
@@ -66,14 +67,35 @@ impl RemotePageTable {
     #[inline(always)]
     pub fn translate(&self, addr: VirtAddr) -> core::option::Option<PhysAddr> {
         let (pt, index) = self.find_l1_page_idx(addr)?;
-        let pt = unsafe { &mut (*pt) };
-        if pt[index] != 0 {
-            Some(PhysAddr::new(pt[index]))
+        let pt = unsafe { &(*pt) };
+        let entry = pt.load(index);
+        if entry != 0 {
+            Some(PhysAddr::new(entry))
         } else {
             None
         }
     }
 
+    /// Lookup the entry of the requested address, which may be empty
+    #[inline(always)]
+    pub fn find_entry(&self, addr: VirtAddr) -> core::option::Option<PteSlot> {
+        let (pt, index) = self.find_l1_page_idx(addr)?;
+        Some(unsafe { (*pt).slot(index) })
+    }
+
+    /// Iterate the mapped entries after the one of $addr$
+    #[inline]
+    pub fn iter_after(&self, addr: VirtAddr) -> core::option::Option<RemotePageTableIter> {
+        let (pt, index) = self.find_l1_page_idx(addr)?;
+        Some(unsafe { RemotePageTableIter::new_from_l1(pt, index) })
+    }
+
+    /// Bytes used by the tables
+    pub fn memory_usage(&self) -> usize {
+        core::mem::size_of::<Self>()
+            + self.l4_page_table.table_count() * core::mem::size_of::<PageTable>()
+    }
+
     /// Lookup the last-level page of the requested address
     /// Return:
     /// - Page ptr, Entry index
@@ -101,12 +123,9 @@ impl RemotePageTable {
         let l1_pt = self.map_to_the_l1(&entry);
         let l1_pt: &mut PageTable = unsafe { &mut (*l1_pt) };
 
-        let res = l1_pt[usize::from(entry.p1_index())];
+        let res = l1_pt.load(usize::from(entry.p1_index()));
         if res == 0 {
-            // The bottom bit of a physical page cannot be 1 (4KB aligned)
-            // We will encode the remote information in this bit
-            // assert!(phy.bottom_bit() == false);
-            l1_pt[usize::from(entry.p1_index())] = phy.as_u64();
+            l1_pt.store(usize::from(entry.p1_index()), phy.as_u64());
             self.cnt += 1;
             return None;
         }
@@ -114,23 +133,59 @@ impl RemotePageTable {
         return Some(PhysAddr::new(res));
     }
 
-    /// Add one (addr, phy) mapping into the page table.
-    /// The new pair mapping would **always overwrite** the origin pair (if exist)
+    /// Lookup the state of the entry of $addr$, None if it is not mapped
+    #[inline(always)]
+    pub fn lookup(&self, addr: VirtAddr) -> core::option::Option<PteState> {
+        let (pt, index) = self.find_l1_page_idx(addr)?;
+        match unsafe { (*pt).state(index) } {
+            PteState::Empty => None,
+            state => Some(state),
+        }
+    }
+
+    /// Move the entry of $addr$ to `next`, see `PteState::can_transit_to`.
+    /// All updates of the mapped entries go through this method.
+    ///
+    /// Return the previous state, or the current one if the transition is illegal.
     #[inline]
-    pub fn force_map(&mut self, addr: VirtAddr, phy: PhysAddr) {
+    pub fn transit(&mut self, addr: VirtAddr, next: PteState) -> Result<PteState, PteState> {
         let entry = RemotePageAddr::containing_address(addr);
 
         let l1_pt = self.map_to_the_l1(&entry);
-        let l1_pt: &mut PageTable = unsafe { &mut (*l1_pt) };
-
-        let res = l1_pt[usize::from(entry.p1_index())];
+        let l1_pt: &PageTable = unsafe { &(*l1_pt) };
 
-        // force update mapping
-        l1_pt[usize::from(entry.p1_index())] = phy.as_u64();
-        // not exist
-        if res == 0 {
+        let prev = l1_pt.transit(usize::from(entry.p1_index()), next)?;
+        if prev == PteState::Empty {
             self.cnt += 1;
         }
+        if next == PteState::Empty {
+            self.cnt -= 1;
+        }
+        Ok(prev)
+    }
+
+    /// Count the entries that point to a local cached page
+    pub fn cached_page_cnt(&self) -> usize {
+        match unsafe { RemotePageTableIter::new(self) } {
+            Some(iter) => iter.filter(|e| e.is_cached()).count(),
+            None => 0,
+        }
+    }
+
+    /// Drop the references of the local cached pages hold by this page table.
+    /// The corresponding entries become empty, since the remote address has been
+    /// overwritten by the cached page.
+    ///
+    /// Return the number of released pages.
+    pub fn release_cached_pages(&mut self) -> usize {
+        let iter = match unsafe { RemotePageTableIter::new(self) } {
+            Some(iter) => iter,
+            None => return 0,
+        };
+
+        let released = iter.filter(|e| e.release_cached_page()).count();
+        self.cnt -= released;
+        released
     }
 
     fn map_to_the_l1(&mut self, entry: &RemotePageAddr) -> *mut PageTable {
@@ -153,35 +208,55 @@ pub struct RemotePageTableIter {
     cur_idx: isize,
 }
 
+/// A mapped entry yielded by the iterators of both backends
 #[derive(Debug, Clone, Copy)]
 pub struct PageEntry {
-    pub page: *mut PageTable,
-    pub index: usize,
+    pub slot: PteSlot,
     pub addr: PhysAddr,
 }
 
 impl PageEntry {
     #[inline]
-    fn new(p: *mut PageTable, index: usize, addr: PhysAddr) -> Self {
+    pub fn new(slot: PteSlot, addr: PhysAddr) -> Self {
         Self {
-            page: p,
-            index: index,
+            slot: slot,
             addr: addr,
         }
     }
+
+    #[inline]
+    pub fn is_cached(&self) -> bool {
+        matches!(self.addr.state(), PteState::Cached { .. })
+    }
+
+    /// Drop the reference of the local cached page, and clear the entry.
+    /// Return false if the entry is not cached.
+    pub fn release_cached_page(&self) -> bool {
+        let page = match self.addr.state() {
+            PteState::Cached { page, .. } => page,
+            _ => return false,
+        };
+        unsafe {
+            crate::bindings::pmem_page_free_rmap(page, false);
+            crate::bindings::pmem_put_page(page);
+        }
+        self.slot
+            .transit(PteState::Empty)
+            .expect("cached entry must be released to empty");
+        true
+    }
 }
 
 impl crate::prefetcher::NeedPrefetch for PageEntry {
     fn need_prefetch(&self) -> bool {
-        self.addr.bottom_bit() == false
+        self.addr.state().need_prefetch()
     }
 }
 
 impl Default for PageEntry {
     fn default() -> Self {
         Self {
-            page: core::ptr::null_mut(),
-            index: 0,
+            slot: Default::default(),
             addr: PhysAddr::new(0),
         }
     }
@@ -204,9 +279,8 @@ impl Iterator for RemotePageTableIter {
                     // done
                     self.cur_idx = idx.unwrap() as isize;
                     return Some(PageEntry::new(
-                        self.cur_page,
-                        idx.unwrap(),
-                        PhysAddr::new(cur_page[idx.unwrap()]),
+                        cur_page.slot(idx.unwrap()),
+                        PhysAddr::new(cur_page.load(idx.unwrap())),
                     ));
                 }
             }
@@ -333,14 +407,8 @@ impl core::fmt::Display for RemotePageTable {
     fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
         if let Some(iter) = unsafe { RemotePageTableIter::new(self) } {
             for item in iter {
-                if item.addr.is_cache() {
-                    write!(
-                        f,
-                        "[table] index:{}, addr:{}, cache bit:{}",
-                        item.index,
-                        item.addr.as_u64(),
-                        item.addr.is_cache()
-                    )?;
+                if item.is_cached() {
+                    write!(f, "[table] entry:{:#x}, state:{:?}", item.slot.as_u64(), item.addr.state())?;
                 }
             }
         }
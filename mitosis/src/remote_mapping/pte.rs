@@ -0,0 +1,282 @@
+//! The typed states of the entries of the remote page table.
+
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use crate::bindings::page;
+use crate::kern_wrappers::mm::PhyAddrType;
+
+use super::PageTableEntry;
+
+const KIND_MASK: u64 = 0b111;
+const READ_ONLY_BIT: u64 = 0b1000;
+
+// the physical address of a 4KB (or 2MB) page at the source machine
+const REMOTE_ADDR_MASK: u64 = 0x000f_ffff_ffff_f000;
+// `*mut page` is 64-byte aligned
+const LOCAL_ADDR_MASK: u64 = !0b1111;
+
+const MACHINE_SHIFT: u64 = 52;
+/// Max machines that can be encoded in an entry
+pub const MAX_ENCODED_MACHINES: usize = 1 << (64 - MACHINE_SHIFT);
+
+#[repr(u64)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Remote = 0,
+    Local = 1,
+    Cached = 2,
+    Zero = 3,
+    Duplicate = 4,
+    InFlight = 5,
+    Huge = 6,
+}
+
+/// A page at the source machine
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RemotePage {
+    pub pa: PhyAddrType,
+    pub ro: bool,
+    // 0 means the machine of the descriptor
+    pub machine: usize,
+}
+
+impl RemotePage {
+    #[inline]
+    pub fn new(pa: PhyAddrType, ro: bool) -> Self {
+        Self { pa, ro, machine: 0 }
+    }
+}
+
+/// The state of an entry of the remote page table.
+///
+/// Encoding of the entry:
+///
+/// |   machine |   address             |   ro  |   kind    |
+/// |   63-52   |   51-4                |   3   |   2-0     |
+///
+/// - The address of the remote kinds (Remote, Duplicate and Huge) is the 4KB-aligned
+///   physical address at the source machine, whose ID is in the machine bits.
+/// - The address of the local kinds (Local and Cached) is a `*mut page`,
+///   which spans the machine bits.
+/// - The all-zero entry is empty. Note that it also encodes a remote page at
+///   physical address 0, which is never used by the user space.
+///
+/// The legal transitions are listed in `can_transit_to`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PteState {
+    Empty,
+    /// The page is at the source machine
+    Remote(RemotePage),
+    /// The page is being read into a local page, e.g., by the prefetcher
+    InFlight,
+    /// The page has been prefetched or pushed into a local page owned by the table
+    Local(*mut page),
+    /// A local page of the page cache, shared by the children with COW
+    Cached { page: *mut page, ro: bool },
+    /// The page is all zero, so it is allocated locally
+    Zero,
+    /// The page has the same content as the remote page, whose local copy is shared
+    Duplicate(RemotePage),
+    /// A 2MB page at the source machine
+    Huge(RemotePage),
+}
+
+impl PteState {
+    #[inline]
+    pub fn decode(entry: PageTableEntry) -> Self {
+        if entry == 0 {
+            return PteState::Empty;
+        }
+        let remote = || RemotePage {
+            pa: entry & REMOTE_ADDR_MASK,
+            ro: entry & READ_ONLY_BIT != 0,
+            machine: (entry >> MACHINE_SHIFT) as usize,
+        };
+        let local = (entry & LOCAL_ADDR_MASK) as *mut page;
+
+        match entry & KIND_MASK {
+            k if k == Kind::Remote as u64 => PteState::Remote(remote()),
+            k if k == Kind::Local as u64 => PteState::Local(local),
+            k if k == Kind::Cached as u64 => PteState::Cached {
+                page: local,
+                ro: entry & READ_ONLY_BIT != 0,
+            },
+            k if k == Kind::Zero as u64 => PteState::Zero,
+            k if k == Kind::Duplicate as u64 => PteState::Duplicate(remote()),
+            k if k == Kind::InFlight as u64 => PteState::InFlight,
+            _ => PteState::Huge(remote()),
+        }
+    }
+
+    #[inline]
+    pub fn encode(&self) -> PageTableEntry {
+        let remote = |kind: Kind, r: &RemotePage| {
+            assert!(r.machine < MAX_ENCODED_MACHINES);
+            ((r.machine as u64) << MACHINE_SHIFT)
+                | (r.pa & REMOTE_ADDR_MASK)
+                | Self::ro_bit(r.ro)
+                | kind as u64
+        };
+        let local = |kind: Kind, p: *mut page, ro: bool| {
+            assert!(p as u64 & !LOCAL_ADDR_MASK == 0);
+            p as u64 | Self::ro_bit(ro) | kind as u64
+        };
+
+        match self {
+            PteState::Empty => 0,
+            PteState::Remote(r) => remote(Kind::Remote, r),
+            PteState::InFlight => Kind::InFlight as u64,
+            PteState::Local(p) => local(Kind::Local, *p, false),
+            PteState::Cached { page, ro } => local(Kind::Cached, *page, *ro),
+            PteState::Zero => Kind::Zero as u64,
+            PteState::Duplicate(r) => remote(Kind::Duplicate, r),
+            PteState::Huge(r) => remote(Kind::Huge, r),
+        }
+    }
+
+    #[inline(always)]
+    fn ro_bit(ro: bool) -> u64 {
+        if ro {
+            READ_ONLY_BIT
+        } else {
+            0
+        }
+    }
+
+    /// Check whether an entry can change from `self` to `next`:
+    ///
+    /// - Empty -> Remote, Zero, Duplicate or Huge: the entry is mapped from the descriptor
+    /// - Remote -> InFlight: a read is issued, e.g., by the prefetcher
+    /// - InFlight -> Local: the read completes
+    /// - InFlight -> Remote: the read fails
+    /// - Remote -> Local: the page is pushed by the parent
+    /// - Local -> Remote: the local page is taken by a fault, and the origin is restored
+    /// - Remote -> Cached: the page read by a fault is kept by the page cache
+    /// - Cached -> Empty: the cached page is released
+    ///
+    /// The Zero, Duplicate and Huge entries never change.
+    #[inline]
+    pub fn can_transit_to(&self, next: &PteState) -> bool {
+        use PteState::*;
+        match (self, next) {
+            (Empty, Remote(_)) | (Empty, Zero) | (Empty, Duplicate(_)) | (Empty, Huge(_)) => true,
+            (Remote(_), InFlight) => true,
+            (InFlight, Local(_)) => true,
+            (InFlight, Remote(_)) => true,
+            (Remote(_), Local(_)) => true,
+            (Local(_), Remote(_)) => true,
+            (Remote(_), Cached { .. }) => true,
+            (Cached { .. }, Empty) => true,
+            _ => false,
+        }
+    }
+
+    /// The remote page, if the entry refers to one
+    #[inline]
+    pub fn remote(&self) -> Option<RemotePage> {
+        match self {
+            PteState::Remote(r) | PteState::Duplicate(r) | PteState::Huge(r) => Some(*r),
+            _ => None,
+        }
+    }
+
+    /// Whether the page can be resolved without the network
+    #[inline]
+    pub fn is_local(&self) -> bool {
+        matches!(
+            self,
+            PteState::Local(_) | PteState::Cached { .. } | PteState::Zero
+        )
+    }
+
+    /// Only the remote pages are prefetched. The zero and duplicated pages are
+    /// resolved locally, see `ChildDescriptor::read_eliminated_page`.
+    #[inline]
+    pub fn need_prefetch(&self) -> bool {
+        matches!(self, PteState::Remote(_))
+    }
+}
+
+/// A reference to an entry of the remote page table, of either backend.
+///
+/// The entries never move once the table is built,
+/// so a slot is valid until its table is dropped.
+/// The entries are accessed atomically, since a fault may read an entry
+/// updated by the prefetcher of another fault.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PteSlot(*const AtomicU64);
+
+impl PteSlot {
+    /// # Safety
+    /// The entry must outlive the slot
+    #[inline(always)]
+    pub unsafe fn new(entry: *const PageTableEntry) -> Self {
+        // u64 and AtomicU64 have the same in-memory representation
+        Self(entry as *const AtomicU64)
+    }
+
+    #[inline(always)]
+    pub fn is_null(&self) -> bool {
+        self.0.is_null()
+    }
+
+    /// The address of the entry, which identifies it in the table
+    #[inline(always)]
+    pub fn as_u64(&self) -> u64 {
+        self.0 as u64
+    }
+
+    #[inline(always)]
+    pub fn load(&self) -> PageTableEntry {
+        unsafe { (*self.0).load(Ordering::Acquire) }
+    }
+
+    #[inline(always)]
+    pub fn store(&self, value: PageTableEntry) {
+        unsafe { (*self.0).store(value, Ordering::Release) }
+    }
+
+    #[inline(always)]
+    pub fn state(&self) -> PteState {
+        PteState::decode(self.load())
+    }
+
+    /// Move the entry to `next`.
+    ///
+    /// Return the previous state, or the current state if it cannot transit to `next`,
+    /// in which case the entry is untouched.
+    #[inline]
+    pub fn transit(&self, next: PteState) -> Result<PteState, PteState> {
+        let entry = unsafe { &*self.0 };
+        let mut cur = entry.load(Ordering::Acquire);
+        loop {
+            let prev = PteState::decode(cur);
+            if !prev.can_transit_to(&next) {
+                return Err(prev);
+            }
+            match entry.compare_exchange(cur, next.encode(), Ordering::AcqRel, Ordering::Acquire) {
+                Ok(_) => return Ok(prev),
+                Err(v) => cur = v,
+            }
+        }
+    }
+}
+
+impl Default for PteSlot {
+    fn default() -> Self {
+        Self(core::ptr::null())
+    }
+}
+
+/// Release the page owned by a dropped entry
+#[inline]
+pub(crate) fn drop_entry(entry: PageTableEntry) {
+    match PteState::decode(entry) {
+        // the prefetched or pushed pages are owned by the table
+        PteState::Local(page) => unsafe { crate::bindings::pmem_free_page(page) },
+        // the page being read is owned by the prefetcher, which failed to drain it,
+        // so it is leaked rather than freed under the DMA
+        PteState::InFlight => crate::log::error!("drop a page table with an in-flight read"),
+        _ => {}
+    }
+}
@@ -17,7 +17,7 @@ pub const TIMEOUT_USEC: i64 = 1000_000; // 1s
 
 /// Derive copy is rather dangerous
 /// This structure is aimed for global usage
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct AccessInfo {
     pub(crate) access_handler: Arc<crate::KRdmaKit::queue_pairs::DatagramEndpoint>,
     pub(crate) rkey: u32,
@@ -79,7 +79,6 @@ impl AccessInfo {
 
 pub struct RemotePagingService;
 
-use crate::remote_mapping::PhysAddr;
 use os_network::msg::UDMsg as RMemory;
 
 impl RemotePagingService {
@@ -87,7 +86,7 @@ impl RemotePagingService {
     #[inline]
     pub(crate) fn remote_descriptor_fetch(
         d: crate::rpc_handlers::DescriptorLookupReply,
-        _caller: &mut crate::rpc_caller_pool::UDCaller,
+        _caller: &mut crate::rpc_caller_pool::RPCCaller,
         _machine_id: c_ulong,
     ) -> Result<RMemory, <DCRemoteDevice as Future>::Error> {
         let pool_idx = unsafe { crate::bindings::pmem_get_current_cpu() } as usize;
@@ -152,7 +151,7 @@ impl RemotePagingService {
             unsafe {
                 remote_device.read(
                     &access_info.access_handler,
-                    &PhysAddr::decode(src), // copy from src into dst
+                    &src, // copy from src into dst
                     &DCKeys::new(access_info.rkey),
                     &mut dst,
                     &sz,
@@ -181,7 +180,7 @@ impl RemotePagingService {
     #[inline]
     pub(crate) fn remote_descriptor_fetch(
         d: crate::rpc_handlers::DescriptorLookupReply,
-        _caller: &mut crate::rpc_caller_pool::UDCaller,
+        _caller: &mut crate::rpc_caller_pool::RPCCaller,
         machine_id: c_ulong,
     ) -> Result<RMemory, <RCRemoteDevice as Future>::Error> {
         let cpu_id = crate::get_calling_cpu_id();
@@ -240,7 +239,7 @@ impl RemotePagingService {
         unsafe {
             remote_device.read(
                 &(),
-                &PhysAddr::decode(src), // copy from src into dst
+                &src, // copy from src into dst
                 &RCKeys::new(access_info.rkey),
                 &mut dst,
                 &sz,
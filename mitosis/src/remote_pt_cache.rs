@@ -1,19 +1,29 @@
 use crate::bindings::*;
 use crate::descriptors::PageMapAllocator;
+use core::sync::atomic::{AtomicUsize, Ordering};
 use hashbrown::hash_map::DefaultHashBuilder;
 use hashbrown::HashMap;
 
 // Map from handler id into the whole page table
 type Key = u64;
-type Value = crate::remote_mapping::RemotePageTable;
+type Value = crate::remote_mapping::ChildPageTable;
 
-/// A global kernel-space KV store that stores 
-/// a mapping between: handler_id -> its page table, 
+struct CacheEntry {
+    pt: Value,
+    // the machine of the parent, whose entries are evicted once it is down
+    mac_id: usize,
+    // logical time of the last lookup, used to find the cold entries
+    last_access: AtomicUsize,
+}
+
+/// A global kernel-space KV store that stores
+/// a mapping between: handler_id -> its page table,
 /// whose page table entries may reference to a local cache
-/// TODO: we can abstract it to a more general KV 
+/// TODO: we can abstract it to a more general KV
 #[derive(Default)]
 pub struct RemotePageTableCache {
-    store: HashMap<Key, Value, DefaultHashBuilder, PageMapAllocator>,
+    store: HashMap<Key, CacheEntry, DefaultHashBuilder, PageMapAllocator>,
+    clock: AtomicUsize,
 }
 
 impl RemotePageTableCache {
@@ -25,13 +35,23 @@ impl RemotePageTableCache {
         handler_id as Key
     }
 
+    #[inline(always)]
+    fn tick(&self) -> usize {
+        self.clock.fetch_add(1, Ordering::Relaxed)
+    }
+
     pub fn lookup_mut(
         &mut self,
         remote_mac_id: usize,
         handler_id: usize,
     ) -> Option<&mut Value> {
+        let now = self.tick();
         self.store
             .get_mut(&Self::gen_cache_key(remote_mac_id, handler_id))
+            .map(|e| {
+                e.last_access.store(now, Ordering::Relaxed);
+                &mut e.pt
+            })
     }
 
     pub fn lookup(
@@ -39,8 +59,13 @@ impl RemotePageTableCache {
         remote_mac_id: usize,
         handler_id: usize,
     ) -> Option<&Value> {
+        let now = self.tick();
         self.store
             .get(&Self::gen_cache_key(remote_mac_id, handler_id))
+            .map(|e| {
+                e.last_access.store(now, Ordering::Relaxed);
+                &e.pt
+            })
     }
 
     pub fn insert(
@@ -50,10 +75,67 @@ impl RemotePageTableCache {
         value: Value,
     ) {
         let key = Self::gen_cache_key(remote_mac_id, handler_id);
-        self.store.insert(key, value);
+        let entry = CacheEntry {
+            pt: value,
+            mac_id: remote_mac_id,
+            last_access: AtomicUsize::new(self.tick()),
+        };
+        self.store.insert(key, entry);
     }
 
     pub fn num(&self) -> usize {
         self.store.len()
     }
+
+    /// Number of pages that can be given back by evicting all the entries,
+    /// including the cached pages and the page tables themselves.
+    pub fn reclaimable_pages(&self) -> usize {
+        self.store
+            .values()
+            .map(|e| e.pt.cached_page_cnt() + Self::table_pages(&e.pt))
+            .sum()
+    }
+
+    #[inline]
+    fn table_pages(pt: &Value) -> usize {
+        (pt.memory_usage() + 4095) / 4096
+    }
+
+    /// Evict the least recently used page table, and release its cached pages.
+    /// Return the number of reclaimed pages (see `reclaimable_pages`),
+    /// or None if the cache is empty.
+    pub fn evict_coldest(&mut self) -> Option<usize> {
+        let key = *self
+            .store
+            .iter()
+            .min_by_key(|(_, e)| e.last_access.load(Ordering::Relaxed))?
+            .0;
+        let mut entry = self.store.remove(&key)?;
+        Some(entry.pt.release_cached_pages() + Self::table_pages(&entry.pt))
+    }
+
+    /// Evict all the page tables of a remote machine, e.g., it is down.
+    /// Return the number of evicted entries.
+    pub fn evict_machine(&mut self, remote_mac_id: usize) -> usize {
+        let keys: alloc::vec::Vec<Key> = self
+            .store
+            .iter()
+            .filter(|(_, e)| e.mac_id == remote_mac_id)
+            .map(|(k, _)| *k)
+            .collect();
+        for key in keys.iter() {
+            if let Some(mut entry) = self.store.remove(key) {
+                entry.pt.release_cached_pages();
+            }
+        }
+        keys.len()
+    }
+}
+
+impl Drop for RemotePageTableCache {
+    fn drop(&mut self) {
+        for (_, mut entry) in self.store.drain() {
+            entry.pt.release_cached_pages();
+        }
+    }
 }
@@ -4,8 +4,10 @@ use alloc::vec::Vec;
 use os_network::datagram::msg::UDMsg;
 use os_network::datagram::ud::*;
 use os_network::datagram::ud_receiver::*;
-use os_network::rpc::impls::ud::UDSession;
+use os_network::rpc::impls::rc::*;
+use os_network::rpc::impls::transport::*;
 use os_network::rpc::*;
+use os_network::timeout::TimeoutWRef;
 use os_network::ud::UDFactory;
 use os_network::Factory;
 use os_network::KRdmaKit::context::Context;
@@ -15,14 +17,29 @@ use crate::linux_kernel_module;
 use crate::lock_bundler::BoxedLockBundler;
 use crate::lock_bundler::LockBundler;
 
-pub(crate) type UDCaller = Caller<UDReceiver, UDSession>;
+/// The caller used by the clients, over the transport of `Config::rpc_transport`
+pub(crate) type RPCCaller = Caller<RPCReceiver, RPCSession>;
+
+use hashbrown::HashMap;
+
+const CONNECT_TIMEOUT_USEC: i64 = 1000_000; // 1s
 
 /// The pool maintains a thread_local_pool of callers
 /// Each CPU core can use the dedicated pool
 #[derive(Default)]
 pub struct CallerPool<'a> {
     // the major caller that is used by the clients
-    pool: Vec<BoxedLockBundler<UDCaller>>,
+    pool: Vec<BoxedLockBundler<RPCCaller>>,
+    transport: RPCTransport,
+
+    // over RC, the mailboxes of the remote handlers are looked up with these callers over UD
+    ud_pool: Vec<BoxedLockBundler<RPCCaller>>,
+    // the lane of the caller's mailbox assigned to each of its sessions
+    lanes: Vec<HashMap<usize, usize>>,
+    // the RC service that the remote handlers connect back to
+    rc_service_ids: Vec<u64>,
+    // the remote endpoint that each session of the caller connects to
+    remotes: Vec<HashMap<usize, UDHyperMeta>>,
 
     // each entry stored the caller to create the corresponding caller
     factories: Vec<&'a UDFactory>,
@@ -33,13 +50,30 @@ pub struct CallerPool<'a> {
     metas: Vec<(usize, u64)>,
 }
 
+/// The remote endpoint of a session and ourself, resolved before the caller is locked
+struct ConnectTarget {
+    ud: UDTarget,
+    // the remote handler that polls the endpoint
+    handler_id: usize,
+    conn_meta: os_network::rdma::ConnMeta,
+    my_rc_service_id: u64,
+}
+
+/// What connects the session over UD
+struct UDTarget {
+    endpoint: crate::KRdmaKit::queue_pairs::DatagramEndpoint,
+    my_gid: crate::KRdmaKit::rdma_shim::bindings::ib_gid,
+    my_qd_hint: usize,
+    my_service_id: u64,
+}
+
 use os_network::MetaFactory;
 
 impl<'a> CallerPool<'a> {
     #[inline(always)]
     pub unsafe fn get_global_caller(
         idx: usize,
-    ) -> core::option::Option<&'static mut BoxedLockBundler<UDCaller>> {
+    ) -> core::option::Option<&'static mut BoxedLockBundler<RPCCaller>> {
         crate::service_caller_pool::get_mut().get_caller(idx)
     }
 
@@ -49,10 +83,23 @@ impl<'a> CallerPool<'a> {
     }
 
     #[inline(always)]
-    pub fn get_caller(&'a mut self, idx: usize) -> core::option::Option<&'a mut BoxedLockBundler<UDCaller>> {
+    pub fn get_caller(&'a mut self, idx: usize) -> core::option::Option<&'a mut BoxedLockBundler<RPCCaller>> {
         self.pool.get_mut(idx)
     }
 
+    /// The statistics of all the callers.
+    /// Over RC, they include the mailbox lookups.
+    pub fn get_analysis(&self) -> os_network::rpc::analysis::RPCAnalysis {
+        let mut res = os_network::rpc::analysis::RPCAnalysis::new();
+        for caller in self.pool.iter() {
+            caller.lock(|c| res.merge(c.get_analysis()));
+        }
+        for caller in self.ud_pool.iter() {
+            caller.lock(|c| res.merge(c.get_analysis()));
+        }
+        res
+    }
+
     #[inline(always)]
     pub fn get_caller_context(&'a self, idx: usize) -> core::option::Option<&'a Arc<Context>> {
         self.contexts.get(idx).map(|r| *r)
@@ -66,47 +113,361 @@ impl<'a> CallerPool<'a> {
         my_session_id : usize,
         meta: UDHyperMeta,
     ) -> core::option::Option<()> {
-        // fetch by sidr connect
-        let meta = self.create_meta_at(idx, meta)?;
-        let my_gid = self.contexts.get(idx).unwrap().query_gid(1, 0).unwrap();
-        let (hint, service_id) = self.metas.get(idx).unwrap().clone();
+        let target = self.prepare_connect_at(idx, meta)?;
+        let transport = self.transport;
+        let (ud_caller, lanes, remotes) = (
+            self.ud_pool.get(idx),
+            self.lanes.get_mut(idx)?,
+            self.remotes.get_mut(idx)?,
+        );
 
-        let caller = self.get_caller(idx)?;
-        caller.lock(|caller| {
+        self.pool.get(idx)?.lock(|caller| {
             if caller.session_connected(session_id) {
                 crate::log::warn!("The session {} has already connected.", session_id);
                 return None;
             }
-    
-            let client_session = caller.get_transport_mut().create(meta).unwrap();
-            let local_port_num = client_session.get_inner().get_qp().port_num();
-    
-            // send the connect message
-            caller
-                .connect(
+            Self::connect_with(transport, caller, ud_caller, lanes, session_id, my_session_id, target)?;
+            remotes.insert(session_id, meta);
+            Some(())
+        })
+    }
+
+    /// Move the session onto the remote endpoint that `spread` picks for this caller
+    /// among the `endpoints` of the remote machine, e.g., after it added more of them.
+    /// The session is only moved if the caller is idle, and it is re-connected
+    /// with the caller locked, so no call of it fails.
+    ///
+    /// Return whether the session is moved, or None if it is lost during the move.
+    pub fn rebalance_session_at(
+        &mut self,
+        idx: usize,
+        session_id: usize,
+        my_session_id: usize,
+        endpoints: usize,
+    ) -> core::option::Option<bool> {
+        let pool = &self.pool;
+        let remotes = &self.remotes;
+        let mut meta = pool
+            .get(idx)?
+            .lock(|_| remotes.get(idx)?.get(&session_id).copied())?;
+        let qd_hint = crate::rpc_service::HandlerConnectInfo::spread_qd_hint(meta.qd_hint, idx, endpoints);
+        if qd_hint == meta.qd_hint {
+            return Some(false);
+        }
+        let old_qd_hint = meta.qd_hint;
+        meta.qd_hint = qd_hint;
+
+        let target = self.prepare_connect_at(idx, meta)?;
+        let transport = self.transport;
+        let (ud_caller, lanes, remotes) = (
+            self.ud_pool.get(idx),
+            self.lanes.get_mut(idx)?,
+            self.remotes.get_mut(idx)?,
+        );
+
+        self.pool.get(idx)?.lock(|caller| {
+            // disconnected or moved by the others meanwhile
+            match remotes.get(&session_id) {
+                Some(m) if m.qd_hint == old_qd_hint => {}
+                _ => return Some(false),
+            };
+            if caller.get_inflight_num() != 0 || caller.disconnect(session_id).is_err() {
+                return Some(false);
+            }
+
+            match Self::connect_with(transport, caller, ud_caller, lanes, session_id, my_session_id, target) {
+                Some(()) => {
+                    remotes.insert(session_id, meta);
+                    Some(true)
+                }
+                None => {
+                    crate::log::error!(
+                        "session {} is lost when moving to the remote endpoint {}",
+                        session_id,
+                        qd_hint
+                    );
+                    remotes.remove(&session_id);
+                    None
+                }
+            }
+        })
+    }
+
+    /// The session IDs that the caller is connected to
+    pub fn get_sessions_at(&self, idx: usize) -> Vec<usize> {
+        let remotes = &self.remotes;
+        self.pool
+            .get(idx)
+            .and_then(|caller| caller.lock(|_| Some(remotes.get(idx)?.keys().copied().collect())))
+            .unwrap_or_default()
+    }
+
+    /// Resolve what the session connects to, before the caller is locked
+    fn prepare_connect_at(&self, idx: usize, meta: UDHyperMeta) -> core::option::Option<ConnectTarget> {
+        // the RC service listens on the same NIC as the UD one
+        let (handler_id, conn_meta) = (
+            meta.qd_hint - crate::rpc_service::QD_HINT_BASE,
+            os_network::rdma::ConnMeta {
+                gid: meta.gid,
+                service_id: crate::rdma_context::RC_SERVICE_ID_BASE
+                    + (meta.service_id - crate::rdma_context::SERVICE_ID_BASE),
+                port: meta.local_port,
+            },
+        );
+
+        // fetch by sidr connect
+        let endpoint = self.create_meta_at(idx, meta)?;
+        let my_gid = self.contexts.get(idx).unwrap().query_gid(1, 0).unwrap();
+        let (hint, service_id) = self.metas.get(idx).unwrap().clone();
+
+        Some(ConnectTarget {
+            ud: UDTarget {
+                endpoint: endpoint,
+                my_gid: my_gid,
+                my_qd_hint: hint,
+                my_service_id: service_id,
+            },
+            handler_id: handler_id,
+            conn_meta: conn_meta,
+            my_rc_service_id: *self.rc_service_ids.get(idx)?,
+        })
+    }
+
+    /// Connect the session of the locked caller over the transport.
+    /// Over RC, `ud_caller` looks up the remote handler's mailbox first.
+    fn connect_with(
+        transport: RPCTransport,
+        caller: &mut RPCCaller,
+        ud_caller: core::option::Option<&BoxedLockBundler<RPCCaller>>,
+        lanes: &mut HashMap<usize, usize>,
+        session_id: usize,
+        my_session_id: usize,
+        target: ConnectTarget,
+    ) -> core::option::Option<()> {
+        match transport {
+            RPCTransport::UD => Self::connect_ud(caller, session_id, my_session_id, target.ud),
+            RPCTransport::RC => {
+                let (handler_id, my_gid) = (target.handler_id, target.ud.my_gid);
+                let ud = target.ud;
+                let remote_mailbox = ud_caller?.lock(|ud_caller| {
+                    Self::lookup_mailbox(ud_caller, session_id, my_session_id, handler_id, ud)
+                })?;
+                let (conn_meta, my_rc_service_id) = (target.conn_meta, target.my_rc_service_id);
+                Self::connect_rc(caller, lanes, session_id, my_session_id, conn_meta, remote_mailbox, my_gid, my_rc_service_id)
+            }
+        }
+    }
+
+    fn connect_ud(
+        caller: &mut RPCCaller,
+        session_id: usize,
+        my_session_id: usize,
+        target: UDTarget,
+    ) -> core::option::Option<()> {
+        let (my_gid, hint, service_id) = (target.my_gid, target.my_qd_hint, target.my_service_id);
+        if caller.session_connected(session_id) {
+            crate::log::warn!("The session {} has already connected.", session_id);
+            return None;
+        }
+
+        let client_session = caller
+            .get_transport_mut()
+            .as_ud_mut()?
+            .get_transport_mut()
+            .create(target.endpoint)
+            .unwrap();
+        let local_port_num = client_session.get_inner().get_qp().port_num();
+
+        // send the connect message
+        caller
+            .connect(
+                session_id,
+                my_session_id,
+                RPCSession::UD(client_session),
+                RPCHyperMeta::UD(UDHyperMeta {
+                    gid: my_gid,
+                    service_id: service_id as _,
+                    qd_hint: hint as _,
+                    local_port: local_port_num,
+                }),
+            )
+            .unwrap();
+
+        // wait for the completion.
+        // The remote drops the connect request if we are not authenticated.
+        let res = {
+            let mut timeout_caller = TimeoutWRef::new(caller, CONNECT_TIMEOUT_USEC);
+            os_network::block_on(&mut timeout_caller)
+        };
+        let (msg, _reply) = match res {
+            Ok(r) => r,
+            Err(e) => {
+                crate::log::error!(
+                    "failed to connect session {}: {:?}, is the RPC auth token the same as the remote's?",
                     session_id,
-                    my_session_id,
-                    client_session,
-                    UDHyperMeta {
-                        gid: my_gid,
-                        service_id: service_id as _,
-                        qd_hint: hint as _,
-                        local_port: local_port_num,
-                    },
-                )
-                .unwrap();
-    
-            // wait for the completion
-            let (msg, _reply) = os_network::block_on(caller).expect("should succeed");
-    
-            // TODO: check the_reply is correct
-    
-            caller.register_recv_buf(msg).unwrap();
+                    e
+                );
+                caller.remove_session(session_id);
+                return None;
+            }
+        };
 
-            Some(())
+        // TODO: check the_reply is correct
+
+        caller.register_recv_buf(msg).unwrap();
+
+        Some(())
+    }
+
+    /// Look up our lane of the remote handler's mailbox over UD, before the session connects over RC.
+    /// The UD session is closed afterwards, and the remote assigns the lane to our session ID.
+    fn lookup_mailbox(
+        caller: &mut RPCCaller,
+        session_id: usize,
+        my_session_id: usize,
+        handler_id: usize,
+        target: UDTarget,
+    ) -> core::option::Option<RCMailboxMeta> {
+        use crate::rpc_handlers::{MailboxLookupReply, MailboxLookupReq, RPCId};
+        use os_network::serialize::Serialize;
+
+        Self::connect_ud(caller, session_id, my_session_id, target)?;
+
+        let res = caller
+            .call_async(
+                session_id,
+                my_session_id,
+                RPCId::Mailbox as _,
+                MailboxLookupReq {
+                    handler_id: handler_id,
+                },
+            )
+            .ok()
+            .and_then(|req_id| {
+                let mut reply_future = caller.get_reply(req_id);
+                let mut timeout_caller =
+                    TimeoutWRef::new(&mut reply_future, CONNECT_TIMEOUT_USEC);
+                let res = os_network::block_on(&mut timeout_caller).ok();
+                if res.is_none() {
+                    caller.cancel(req_id);
+                }
+                res
+            })
+            .and_then(|(msg, reply)| {
+                let reply = MailboxLookupReply::deserialize(&reply);
+                caller.register_recv_buf(msg).unwrap();
+                reply
+            });
+        if caller.disconnect(session_id).is_err() {
+            crate::log::warn!("failed to close the UD session {}", session_id);
+        }
+
+        match res {
+            Some(r) => Some(RCMailboxMeta {
+                addr: r.addr,
+                rkey: r.rkey,
+                depth: r.depth,
+            }),
+            None => {
+                crate::log::error!("failed to look up the mailbox of session {}", session_id);
+                None
+            }
+        }
+    }
+
+    /// Connect the session over RC, by writing to the remote handler's mailbox
+    fn connect_rc(
+        caller: &mut RPCCaller,
+        lanes: &mut HashMap<usize, usize>,
+        session_id: usize,
+        my_session_id: usize,
+        conn_meta: os_network::rdma::ConnMeta,
+        remote_mailbox: RCMailboxMeta,
+        my_gid: crate::KRdmaKit::rdma_shim::bindings::ib_gid,
+        service_id: u64,
+    ) -> core::option::Option<()> {
+        let port = conn_meta.port;
+
+        // a re-connected session reuses its lane
+        let lane = match lanes.get(&session_id) {
+            Some(lane) => *lane,
+            None => {
+                let lane = caller.get_transport_mut().as_rc_mut()?.add_lane()?;
+                lanes.insert(session_id, lane);
+                lane
+            }
+        };
+        let my_mailbox = caller.get_transport_mut().as_rc_mut()?.get_lane_meta(lane)?;
+
+        let client_session = match RPCFactory::create(
+            caller.get_transport_mut().as_rc_mut()?,
+            RCSessionMeta {
+                conn: conn_meta,
+                mailbox: remote_mailbox,
+            },
+        ) {
+            Ok(s) => s,
+            Err(_) => {
+                crate::log::error!("failed to create the RC session {}", session_id);
+                return None;
+            }
+        };
+
+        if let Err(e) = caller.connect(
+            session_id,
+            my_session_id,
+            RPCSession::RC(client_session),
+            RPCHyperMeta::RC(RCHyperMeta {
+                gid: my_gid,
+                service_id: service_id,
+                port: port,
+                mailbox: my_mailbox,
+            }),
+        ) {
+            crate::log::error!("failed to connect session {} over RC: {:?}", session_id, e);
+            return None;
+        }
+
+        // the remote connects back to us before the reply
+        let res = {
+            let mut timeout_caller = TimeoutWRef::new(caller, CONNECT_TIMEOUT_USEC);
+            os_network::block_on(&mut timeout_caller)
+        };
+        match res {
+            Ok((msg, _reply)) => {
+                caller.register_recv_buf(msg).unwrap();
+                Some(())
+            }
+            Err(e) => {
+                crate::log::error!("failed to connect session {} over RC: {:?}", session_id, e);
+                caller.remove_session(session_id);
+                None
+            }
+        }
+    }
+
+    /// Close the session, and notify the remote to release it
+    pub fn disconnect_session_at(&'a mut self, idx: usize, session_id: usize) -> core::option::Option<()> {
+        let remotes = self.remotes.get_mut(idx)?;
+        self.pool.get(idx)?.lock(|caller| {
+            if !caller.session_connected(session_id) {
+                return None;
+            }
+            remotes.remove(&session_id);
+            caller.disconnect(session_id).ok()
         })
     }
 
+    /// Remove the session without notifying the remote, e.g., it is down.
+    /// Return the number of the in-flight calls failed.
+    pub fn remove_session_at(&'a mut self, idx: usize, session_id: usize) -> core::option::Option<usize> {
+        let remotes = self.remotes.get_mut(idx)?;
+        Some(self.pool.get(idx)?.lock(|caller| {
+            remotes.remove(&session_id);
+            caller.remove_session(session_id)
+        }))
+    }
+
     #[inline]
     pub fn create_meta_at(
         &self,
@@ -131,10 +492,13 @@ impl<'a> CallerPool<'a> {
         let mut factories = Vec::new();
         let mut contexts = Vec::new();
         let mut metas = Vec::new();
+        let (mut ud_pool, mut lanes, mut rc_service_ids) = (Vec::new(), Vec::new(), Vec::new());
+        let mut remotes = Vec::new();
 
         for i in 0..config.max_core_cnt {
             let nic_idx = i % config.num_nics_used;
-            let client_ud_hint = crate::rpc_service::QD_HINT_BASE + config.rpc_threads_num * 2 + i;
+            // above the hints of all the endpoints, which can be added at runtime
+            let client_ud_hint = crate::rpc_service::QD_HINT_BASE + crate::MAX_RPC_THREADS_CNT * 2 + i;
 
             // acquire all the contexts
             let context = unsafe {
@@ -155,7 +519,8 @@ impl<'a> CallerPool<'a> {
                 .set_qd_hint(client_ud_hint)
                 .create(client_ud.clone());
 
-            let mut caller = UDCaller::new(client_receiver);
+            let mut caller = RPCCaller::new(RPCReceiver::UD(client_receiver));
+            caller.set_auth_token(config.rpc_auth_token);
             for _ in 0..64 {
                 caller
                     .register_recv_buf(UDMsg::new(4096, 0, client_ud.get_qp().ctx().clone()))
@@ -164,7 +529,28 @@ impl<'a> CallerPool<'a> {
             }
             let caller = LockBundler::new(caller);
 
-            pool.push(caller);
+            if config.rpc_transport == RPCTransport::UD {
+                pool.push(caller);
+            } else {
+                ud_pool.push(caller);
+
+                // the lanes are added when the sessions connect
+                let client_receiver = RCReceiverFactory::new()
+                    .set_lanes(0)
+                    .set_depth(crate::rpc_service::RPC_LANE_DEPTH)
+                    .create(context);
+                let mut caller = RPCCaller::new(RPCReceiver::RC(client_receiver));
+                caller.set_auth_token(config.rpc_auth_token);
+                for _ in 0..64 {
+                    caller
+                        .register_recv_buf(UDMsg::new(4096, 0, context.clone()))
+                        .expect("failed to register receive buffer for the RPC caller");
+                }
+                pool.push(LockBundler::new(caller));
+            }
+            lanes.push(HashMap::new());
+            rc_service_ids.push(crate::rdma_context::RC_SERVICE_ID_BASE + nic_idx as u64);
+            remotes.push(HashMap::new());
             factories.push(factory);
             contexts.push(context);
             metas.push((client_ud_hint, cm_server.listen_id()))
@@ -172,6 +558,11 @@ impl<'a> CallerPool<'a> {
 
         Some(Self {
             pool: pool,
+            transport: config.rpc_transport,
+            ud_pool: ud_pool,
+            lanes: lanes,
+            rc_service_ids: rc_service_ids,
+            remotes: remotes,
             factories: factories,
             contexts: contexts,
             metas: metas,
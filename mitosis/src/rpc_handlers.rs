@@ -1,4 +1,5 @@
 use core::fmt::Write;
+use core::sync::atomic::{AtomicUsize, Ordering};
 use os_network::bytes::BytesMut;
 use os_network::serialize::Serialize;
 
@@ -11,6 +12,12 @@ pub enum RPCId {
     Echo = 2,
     // Resume fork by fetching remote descriptor
     Query = 3,
+    // Push the hot pages of the image into the child
+    Push = 4,
+    // Look up the mailbox of a handler, to connect the RPC session over RC
+    Mailbox = 5,
+    // Query the number of the endpoints, to spread the sessions over them
+    Endpoints = 6,
 }
 
 pub(crate) fn handle_nil(_input: &BytesMut, _output: &mut BytesMut) -> usize {
@@ -23,11 +30,48 @@ pub(crate) fn handle_echo(input: &BytesMut, output: &mut BytesMut) -> usize {
     64
 }
 
+// the queries (and pushes) of the images without their secrets
+static REJECTED_QUERIES: AtomicUsize = AtomicUsize::new(0);
+
+/// Number of the requests rejected for lack of the secret of the image
+pub fn rejected_queries() -> usize {
+    REJECTED_QUERIES.load(Ordering::Relaxed)
+}
+
+/// Check the secret of the image presented by a request, and count the rejected ones.
+/// The unknown images are rejected as well, yet not counted.
+///
+/// The secret is carried in plaintext and compared with `==`, so it keeps a child
+/// from resuming the wrong image by mistake, rather than an attacker on the fabric.
+pub(crate) fn authorize(key: usize, secret: u64) -> bool {
+    let process_service = unsafe { crate::get_sps_ref() };
+    if process_service.check_secret(key, secret) {
+        return true;
+    }
+    if process_service.query_descriptor(key).is_some() {
+        crate::log::warn!("reject the request of the image {}: bad secret", key);
+        REJECTED_QUERIES.fetch_add(1, Ordering::Relaxed);
+    }
+    false
+}
+
+/// The child looks up the image with its secret given at prepare.
+/// Not `Debug`, so the secret is never logged, though it is sent in plaintext.
+#[derive(Default, Copy, Clone)]
+pub(crate) struct DescriptorQueryReq {
+    pub(crate) key: usize,
+    pub(crate) secret: u64,
+}
+
+impl os_network::serialize::Serialize for DescriptorQueryReq {}
+
 #[derive(Debug, Default, Copy, Clone)]
 pub(crate) struct DescriptorLookupReply {
     pub(crate) pa: u64,
     pub(crate) sz: usize,
     pub(crate) ready: bool,
+    // distinguishes the images registered with the same key over time
+    pub(crate) generation: u64,
 
     // for remote dct access
     pub(crate) rkey: u32,
@@ -44,11 +88,25 @@ pub(crate) struct DescriptorLookupReply {
 impl os_network::serialize::Serialize for DescriptorLookupReply {}
 
 pub(crate) fn handle_descriptor_addr_lookup(input: &BytesMut, output: &mut BytesMut) -> usize {
-    let mut key: usize = 0;
-    unsafe { input.memcpy_deserialize(&mut key) };
+    let req = match DescriptorQueryReq::deserialize(input) {
+        Some(req) => req,
+        None => {
+            crate::log::error!("failed to deserialize the query request");
+            return os_network::rpc::service::MALFORMED_REQ;
+        }
+    };
+    let key = req.key;
+
+    // the rejected ones are replied as the unknown keys,
+    // so the keys cannot be probed without their secrets
+    if !authorize(key, req.secret) {
+        crate::log::error!("empty addr, key:{}!", key);
+        return 0; // a null reply indicate that the we don't have the key
+    }
 
     let process_service = unsafe { crate::get_sps_mut() };
     let buf = process_service.query_descriptor_buf(key);
+    let generation = process_service.query_generation(key).unwrap_or(0);
 
     if buf.is_none() {
         crate::log::error!("empty addr, key:{}!", key);
@@ -74,6 +132,7 @@ pub(crate) fn handle_descriptor_addr_lookup(input: &BytesMut, output: &mut Bytes
                 pa: addr.get_pa(),
                 sz: len,
                 ready: true,
+                generation: generation,
 
                 rkey: dc_target.ctx().rkey(),
                 lid: lid as u32,
@@ -91,6 +150,7 @@ pub(crate) fn handle_descriptor_addr_lookup(input: &BytesMut, output: &mut Bytes
                 pa: 0,
                 sz: 0,
                 ready: false,
+                generation: 0,
 
                 rkey: 0,
                 lid: 0,
@@ -108,3 +168,100 @@ pub(crate) fn handle_descriptor_addr_lookup(input: &BytesMut, output: &mut Bytes
     reply.serialization_buf_len()
 }
 
+
+#[derive(Debug, Default, Copy, Clone)]
+pub(crate) struct EndpointsReply {
+    pub(crate) num: usize,
+}
+
+impl os_network::serialize::Serialize for EndpointsReply {}
+
+pub(crate) fn handle_endpoints_query(_input: &BytesMut, output: &mut BytesMut) -> usize {
+    let reply = EndpointsReply {
+        num: unsafe { crate::service_rpc::get_ref() }.get_endpoints_num(),
+    };
+    reply.serialize(output);
+    reply.serialization_buf_len()
+}
+
+/// The caller asks for a lane of the handler's mailbox, see `os_network::rpc::impls::rc`.
+/// The lane is assigned to the session the request comes from.
+#[derive(Debug, Default, Copy, Clone)]
+pub(crate) struct MailboxLookupReq {
+    pub(crate) handler_id: usize,
+}
+
+impl os_network::serialize::Serialize for MailboxLookupReq {}
+
+#[derive(Debug, Default, Copy, Clone)]
+pub(crate) struct MailboxLookupReply {
+    pub(crate) addr: u64,
+    pub(crate) rkey: u32,
+    pub(crate) depth: u32,
+}
+
+impl os_network::serialize::Serialize for MailboxLookupReply {}
+
+/// The session ID is the one authenticated by the hook, not given by the caller,
+/// so a caller cannot take (or look up) the lane of another one
+pub(crate) fn handle_mailbox_lookup(
+    handler_id: usize,
+    mailbox: &crate::lock_bundler::BoxedLockBundler<crate::rpc_service::Mailbox>,
+    session_id: usize,
+    input: &BytesMut,
+    output: &mut BytesMut,
+) -> usize {
+    let req = match MailboxLookupReq::deserialize(input) {
+        Some(req) => req,
+        None => {
+            crate::log::error!("failed to deserialize the mailbox lookup request");
+            return os_network::rpc::service::MALFORMED_REQ;
+        }
+    };
+    if req.handler_id != handler_id {
+        crate::log::error!(
+            "mailbox lookup of handler {} sent to handler {}",
+            req.handler_id,
+            handler_id
+        );
+        return os_network::rpc::service::MALFORMED_REQ;
+    }
+
+    let meta = match mailbox.lock(|m| m.assign(session_id)) {
+        Some(meta) => meta,
+        None => {
+            crate::log::error!(
+                "no free lane in the mailbox of handler {} for session {}",
+                handler_id,
+                session_id
+            );
+            return 0; // a null reply indicate that no lane is assigned
+        }
+    };
+    let reply = MailboxLookupReply {
+        addr: meta.addr,
+        rkey: meta.rkey,
+        depth: meta.depth,
+    };
+    reply.serialize(output);
+    reply.serialization_buf_len()
+}
+
+pub(crate) fn handle_push_pages(input: &BytesMut, output: &mut BytesMut) -> usize {
+    use crate::push_resume::PushPagesReq;
+
+    let req = match crate::push_resume::deserialize_boxed::<PushPagesReq>(input) {
+        Some(req) => req,
+        None => {
+            crate::log::error!("failed to deserialize the push request");
+            return os_network::rpc::service::MALFORMED_REQ;
+        }
+    };
+
+    // the pages are written in the background
+    let reply = crate::push_resume::accept_push(&req);
+    crate::log::debug!("plan to push {} pages for handler {}", reply.planned, req.handler_id);
+
+    reply.serialize(output);
+    reply.serialization_buf_len()
+}
@@ -1,4 +1,5 @@
 use alloc::boxed::Box;
+use alloc::sync::Arc;
 use alloc::vec::Vec;
 
 use os_network::KRdmaKit::comm_manager::Explorer;
@@ -11,6 +12,44 @@ use core::sync::atomic::{AtomicUsize, Ordering};
 
 static RPC_HANDLER_READY_NUM: AtomicUsize = AtomicUsize::new(0);
 
+// the connect requests rejected by all the handlers, for lack of the auth token
+static REJECTED_CONNECTS: AtomicUsize = AtomicUsize::new(0);
+
+/// Number of the (dis-)connect requests rejected for lack of the auth token, see `Config::rpc_auth_token`
+pub fn rejected_connects() -> usize {
+    REJECTED_CONNECTS.load(Ordering::Relaxed)
+}
+
+/// Convert the statistics to the format copied to the user, see `LibMITOSISCmd::RpcStats`
+pub fn analysis_to_user(analysis: &RPCAnalysis) -> crate::bindings::rpc_side_stats_t {
+    let mut stats: crate::bindings::rpc_side_stats_t = Default::default();
+    let errors = analysis.get_errors();
+    stats.ncalls = analysis.get_ncalls() as _;
+    stats.timeouts = errors.timeout as _;
+    stats.no_ids = errors.no_id as _;
+    stats.corrupted = errors.corrupted as _;
+    stats.other_errors = errors.other as _;
+    stats.bytes_in = analysis.get_bytes_in() as _;
+    stats.bytes_out = analysis.get_bytes_out() as _;
+    stats.queue_depth = analysis.get_queue_depth() as _;
+    stats.max_queue_depth = analysis.get_max_queue_depth() as _;
+    stats.sessions = analysis.get_sessions().len() as _;
+
+    for (rpc_id, histogram) in analysis.get_latencies().iter() {
+        let latency = match stats.latencies.get_mut(*rpc_id) {
+            Some(l) => l,
+            None => continue,
+        };
+        latency.count = histogram.get_count() as _;
+        latency.avg_usec = histogram.get_avg_usec() as _;
+        latency.max_usec = histogram.get_max_usec() as _;
+        for (b, h) in latency.buckets.iter_mut().zip(histogram.get_buckets().iter()) {
+            *b = *h as _;
+        }
+    }
+    stats
+}
+
 pub(crate) fn wait_handlers_ready_barrier(wait_num: usize) {
     loop {
         if RPC_HANDLER_READY_NUM.load(Ordering::SeqCst) >= wait_num {
@@ -58,6 +97,30 @@ impl HandlerConnectInfo {
     }
 }
 
+impl HandlerConnectInfo {
+    /// The endpoint for the `idx`-th caller, among the `endpoints` of the remote machine
+    /// on the same NIC as this one, so that the callers' load is balanced across them.
+    ///
+    /// FIXME: like `create_with_port`, we assume that the remote machine has the same
+    /// number of NICs used as ourself.
+    pub fn spread(&self, idx: usize, endpoints: usize) -> Self {
+        let mut res = self.clone();
+        res.qd_hint = Self::spread_qd_hint(self.qd_hint, idx, endpoints);
+        res
+    }
+
+    /// The qd_hint of the endpoint for the `idx`-th caller, see `spread`
+    pub fn spread_qd_hint(qd_hint: usize, idx: usize, endpoints: usize) -> usize {
+        let nics = unsafe { *crate::max_nics_used::get_ref() };
+        let nic = (qd_hint - QD_HINT_BASE) % nics;
+        let per_nic = (endpoints + nics - 1 - nic) / nics;
+        if per_nic == 0 {
+            return qd_hint;
+        }
+        Service::calculate_qd_hint(nic + nics * (idx % per_nic))
+    }
+}
+
 impl Clone for HandlerConnectInfo {
     fn clone(&self) -> HandlerConnectInfo {
         Self {
@@ -69,112 +132,405 @@ impl Clone for HandlerConnectInfo {
     }
 }
 
-/// RPC service implements a UDRpcHook defined in os-network
+/// RPC service implements the RPC hooks defined in os-network
 /// Note: It must be created after all the RDMA context has been initialized.
+///
+/// The remote callers connect to the endpoints, each of which has its own hooks.
+/// The handler threads poll all the endpoints (one thread at a time for each endpoint),
+/// so they can be added or removed at runtime without touching the connected sessions,
+/// see `set_handlers`.
+///
 /// Session ID mapping:
 ///    The RPC caller that connects to the machine (mac_id)'s (thread_id)'s:
 ///    * session_id = mac_id * max_rpc_threads + thread_id
-#[derive(Default)]
+///
+/// Over RC (see `Config::rpc_transport`), the sessions are connected back to the callers
+/// by the connector thread, so the handshakes never block the handler threads.
 pub struct Service {
-    threads: Vec<JoinHandler>,
-    connect_infos: Vec<HandlerConnectInfo>,
+    threads: BoxedLockBundler<Vec<JoinHandler>>,
+    worker: Option<extern "C" fn(*mut c_void) -> c_int>,
+    // creates the sessions over RC, if any
+    connector: Option<JoinHandler>,
+    endpoints: Arc<Endpoints>,
+    connect_infos: BoxedLockBundler<Vec<HandlerConnectInfo>>,
+    // to create the endpoints added at runtime
+    config: crate::Config,
+    // notified by the endpoints' receive CQs, dropped after them
+    waiter: Arc<Box<CQWaiter>>,
 }
 
+impl Default for Service {
+    fn default() -> Self {
+        Self {
+            threads: LockBundler::new(Vec::new()),
+            worker: None,
+            connector: None,
+            endpoints: Arc::new(Endpoints::new(crate::MAX_RPC_THREADS_CNT)),
+            connect_infos: LockBundler::new(Vec::new()),
+            config: Default::default(),
+            waiter: Arc::new(CQWaiter::new()),
+        }
+    }
+}
+
+/// The endpoints added so far, at most `MAX_RPC_THREADS_CNT` of them.
+/// Their slots never move, so the threads poll them without locking the list,
+/// while the new ones are added, see `Service::set_handlers`.
+pub(crate) struct Endpoints {
+    slots: Vec<BoxedLockBundler<Option<Endpoint>>>,
+    num: AtomicUsize,
+}
+
+impl Endpoints {
+    fn new(max: usize) -> Self {
+        Self {
+            slots: (0..max).map(|_| LockBundler::new(None)).collect(),
+            num: AtomicUsize::new(0),
+        }
+    }
+
+    #[inline]
+    fn len(&self) -> usize {
+        self.num.load(Ordering::Acquire)
+    }
+
+    #[inline]
+    fn get(&self, idx: usize) -> core::option::Option<&BoxedLockBundler<Option<Endpoint>>> {
+        self.slots[..self.len()].get(idx)
+    }
+
+    #[inline]
+    fn iter(&self) -> core::slice::Iter<'_, BoxedLockBundler<Option<Endpoint>>> {
+        self.slots[..self.len()].iter()
+    }
+
+    /// Add the endpoint after the others, by one thread at a time.
+    /// Return false if all the slots are taken.
+    fn push(&self, endpoint: Endpoint) -> bool {
+        let num = self.len();
+        match self.slots.get(num) {
+            Some(slot) => slot.lock(|e| *e = Some(endpoint)),
+            None => return false,
+        };
+        self.num.store(num + 1, Ordering::Release);
+        true
+    }
+}
+
+/// The lanes of an endpoint's mailbox, see `os_network::rpc::impls::rc`
+pub(crate) struct Mailbox {
+    lanes: Vec<RCMailboxMeta>,
+    // the lane of each remote caller (by its session ID), so a re-connected caller reuses it
+    assigned: HashMap<usize, usize>,
+    // the lanes not assigned, including the ones of the released sessions
+    free: Vec<usize>,
+}
+
+impl Mailbox {
+    fn new(lanes: Vec<RCMailboxMeta>) -> Self {
+        let free = (0..lanes.len()).rev().collect();
+        Self {
+            lanes: lanes,
+            assigned: HashMap::new(),
+            free: free,
+        }
+    }
+
+    /// The lane that the caller of the session writes to.
+    /// Return None if all the lanes are taken.
+    pub(crate) fn assign(&mut self, session_id: usize) -> core::option::Option<RCMailboxMeta> {
+        let lane = match self.assigned.get(&session_id) {
+            Some(lane) => *lane,
+            None => {
+                let lane = self.free.pop()?;
+                self.assigned.insert(session_id, lane);
+                lane
+            }
+        };
+        self.lanes.get(lane).copied()
+    }
+
+    /// Hand the lane of the released session to the next caller
+    fn release(&mut self, session_id: usize) {
+        if let Some(lane) = self.assigned.remove(&session_id) {
+            self.free.push(lane);
+        }
+    }
+}
+
+use crate::lock_bundler::{BoxedLockBundler, LockBundler};
+use os_network::rpc::analysis::RPCAnalysis;
+
+use hashbrown::HashMap;
+use os_network::rdma::rc::RCFactory;
+use os_network::rpc::impls::rc::{
+    RCMailboxMeta, RCReceiver, RCReceiverFactory, RCSession, RCSessionMeta,
+};
+use os_network::rpc::impls::transport::RPCTransport;
+use os_network::KRdmaKit::QueuePair;
+
+use crate::kern_wrappers::cq_waiter::CQWaiter;
+
+/// Number of the message slots of each lane, also the credits of a session over RC
+pub const RPC_LANE_DEPTH: usize = 8;
+
 impl core::fmt::Debug for Service {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         f.debug_struct("MitosisRPCService")
-            .field("threads_num", &self.threads.len())
-            .field("connect_infos", &self.connect_infos)
+            .field("threads_num", &self.get_handlers_num())
+            .field("endpoints_num", &self.get_endpoints_num())
             .finish()
     }
 }
 
 impl Service {
     pub fn get_connect_info(&self, idx: usize) -> core::option::Option<HandlerConnectInfo> {
-        self.connect_infos.get(idx).map(|c| c.clone())
+        self.connect_infos.lock(|infos| infos.get(idx).map(|c| c.clone()))
     }
 
     pub fn calculate_qd_hint(idx: usize) -> usize {
         QD_HINT_BASE + idx
     }
-}
 
-impl Service {
-    /// Deprecated
-    pub fn new(config: &crate::Config) -> core::option::Option<Self> {
-        let mut res = Self {
-            threads: Vec::new(),
-            connect_infos: Vec::new(),
+    /// Number of the endpoints that the remote callers can connect to
+    #[inline]
+    pub fn get_endpoints_num(&self) -> usize {
+        self.endpoints.len()
+    }
+
+    /// Number of the running handler threads
+    pub fn get_handlers_num(&self) -> usize {
+        self.threads.lock(|threads| threads.len())
+    }
+
+    /// Start or stop the handler threads, until `num` of them are running.
+    /// At least one, and at most `MAX_RPC_THREADS_CNT` threads are kept.
+    ///
+    /// Since an endpoint is polled by one thread at a time, the endpoints are added
+    /// until there are as many as the threads, and the remote callers move their idle
+    /// sessions onto the new ones, see `startup::rebalance_remote_rpc_end`.
+    /// The endpoints are never removed, so their sessions stay connected.
+    ///
+    /// Return the number of the running threads.
+    pub fn set_handlers(&self, num: usize) -> usize {
+        let num = core::cmp::min(core::cmp::max(num, 1), crate::MAX_RPC_THREADS_CNT);
+        let worker = match self.worker {
+            Some(w) => w,
+            None => return 0,
         };
+        self.threads.lock(|threads| {
+            while self.endpoints.len() < num {
+                let id = self.endpoints.len();
+                let added = self
+                    .create_endpoint(id)
+                    .map(|e| self.endpoints.push(e))
+                    .unwrap_or(false);
+                if !added {
+                    crate::log::error!("failed to add RPC endpoint {}", id);
+                    break;
+                }
+            }
+            while threads.len() > num {
+                threads.pop().map(|handler| handler.join());
+            }
+            while threads.len() < num {
+                match Self::spawn_handler(threads.len(), &self.endpoints, &self.waiter, worker) {
+                    Some(handler) => threads.push(handler),
+                    None => {
+                        crate::log::error!("failed to start RPC handler thread {}", threads.len());
+                        break;
+                    }
+                }
+            }
+            threads.len()
+        })
+    }
 
-        // init the RPC connect info
-        for i in 0..config.rpc_threads_num {
-            let nic_to_use = i % config.num_nics_used;
-            let local_context = unsafe { crate::rdma_contexts::get_ref().get(nic_to_use).unwrap() };
-            let qd_hint = Self::calculate_qd_hint(i);
-
-            res.connect_infos.push(HandlerConnectInfo {
-                gid: Explorer::gid_to_string(&local_context.query_gid(config.default_nic_port, 0).ok()?),
-                service_id: crate::rdma_context::SERVICE_ID_BASE + nic_to_use as u64,
-                qd_hint: qd_hint,
-                local_port: config.default_nic_port,
-            });
+    fn spawn_handler(
+        id: usize,
+        endpoints: &Arc<Endpoints>,
+        waiter: &Arc<Box<CQWaiter>>,
+        worker: extern "C" fn(*mut c_void) -> c_int,
+    ) -> core::option::Option<JoinHandler> {
+        let arg = Box::new(ThreadCTX {
+            id: id,
+            endpoints: endpoints.clone(),
+            waiter: waiter.clone(),
+        });
+        let arg_ptr = Box::into_raw(arg);
+
+        let builder = kthread::Builder::new()
+            .set_name(alloc::format!("MITOSIS RPC handler Thread {}", id))
+            .set_parameter(arg_ptr as *mut c_void);
+        builder.spawn(worker).ok()
+    }
+
+    /// The statistics of all the endpoints
+    pub fn get_analysis(&self) -> RPCAnalysis {
+        let mut res = RPCAnalysis::new();
+        for endpoint in self.endpoints.iter() {
+            endpoint.lock(|e| e.as_ref().map(|e| e.merge_analysis(&mut res)));
         }
+        res
+    }
 
-        for i in 0..config.rpc_threads_num {
-            let arg = Box::new(ThreadCTX {
-                id: i,
-                nic_to_use: i % config.num_nics_used,
-            });
-            let arg_ptr = Box::into_raw(arg);
+    /// Create the mailbox of an endpoint, whose lanes are handed out by `Mailbox::assign`
+    fn create_mailbox(
+        config: &crate::Config,
+        nic_idx: usize,
+    ) -> (RCReceiver, Arc<BoxedLockBundler<Mailbox>>) {
+        let context = unsafe { crate::rdma_contexts::get_ref().get(nic_idx).unwrap() };
+        let receiver = RCReceiverFactory::new()
+            .set_lanes(config.rpc_mailbox_lanes)
+            .set_depth(RPC_LANE_DEPTH)
+            .create(context);
+        let lanes = (0..receiver.get_lanes())
+            .map(|i| receiver.get_lane_meta(i).unwrap())
+            .collect();
+        (receiver, Arc::new(LockBundler::new(Mailbox::new(lanes))))
+    }
+
+    /// Create the endpoint `id`, and the info for the remote callers to connect to it
+    fn create_endpoint(&self, id: usize) -> core::option::Option<Endpoint> {
+        let config = &self.config;
+        let nic_to_use = id % config.num_nics_used;
+        let local_context = unsafe { crate::rdma_contexts::get_ref().get(nic_to_use)? };
+
+        let info = HandlerConnectInfo {
+            gid: Explorer::gid_to_string(&local_context.query_gid(config.default_nic_port, 0).ok()?),
+            service_id: crate::rdma_context::SERVICE_ID_BASE + nic_to_use as u64,
+            qd_hint: Self::calculate_qd_hint(id),
+            local_port: config.default_nic_port,
+        };
+        self.connect_infos.lock(|infos| infos.push(info));
+
+        let server_ud = unsafe {
+            crate::ud_factories::get_ref()
+                .get(nic_to_use)
+                .expect("failed to query the factory")
+                .create(UDCreationMeta { port: 1 }) // WTX: use the default port to create server-side ud
+                .expect("failed to create server UD")
+        };
+        let temp_ud = server_ud.clone();
+
+        unsafe {
+            crate::ud_service::get_mut()
+                .get(nic_to_use)
+                .unwrap()
+                .reg_qp(Self::calculate_qd_hint(id), &server_ud.get_qp());
+            // the waiter is dropped after the endpoints
+            self.waiter.attach(&server_ud.get_qp());
+        };
 
-            let builder = kthread::Builder::new()
-                .set_name(alloc::format!("MITOSIS RPC handler Thread {}", i))
-                .set_parameter(arg_ptr as *mut c_void);
-            let handler = builder.spawn(Self::worker).ok()?;
-            res.threads.push(handler);
+        let mut ud = UDRPCHook::new(
+            unsafe { crate::get_ud_factory(nic_to_use).unwrap().clone() },
+            server_ud.clone(),
+            UDReceiverFactory::new()
+                .set_qd_hint(Self::calculate_qd_hint(id) as _)
+                .create(temp_ud),
+        );
+
+        Self::register_handlers(ud.get_mut_service());
+        let mailbox = match config.rpc_transport {
+            RPCTransport::RC => Some(Self::create_mailbox(config, nic_to_use)),
+            RPCTransport::UD => None,
+        };
+        // the callers look up the mailboxes over UD, then connect over RC
+        if let Some((_, mailbox)) = mailbox.as_ref() {
+            let mailbox = mailbox.clone();
+            ud.get_mut_service().register_idempotent_with_session(
+                RPCId::Mailbox as _,
+                move |session_id, input, output| {
+                    handle_mailbox_lookup(id, &mailbox, session_id, input, output)
+                },
+            );
         }
 
-        crate::log::debug!("RPC service creation done: {:?}", res);
+        // only the machines sharing the token can connect
+        ud.set_auth_token(config.rpc_auth_token);
 
-        Some(res)
+        // register msg buffers
+        // pre-most receive buffers
+        for _ in 0..2048 {
+            // 64 is the header
+            match ud.post_msg_buf(UDMsg::new(4096, 0, server_ud.get_qp().ctx().clone())) {
+                Ok(_) => {}
+                Err(e) => crate::log::error!("post recv buf err: {:?}", e),
+            }
+        }
+
+        // the live callers send heartbeats, see `crate::peer_monitor`
+        ud.set_session_timeout(2 * os_network::rpc::HeartbeatConfig::default().down_timeout_usec());
+
+        let lanes = mailbox.as_ref().map(|(_, mailbox)| mailbox.clone());
+        let rc = mailbox.map(|(receiver, mailbox)| {
+            let mut rc = RCRPCHook::new(
+                Arc::new(RCFactory::new(local_context)),
+                RCFactory::new(local_context),
+                receiver,
+            );
+            Self::register_handlers(rc.get_mut_service());
+            rc.set_auth_token(config.rpc_auth_token);
+            // the connector thread connects back to the callers, see `Self::connector`
+            rc.set_deferred_connect(true);
+            rc.set_release_callback(move |session_id| {
+                mailbox.lock(|m| m.release(session_id))
+            });
+            for _ in 0..2048 {
+                match rc.post_msg_buf(UDMsg::new(4096, 0, local_context.clone())) {
+                    Ok(_) => {}
+                    Err(e) => crate::log::error!("post recv buf err: {:?}", e),
+                }
+            }
+            rc.set_session_timeout(
+                2 * os_network::rpc::HeartbeatConfig::default().down_timeout_usec(),
+            );
+            rc
+        });
+
+        crate::log::info!(
+            "MITOSIS RPC endpoint {} created, listing on gid: {:?}",
+            id,
+            local_context.query_gid(1, 0).unwrap(), // WTX: use the default port to query gid
+        );
+
+        Some(Endpoint {
+            id: id,
+            nic: nic_to_use,
+            ud_qp: server_ud.get_qp(),
+            ud: ud,
+            rc: rc,
+            mailbox: lanes,
+            polls: 0,
+            timer: KTimer::new(),
+            rejected: 0,
+        })
     }
 }
 
 impl Service {
+    /// Deprecated
+    pub fn new(config: &crate::Config) -> core::option::Option<Self> {
+        Self::new_with_worker(config, Self::worker)
+    }
+
     pub fn new_with_worker(config: &crate::Config,
                            worker: extern "C" fn(*mut c_void) -> i32) -> core::option::Option<Self> {
         let mut res = Self {
-            threads: Vec::new(),
-            connect_infos: Vec::new(),
+            worker: Some(worker),
+            config: config.clone(),
+            ..Default::default()
         };
 
-        // init the RPC connect info
-        for i in 0..config.rpc_threads_num {
-            let nic_to_use = i % config.num_nics_used;
-            let local_context = unsafe { crate::rdma_contexts::get_ref().get(nic_to_use).unwrap() };
-            let qd_hint = Self::calculate_qd_hint(i);
-
-            res.connect_infos.push(HandlerConnectInfo {
-                gid: Explorer::gid_to_string(&local_context.query_gid(config.default_nic_port, 0).unwrap()),
-                service_id: crate::rdma_context::SERVICE_ID_BASE + nic_to_use as u64,
-                qd_hint: qd_hint,
-                local_port: config.default_nic_port,
-            });
+        // the endpoints (and their connect info) are created with the threads
+        if res.set_handlers(config.rpc_threads_num) < config.rpc_threads_num
+            || res.get_endpoints_num() < config.rpc_threads_num
+        {
+            return None;
         }
 
-        for i in 0..config.rpc_threads_num {
-            let arg = Box::new(ThreadCTX {
-                id: i,
-                nic_to_use: i % config.num_nics_used,
-            });
-            let arg_ptr = Box::into_raw(arg);
-
-            let builder = kthread::Builder::new()
-                .set_name(alloc::format!("MITOSIS RPC handler Thread {}", i))
-                .set_parameter(arg_ptr as *mut c_void);
-            let handler = builder.spawn(worker).ok()?;
-            res.threads.push(handler);
+        if config.rpc_transport == RPCTransport::RC {
+            res.connector = Some(res.spawn_connector()?);
         }
 
         crate::log::debug!("RPC service creation done: {:?}", res);
@@ -185,16 +541,22 @@ impl Service {
 
 impl Drop for Service {
     fn drop(&mut self) {
-        let total = self.threads.len();
-        for _ in 0..total {
-            self.threads.pop().map(|handler| handler.join());
+        // the endpoints are dropped after all the threads polling them stop
+        if let Some(connector) = self.connector.take() {
+            connector.join();
         }
+        self.threads.lock(|threads| {
+            while let Some(handler) = threads.pop() {
+                handler.join();
+            }
+        });
     }
 }
 
 struct ThreadCTX {
     pub(crate) id: usize,
-    pub(crate) nic_to_use: usize,
+    pub(crate) endpoints: Arc<Endpoints>,
+    pub(crate) waiter: Arc<Box<CQWaiter>>,
 }
 
 use os_network::datagram::msg::UDMsg;
@@ -209,100 +571,342 @@ pub const QD_HINT_BASE: usize = 12;
 
 use super::rpc_handlers::*;
 
-impl Service {
-    const YIELD_THRESHOLD: usize = 10000;
-    const YIELD_TIME_USEC: i64 = 2000; // 1ms
+type UDRPCHook<'a> = os_network::rpc::hook::RPCHook<'a, UDDatagram, UDReceiver, UDFactory>;
+
+type RCRPCHook<'a> = os_network::rpc::hook::RPCHook<'a, RCFactory, RCReceiver, RCFactory>;
+
+/// The hooks that the remote callers connect to
+pub(crate) struct Endpoint {
+    id: usize,
+    nic: usize,
+    // whose receive CQ notifies the idle threads
+    ud_qp: Arc<QueuePair>,
+    ud: UDRPCHook<'static>,
+    // with the mailbox looked up over `ud`, see `Config::rpc_transport`
+    rc: Option<RCRPCHook<'static>>,
+    mailbox: Option<Arc<BoxedLockBundler<Mailbox>>>,
+
+    // the silent sessions are checked every `MAINTAIN_TIME_USEC`
+    polls: usize,
+    timer: KTimer,
+    // the rejected connects already counted in `REJECTED_CONNECTS`
+    rejected: usize,
+}
 
-    #[allow(non_snake_case)]
-    pub(crate) extern "C" fn worker(ctx: *mut c_void) -> c_int {
-        let arg = unsafe { Box::from_raw(ctx as *mut ThreadCTX) };
+impl Endpoint {
+    // messages handled in a poll, so that the other endpoints are not starved
+    const POLL_BUDGET: usize = 32;
+    const CHECK_THRESHOLD: usize = 1024;
+    const MAINTAIN_TIME_USEC: i64 = 2000;
+
+    /// Handle the received messages, return the number handled
+    fn poll(&mut self) -> usize {
+        let mut handled = 0;
+        while handled < Self::POLL_BUDGET {
+            match self.ud.poll() {
+                Ok(Async::Ready(_)) => handled += 1,
+                Ok(_NotReady) => break,
+                Err(e) => {
+                    crate::log::error!(
+                        "RPC endpoint {} meets an error {:?}, status {:?}",
+                        self.id,
+                        e,
+                        self.ud
+                    );
+                    handled += 1;
+                }
+            };
+        }
+        if let Some(rc) = self.rc.as_mut() {
+            while handled < 2 * Self::POLL_BUDGET {
+                match rc.poll() {
+                    Ok(Async::Ready(_)) => handled += 1,
+                    Ok(_NotReady) => break,
+                    Err(e) => {
+                        crate::log::error!(
+                            "RPC endpoint {} meets an error {:?} over RC, status {:?}",
+                            self.id,
+                            e,
+                            rc
+                        );
+                        handled += 1;
+                    }
+                };
+            }
+        }
 
-        // init the UD RPC hook
-        type UDRPCHook<'a> =
-            os_network::rpc::hook::RPCHook<'a, UDDatagram, UDReceiver, UDFactory>;
+        self.polls += 1;
+        if core::intrinsics::unlikely(self.polls > Self::CHECK_THRESHOLD) {
+            self.polls = 0;
+            if self.timer.get_passed_usec() > Self::MAINTAIN_TIME_USEC {
+                self.maintain();
+                self.timer.reset();
+            }
+        }
+        handled
+    }
 
-        let local_context = unsafe { crate::rdma_contexts::get_ref().get(arg.nic_to_use).unwrap() };
-        crate::log::info!(
-            "MITOSIS RPC thread {} started, listing on gid: {:?}",
-            arg.id,
-            local_context.query_gid(1, 0).unwrap(), // WTX: use the default port to query gid
-        );
+    /// Expire the silent sessions, and count the rejected connects
+    fn maintain(&mut self) {
+        self.ud.expire_sessions();
+        let mut nrejected = self.ud.get_analysis().get_nrejected();
+        if let Some(rc) = self.rc.as_mut() {
+            rc.expire_sessions();
+            nrejected += rc.get_analysis().get_nrejected();
+        }
+        REJECTED_CONNECTS.fetch_add(nrejected - self.rejected, Ordering::Relaxed);
+        self.rejected = nrejected;
+    }
 
-        let server_ud = unsafe {
-            crate::ud_factories::get_ref()
-                .get(arg.nic_to_use)
-                .expect("failed to query the factory")
-                .create(UDCreationMeta { port: 1 }) // WTX: use the default port to create server-side ud
-                .expect("failed to create server UD")
-        };
-        let temp_ud = server_ud.clone();
+    fn merge_analysis(&self, res: &mut RPCAnalysis) {
+        res.merge(self.ud.get_analysis());
+        if let Some(rc) = self.rc.as_ref() {
+            res.merge(rc.get_analysis());
+        }
+    }
 
-        unsafe {
-            crate::ud_service::get_mut()
-                .get(arg.nic_to_use)
-                .unwrap()
-                .reg_qp(Service::calculate_qd_hint(arg.id), &server_ud.get_qp())
+    /// Let the next request wake up the idle threads.
+    /// Return false if it may be missed, e.g., it has arrived, or it comes over RC.
+    fn arm_notify(&self) -> bool {
+        self.rc.is_none() && CQWaiter::arm(&self.ud_qp)
+    }
+
+    /// Take the next connect request over RC, with the NIC to connect back from
+    fn pop_connect(&mut self) -> core::option::Option<(usize, usize, RCSessionMeta)> {
+        let nic = self.nic;
+        self.rc
+            .as_mut()?
+            .pop_connect()
+            .map(|(session_id, meta)| (nic, session_id, meta))
+    }
+
+    /// Reply to the caller with the session connected back to it,
+    /// or give up the session (and its lane) if it cannot be created
+    fn complete_connect(&mut self, session_id: usize, session: core::option::Option<RCSession>) {
+        let rc = match self.rc.as_mut() {
+            Some(rc) => rc,
+            None => return,
         };
+        match session {
+            Some(session) => match rc.complete_connect(session_id, session) {
+                Ok(true) => {}
+                // the lane is freed when the session is released
+                Ok(false) => crate::log::debug!("session {} released before connected", session_id),
+                Err(e) => crate::log::error!(
+                    "RPC endpoint {} failed to accept session {}: {:?}",
+                    self.id,
+                    session_id,
+                    e
+                ),
+            },
+            None => {
+                rc.abort_connect(session_id);
+                if let Some(mailbox) = self.mailbox.as_ref() {
+                    mailbox.lock(|m| m.release(session_id));
+                }
+            }
+        }
+    }
+}
 
-        let mut rpc_server = UDRPCHook::new(
-            unsafe { crate::get_ud_factory(arg.nic_to_use).unwrap().clone() },
-            server_ud.clone(),
-            UDReceiverFactory::new()
-                .set_qd_hint(Service::calculate_qd_hint(arg.id) as _)
-                .create(temp_ud),
+impl Drop for Endpoint {
+    fn drop(&mut self) {
+        crate::log::info!(
+            "MITOSIS RPC endpoint {} closed. rpc status: {:?} ",
+            self.id,
+            self.ud
         );
+    }
+}
 
-        // register the callbacks
-        rpc_server
-            .get_mut_service()
-            .register(RPCId::Nil as _, handle_nil);
-        rpc_server
-            .get_mut_service()
-            .register(RPCId::Echo as _, handle_echo);
-        rpc_server
-            .get_mut_service()
-            .register(RPCId::Query as _, handle_descriptor_addr_lookup);
+/// A handler thread busy-polls while there are requests, and yields the CPU every
+/// `YIELD_TIME_USEC`. After idling for `NAP_AFTER_USEC`, it waits between the polls,
+/// see `Service::idle_wait`: until the next request if the endpoints can be notified,
+/// otherwise it naps for longer and longer up to `MAX_NAP_USEC`, which also bounds
+/// the extra latency of the first request after a quiet period.
+struct AdaptivePoller {
+    counter: usize,
+    // since the last request handled
+    idle: KTimer,
+    // since the last time the CPU is yielded
+    busy: KTimer,
+    // 0 if not napping
+    nap_usec: i64,
+}
 
-        // register msg buffers
-        // pre-most receive buffers
-        for _ in 0..2048 {
-            // 64 is the header
-            match rpc_server.post_msg_buf(UDMsg::new(4096, 0, server_ud.get_qp().ctx().clone())) {
-                Ok(_) => {}
-                Err(e) => crate::log::error!("post recv buf err: {:?}", e),
+impl AdaptivePoller {
+    const YIELD_THRESHOLD: usize = 10000;
+    const YIELD_TIME_USEC: i64 = 2000; // 2ms
+    const NAP_AFTER_USEC: i64 = 50_000; // 50ms
+    const MIN_NAP_USEC: i64 = 50;
+    const MAX_NAP_USEC: i64 = 1000;
+
+    fn new() -> Self {
+        Self {
+            counter: 0,
+            idle: KTimer::new(),
+            busy: KTimer::new(),
+            nap_usec: 0,
+        }
+    }
+
+    #[inline]
+    fn is_napping(&self) -> bool {
+        self.nap_usec > 0
+    }
+
+    /// Wait (or not) before the next poll, given the number of messages handled by this one.
+    /// The `wait` is called with the length of the nap.
+    fn on_poll(&mut self, handled: usize, wait: impl FnOnce(i64)) {
+        if handled > 0 {
+            if self.is_napping() {
+                self.nap_usec = 0;
+                self.busy.reset();
             }
+            self.idle.reset();
+        } else if self.is_napping() {
+            // the waits are short, so the thread still stops (or sees a request) in time
+            wait(self.nap_usec);
+            self.nap_usec = core::cmp::min(self.nap_usec * 2, Self::MAX_NAP_USEC);
+            return;
+        }
+
+        self.counter += 1;
+        if core::intrinsics::likely(self.counter <= Self::YIELD_THRESHOLD) {
+            return;
+        }
+        self.counter = 0;
+        if handled == 0 && self.idle.get_passed_usec() > Self::NAP_AFTER_USEC {
+            self.nap_usec = Self::MIN_NAP_USEC;
+        } else if self.busy.get_passed_usec() > Self::YIELD_TIME_USEC {
+            kthread::yield_now();
+            self.busy.reset();
+        }
+    }
+}
+
+impl Service {
+    /// Register the callbacks, the same ones over UD and RC
+    fn register_handlers(service: &mut os_network::rpc::Service<'_>) {
+        // the idempotent ones are re-executed on retransmitted requests, instead of cached
+        service.register_idempotent(RPCId::Nil as _, handle_nil);
+        service.register_idempotent(RPCId::Echo as _, handle_echo);
+        service.register_idempotent(RPCId::Query as _, handle_descriptor_addr_lookup);
+        service.register(RPCId::Push as _, handle_push_pages);
+        service.register_idempotent(RPCId::Endpoints as _, handle_endpoints_query);
+    }
+
+    // bounds the wait, so the silent sessions are still expired in time
+    const MAX_IDLE_WAIT_USEC: u64 = 10_000;
+
+    /// Sleep until a request arrives at the endpoints, or nap if some cannot be notified:
+    /// the ones being polled by the others, or over RC, whose mailboxes raise no completion.
+    fn idle_wait(arg: &ThreadCTX, nap_usec: i64) {
+        let seen = arg.waiter.events();
+        let armed = arg
+            .endpoints
+            .iter()
+            .all(|e| {
+                e.try_lock(|e| e.as_ref().map(|e| e.arm_notify()))
+                    .flatten()
+                    .unwrap_or(false)
+            });
+        if armed {
+            arg.waiter.wait(seen, Self::MAX_IDLE_WAIT_USEC);
+        } else {
+            unsafe { crate::bindings::pmem_sleep_usecs(nap_usec as _) };
         }
+    }
+
+    #[allow(non_snake_case)]
+    pub(crate) extern "C" fn worker(ctx: *mut c_void) -> c_int {
+        let arg = unsafe { Box::from_raw(ctx as *mut ThreadCTX) };
+        crate::log::info!(
+            "MITOSIS RPC thread {} started, polling {} endpoints",
+            arg.id,
+            arg.endpoints.len()
+        );
+
         RPC_HANDLER_READY_NUM.fetch_add(1, core::sync::atomic::Ordering::SeqCst);
 
-        let mut counter = 0;
-        let mut timer = KTimer::new();
+        let mut poller = AdaptivePoller::new();
+        while !kthread::should_stop() {
+            let mut handled = 0;
+            // the threads start from different endpoints, and take over the ones not being polled.
+            // The endpoints added meanwhile are polled since the next round.
+            let num = arg.endpoints.len();
+            for i in 0..num {
+                let endpoint = match arg.endpoints.get((arg.id + i) % num) {
+                    Some(e) => e,
+                    None => continue,
+                };
+                handled += endpoint
+                    .try_lock(|e| e.as_mut().map(|e| e.poll()))
+                    .flatten()
+                    .unwrap_or(0);
+            }
+            poller.on_poll(handled, |nap_usec| Self::idle_wait(&arg, nap_usec));
+        }
+
+        RPC_HANDLER_READY_NUM.fetch_sub(1, core::sync::atomic::Ordering::SeqCst);
+        crate::log::info!("MITOSIS RPC thread {} ended.", arg.id);
+        0
+    }
+}
+
+impl Service {
+    // the connector naps when there is no connect request
+    const CONNECTOR_NAP_USEC: usize = 1000;
+
+    fn spawn_connector(&self) -> core::option::Option<JoinHandler> {
+        let arg = Box::new(ThreadCTX {
+            id: 0,
+            endpoints: self.endpoints.clone(),
+            waiter: self.waiter.clone(),
+        });
+        let arg_ptr = Box::into_raw(arg);
+
+        let builder = kthread::Builder::new()
+            .set_name(alloc::string::String::from("MITOSIS RPC connector Thread"))
+            .set_parameter(arg_ptr as *mut c_void);
+        builder.spawn(Self::connector).ok()
+    }
+
+    /// Connect the sessions over RC back to their callers.
+    /// The endpoints are only locked to take the requests and to hand back the sessions,
+    /// so the handler threads keep polling them during the handshakes.
+    extern "C" fn connector(ctx: *mut c_void) -> c_int {
+        let arg = unsafe { Box::from_raw(ctx as *mut ThreadCTX) };
+        crate::log::info!("MITOSIS RPC connector started");
 
         while !kthread::should_stop() {
-            match rpc_server.poll() {
-                Ok(Async::Ready(_)) => {}
-                Ok(_NotReady) => {}
-                Err(e) => crate::log::error!(
-                    "RPC handler {} meets an error {:?}, status {:?}",
-                    arg.id,
-                    e,
-                    rpc_server
-                ),
-            };
-            counter += 1;
-            if core::intrinsics::unlikely(counter > Self::YIELD_THRESHOLD) {
-                if core::intrinsics::unlikely(timer.get_passed_usec() > Self::YIELD_TIME_USEC) {
-                    kthread::yield_now();
-                    timer.reset();
+            let mut connected = 0;
+            for endpoint in arg.endpoints.iter() {
+                while let Some((nic, session_id, meta)) =
+                    endpoint.lock(|e| e.as_mut().and_then(|e| e.pop_connect()))
+                {
+                    let session = unsafe { crate::get_rdma_context_ref(nic) }
+                        .and_then(|context| {
+                            os_network::rpc::RPCFactory::create(&RCFactory::new(context), meta)
+                                .map_err(|e| {
+                                    crate::log::error!(
+                                        "failed to connect session {} over RC: {:?}",
+                                        session_id,
+                                        e
+                                    )
+                                })
+                                .ok()
+                        });
+                    endpoint.lock(|e| e.as_mut().map(|e| e.complete_connect(session_id, session)));
+                    connected += 1;
                 }
-                counter = 0;
+            }
+            if connected == 0 {
+                unsafe { crate::bindings::pmem_sleep_usecs(Self::CONNECTOR_NAP_USEC as _) };
             }
         }
 
-        crate::log::info!(
-            "MITOSIS RPC thread {} ended. rpc status: {:?} ",
-            arg.id,
-            rpc_server
-        );
+        crate::log::info!("MITOSIS RPC connector ended.");
         0
     }
 }
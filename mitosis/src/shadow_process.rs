@@ -1,6 +1,7 @@
 pub use vma::*;
 pub use page_table::*;
 pub use page::*;
+pub use dedup::PageDedupTable;
 
 use crate::descriptors::{ParentDescriptor, CompactPageTable};
 use alloc::vec::Vec;
@@ -31,7 +32,12 @@ impl ShadowProcess {
 impl ShadowProcess {
     /// Crate a new shadow processing by marking all the
     /// memories of the original one to copy-on-write(COW).
-    pub fn new_cow(rdma_descriptor: crate::descriptors::RDMADescriptor) -> Self {
+    ///
+    /// `mitosis_file` is the mitosis device that prepares the process.
+    pub fn new_cow(
+        rdma_descriptor: crate::descriptors::RDMADescriptor,
+        mitosis_file: *mut crate::bindings::file,
+    ) -> Self {
         let mut shadow_pt = ShadowPageTable::<COW4KPage>::new();
         let mut shadow_vmas: Vec<ShadowVMA<'static>> = Vec::new();
 
@@ -47,28 +53,48 @@ impl ShadowProcess {
             vma_page_table.push(Default::default());
         }
 
+        // pages are deduplicated across all the VMAs of the image
+        let mut dedup = PageDedupTable::default();
         for (idx, _) in mm.get_vma_iter().enumerate() {
+            // re-established by the child's kernel, see `SpecialMapping`
+            if vma_descriptors[idx].is_special() {
+                continue;
+            }
             let pt: &mut CompactPageTable = vma_page_table.get_mut(idx).unwrap();
             let s_vma = shadow_vmas.get(idx).unwrap();
-            VMACOWPTGenerator::new(s_vma, &mut shadow_pt, pt).generate();
+            VMACOWPTGenerator::new(s_vma, &mut shadow_pt, pt, &mut dedup).generate();
         }
+        crate::log::debug!("duplicated pages eliminated: {}", dedup.dup_cnt());
         // clear the TLB
         mm.flush_tlb_mm();
 
+        #[allow(unused_mut)]
+        let mut descriptor = ParentDescriptor {
+            machine_info: rdma_descriptor,
+            regs: task.generate_reg_descriptor(),
+            mm_layout: task.generate_mm_layout_descriptor(),
+            files: task.generate_file_table_descriptor(mitosis_file),
+            task_state: task.generate_task_state_descriptor(),
+            page_table: vma_page_table,
+            vma: vma_descriptors,
+            #[cfg(feature = "piggyback-pages")]
+            piggybacked: Vec::new(),
+        };
+        #[cfg(feature = "piggyback-pages")]
+        descriptor.pick_piggybacked_pages();
+
         Self {
             shadow_vmas,
             cow_shadow_pagetable: Some(shadow_pt),
             copy_shadow_pagetable: None,
-            descriptor: ParentDescriptor {
-                machine_info: rdma_descriptor,
-                regs: task.generate_reg_descriptor(),
-                page_table: vma_page_table,
-                vma: vma_descriptors,
-            },
+            descriptor,
         }
     }
 
-    pub fn new_copy(rdma_descriptor: crate::descriptors::RDMADescriptor) -> Self {
+    pub fn new_copy(
+        rdma_descriptor: crate::descriptors::RDMADescriptor,
+        mitosis_file: *mut crate::bindings::file,
+    ) -> Self {
         let mut shadow_pt = ShadowPageTable::<Copy4KPage>::new();
         let mut shadow_vmas: Vec<ShadowVMA<'static>> = Vec::new();
 
@@ -86,22 +112,39 @@ impl ShadowProcess {
         }
 
         // crate::log::debug!("before iterating the page table");
+        // pages are deduplicated across all the VMAs of the image
+        let mut dedup = PageDedupTable::default();
         for (idx, _) in mm.get_vma_iter().enumerate() {
+            // re-established by the child's kernel, see `SpecialMapping`
+            if vma_descriptors[idx].is_special() {
+                continue;
+            }
             let pt: &mut CompactPageTable = vma_page_table.get_mut(idx).unwrap();
             let s_vma = shadow_vmas.get(idx).unwrap();
-            VMACopyPTGenerator::new(s_vma, &mut shadow_pt, pt).generate();
+            VMACopyPTGenerator::new(s_vma, &mut shadow_pt, pt, &mut dedup).generate();
         }
+        crate::log::debug!("duplicated pages eliminated: {}", dedup.dup_cnt());
+
+        #[allow(unused_mut)]
+        let mut descriptor = ParentDescriptor {
+            machine_info: rdma_descriptor,
+            regs: task.generate_reg_descriptor(),
+            mm_layout: task.generate_mm_layout_descriptor(),
+            files: task.generate_file_table_descriptor(mitosis_file),
+            task_state: task.generate_task_state_descriptor(),
+            page_table: vma_page_table,
+            vma: vma_descriptors,
+            #[cfg(feature = "piggyback-pages")]
+            piggybacked: Vec::new(),
+        };
+        #[cfg(feature = "piggyback-pages")]
+        descriptor.pick_piggybacked_pages();
 
         Self {
             shadow_vmas,
             cow_shadow_pagetable: None,
             copy_shadow_pagetable: Some(shadow_pt),
-            descriptor: ParentDescriptor {
-                machine_info: rdma_descriptor,
-                regs: task.generate_reg_descriptor(),
-                page_table: vma_page_table,
-                vma: vma_descriptors,
-            },
+            descriptor,
         }
     }
 }
@@ -109,4 +152,5 @@ impl ShadowProcess {
 pub mod vma;
 pub mod page_table;
 pub mod page;
+pub mod dedup;
 
@@ -0,0 +1,72 @@
+use hashbrown::HashMap;
+
+use crate::kern_wrappers::mm::PhyAddrType;
+use crate::linux_kernel_module::c_types::c_void;
+
+const WORDS_PER_PAGE: usize = 4096 / core::mem::size_of::<u64>();
+
+type PageContent = [u64; WORDS_PER_PAGE];
+
+/// Find the pages with identical content in one image at prepare time.
+///
+/// Pages are indexed by a content hash, and a hash hit is always confirmed by
+/// a full comparison. So a collision only misses a duplication, and never
+/// returns a page with different content.
+#[derive(Default)]
+pub struct PageDedupTable {
+    // content hash -> the recorded physical address of the first page with this content
+    seen: HashMap<u64, PhyAddrType>,
+    dup_cnt: usize,
+}
+
+impl PageDedupTable {
+    /// Check whether the page at kernel virtual address `kva` is identical to a page recorded before.
+    ///
+    /// - Return the physical address recorded for the identical page, if any.
+    /// - Otherwise, record the page with `phy_addr`, i.e., the physical address of `kva`.
+    pub unsafe fn lookup_or_insert(
+        &mut self,
+        kva: *mut c_void,
+        phy_addr: PhyAddrType,
+    ) -> Option<PhyAddrType> {
+        let content = Self::content_of(kva as _);
+        let hash = Self::hash(content);
+
+        match self.seen.get(&hash) {
+            Some(origin) => {
+                let origin_content =
+                    Self::content_of(crate::bindings::pmem_phys_to_virt(*origin) as _);
+                if origin_content == content {
+                    self.dup_cnt += 1;
+                    return Some(*origin);
+                }
+                None
+            }
+            None => {
+                self.seen.insert(hash, phy_addr);
+                None
+            }
+        }
+    }
+
+    /// Number of duplicated pages found
+    pub fn dup_cnt(&self) -> usize {
+        self.dup_cnt
+    }
+
+    #[inline(always)]
+    unsafe fn content_of(kva: u64) -> &'static PageContent {
+        &*(kva as *const PageContent)
+    }
+
+    /// FNV-1a over the 8-byte words of the page
+    #[inline]
+    fn hash(content: &PageContent) -> u64 {
+        let mut h: u64 = 0xcbf29ce484222325;
+        for w in content.iter() {
+            h ^= *w;
+            h = h.wrapping_mul(0x100000001b3);
+        }
+        h
+    }
+}
@@ -61,6 +61,7 @@ pub(crate) struct VMACopyPTGenerator<'a, 'b> {
     vma: &'a ShadowVMA<'a>,
     inner: &'b mut CopyPageTable,
     inner_flat: &'b mut crate::descriptors::CompactPageTable,
+    dedup: &'b mut PageDedupTable,
 }
 
 impl<'a, 'b> VMACopyPTGenerator<'a, 'b> {
@@ -68,17 +69,43 @@ impl<'a, 'b> VMACopyPTGenerator<'a, 'b> {
         vma: &'a ShadowVMA,
         inner: &'b mut CopyPageTable,
         inner_flat: &'b mut crate::descriptors::CompactPageTable,
+        dedup: &'b mut PageDedupTable,
     ) -> Self {
         Self {
             vma: vma,
             inner: inner,
             inner_flat: inner_flat,
+            dedup: dedup,
         }
     }
 }
 
 use crate::kern_wrappers::vma_iters::*;
-use crate::remote_mapping::{PhysAddr, PhysAddrBitFlag};
+use crate::remote_mapping::{PteState, RemotePage};
+use super::PageDedupTable;
+
+/// The flat page table entry of an all-zero page.
+/// The child allocates it locally instead of reading from us.
+#[inline(always)]
+fn zero_entry() -> crate::kern_wrappers::mm::PhyAddrType {
+    PteState::Zero.encode()
+}
+
+/// The flat page table entry of a page identical to a previous one of the image, if any.
+/// Otherwise, the page is recorded with `phy_addr`, which will be read by the child.
+#[inline(always)]
+unsafe fn duplicate_entry(
+    kva: *mut crate::linux_kernel_module::c_types::c_void,
+    phy_addr: crate::kern_wrappers::mm::PhyAddrType,
+    dedup: &mut PageDedupTable,
+) -> Option<crate::kern_wrappers::mm::PhyAddrType> {
+    if !cfg!(feature = "dedup-pages") {
+        return None;
+    }
+    dedup
+        .lookup_or_insert(kva, phy_addr)
+        .map(|origin| PteState::Duplicate(RemotePage::new(origin, false)).encode())
+}
 
 impl VMACopyPTGenerator<'_, '_> {
     pub fn generate(&self) {
@@ -105,15 +132,33 @@ impl VMACopyPTGenerator<'_, '_> {
         let phy_addr = pmem_get_phy_from_pte(pte);
 
         if phy_addr > 0 {
+            let start = my.vma.vma_inner.get_start();
+
+            // zero pages need not to be copied
+            if pmem_check_page_zero(pmem_pte_to_page(pte)) != 0 {
+                my.inner_flat
+                    .add_one((addr as VirtAddrType - start) as _, zero_entry() as _);
+                return 0;
+            }
+
             let copied_page = Copy4KPage::new(addr as _).expect("Fail to copy from user space");
-            // my.inner_flat.add_one(addr, copied_page.get_physical_addr());
-            {
-                let start = my.vma.vma_inner.get_start();
-                my.inner_flat.add_one(
-                    (addr as VirtAddrType - start) as _,
-                    copied_page.get_physical_addr() as _,
-                );
+
+            // duplicated pages refer to the copy of the first one
+            if let Some(entry) = duplicate_entry(
+                copied_page.get_kva(),
+                copied_page.get_physical_addr(),
+                my.dedup,
+            ) {
+                my.inner_flat
+                    .add_one((addr as VirtAddrType - start) as _, entry as _);
+                return 0;
             }
+
+            // my.inner_flat.add_one(addr, copied_page.get_physical_addr());
+            my.inner_flat.add_one(
+                (addr as VirtAddrType - start) as _,
+                copied_page.get_physical_addr() as _,
+            );
             // the page table is present
             my.inner.add_page(copied_page);
         }
@@ -127,6 +172,7 @@ pub(crate) struct VMACOWPTGenerator<'a, 'b> {
     vma: &'a ShadowVMA<'a>,
     inner: &'b mut COWPageTable,
     inner_flat: &'b mut crate::descriptors::CompactPageTable,
+    dedup: &'b mut PageDedupTable,
 }
 
 impl<'a, 'b> VMACOWPTGenerator<'a, 'b> {
@@ -134,11 +180,13 @@ impl<'a, 'b> VMACOWPTGenerator<'a, 'b> {
         vma: &'a ShadowVMA,
         inner: &'b mut COWPageTable,
         inner_flat: &'b mut crate::descriptors::CompactPageTable,
+        dedup: &'b mut PageDedupTable,
     ) -> Self {
         Self {
             vma,
             inner,
             inner_flat,
+            dedup,
         }
     }
 }
@@ -164,25 +212,37 @@ impl VMACOWPTGenerator<'_, '_> {
         use core::intrinsics::{likely, unlikely};
         let my: &mut Self = &mut (*((*walk).private as *mut Self));
 
-        let mut phy_addr = pmem_get_phy_from_pte(pte);
+        let phy_addr = pmem_get_phy_from_pte(pte);
         if likely(phy_addr > 0) {
+            let start = my.vma.vma_inner.get_start();
+
+            // The child never reads the zero and duplicated pages from us,
+            // so they need not to be marked as COW
+            let page = pmem_pte_to_page(pte);
+            let entry = if pmem_check_page_zero(page) != 0 {
+                Some(zero_entry())
+            } else {
+                duplicate_entry(pmem_page_to_virt(page) as _, phy_addr, my.dedup)
+            };
+            if let Some(entry) = entry {
+                my.inner_flat
+                    .add_one((addr as VirtAddrType - start) as _, entry as _);
+                return 0;
+            }
+
             if unlikely(my.vma.has_write_permission()) {
                 my.inner
                     .add_page(COW4KPage::new(pmem_pte_to_page(pte)).unwrap());
                 pmem_clear_pte_write(pte);
             }
-            if likely(pmem_check_pte_write(pte) == 0) {
-                // Read only page
-                phy_addr = PhysAddr::encode(phy_addr, PhysAddrBitFlag::ReadOnly as _);
-            }
+            // Read only page
+            let ro = likely(pmem_check_pte_write(pte) == 0);
+            let entry = PteState::Remote(RemotePage::new(phy_addr, ro)).encode();
             // #[cfg(not(feature = "fast-descriptors"))]
             // my.inner_flat.add_one(addr, phy_addr);
             // #[cfg(feature = "fast-descriptors")]
-            {
-                let start = my.vma.vma_inner.get_start();
-                my.inner_flat
-                    .add_one((addr as VirtAddrType - start) as _, phy_addr as _);
-            }
+            my.inner_flat
+                .add_one((addr as VirtAddrType - start) as _, entry as _);
         }
         0
     }
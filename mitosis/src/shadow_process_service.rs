@@ -13,7 +13,7 @@ use crate::shadow_process::*;
 #[allow(unused_imports)]
 use crate::linux_kernel_module;
 
-use crate::get_mem_pool_mut;
+use crate::get_mem_pool_ref;
 use os_network::bytes::ToBytes;
 use os_network::{msg::UDMsg as RMemory, serialize::Serialize};
 
@@ -22,19 +22,23 @@ struct ProcessBundler {
     process: ShadowProcess,
     serialized_buf: RMemory,
     serialized_buf_len: usize,
+    // the resumes must present it, 0 if any can resume
+    secret: u64,
+    // unique among the images ever registered, even with the same key
+    generation: u64,
 
     #[allow(dead_code)] // place holder to prevent NIC release the resources
     bound_dc_targets: Vec<Arc<DCTarget>>,
 }
 
 impl ProcessBundler {
-    fn new(process: ShadowProcess, targets: Arc<DCTarget>) -> Self {
+    fn new(process: ShadowProcess, targets: Arc<DCTarget>, secret: u64, generation: u64) -> Self {
         let len = process.get_descriptor_ref().serialization_buf_len();
         crate::log::debug!(
             "Alloc serialization buf sz {} KB",
             len / 1024
         );
-        let mut buf = unsafe { get_mem_pool_mut() }.pop_one();
+        let mut buf = unsafe { get_mem_pool_ref() }.lock(|p| p.pop_one());
         crate::log::debug!("serialization buf allocation done!");
 
         process.get_descriptor_ref().serialize(buf.get_bytes_mut());
@@ -49,6 +53,8 @@ impl ProcessBundler {
             process: process,
             serialized_buf: buf,
             serialized_buf_len: len,
+            secret: secret,
+            generation: generation,
             bound_dc_targets: bound_targets,
         }
     }
@@ -60,21 +66,48 @@ impl ProcessBundler {
 
 pub struct ShadowProcessService {
     registered_processes: HashMap<usize, ProcessBundler>,
+    next_generation: u64,
 }
 
 impl ShadowProcessService {
     pub fn new() -> Self {
         Self {
             registered_processes: Default::default(),
+            // random per boot, so the children never take a new image for one before the reboot
+            next_generation: unsafe { crate::bindings::pmem_get_random_u64() },
         }
     }
 
+    /// The generation of the image, so that the children can tell
+    /// a new image registered with a reused key from the previous one
+    pub fn query_generation(&self, key: usize) -> core::option::Option<u64> {
+        self.registered_processes.get(&key).map(|s| s.generation)
+    }
+
+    fn alloc_generation(&mut self) -> u64 {
+        self.next_generation = self.next_generation.wrapping_add(1);
+        // 0 is replied for the images not found, see `rpc_handlers::DescriptorLookupReply`
+        if self.next_generation == 0 {
+            self.next_generation = 1;
+        }
+        self.next_generation
+    }
+
     pub fn query_descriptor_buf(&self, key: usize) -> core::option::Option<(&RMemory, usize)> {
         self.registered_processes
             .get(&key)
             .map(|s| (&s.serialized_buf, s.serialized_buf_len))
     }
 
+    /// Whether the resume of the image presents its secret given at prepare.
+    /// Return false if the image is not found.
+    pub fn check_secret(&self, key: usize, secret: u64) -> bool {
+        self.registered_processes
+            .get(&key)
+            .map(|s| s.secret == 0 || s.secret == secret)
+            .unwrap_or(false)
+    }
+
     pub fn query_descriptor(
         &self,
         key: usize,
@@ -84,9 +117,17 @@ impl ShadowProcessService {
             .map(|s| s.process.get_descriptor_ref())
     }
 
+    /// # Arguments
+    /// * secret: the resumes of the image must present it, 0 if any can resume
+    ///
     /// # Return
     /// * The size of the serialization buffer
-    pub fn add_myself_copy(&mut self, key: usize) -> core::option::Option<usize> {
+    pub fn add_myself_copy(
+        &mut self,
+        key: usize,
+        secret: u64,
+        mitosis_file: *mut crate::bindings::file,
+    ) -> core::option::Option<usize> {
         if self.registered_processes.contains_key(&key) {
             crate::log::warn!(
                 "Failed to prepare: the register key {} has already been taken. ",
@@ -96,10 +137,13 @@ impl ShadowProcessService {
         }
 
         let (target, descriptor) = RDMADescriptor::new_from_dc_target_pool()?;
+        let generation = self.alloc_generation();
 
         let bundler = ProcessBundler::new(
-            crate::shadow_process::ShadowProcess::new_copy(descriptor),
+            crate::shadow_process::ShadowProcess::new_copy(descriptor, mitosis_file),
             target,
+            secret,
+            generation,
         );
         let ret = bundler.get_serialize_buf_sz();
 
@@ -110,7 +154,12 @@ impl ShadowProcessService {
 
     /// # Return
     /// * The size of the serialization buffer    
-    pub fn add_myself_cow(&mut self, key: usize) -> core::option::Option<usize> {
+    pub fn add_myself_cow(
+        &mut self,
+        key: usize,
+        secret: u64,
+        mitosis_file: *mut crate::bindings::file,
+    ) -> core::option::Option<usize> {
         if self.registered_processes.contains_key(&key) {
             crate::log::warn!(
                 "Failed to prepare: the register key {} has already been taken. ",
@@ -120,10 +169,13 @@ impl ShadowProcessService {
         }
 
         let (target, descriptor) = RDMADescriptor::new_from_dc_target_pool()?;
+        let generation = self.alloc_generation();
 
         let bundler = ProcessBundler::new(
-            crate::shadow_process::ShadowProcess::new_cow(descriptor),
+            crate::shadow_process::ShadowProcess::new_cow(descriptor, mitosis_file),
             target,
+            secret,
+            generation,
         );
         let ret = bundler.get_serialize_buf_sz();
 
@@ -133,6 +185,14 @@ impl ShadowProcessService {
     }
 
     pub fn unregister(&mut self, key: usize) {
-        self.registered_processes.remove(&key);
+        if self.registered_processes.remove(&key).is_none() {
+            return;
+        }
+        // the local children share the pages of the image
+        #[cfg(feature = "page-sharing")]
+        unsafe {
+            let mac_id = *crate::mac_id::get_ref();
+            crate::get_shared_page_cache_ref().lock(|c| c.evict_image(mac_id, key));
+        }
     }
 }
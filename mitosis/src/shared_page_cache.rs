@@ -0,0 +1,161 @@
+use crate::bindings::page;
+use crate::descriptors::PageMapAllocator;
+use crate::kern_wrappers::mm::PhyAddrType;
+use hashbrown::hash_map::DefaultHashBuilder;
+use hashbrown::HashMap;
+
+// (remote machine id, handler id, image generation, remote physical address) -> local page
+type Key = (usize, usize, u64, PhyAddrType);
+type Value = *mut page;
+
+// (remote machine id, handler id) -> generation of the image, see `check_image`
+type ImageKey = (usize, usize);
+
+/// A host-wide store of the read-only pages fetched by the children.
+///
+/// Different from the `RemotePageTableCache`, which only serves the next child
+/// after the previous one exits, this cache is shared by all the *running*
+/// children resumed from the same image (i.e., the same handler on the same
+/// remote machine). Thus N concurrent siblings only fetch one copy of the
+/// read-only memory.
+///
+/// Each cached page holds one reference owned by the cache.
+/// The child maps it through its private VMA, so a write triggers the kernel's COW
+/// and never pollutes the shared copy.
+///
+/// The pages of an image are evicted once it is unregistered, its handler ID
+/// is reused by a new one, or its machine is down.
+/// The pages are keyed by the image's generation as well, so the children still
+/// faulting from a replaced image never share their (stale) pages with the new one's.
+#[derive(Default)]
+pub struct SharedPageCache {
+    store: HashMap<Key, Value, DefaultHashBuilder, PageMapAllocator>,
+    images: HashMap<ImageKey, u64, DefaultHashBuilder, PageMapAllocator>,
+}
+
+impl SharedPageCache {
+    #[inline(always)]
+    fn gen_cache_key(
+        remote_mac_id: usize,
+        handler_id: usize,
+        generation: u64,
+        remote_pa: PhyAddrType,
+    ) -> Key {
+        (remote_mac_id, handler_id, generation, remote_pa)
+    }
+
+    /// Lookup the page of the image.
+    /// If hit, the reference of the page is increased on behalf of the caller.
+    pub fn lookup(
+        &self,
+        remote_mac_id: usize,
+        handler_id: usize,
+        generation: u64,
+        remote_pa: PhyAddrType,
+    ) -> Option<*mut page> {
+        let page_p = *self
+            .store
+            .get(&Self::gen_cache_key(remote_mac_id, handler_id, generation, remote_pa))?;
+        unsafe { crate::kern_wrappers::Page::new_from_raw(page_p).increase_ref_count() };
+        Some(page_p)
+    }
+
+    /// Share a freshly fetched page with the siblings.
+    ///
+    /// Return false if the page has already been inserted by others,
+    /// or its image has been replaced, in which case the caller still solely owns `page_p`.
+    pub fn insert(
+        &mut self,
+        remote_mac_id: usize,
+        handler_id: usize,
+        generation: u64,
+        remote_pa: PhyAddrType,
+        page_p: *mut page,
+    ) -> bool {
+        if self.images.get(&(remote_mac_id, handler_id)) != Some(&generation) {
+            return false;
+        }
+        let key = Self::gen_cache_key(remote_mac_id, handler_id, generation, remote_pa);
+        if self.store.contains_key(&key) {
+            return false;
+        }
+        // the reference held by the cache
+        unsafe { crate::bindings::pmem_get_page(page_p) };
+        self.store.insert(key, page_p);
+        true
+    }
+
+    /// Called by each resume with the generation of its image (see `DescriptorLookupReply`),
+    /// before any of the image's pages is looked up.
+    /// The pages of a previous image with the same handler ID are evicted,
+    /// return the number of them.
+    pub fn check_image(&mut self, remote_mac_id: usize, handler_id: usize, generation: u64) -> usize {
+        match self.images.insert((remote_mac_id, handler_id), generation) {
+            Some(g) if g != generation => self.evict_image(remote_mac_id, handler_id),
+            _ => 0,
+        }
+    }
+
+    /// Release all the pages of an image, e.g., the parent has unregistered it.
+    /// Return the number of evicted pages.
+    pub fn evict_image(&mut self, remote_mac_id: usize, handler_id: usize) -> usize {
+        self.images.remove(&(remote_mac_id, handler_id));
+        let mut evicted = 0;
+        self.store.retain(|(mac_id, id, _, _), page_p| {
+            if *mac_id == remote_mac_id && *id == handler_id {
+                unsafe { crate::bindings::pmem_put_page(*page_p) };
+                evicted += 1;
+                false
+            } else {
+                true
+            }
+        });
+        evicted
+    }
+
+    /// Release all the pages of the images of a remote machine, e.g., it is down.
+    /// Return the number of evicted pages.
+    pub fn evict_machine(&mut self, remote_mac_id: usize) -> usize {
+        self.images.retain(|(mac_id, _), _| *mac_id != remote_mac_id);
+        let mut evicted = 0;
+        self.store.retain(|(mac_id, _, _, _), page_p| {
+            if *mac_id == remote_mac_id {
+                unsafe { crate::bindings::pmem_put_page(*page_p) };
+                evicted += 1;
+                false
+            } else {
+                true
+            }
+        });
+        evicted
+    }
+
+    /// Drop the cache's references of at most `num` pages.
+    /// The pages mapped by the running children are still valid,
+    /// since they hold their own references.
+    pub fn shrink(&mut self, num: usize) -> usize {
+        let mut released = 0;
+        self.store.retain(|_, page_p| {
+            if released < num {
+                unsafe { crate::bindings::pmem_put_page(*page_p) };
+                released += 1;
+                false
+            } else {
+                true
+            }
+        });
+        released
+    }
+
+    pub fn num(&self) -> usize {
+        self.store.len()
+    }
+}
+
+impl Drop for SharedPageCache {
+    fn drop(&mut self) {
+        for (_, page_p) in self.store.drain() {
+            unsafe { crate::bindings::pmem_put_page(page_p) };
+        }
+    }
+}
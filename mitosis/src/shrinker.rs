@@ -0,0 +1,143 @@
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::bindings::{shrink_control, shrinker};
+use crate::linux_kernel_module::c_types::c_ulong;
+use crate::KRdmaKit::consts::MAX_KMALLOC_SZ;
+
+// the shrinker counts its objects in 4KB pages
+const PAGES_PER_MEM_POOL_BUF: usize = (MAX_KMALLOC_SZ + 4095) / 4096;
+
+static SCAN_CALLS: AtomicUsize = AtomicUsize::new(0);
+static RECLAIMED_PAGE_TABLES: AtomicUsize = AtomicUsize::new(0);
+static RECLAIMED_PAGES: AtomicUsize = AtomicUsize::new(0);
+static RECLAIMED_MEM_POOL_BUFS: AtomicUsize = AtomicUsize::new(0);
+
+/// Statistics of the memory given back to the kernel
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ReclaimStats {
+    /// How many times the kernel asks us to reclaim
+    pub scan_calls: usize,
+    /// Evicted page tables of the `RemotePageTableCache`
+    pub reclaimed_page_tables: usize,
+    /// Released cached pages, from both the page table cache and the shared page cache
+    pub reclaimed_pages: usize,
+    /// Released `MemPool` buffers
+    pub reclaimed_mem_pool_bufs: usize,
+}
+
+#[inline]
+pub fn get_reclaim_stats() -> ReclaimStats {
+    ReclaimStats {
+        scan_calls: SCAN_CALLS.load(Ordering::Relaxed),
+        reclaimed_page_tables: RECLAIMED_PAGE_TABLES.load(Ordering::Relaxed),
+        reclaimed_pages: RECLAIMED_PAGES.load(Ordering::Relaxed),
+        reclaimed_mem_pool_bufs: RECLAIMED_MEM_POOL_BUFS.load(Ordering::Relaxed),
+    }
+}
+
+/// The kernel shrinker that reclaims MITOSIS's cached memory under memory pressure.
+///
+/// The reclaim order is:
+/// 1. cold page tables in the `RemotePageTableCache`, together with their cached pages
+/// 2. pages in the shared page cache (if `page-sharing` is enabled)
+/// 3. surplus buffers of the `MemPool`
+///
+/// The caches are accessed with `try_lock`, because the shrinker may be called
+/// by an allocation that already holds the lock.
+pub struct MitosisShrinker {
+    inner: *mut shrinker,
+}
+
+impl MitosisShrinker {
+    pub fn new() -> Option<Self> {
+        let inner = unsafe {
+            crate::bindings::pmem_register_shrinker(
+                Some(Self::count_objects),
+                Some(Self::scan_objects),
+            )
+        };
+        if inner.is_null() {
+            return None;
+        }
+        Some(Self { inner })
+    }
+
+    #[allow(unused_variables)]
+    unsafe extern "C" fn count_objects(s: *mut shrinker, sc: *mut shrink_control) -> c_ulong {
+        let mut cnt = crate::get_pt_cache_ref()
+            .try_lock(|c| c.reclaimable_pages())
+            .unwrap_or(0);
+
+        #[cfg(feature = "page-sharing")]
+        {
+            cnt += crate::get_shared_page_cache_ref()
+                .try_lock(|c| c.num())
+                .unwrap_or(0);
+        }
+
+        cnt += crate::get_mem_pool_ref()
+            .try_lock(|p| p.surplus())
+            .unwrap_or(0)
+            * PAGES_PER_MEM_POOL_BUF;
+        cnt as _
+    }
+
+    #[allow(unused_variables)]
+    unsafe extern "C" fn scan_objects(s: *mut shrinker, sc: *mut shrink_control) -> c_ulong {
+        SCAN_CALLS.fetch_add(1, Ordering::Relaxed);
+        let target = (*sc).nr_to_scan as usize;
+        let mut freed = 0;
+
+        // 1. cold page tables and their pages
+        let res = crate::get_pt_cache_ref().try_lock(|c| {
+            let mut freed = 0;
+            while freed < target {
+                match c.evict_coldest() {
+                    Some(pages) => {
+                        RECLAIMED_PAGE_TABLES.fetch_add(1, Ordering::Relaxed);
+                        // the page table itself is not a cached page
+                        RECLAIMED_PAGES.fetch_add(pages - 1, Ordering::Relaxed);
+                        freed += pages;
+                    }
+                    None => break,
+                }
+            }
+            freed
+        });
+        freed += res.unwrap_or(0);
+
+        // 2. shared pages
+        #[cfg(feature = "page-sharing")]
+        if freed < target {
+            let res = crate::get_shared_page_cache_ref().try_lock(|c| c.shrink(target - freed));
+            if let Some(pages) = res {
+                RECLAIMED_PAGES.fetch_add(pages, Ordering::Relaxed);
+                freed += pages;
+            }
+        }
+
+        // 3. surplus memory pool buffers
+        if freed < target {
+            let num = (target - freed + PAGES_PER_MEM_POOL_BUF - 1) / PAGES_PER_MEM_POOL_BUF;
+            if let Some(bufs) = crate::get_mem_pool_ref().try_lock(|p| p.shrink(num)) {
+                RECLAIMED_MEM_POOL_BUFS.fetch_add(bufs, Ordering::Relaxed);
+                freed += bufs * PAGES_PER_MEM_POOL_BUF;
+            }
+        }
+
+        if freed == 0 && res.is_none() {
+            // the caches are busy, tell the kernel to try others
+            return crate::bindings::PMEM_SHRINK_STOP as _;
+        }
+        crate::log::debug!("shrinker reclaims {} pages, target {}", freed, target);
+        freed as _
+    }
+}
+
+impl Drop for MitosisShrinker {
+    fn drop(&mut self) {
+        let stats = get_reclaim_stats();
+        crate::log::info!("unregister the shrinker, reclaim stats: {:?}", stats);
+        unsafe { crate::bindings::pmem_unregister_shrinker(self.inner) };
+    }
+}
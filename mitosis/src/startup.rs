@@ -30,12 +30,42 @@ pub fn check_global_configurations() {
         crate::log::info!("[check]: Disable prefetching.");
     }
 
+    if cfg!(feature = "async-fault") {
+        crate::log::info!("[check]: Release the mmap_sem while the faulted page is being read.")
+    } else {
+        crate::log::info!("[check]: Hold the mmap_sem during the remote read of page faults.")
+    }
+
     if cfg!(feature = "page-cache") {
         crate::log::info!("[check]: Cache remote page table optimization is enabled.")
     } else {
         crate::log::info!("[check]: Not cache remote page table.")
     }
 
+    if cfg!(feature = "dedup-pages") {
+        crate::log::info!("[check]: Eliminate duplicated pages of the image at prepare time.")
+    } else {
+        crate::log::info!("[check]: Only eliminate zero pages of the image at prepare time.")
+    }
+
+    if cfg!(feature = "push-resume") {
+        crate::log::info!("[check]: Let the parent push the hot pages at resume.")
+    } else {
+        crate::log::info!("[check]: All the pages are pulled by the child.")
+    }
+
+    if cfg!(feature = "piggyback-pages") {
+        crate::log::info!("[check]: Piggyback {} hot pages in the descriptor.", crate::PIGGYBACK_PAGES)
+    } else {
+        crate::log::info!("[check]: The descriptor carries no page.")
+    }
+
+    if cfg!(feature = "page-sharing") {
+        crate::log::info!("[check]: Share read-only pages among children of the same image.")
+    } else {
+        crate::log::info!("[check]: Not share pages among children.")
+    }
+
     if cfg!(feature = "use_rc") {
         crate::log::info!("[check]: Use RDMA's reliable connection for communications.")
     } else {
@@ -48,12 +78,22 @@ pub fn check_global_configurations() {
 pub fn init_mitosis(config: &crate::Config) -> core::option::Option<()> {
     crate::log::info!("Try to start MITOSIS instance, init global services");
     check_global_configurations();
+    match config.rpc_transport {
+        os_network::rpc::impls::transport::RPCTransport::RC => {
+            crate::log::info!("[check]: Run the RPC sessions over RDMA's reliable connection.")
+        }
+        os_network::rpc::impls::transport::RPCTransport::UD => {
+            crate::log::info!("[check]: Run the RPC sessions over RDMA's unreliable datagram.")
+        }
+    }
 
     unsafe {
         crate::mac_id::init(config.machine_id);
         crate::max_caller_num::init(config.max_core_cnt);
         crate::max_nics_used::init(config.num_nics_used);
         crate::max_cluster_size::init(config.max_cluster_size);
+        crate::eager_resume_threads::init(config.eager_resume_threads);
+        crate::page_table_kind::init(config.page_table_kind);
     };
 
 
@@ -143,13 +183,49 @@ pub fn init_mitosis(config: &crate::Config) -> core::option::Option<()> {
     // Global shadow process service
     unsafe { crate::sp_service::init(crate::shadow_process_service::ShadowProcessService::new()) };
 
+    // the children may ask us (as their parent) to push the hot pages
+    unsafe {
+        crate::push_service::init(crate::push_resume::Pusher::new());
+        crate::push_service::get_mut()
+            .start()
+            .expect("Failed to start the pusher");
+    };
+    #[cfg(feature = "push-resume")]
+    unsafe {
+        crate::abandoned_push_regions::init(crate::push_resume::AbandonedRegions::new())
+    };
+
     // Memory pool for the shadow process service
     // The context is not important here as we only allocate a slice of memory
-    unsafe { crate::mem_pool::init(crate::mem_pools::MemPool::new(config.mem_pool_size, crate::get_rdma_context_ref(0).unwrap().clone())) };
+    unsafe {
+        crate::mem_pool::init(crate::lock_bundler::LockBundler::new(
+            crate::mem_pools::MemPool::new(
+                config.mem_pool_size,
+                crate::get_rdma_context_ref(0).unwrap().clone(),
+            ),
+        ))
+    };
 
     // cache for storing the remote page table cache
     unsafe {
-        crate::global_pt_cache::init(crate::remote_pt_cache::RemotePageTableCache::default())
+        crate::global_pt_cache::init(crate::lock_bundler::LockBundler::new(
+            crate::remote_pt_cache::RemotePageTableCache::default(),
+        ))
+    };
+
+    // host-wide pages shared by the concurrent children
+    #[cfg(feature = "page-sharing")]
+    unsafe {
+        crate::global_shared_page_cache::init(crate::lock_bundler::LockBundler::new(
+            crate::shared_page_cache::SharedPageCache::default(),
+        ))
+    };
+
+    // give the cached memory back under memory pressure
+    unsafe {
+        crate::mitosis_shrinker::init(
+            crate::shrinker::MitosisShrinker::new().expect("Failed to register the shrinker"),
+        )
     };
 
 
@@ -199,6 +275,14 @@ pub fn init_rpc(config: &crate::Config,
     crate::rpc_service::wait_handlers_ready_barrier(config.rpc_threads_num);
     crate::log::info!("All RPC thread handlers initialized!");
 
+    // monitor the peers connected by the callers
+    unsafe {
+        crate::peer_monitor_service::init(crate::peer_monitor::PeerMonitor::new(config));
+        crate::peer_monitor_service::get_mut()
+            .start()
+            .expect("Failed to start the peer monitor");
+    };
+
     Some(())
 }
 
@@ -218,6 +302,10 @@ pub fn start_instance(config: crate::Config) -> core::option::Option<()> {
 pub fn end_instance() {
     crate::log::info!("Stop MITOSIS instance, start cleaning up...");
     unsafe {
+        // stop checking the peers first, since it releases the callers, RC connections
+        // and caches of the down peers
+        crate::peer_monitor_service::drop();
+
         crate::ud_factories::drop();
         crate::dc_factories::drop();
         #[cfg(feature = "use_rc")]
@@ -231,6 +319,11 @@ pub fn end_instance() {
         crate::service_rpc::drop();
         crate::access_info_service::drop();
 
+        // the pushes write through the DC pool
+        crate::push_service::drop();
+        #[cfg(feature = "push-resume")]
+        crate::abandoned_push_regions::drop();
+
         crate::log::debug!("drop dc targets");
         crate::dc_target_service::drop();
 
@@ -243,12 +336,18 @@ pub fn end_instance() {
         crate::service_caller_pool::drop();
 
 
+        // must be unregistered before the reclaimed data structures are dropped
+        crate::mitosis_shrinker::drop();
+
         crate::log::debug!("drop shadow process service");
         crate::sp_service::drop();
         crate::mem_pool::drop();
 
         crate::global_pt_cache::drop();
 
+        #[cfg(feature = "page-sharing")]
+        crate::global_shared_page_cache::drop();
+
         crate::global_locks::drop();
     };
     end_rdma();
@@ -275,9 +374,16 @@ pub fn probe_remote_rpc_end(
     connect_info: crate::rpc_service::HandlerConnectInfo,
 ) -> core::option::Option<()> {
     let len = unsafe { crate::get_rpc_caller_pool_ref().len() };
+    // the remote handler threads poll all its endpoints, so the callers are spread over them
+    let mut endpoints = unsafe { crate::service_rpc::get_ref().get_endpoints_num() };
     for i in 0..len {
+        // the first session always connects to the endpoint of the `connect_info`
+        if i == 1 {
+            endpoints = query_remote_endpoints(remote_machine_id).unwrap_or(endpoints);
+        }
         let session_id = calculate_session_id(remote_machine_id, i, len);
         let my_session_id = calculate_session_id(unsafe { *crate::mac_id::get_ref() }, i, len);
+        let connect_info = connect_info.spread(i, endpoints);
         let gid = Explorer::string_to_gid(&connect_info.gid).ok()?;
         // assert_ne!(session_id, my_session_id);
 
@@ -294,5 +400,81 @@ pub fn probe_remote_rpc_end(
             },
         )?;
     }
+    crate::peer_monitor::mark_peer_up(remote_machine_id);
     Some(())
 }
+
+const QUERY_TIMEOUT_USEC: i64 = 1000_000; // 1s
+
+/// Query the number of the endpoints of the remote node, over the session of the first caller
+fn query_remote_endpoints(remote_machine_id: usize) -> core::option::Option<usize> {
+    use crate::rpc_handlers::{EndpointsReply, RPCId};
+    use os_network::serialize::Serialize;
+    use os_network::timeout::TimeoutWRef;
+
+    let len = unsafe { crate::get_rpc_caller_pool_ref().len() };
+    let session_id = calculate_session_id(remote_machine_id, 0, len);
+    let my_session_id = calculate_session_id(unsafe { *crate::mac_id::get_ref() }, 0, len);
+
+    let caller = unsafe { crate::rpc_caller_pool::CallerPool::get_global_caller(0) }?;
+    caller.lock(|caller| {
+        let req_id = caller
+            .call_async::<usize>(session_id, my_session_id, RPCId::Endpoints as _, 0)
+            .ok()?;
+        let res = {
+            let mut reply_future = caller.get_reply(req_id);
+            let mut timeout_caller = TimeoutWRef::new(&mut reply_future, QUERY_TIMEOUT_USEC);
+            os_network::block_on(&mut timeout_caller).ok()
+        };
+        let (msg, reply) = match res {
+            Some(r) => r,
+            None => {
+                caller.cancel(req_id);
+                crate::log::error!("failed to query the endpoints of machine {}", remote_machine_id);
+                return None;
+            }
+        };
+        let reply = EndpointsReply::deserialize(&reply);
+        caller.register_recv_buf(msg).unwrap();
+        reply.map(|r| r.num)
+    })
+}
+
+/// Move the idle RPC sessions to the remote node onto its endpoints added since they connected,
+/// see `rpc_service::Service::set_handlers`.
+///
+/// Return the number of the sessions moved.
+pub fn rebalance_remote_rpc_end(remote_machine_id: usize) -> core::option::Option<usize> {
+    let endpoints = query_remote_endpoints(remote_machine_id)?;
+    let len = unsafe { crate::get_rpc_caller_pool_ref().len() };
+    let mut moved = 0;
+    for i in 0..len {
+        let session_id = calculate_session_id(remote_machine_id, i, len);
+        let my_session_id = calculate_session_id(unsafe { *crate::mac_id::get_ref() }, i, len);
+        if let Some(true) = unsafe { crate::get_rpc_caller_pool_mut() }
+            .rebalance_session_at(i, session_id, my_session_id, endpoints)
+        {
+            moved += 1;
+        }
+    }
+    Some(moved)
+}
+
+/// Close the RPC sessions (and RC connections) to the remote node, and notify it to release them
+pub fn disconnect_remote_rpc_end(remote_machine_id: usize) {
+    let len = unsafe { crate::get_rpc_caller_pool_ref().len() };
+    for i in 0..len {
+        let session_id = calculate_session_id(remote_machine_id, i, len);
+        if unsafe { crate::get_rpc_caller_pool_mut() }
+            .disconnect_session_at(i, session_id)
+            .is_none()
+        {
+            crate::log::debug!("session {} is not connected", session_id);
+        }
+
+        #[cfg(feature = "use_rc")]
+        if let Some(pool) = unsafe { crate::get_rc_conn_pool_mut(i) } {
+            pool.remove_rc_connection(session_id);
+        }
+    }
+}
@@ -93,8 +93,11 @@ impl MySyscallHandler {
         let mut page_table = RemotePageTable::new();
 
         for i in 0..20 {
-            let u = PhysAddr::encode((i + 1) * 4096, PhysAddrBitFlag::Cache as _);
-            page_table.map(VirtAddr::new(i * 4096), PhysAddr::new(u));
+            let u = PteState::Cached {
+                page: ((i + 1) * 4096) as _,
+                ro: false,
+            };
+            page_table.map(VirtAddr::new(i * 4096), PhysAddr::from(u));
         }
 
         log::info!("get initial page size:{}", page_table.len());
@@ -105,7 +108,6 @@ impl MySyscallHandler {
         log::debug!("{}", copied_pg_table);
 
         for i in 0..20 {
-            let u = PhysAddr::encode((i + 1) * 4096, PhysAddrBitFlag::Cache as _);
             assert_eq!(
                 page_table.translate(VirtAddr::new(i * 4096)).unwrap(),
                 copied_pg_table.translate(VirtAddr::new(i * 4096)).unwrap(),
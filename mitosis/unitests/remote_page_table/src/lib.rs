@@ -23,11 +23,60 @@ fn test_basic() {
     log::info!("check lookup result {:?}", pt.translate(VirtAddr::new(4096)));
 
     // test paging data structures 
-    let zero = PhysAddr::new(0xdeadbeaf);     
-    log::info!("top bit: {}", zero.bottom_bit()); 
+    let remote = PteState::Remote(RemotePage::new(0xdeadb000, true));
+    assert_eq!(PhysAddr::from(remote).state(), remote);
+    assert_eq!(PhysAddr::new(0).state(), PteState::Empty);
+    assert_eq!(PhysAddr::new(8192).state(), PteState::Remote(RemotePage::new(8192, false)));
 
-    let one = PhysAddr::new(0x73 << 2);
-    log::info!("top bit: {}", one.bottom_bit()); 
+    // the in-flight page can only complete locally
+    assert!(remote.can_transit_to(&PteState::InFlight));
+    assert!(!PteState::InFlight.can_transit_to(&remote));
+    assert!(!PteState::Zero.can_transit_to(&PteState::InFlight));
+
+    let page = 0x73 << 12;
+    let prev = pt.transit(VirtAddr::new(4096), PteState::Local(page as _));
+    log::info!("transit result {:?}, state {:?}", prev, pt.lookup(VirtAddr::new(4096)));
+    assert!(pt.transit(VirtAddr::new(4096), PteState::InFlight).is_err());
+    assert!(pt.transit(VirtAddr::new(4096), PteState::Remote(RemotePage::new(4096, false))).is_ok());
+}
+
+fn test_extent() {
+    let mut radix = ChildPageTable::new(PageTableKind::Radix);
+    let mut extent = ChildPageTable::new(PageTableKind::Extent);
+
+    // two dense regions far away from each other, and a lonely page
+    let regions = [(0x400000u64, 64u64), (0x7fff_f000_0000u64, 600u64), (0x1000_0000u64, 1u64)];
+    for (start, num) in regions.iter() {
+        for i in 0..*num {
+            let va = VirtAddr::new(start + i * 4096);
+            let pa = PhysAddr::new((i + 1) * 4096);
+            assert!(radix.map(va, pa).is_none());
+            assert!(extent.map(va, pa).is_none());
+        }
+    }
+    extent.finish_mapping();
+
+    assert_eq!(radix.len(), extent.len());
+    assert_eq!(radix.iter().count(), extent.iter().count());
+    for (start, num) in regions.iter() {
+        for i in 0..*num {
+            let va = VirtAddr::new(start + i * 4096);
+            assert_eq!(radix.translate(va), extent.translate(va));
+        }
+    }
+    assert!(extent.translate(VirtAddr::new(0x400000 + 64 * 4096)).is_none());
+
+    // the iterators start after the given page, across the extents
+    let va = VirtAddr::new(0x400000 + 62 * 4096);
+    assert_eq!(radix.iter_after(va).count(), extent.iter_after(va).count());
+
+    log::info!(
+        "memory usage of {} pages, radix: {} KB, extent: {} KB",
+        extent.len(),
+        radix.memory_usage() / 1024,
+        extent.memory_usage() / 1024
+    );
+    assert!(extent.memory_usage() < radix.memory_usage());
 }
 
 #[allow(dead_code)]
@@ -38,6 +87,7 @@ struct Module {
 impl linux_kernel_module::KernelModule for Module {
     fn init() -> linux_kernel_module::KernelResult<Self> {
         test_basic();
+        test_extent();
         Ok(Self { 
             service : SysCallsService::<MySyscallHandler>::new()?
         })
@@ -262,6 +262,9 @@ impl MySyscallHandler {
 
         let descriptor = ParentDescriptor {
             regs: task.generate_reg_descriptor(),
+            mm_layout: task.generate_mm_layout_descriptor(),
+            files: task.generate_file_table_descriptor(core::ptr::null_mut()),
+            task_state: task.generate_task_state_descriptor(),
             page_table: pg_table,
             vma,
             machine_info: mac_info.clone(),
@@ -305,7 +308,34 @@ impl MySyscallHandler {
             result.machine_info,
             descriptor.machine_info
         );
-        
+        crate::log::debug!("check mm layout {:?}", result.mm_layout);
+        assert_eq!(result.mm_layout.get_brk(), descriptor.mm_layout.get_brk());
+        assert_eq!(
+            result.mm_layout.get_start_stack(),
+            descriptor.mm_layout.get_start_stack()
+        );
+        assert_eq!(
+            result.mm_layout.get_exe_path(),
+            descriptor.mm_layout.get_exe_path()
+        );
+        crate::log::debug!("check files {:?}", result.files);
+        // at least the standard streams of insmod
+        assert!(!descriptor.files.is_empty());
+        assert_eq!(result.files.len(), descriptor.files.len());
+        for (r, d) in result.files.iter().zip(descriptor.files.iter()) {
+            assert_eq!(r.get_fd(), d.get_fd());
+            assert_eq!(r.get_kind(), d.get_kind());
+            assert_eq!(r.get_flags(), d.get_flags());
+            assert_eq!(r.get_pos(), d.get_pos());
+            assert_eq!(r.get_path(), d.get_path());
+        }
+        // no mitosis file is given
+        assert!(result.files.iter().all(|f| f.get_kind() != FileKind::Mitosis));
+        crate::log::debug!("check task state {:?}", result.task_state);
+        assert_eq!(result.task_state.get_uid(), descriptor.task_state.get_uid());
+        assert_eq!(result.task_state.get_blocked(), descriptor.task_state.get_blocked());
+        assert!(task.check_task_state(&result.task_state));
+
         crate::log::info!("pass process ParentDescriptor (de)serialization test\n");
 
         0
@@ -330,6 +360,9 @@ impl MySyscallHandler {
 
         let descriptor = ParentDescriptor {
             regs: task.generate_reg_descriptor(),
+            mm_layout: task.generate_mm_layout_descriptor(),
+            files: task.generate_file_table_descriptor(core::ptr::null_mut()),
+            task_state: task.generate_task_state_descriptor(),
             page_table: pg_table,
             vma,
             machine_info: mac_info.clone(),
@@ -21,7 +21,7 @@ use os_network::Factory;
 use os_network::MetaFactory;
 
 use os_network::block_on;
-use os_network::timeout::Timeout;
+use os_network::timeout::{Timeout, TimeoutWRef};
 
 use krdma_test::*;
 
@@ -90,6 +90,39 @@ fn test_rpc_two() {
     })
 }
 
+// Note, test_rpc_scale should be called after `test_rpc_two`, reusing its session
+fn test_rpc_scale() {
+    log::info!("Test scaling the RPC handler threads.");
+
+    let service = unsafe { mitosis::get_service_rpc_ref() };
+    let caller = unsafe {
+        mitosis::rpc_caller_pool::CallerPool::get_global_caller(0)
+            .expect("the caller should be properly inited")
+    };
+
+    // the endpoints are added with the threads, but never removed
+    for (num, expected, endpoints) in [(1, 1, 2), (4, 4, 4), (0, 1, 4), (2, 2, 4)].iter() {
+        assert_eq!(service.set_handlers(*num), *expected);
+        assert_eq!(service.get_handlers_num(), *expected);
+        assert_eq!(service.get_endpoints_num(), *endpoints);
+
+        caller.lock(|caller| {
+            caller
+                .sync_call(
+                    0xdeadbeaf,
+                    0xdeadbeaf,
+                    mitosis::rpc_handlers::RPCId::Echo as _,
+                    0xffffffff as u64,
+                )
+                .unwrap();
+            // the echo must be handled by whichever threads remain
+            let (msg, _) = block_on(&mut TimeoutWRef::new(caller, 1000_000))
+                .unwrap_or_else(|e| panic!("client call error with {} threads: {:?}", expected, e));
+            caller.register_recv_buf(msg).unwrap();
+        });
+    }
+}
+
 fn test_rpc() {
     type UDCaller = Caller<UDReceiver, UDSession>;
 
@@ -250,7 +283,7 @@ fn test_rpc() {
     };
 }
 
-#[krdma_test(test_rpc, test_rpc_two)]
+#[krdma_test(test_rpc, test_rpc_two, test_rpc_scale)]
 fn init() {
     log::info!("in test mitosis service startups!");
 
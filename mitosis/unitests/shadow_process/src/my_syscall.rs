@@ -114,7 +114,7 @@ impl MySyscallHandler {
         let mut mac_info: mitosis::descriptors::RDMADescriptor = Default::default();
         mac_info.set_rkey(0xdeadbeaf).set_service_id(73);
 
-        let _sp = ShadowProcess::new_copy(mac_info.clone());
+        let _sp = ShadowProcess::new_copy(mac_info.clone(), core::ptr::null_mut());
         log::debug!("page table test done");
         0
     }
@@ -1,4 +1,5 @@
 use alloc::sync::Arc;
+use core::sync::atomic::{AtomicU64, Ordering};
 
 use crate::{
     future::{Async, Future, Poll},
@@ -39,6 +40,15 @@ impl UDFactory {
 pub struct UDDatagram {
     pub(crate) ud: Arc<QueuePair>,
     pending: usize,
+    // shared by the clones, since they post to (and poll) the same qp
+    signaled: Arc<SignaledReqs>,
+}
+
+/// The signaled send requests of a qp, counted from its creation
+#[derive(Default)]
+struct SignaledReqs {
+    posted: AtomicU64,
+    completed: AtomicU64,
 }
 
 impl UDDatagram {
@@ -50,10 +60,22 @@ impl UDDatagram {
         self.pending
     }
 
+    /// Number of the signaled requests posted to the qp, by all the clones
+    pub fn get_signaled(&self) -> u64 {
+        self.signaled.posted.load(Ordering::Relaxed)
+    }
+
+    /// Number of the signaled requests completed, by all the clones.
+    /// The completions are in order, so the requests posted before them are done as well.
+    pub fn get_completed(&self) -> u64 {
+        self.signaled.completed.load(Ordering::Relaxed)
+    }
+
     pub fn clone(&self) -> Self {
         Self {
             ud: self.ud.clone(),
             pending: self.pending,
+            signaled: self.signaled.clone(),
         }
     }
 }
@@ -72,6 +94,7 @@ impl Future for UDDatagram {
         let ret = self.get_qp().poll_send_cq(&mut completion)?;
         if ret.len() > 0 {
             self.pending = 0;
+            self.signaled.completed.fetch_add(1, Ordering::Relaxed);
             Ok(Async::Ready(completion[0]))
         } else {
             Ok(Async::NotReady)
@@ -97,6 +120,9 @@ impl crate::conn::Conn for UDDatagram {
             req.is_signaled(),
         )?;
         self.pending += 1;
+        if req.is_signaled() {
+            self.signaled.posted.fetch_add(1, Ordering::Relaxed);
+        }
         Ok(())
     }
 }
@@ -119,10 +145,15 @@ impl crate::conn::Factory for UDFactory {
             .set_max_send_wr(MAX_SEND_WR)
             .set_port_num(meta.port);
         let qp = builder.build_ud()?.bring_up_ud()?;
-        Ok(UDDatagram { ud: qp, pending: 0 })
+        Ok(UDDatagram {
+            ud: qp,
+            pending: 0,
+            signaled: Default::default(),
+        })
     }
 }
 
+#[derive(Clone, Copy)]
 pub struct UDHyperMeta {
     pub gid: KRdmaKit::rdma_shim::bindings::ib_gid,
     pub service_id: u64,
@@ -137,6 +168,9 @@ impl Default for UDHyperMeta {
     }
 }
 
+// sent by memory copy in the connect request
+impl crate::serialize::Serialize for UDHyperMeta {}
+
 #[derive(Default)]
 pub struct UDCreationMeta {
     pub port: u8,
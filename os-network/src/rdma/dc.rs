@@ -1,9 +1,10 @@
 use alloc::sync::Arc;
+use core::ops::Range;
 
 use KRdmaKit::queue_pairs::dynamic_connected_transport::DynamicConnectedTargetBuilder;
 use KRdmaKit::queue_pairs::DynamicConnectedTarget;
 use KRdmaKit::{context::Context, ControlpathError, QueuePair, QueuePairStatus};
-use KRdmaKit::{DatapathError, QueuePairBuilder};
+use KRdmaKit::{DatagramEndpoint, DatapathError, MemoryRegion, QueuePairBuilder};
 
 use super::payload::dc::DCReqPayload;
 use super::payload::{RDMAOp, RDMAWR};
@@ -101,6 +102,78 @@ impl crate::Conn for DCConn {
     }
 }
 
+/// Max local buffers gathered by one `post_sg`
+pub const MAX_DC_SGE: usize = 16;
+
+impl DCConn {
+    /// Post one read/write of the remote range starting at `raddr`,
+    /// whose local buffer is scattered over `sges`, i.e., the (MR, range) pairs in order.
+    ///
+    /// Unlike `post`, the local buffer needs not be physically contiguous,
+    /// e.g., contiguous remote pages can be read into independent order-0 pages.
+    ///
+    /// # Errors:
+    /// - `DatapathError`: There are more than `MAX_DC_SGE` buffers, or the post fails.
+    pub fn post_sg(
+        &mut self,
+        op: RDMAOp,
+        endpoint: &DatagramEndpoint,
+        sges: &[(&MemoryRegion, Range<u64>)],
+        signaled: bool,
+        raddr: u64,
+        rkey: u32,
+    ) -> Result<(), DatapathError> {
+        use KRdmaKit::rdma_shim::bindings::*;
+
+        if sges.is_empty() || sges.len() > MAX_DC_SGE {
+            return Err(DatapathError::PostSendError(
+                crate::linux_kernel_module::Error::EINVAL,
+            ));
+        }
+
+        let mut sg_list: [ib_sge; MAX_DC_SGE] = unsafe { core::mem::zeroed() };
+        for (sge, (mr, range)) in sg_list.iter_mut().zip(sges.iter()) {
+            sge.addr = unsafe { mr.get_rdma_addr() } + range.start;
+            sge.length = (range.end - range.start) as u32;
+            sge.lkey = mr.lkey().0;
+        }
+
+        let mut wr: ib_dc_wr = Default::default();
+        wr.wr.opcode = match op {
+            RDMAOp::READ => ib_wr_opcode::IB_WR_RDMA_READ,
+            RDMAOp::WRITE => ib_wr_opcode::IB_WR_RDMA_WRITE,
+        };
+        wr.wr.send_flags = if signaled {
+            ib_send_flags::IB_SEND_SIGNALED
+        } else {
+            0
+        };
+        wr.wr.sg_list = sg_list.as_mut_ptr();
+        wr.wr.num_sge = sges.len() as _;
+        wr.remote_addr = raddr;
+        wr.rkey = rkey;
+        wr.ah = endpoint.raw_address_handler_ptr().as_ptr();
+        wr.dct_access_key = endpoint.dc_key();
+        wr.dct_number = endpoint.qpn();
+
+        compiler_fence(SeqCst);
+        let mut bad_wr: *mut ib_send_wr = core::ptr::null_mut();
+        let err = unsafe {
+            bd_ib_post_send(
+                self.dc.raw_ptr().as_ptr(),
+                &mut wr.wr as *mut _,
+                &mut bad_wr as *mut _,
+            )
+        };
+        if err != 0 {
+            return Err(DatapathError::PostSendError(
+                crate::linux_kernel_module::Error::from_kernel_errno(err),
+            ));
+        }
+        Ok(())
+    }
+}
+
 use crate::future::{Async, Future, Poll};
 use crate::rdma::payload::{EndPoint, LocalMR, Signaled};
 
@@ -156,6 +229,7 @@ impl crate::conn::Factory for DCFactory {
         builder
             .allow_remote_rw()
             .allow_remote_atomic()
+            .set_max_send_sge(MAX_DC_SGE as _)
             .set_port_num(meta.port);
         let qp = builder.build_dc()?.bring_up_dc()?;
         Ok(DCConn {
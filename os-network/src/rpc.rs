@@ -1,6 +1,8 @@
 use core::panic;
 
+use alloc::collections::VecDeque;
 use alloc::sync::Arc;
+use alloc::vec::Vec;
 
 #[allow(unused_imports)]
 use crate::linux_kernel_module;
@@ -9,7 +11,8 @@ use crate::{
     bytes::{BytesMut, ToBytes},
     Receiver,
 };
-use hashbrown::HashMap;
+use hashbrown::{HashMap, HashSet};
+use rust_kernel_linux_util::timer::KTimer;
 
 pub enum Err {
     /// Timeout error
@@ -23,13 +26,66 @@ pub trait GetTransport {
     fn get_transport_mut(&mut self) -> &mut Self::Transport;
 }
 
+/// The ID of an in-flight call, echoed back by its reply
+pub type ReqId = u64;
+
 /// A RPC caller is the combination of a receiver and several connected sessions.
 ///
+/// Each call is tagged with a request ID, so a caller can have many calls in flight
+/// (to one or many sessions) and match the replies out-of-order with `get_reply`.
+/// A call whose reply is no longer waited for should be cancelled, see `cancel`.
+/// A call is retransmitted if its reply does not arrive in time, see `RetransmitConfig`.
+/// The requests and replies larger than a datagram are fragmented, see `FragmentConfig`.
+/// The peers are monitored by heartbeats, see `check_peers`.
+/// The requests to a session are flow controlled by its credits, see `credit`.
+///
 pub struct Caller<R: Receiver, S: RPCConn> {
     inner_receiver: R,
-    connected_sessions: HashMap<usize, (S, S::ReqPayload)>,
+    connected_sessions: HashMap<usize, (S, send_buf::SendBufs<S::ReqPayload>)>,
+
+    // 0 is reserved for the connect requests
+    next_req_id: ReqId,
+    // replies received while waiting for other calls, dropped if not waited for long
+    completed: HashMap<ReqId, (R::Output, CallResult, KTimer)>,
+
+    // calls waiting for the replies
+    inflight: HashMap<ReqId, reliable::InflightCall>,
+    retransmit: reliable::RetransmitConfig,
+
+    // the large replies being reassembled
+    reassemblies: HashMap<ReqId, fragment::Reassembly>,
+    // the reassembled replies, indexed by the address of the message returned with them.
+    // They are freed when the message is registered again.
+    assembled: HashMap<u64, Vec<u8>>,
+    frag: fragment::FragmentConfig,
+
+    // the liveness of the connected sessions' peers
+    peers: HashMap<usize, heartbeat::Liveness>,
+    heartbeat: heartbeat::HeartbeatConfig,
+    // calls failed since their sessions are removed, until they are waited
+    aborted: HashSet<ReqId>,
+
+    // the credits of the connected sessions
+    credits: HashMap<usize, credit::Credits>,
+    // calls waiting for the credits to send, in the order of the calls
+    blocked: VecDeque<ReqId>,
+
+    // the token shared with the hooks, see `RPCHook::set_auth_token`
+    auth_token: u64,
+    // the nonces given by the hooks on connect, carried by the messages of the sessions
+    nonces: HashMap<usize, u64>,
+
+    // counting data
+    analysis: analysis::RPCAnalysis,
 }
 
+// The parsed reply: the payload on success, or the failed status
+type CallResult = Result<BytesMut, header::ReplyStatus>;
+
+/// The received replies not waited for so long are dropped (with their buffers re-posted),
+/// e.g., the waiter has timed out without `Caller::cancel`
+pub const UNCLAIMED_REPLY_TIMEOUT_USEC: i64 = 10_000_000;
+
 impl<R, S: RPCConn> GetTransport for Caller<R, S>
 where
     R: Receiver + GetTransport,
@@ -53,36 +109,107 @@ where
             .map(|(s, _)| s.get_pending_reqs())
     }
 
+    /// Number of the messages the session can send before the hook returns the credits
+    pub fn get_credits(&self, session_id: usize) -> core::option::Option<usize> {
+        self.credits.get(&session_id).map(|c| c.available())
+    }
+
     /// Call the corresponding RPC and use Future to wait.
     ///
+    /// The caller (as a Future) returns the first reply it receives,
+    /// so only one call should be in flight. Use `call_async` to pipeline calls.
+    ///
     pub fn sync_call<Args>(
         &mut self,
         session_id: usize,
         my_session_id: usize,
         rpc_id: usize,
         arg: Args,
-    ) -> Result<(), SS::IOResult> {
-        let (session, msg) = self
-            .connected_sessions
-            .get_mut(&session_id)
-            .expect("failed to get session");
-
-        let signal_flag = session.get_pending_reqs() == 0;
-        let req_sz = header_factory::CallStubFactory::new(my_session_id, rpc_id)
-            .generate(&arg, msg.get_bytes_mut())
-            .unwrap();
-        #[cfg(feature = "resume-profile")]
-        crate::log::info!("rpc req size:{}", req_sz);
-        session.post(msg, req_sz, signal_flag)?;
+    ) -> Result<(), CallError<SS::IOResult>> {
+        self.call_async(session_id, my_session_id, rpc_id, arg)
+            .map(|_| ())
+    }
+
+    /// Call the corresponding RPC without waiting for the reply.
+    /// If the session runs out of the credits, the call is sent after they are returned.
+    ///
+    /// Return
+    /// * the request ID, whose reply can be waited with `get_reply`
+    ///
+    pub fn call_async<Args>(
+        &mut self,
+        session_id: usize,
+        my_session_id: usize,
+        rpc_id: usize,
+        arg: Args,
+    ) -> Result<ReqId, CallError<SS::IOResult>> {
+        let payload = unsafe {
+            core::slice::from_raw_parts(
+                &arg as *const Args as *const u8,
+                core::mem::size_of::<Args>(),
+            )
+        };
+        self.call_async_bytes(session_id, my_session_id, rpc_id, payload)
+    }
 
-        // 8 is really really a magic number
-        if session.get_pending_reqs() > 8 {
-            let res = crate::block_on(session); // should never fail
-            assert!(res.is_ok());
-            assert!(session.get_pending_reqs() == 0);
+    /// Like `call_async`, but the argument is a raw payload.
+    /// The payload larger than a datagram is sent as fragments,
+    /// up to the `max_msg_size` of the `FragmentConfig`.
+    ///
+    /// Fail with `CallError::peer_down` if the session is not connected.
+    pub fn call_async_bytes(
+        &mut self,
+        session_id: usize,
+        my_session_id: usize,
+        rpc_id: usize,
+        payload: &[u8],
+    ) -> Result<ReqId, CallError<SS::IOResult>> {
+        if payload.len() > self.frag.max_msg_size {
+            return Err(CallError::too_large());
+        }
+        // e.g., the session is removed since its peer is down
+        if !self.session_connected(session_id) {
+            return Err(CallError::peer_down());
         }
+        let req_id = self.next_req_id;
 
-        Ok(())
+        let header = header::MsgHeader::gen_call_stub(my_session_id, rpc_id, req_id, payload.len())
+            .clone_with_nonce(self.nonce_of(session_id));
+        let req = fragment::OutgoingMsg::new(header, Arc::from(payload));
+        self.inflight.insert(
+            req_id,
+            reliable::InflightCall::new(session_id, my_session_id, rpc_id, req, &self.retransmit),
+        );
+
+        // the blocked calls of the session go first
+        let starved = self
+            .credits
+            .get(&session_id)
+            .map(|c| c.is_starved())
+            .unwrap_or(false);
+        let sent = if starved { Ok(false) } else { self.send_call(req_id) };
+        match sent {
+            Ok(true) => {}
+            Ok(false) => self.blocked.push_back(req_id),
+            Err(e) => {
+                self.inflight.remove(&req_id);
+                return Err(CallError::inner(e));
+            }
+        }
+        self.analysis.handle_one();
+        self.analysis.handle_session_call(session_id);
+        self.analysis.handle_bytes_out(session_id, payload.len());
+        self.analysis.handle_queue_depth(self.inflight.len());
+
+        // skip 0 on wrap-around, which is reserved for connect
+        self.next_req_id = self.next_req_id.wrapping_add(1).max(1);
+        Ok(req_id)
+    }
+
+    /// The nonce of the session, 0 before its connect reply
+    #[inline]
+    fn nonce_of(&self, session_id: usize) -> u64 {
+        self.nonces.get(&session_id).copied().unwrap_or(0)
     }
 
     /// Return if the corresponding session exists
@@ -108,28 +235,56 @@ where
     ) -> Result<(), SS::IOResult>
     where
         SS::ReqPayload: ToBytes,
+        SS::HyperMeta: crate::serialize::Serialize,
     {
-        let mut msg_buf = SS::ReqPayload::create(R::MTU, 0, self.inner_receiver.get_context());
+        let nbufs = send_buf::num_send_bufs(&s, self.frag.window);
+        let mut bufs = send_buf::SendBufs::new(
+            (0..nbufs)
+                .map(|_| SS::ReqPayload::create(R::MTU, 0, self.inner_receiver.get_context()))
+                .collect(),
+        );
+
+        // ask for as many credits as the send queue holds
+        let credit = header::CreditStub::new(session_id, s.get_send_depth() as u64);
         let req_sz = ConnectStubFactory::new(my_session_id)
-            .generate(&meta, msg_buf.get_bytes_mut())
+            .set_credit(credit)
+            .set_auth(self.auth_token)
+            .generate(&meta, bufs.next_buf(&mut s).get_bytes_mut())
             .unwrap();
-        s.post(&msg_buf, req_sz, true)?;
-
-        // FIXME: timeout?
-        // In our UD-based session, it will never happen
-        // Yet, we should handle this
-        match crate::block_on(&mut s) {
-            Ok(_) => {}
-            _ => panic!(),
-        };
+        bufs.post(&mut s, req_sz)?;
 
-        self.connected_sessions.insert(session_id, (s, msg_buf));
+        self.connected_sessions.insert(session_id, (s, bufs));
+        self.peers
+            .insert(session_id, heartbeat::Liveness::new(my_session_id));
+        // granted with the reply
+        self.credits.insert(session_id, Default::default());
+        Ok(())
+    }
+
+    /// Close the session, and notify the remote hook to release it.
+    ///
+    /// The in-flight calls of the session fail with `CallError::peer_down`.
+    pub fn disconnect(&mut self, session_id: usize) -> Result<(), SS::IOResult> {
+        let my_session_id = match self.peers.get(&session_id) {
+            Some(p) => p.get_my_session_id(),
+            None => return Ok(()),
+        };
+        if let Some((session, bufs)) = self.connected_sessions.get_mut(&session_id) {
+            let header = header::MsgHeader::gen_disconnect_stub(my_session_id)
+                .clone_with_auth(self.auth_token)
+                .clone_with_nonce(self.nonces.get(&session_id).copied().unwrap_or(0));
+            let sz = fragment::write_msg(bufs.next_buf(session).get_bytes_mut(), &header, &[])
+                .unwrap();
+            bufs.post(session, sz)?;
+        }
+        // sent before the buffers are freed
+        self.remove_session(session_id);
         Ok(())
     }
 
     /// Get the corresponding session
-    pub fn get_ss(&self, session_id: usize) -> Option<&(SS, <SS as RPCConn<SS>>::ReqPayload)> {
-        self.connected_sessions.get(&session_id)
+    pub fn get_ss(&self, session_id: usize) -> Option<&SS> {
+        self.connected_sessions.get(&session_id).map(|(s, _)| s)
     }
 }
 
@@ -142,63 +297,701 @@ where
         Self {
             inner_receiver: inner,
             connected_sessions: HashMap::new(),
+            next_req_id: 1,
+            completed: HashMap::new(),
+            inflight: HashMap::new(),
+            retransmit: Default::default(),
+            reassemblies: HashMap::new(),
+            assembled: HashMap::new(),
+            frag: Default::default(),
+            peers: HashMap::new(),
+            heartbeat: Default::default(),
+            aborted: HashSet::new(),
+            credits: HashMap::new(),
+            blocked: VecDeque::new(),
+            auth_token: 0,
+            nonces: HashMap::new(),
+            analysis: analysis::RPCAnalysis::new(),
         }
     }
 
-    pub fn register_recv_buf(&mut self, msg: R::MsgBuf) -> Result<(), R::IOResult> {
+    /// The latencies are measured from the call to the arrival of its reply
+    pub fn get_analysis(&self) -> &analysis::RPCAnalysis {
+        &self.analysis
+    }
+
+    /// Set the max message size and the window of fragments.
+    /// The hooks of the connected sessions should accept the max message size.
+    pub fn set_fragment_config(&mut self, config: fragment::FragmentConfig) {
+        self.frag = config;
+    }
+
+    /// Set how the lost calls are retransmitted
+    pub fn set_retransmit_config(&mut self, config: reliable::RetransmitConfig) {
+        self.retransmit = config;
+    }
+
+    /// Set how often the heartbeats are sent, and when a silent peer is down
+    pub fn set_heartbeat_config(&mut self, config: heartbeat::HeartbeatConfig) {
+        self.heartbeat = config;
+    }
+
+    /// Set the token to authenticate the sessions connected (and dis-connected) later.
+    /// A hook silently drops the connect requests without its token.
+    ///
+    /// The calls of a session should be issued after its connect reply,
+    /// which gives the nonce the hook requires, see `RPCHook::set_auth_token`.
+    /// The token is sent in plaintext, so it guards against misconfiguration, not attackers.
+    pub fn set_auth_token(&mut self, token: u64) {
+        self.auth_token = token;
+    }
+
+    /// Remove the session without notifying the remote, e.g., its peer is down.
+    /// The in-flight calls of the session fail with `CallError::peer_down`.
+    ///
+    /// Return the number of the failed calls.
+    pub fn remove_session(&mut self, session_id: usize) -> usize {
+        if let Some((mut session, bufs)) = self.connected_sessions.remove(&session_id) {
+            // the send buffers are freed with the session
+            bufs.wait_all(&mut session);
+        }
+        self.peers.remove(&session_id);
+        self.credits.remove(&session_id);
+        self.nonces.remove(&session_id);
+
+        let aborted: Vec<ReqId> = self
+            .inflight
+            .iter()
+            .filter(|(_, c)| c.get_session_id() == session_id)
+            .map(|(id, _)| *id)
+            .collect();
+        for id in aborted.iter() {
+            self.inflight.remove(id);
+            self.reassemblies.remove(id);
+            self.aborted.insert(*id);
+        }
+        self.blocked.retain(|id| !aborted.contains(id));
+        aborted.len()
+    }
+
+    #[inline]
+    fn touch_peer(&mut self, session_id: usize) {
+        if let Some(p) = self.peers.get_mut(&session_id) {
+            p.touch();
+        }
+    }
+
+    pub fn register_recv_buf(&mut self, msg: R::MsgBuf) -> Result<(), R::IOResult>
+    where
+        R::MsgBuf: ToBytes,
+    {
+        // the reassembled reply returned with the message is no longer used
+        if !self.assembled.is_empty() {
+            self.assembled.remove(&msg.get_bytes().get_raw());
+        }
         self.inner_receiver.post_recv_buf(msg)
     }
+
+    /// Number of the received replies that have not been taken yet
+    pub fn get_completed_num(&self) -> usize {
+        self.completed.len()
+    }
+
+    /// Number of the calls waiting for the replies
+    pub fn get_inflight_num(&self) -> usize {
+        self.inflight.len()
+    }
+}
+
+impl<R, SS> Caller<R, SS>
+where
+    R: Receiver,
+    SS: RPCConn,
+    SS::ReqPayload: ToBytes,
+{
+    /// Send the call (or the rest of its fragments) as far as the credits allow.
+    ///
+    /// Return false if it is blocked for the credits, or the send buffers of a fragmented call.
+    fn send_call(&mut self, req_id: ReqId) -> Result<bool, SS::IOResult> {
+        let call = match self.inflight.get_mut(&req_id) {
+            Some(c) => c,
+            None => return Ok(true),
+        };
+        let (session, bufs) = match self.connected_sessions.get_mut(&call.get_session_id()) {
+            Some(s) => s,
+            None => return Ok(true),
+        };
+        let credits = self.credits.entry(call.get_session_id()).or_default();
+
+        if !call.req.is_single::<R>() {
+            return call
+                .req
+                .send_window::<R, SS, _>(self.frag.window, session, bufs, credits);
+        }
+
+        let stamp = match credits.take() {
+            Some(c) => c,
+            None => return Ok(false),
+        };
+        let req_sz = call
+            .req
+            .write_single(bufs.next_buf(session).get_bytes_mut(), stamp)
+            .unwrap();
+        #[cfg(feature = "resume-profile")]
+        crate::log::info!("rpc req size:{}", req_sz);
+        bufs.post(session, req_sz)?;
+        Ok(true)
+    }
+
+    /// Resume the blocked calls of the session in order, until it runs out of the credits again
+    fn resume_blocked(&mut self, session_id: usize) {
+        let mut i = 0;
+        while i < self.blocked.len() {
+            let req_id = self.blocked[i];
+            match self.inflight.get_mut(&req_id) {
+                None => {
+                    self.blocked.remove(i);
+                    continue;
+                }
+                Some(call) if call.get_session_id() != session_id => {
+                    i += 1;
+                    continue;
+                }
+                // the wait for the reply starts on sending
+                Some(call) => call.restart(),
+            }
+
+            match self.send_call(req_id) {
+                Ok(true) => {
+                    self.blocked.remove(i);
+                }
+                Ok(false) => break,
+                Err(_) => {
+                    // retransmitted later
+                    crate::log::error!("failed to send the request {}", req_id);
+                    self.blocked.remove(i);
+                }
+            }
+        }
+    }
+
+    /// Resume the blocked calls that stopped for the busy send buffers rather than the credits
+    fn resume_unstarved(&mut self) {
+        let req_id = match self.blocked.front() {
+            Some(id) => *id,
+            None => return,
+        };
+        let session_id = match self.inflight.get(&req_id) {
+            Some(call) => call.get_session_id(),
+            None => {
+                self.blocked.pop_front();
+                return;
+            }
+        };
+        let starved = self
+            .credits
+            .get(&session_id)
+            .map(|c| c.is_starved())
+            .unwrap_or(false);
+        if !starved {
+            self.resume_blocked(session_id);
+        }
+    }
+
+    /// The hook returns the credits of a session
+    fn on_credit(&mut self, credit: &header::CreditStub) {
+        let resume = match self.credits.get_mut(&credit.get_token()) {
+            Some(c) => c.on_limit(credit.get_seq()),
+            None => false,
+        };
+        if resume {
+            self.resume_blocked(credit.get_token());
+        }
+    }
 }
 
 use crate::future::*;
 
-impl<R, SS> crate::future::Future for Caller<R, SS>
+impl<R, SS> Caller<R, SS>
+where
+    R: Receiver<MsgBuf = <R as Future>::Output>,
+    SS: RPCConn,
+    R::Output: ToBytes,
+    SS::ReqPayload: ToBytes,
+{
+    /// Wait for the reply of a call issued by `call_async`.
+    ///
+    /// The replies of other calls received in the meantime are kept in the caller,
+    /// until they are waited by their own futures (or `UNCLAIMED_REPLY_TIMEOUT_USEC` passes).
+    ///
+    /// Example:
+    /// ```
+    /// let id = caller.call_async(session_id, my_session_id, rpc_id, arg)?;
+    /// let (msg, reply) = block_on(&mut TimeoutWRef::new(&mut caller.get_reply(id), timeout))?;
+    /// caller.register_recv_buf(msg)?;
+    /// ```
+    #[inline]
+    pub fn get_reply(&mut self, req_id: ReqId) -> CallFuture<'_, R, SS> {
+        CallFuture {
+            caller: self,
+            req_id: req_id,
+        }
+    }
+
+    /// Poll the reply of a specific call
+    pub fn poll_reply(
+        &mut self,
+        req_id: ReqId,
+    ) -> Poll<(R::Output, BytesMut), CallError<R::Error>> {
+        if let Some((msg, res, _)) = self.completed.remove(&req_id) {
+            return Self::to_reply(msg, res);
+        }
+        if self.aborted.remove(&req_id) {
+            return Err(CallError::peer_down());
+        }
+
+        // drain the received messages until we find the one we want
+        loop {
+            match self.poll_one()? {
+                Async::NotReady => break,
+                Async::Ready((id, msg, res)) => {
+                    if id == req_id {
+                        return Self::to_reply(msg, res);
+                    }
+                    self.completed.insert(id, (msg, res, KTimer::new()));
+                }
+            }
+        }
+        self.expire_unclaimed();
+        self.resume_unstarved();
+
+        self.retransmit_expired();
+        if self.is_lost(req_id) {
+            self.inflight.remove(&req_id);
+            self.reassemblies.remove(&req_id);
+            self.analysis.handle_error(analysis::ErrorKind::Timeout);
+            self.analysis.handle_queue_depth(self.inflight.len());
+            return Err(CallError::lost());
+        }
+        Ok(Async::NotReady)
+    }
+
+    /// Stop waiting for the reply of a call, e.g., its future has timed out.
+    /// The reply is dropped whenever it arrives, and the call is no longer retransmitted.
+    /// A call still in flight is counted as timed out.
+    pub fn cancel(&mut self, req_id: ReqId) {
+        if self.inflight.remove(&req_id).is_some() {
+            self.analysis.handle_error(analysis::ErrorKind::Timeout);
+            self.analysis.handle_queue_depth(self.inflight.len());
+        }
+        self.reassemblies.remove(&req_id);
+        self.aborted.remove(&req_id);
+        self.blocked.retain(|id| *id != req_id);
+        if let Some((msg, _, _)) = self.completed.remove(&req_id) {
+            self.recycle(msg);
+        }
+    }
+
+    /// Drop the received replies that have not been waited for long
+    fn expire_unclaimed(&mut self) {
+        if self.completed.is_empty() {
+            return;
+        }
+        let expired: Vec<ReqId> = self
+            .completed
+            .iter()
+            .filter(|(_, (_, _, t))| t.get_passed_usec() >= UNCLAIMED_REPLY_TIMEOUT_USEC)
+            .map(|(id, _)| *id)
+            .collect();
+        for id in expired {
+            crate::log::debug!("drop the unclaimed reply of request {}", id);
+            let (msg, _, _) = self.completed.remove(&id).unwrap();
+            self.recycle(msg);
+        }
+    }
+
+    /// Retransmit the calls whose replies have not arrived in time
+    fn retransmit_expired(&mut self) {
+        let config = self.retransmit;
+        let expired: Vec<ReqId> = self
+            .inflight
+            .iter()
+            .filter(|(_, c)| c.is_expired() && !c.is_exhausted(&config))
+            .map(|(id, _)| *id)
+            .collect();
+
+        for id in expired {
+            if self.blocked.contains(&id) {
+                continue;
+            }
+            let call = self.inflight.get_mut(&id).unwrap();
+            // wait for the credits, instead of burning the retries
+            match self.credits.get(&call.get_session_id()) {
+                Some(c) if c.available() > 0 && !c.is_starved() => {}
+                _ => continue,
+            }
+            call.backoff(&config);
+            call.req.rewind();
+            crate::log::debug!("retransmit request {} to session {}", id, call.get_session_id());
+
+            match self.send_call(id) {
+                Ok(true) => {}
+                Ok(false) => self.blocked.push_back(id),
+                Err(_) => crate::log::error!("failed to retransmit request {}", id),
+            }
+        }
+    }
+
+    /// Check the liveness of the connected peers, which should be called periodically.
+    ///
+    /// The received replies are kept until they are waited, and the heartbeats are sent if due.
+    /// The sessions of the down peers are removed (see `remove_session`).
+    ///
+    /// Return the IDs of the removed sessions.
+    pub fn check_peers(&mut self) -> Result<Vec<usize>, CallError<R::Error>> {
+        // the heartbeats may be waiting behind the replies
+        while let Async::Ready((id, msg, res)) = self.poll_one()? {
+            self.completed.insert(id, (msg, res, KTimer::new()));
+        }
+        self.expire_unclaimed();
+
+        let config = self.heartbeat;
+        let mut down = Vec::new();
+        for (session_id, peer) in self.peers.iter_mut() {
+            if peer.is_down(&config) {
+                down.push(*session_id);
+                continue;
+            }
+            if !peer.should_ping(&config) {
+                continue;
+            }
+            peer.on_ping();
+
+            let (session, bufs) = match self.connected_sessions.get_mut(session_id) {
+                Some(s) => s,
+                None => continue,
+            };
+            // sent beyond the credits, so the ones held by the lost messages are returned
+            let stamp = match self.credits.entry(*session_id).or_default().take_control() {
+                Some(s) => s,
+                None => continue,
+            };
+            let header = header::MsgHeader::gen_heartbeat_stub(peer.get_my_session_id(), *session_id)
+                .clone_with_credit(stamp)
+                .clone_with_nonce(self.nonces.get(session_id).copied().unwrap_or(0));
+            let sz = fragment::write_msg(bufs.next_buf(session).get_bytes_mut(), &header, &[])
+                .unwrap();
+            if bufs.post(session, sz).is_err() {
+                crate::log::error!("failed to send the heartbeat to session {}", session_id);
+            }
+        }
+
+        for session_id in down.iter() {
+            let failed = self.remove_session(*session_id);
+            crate::log::warn!(
+                "the peer of session {} is down, fail {} in-flight calls",
+                session_id,
+                failed
+            );
+        }
+        Ok(down)
+    }
+
+    /// Whether the call has been retransmitted too many times without the reply
+    #[inline]
+    fn is_lost(&self, req_id: ReqId) -> bool {
+        self.inflight
+            .get(&req_id)
+            .map(|c| c.is_exhausted(&self.retransmit) && c.is_expired())
+            .unwrap_or(false)
+    }
+
+    /// Poll one reply from the receiver and parse its header.
+    /// The duplicated replies (of the retransmitted calls) are dropped.
+    fn poll_one(&mut self) -> Poll<(ReqId, R::Output, CallResult), CallError<R::Error>> {
+        loop {
+            let polled = self.poll_one_msg().map_err(|e| {
+                // the other errors are raised by the malformed messages
+                self.analysis.handle_error(if e.is_inner() {
+                    analysis::ErrorKind::Other
+                } else {
+                    analysis::ErrorKind::Corrupted
+                });
+                e
+            })?;
+            match polled {
+                Async::NotReady => return Ok(Async::NotReady),
+                // consumed by the fragmentation
+                Async::Ready(None) => {}
+                Async::Ready(Some((id, msg, res))) => {
+                    // the connect requests (ID 0) are not tracked
+                    if id == 0 {
+                        return Ok(Async::Ready((id, msg, res)));
+                    }
+                    if let Some(call) = self.inflight.remove(&id) {
+                        self.touch_peer(call.get_session_id());
+                        self.analysis.handle_queue_depth(self.inflight.len());
+                        self.analysis
+                            .handle_latency(call.get_rpc_id(), call.get_latency_usec());
+                        match res.as_ref() {
+                            Ok(reply) => self.analysis.handle_bytes_in(call.get_session_id(), reply.len()),
+                            Err(_) => self.analysis.handle_error(analysis::ErrorKind::NoID),
+                        }
+                        return Ok(Async::Ready((id, msg, res)));
+                    }
+                    crate::log::debug!("drop the duplicated (or cancelled) reply of request {}", id);
+                    self.recycle(msg);
+                }
+            }
+        }
+    }
+
+    #[inline]
+    fn recycle(&mut self, msg: R::Output) {
+        if self.register_recv_buf(msg).is_err() {
+            crate::log::error!("failed to re-post a recv buf");
+        }
+    }
+
+    fn poll_one_msg(
+        &mut self,
+    ) -> Poll<Option<(ReqId, R::Output, CallResult)>, CallError<R::Error>> {
+        let msg = match self.inner_receiver.poll() {
+            Ok(Async::NotReady) => return Ok(Async::NotReady),
+            Ok(Async::Ready(msg)) => msg,
+            Err(e) => return Err(CallError::inner(e)),
+        };
+
+        let mut msg_header: header::MsgHeader = Default::default();
+
+        let bytes = unsafe { msg.get_bytes().clone() };
+        let msg_header_bytes = unsafe { bytes.truncate_header(R::HEADER).unwrap() };
+        unsafe { msg_header_bytes.memcpy_deserialize(&mut msg_header) };
+
+        let payload = unsafe {
+            msg_header_bytes
+                .truncate_header(core::mem::size_of::<header::MsgHeader>())
+                .and_then(|b| b.clone_and_resize(msg_header.get_payload()))
+                .ok_or(CallError::fatal())?
+        };
+
+        // all the messages from the hook carry the credits
+        self.on_credit(msg_header.get_credit());
+
+        // the connect reply gives the nonce of the session
+        if let Some(stub) = msg_header.get_reply_stub() {
+            let session_id = msg_header.get_credit().get_token();
+            if stub.get_req_id() == 0 && self.session_connected(session_id) {
+                self.nonces.insert(session_id, msg_header.get_nonce());
+            }
+        }
+
+        match msg_header.get_marker() {
+            header::ReqType::Reply => {
+                let stub = *msg_header.get_reply_stub().ok_or(CallError::fatal())?;
+                if msg_header.is_fragment() {
+                    return Ok(Async::Ready(self.on_reply_fragment(msg, &msg_header, &payload)));
+                }
+                let res = match stub.get_status() {
+                    header::ReplyStatus::Ok => Ok(payload),
+                    status => Err(status),
+                };
+                Ok(Async::Ready(Some((stub.get_req_id(), msg, res))))
+            }
+            header::ReqType::FragAck => {
+                let stub = *msg_header.get_frag_ack_stub().ok_or(CallError::fatal())?;
+                self.on_frag_ack(&stub);
+                self.recycle(msg);
+                Ok(Async::Ready(None))
+            }
+            header::ReqType::Heartbeat => {
+                let stub = *msg_header.get_heartbeat_stub().ok_or(CallError::fatal())?;
+                self.touch_peer(stub.get_token());
+                self.recycle(msg);
+                Ok(Async::Ready(None))
+            }
+            header::ReqType::Credit => {
+                self.recycle(msg);
+                Ok(Async::Ready(None))
+            }
+            _ => Err(CallError::fatal()),
+        }
+    }
+
+    /// Reassemble a fragment of a reply, return the reply if it is complete
+    fn on_reply_fragment(
+        &mut self,
+        msg: R::Output,
+        msg_header: &header::MsgHeader,
+        payload: &BytesMut,
+    ) -> Option<(ReqId, R::Output, CallResult)> {
+        use fragment::{Assemble, Reassembly};
+
+        let req_id = msg_header.get_reply_stub().unwrap().get_req_id();
+        let total = msg_header.get_frag().get_total();
+
+        // only the replies of my in-flight calls are reassembled
+        if !self.inflight.contains_key(&req_id) || total > self.frag.max_msg_size {
+            self.recycle(msg);
+            return None;
+        }
+
+        if Reassembly::is_first(msg_header) {
+            self.reassemblies.insert(req_id, Reassembly::new(total));
+        }
+        let res = match self.reassemblies.get_mut(&req_id) {
+            Some(r) => r.on_fragment(msg_header, payload, self.frag.ack_every()),
+            None => Assemble::Ignored,
+        };
+
+        match res {
+            Assemble::Ignored => {
+                self.recycle(msg);
+                None
+            }
+            Assemble::Partial(ack) => {
+                self.recycle(msg);
+                if let Some(received) = ack {
+                    self.send_frag_ack(req_id, received);
+                }
+                None
+            }
+            Assemble::Complete => {
+                let mut reply = self.reassemblies.remove(&req_id).unwrap().into_inner();
+                // the hook keeps the reply until all of it is acknowledged
+                self.send_frag_ack(req_id, reply.len());
+
+                let bytes = unsafe { BytesMut::from_raw(reply.as_mut_ptr(), reply.len()) };
+                self.assembled.insert(msg.get_bytes().get_raw(), reply);
+                Some((req_id, msg, Ok(bytes)))
+            }
+        }
+    }
+
+    /// Acknowledge the received bytes of a fragmented reply
+    fn send_frag_ack(&mut self, req_id: ReqId, received: usize) {
+        let call = match self.inflight.get(&req_id) {
+            Some(c) => c,
+            None => return,
+        };
+        let (session, bufs) = match self.connected_sessions.get_mut(&call.get_session_id()) {
+            Some(s) => s,
+            None => return,
+        };
+
+        // sent beyond the credits, otherwise the reply stalls.
+        // Without any, the reply is sent again when the call is retransmitted.
+        let stamp = match self.credits.entry(call.get_session_id()).or_default().take_control() {
+            Some(s) => s,
+            None => return,
+        };
+        let ack = header::MsgHeader::gen_frag_ack_stub(call.get_my_session_id(), req_id, received)
+            .clone_with_credit(stamp)
+            .clone_with_nonce(self.nonces.get(&call.get_session_id()).copied().unwrap_or(0));
+        let sz = fragment::write_msg(bufs.next_buf(session).get_bytes_mut(), &ack, &[]).unwrap();
+        if bufs.post(session, sz).is_err() {
+            crate::log::error!("failed to ack the reply of request {}", req_id);
+        }
+    }
+
+    /// The hook has received part of a fragmented request, so send more
+    fn on_frag_ack(&mut self, stub: &header::FragAckStub) {
+        let req_id = stub.get_req_id();
+        match self.inflight.get_mut(&req_id) {
+            Some(call) if !call.req.is_single::<R>() => call.req.on_ack(stub.get_received()),
+            _ => return,
+        }
+        // sent when the credits are returned
+        if self.blocked.contains(&req_id) {
+            return;
+        }
+
+        match self.send_call(req_id) {
+            Ok(true) => {}
+            Ok(false) => self.blocked.push_back(req_id),
+            Err(_) => crate::log::error!("failed to send the request {}", req_id),
+        }
+    }
+
+    #[inline]
+    fn to_reply(msg: R::Output, res: CallResult) -> Poll<(R::Output, BytesMut), CallError<R::Error>> {
+        match res {
+            Ok(reply) => Ok(Async::Ready((msg, reply))),
+            Err(header::ReplyStatus::Malformed) => Err(CallError::malformed()),
+            Err(header::ReplyStatus::TooLarge) => Err(CallError::too_large()),
+            Err(_) => Err(CallError::no_id()),
+        }
+    }
+}
+
+/// The future of a call issued by `Caller::call_async`
+pub struct CallFuture<'a, R: Receiver, S: RPCConn> {
+    caller: &'a mut Caller<R, S>,
+    req_id: ReqId,
+}
+
+impl<'a, R, SS> CallFuture<'a, R, SS>
 where
     R: Receiver,
     SS: RPCConn,
+{
+    #[inline]
+    pub fn get_req_id(&self) -> ReqId {
+        self.req_id
+    }
+}
+
+impl<'a, R, SS> crate::future::Future for CallFuture<'a, R, SS>
+where
+    R: Receiver<MsgBuf = <R as Future>::Output>,
+    SS: RPCConn,
     R::Output: ToBytes,
+    SS::ReqPayload: ToBytes,
+{
+    type Output = (R::Output, BytesMut);
+    type Error = CallError<R::Error>;
+
+    fn poll(&mut self) -> Poll<Self::Output, Self::Error> {
+        self.caller.poll_reply(self.req_id)
+    }
+}
+
+impl<R, SS> crate::future::Future for Caller<R, SS>
+where
+    R: Receiver<MsgBuf = <R as Future>::Output>,
+    SS: RPCConn,
+    R::Output: ToBytes,
+    SS::ReqPayload: ToBytes,
 {
     type Output = (R::Output, BytesMut);
     // registered message, the reply
     type Error = CallError<R::Error>;
 
+    /// Return the first received reply, regardless of its request ID
     fn poll(&mut self) -> Poll<Self::Output, Self::Error> {
-        match self.inner_receiver.poll() {
-            Ok(Async::NotReady) => Ok(Async::NotReady),
-            Ok(Async::Ready(msg)) => {
-                let mut msg_header: header::MsgHeader = Default::default();
-
-                let bytes = unsafe { msg.get_bytes().clone() };
-                let msg_header_bytes = unsafe { bytes.truncate_header(R::HEADER).unwrap() };
-                unsafe { msg_header_bytes.memcpy_deserialize(&mut msg_header) };
-
-                if msg_header.is_reply() {
-                    match msg_header.get_reply_stub() {
-                        Some(stub) => {
-                            match stub {
-                                header::ReplyStatus::Ok => {
-                                    // parse and return the detailed message
-                                    Ok(Async::Ready((msg, unsafe {
-                                        msg_header_bytes
-                                            .truncate_header(
-                                                core::mem::size_of::<header::MsgHeader>(),
-                                            )
-                                            .unwrap()
-                                            .clone_and_resize(msg_header.get_payload())
-                                            .unwrap()
-                                    })))
-                                }
-                                header::ReplyStatus::NotExist => Err(CallError::no_id()),
-                            }
-                        }
-                        None => Err(CallError::fatal()),
-                    }
-                } else {
-                    Err(CallError::fatal())
-                }
-            }
-            Err(e) => Err(CallError::inner(e)),
+        if let Some(id) = self.completed.keys().next().copied() {
+            let (msg, res, _) = self.completed.remove(&id).unwrap();
+            return Self::to_reply(msg, res);
         }
+        if let Some(id) = self.aborted.iter().next().copied() {
+            self.aborted.remove(&id);
+            return Err(CallError::peer_down());
+        }
+        match self.poll_one()? {
+            Async::NotReady => {}
+            Async::Ready((_, msg, res)) => return Self::to_reply(msg, res),
+        }
+
+        self.retransmit_expired();
+        let lost = self.inflight.keys().copied().find(|id| self.is_lost(*id));
+        if let Some(id) = lost {
+            self.inflight.remove(&id);
+            self.reassemblies.remove(&id);
+            self.analysis.handle_error(analysis::ErrorKind::Timeout);
+            self.analysis.handle_queue_depth(self.inflight.len());
+            return Err(CallError::lost());
+        }
+        Ok(Async::NotReady)
     }
 }
 
@@ -208,6 +1001,27 @@ pub mod hook;
 pub mod header;
 pub mod header_factory;
 
+// retransmission and duplicate detection
+pub mod reliable;
+
+pub use reliable::RetransmitConfig;
+
+// fragmentation of the large messages
+pub mod fragment;
+
+pub use fragment::FragmentConfig;
+
+// flow control of the sessions
+pub mod credit;
+
+// the send buffers of the sessions
+pub mod send_buf;
+
+// liveness of the peers
+pub mod heartbeat;
+
+pub use heartbeat::HeartbeatConfig;
+
 pub use header_factory::*;
 
 // modules for registering RPC callbacks
@@ -235,6 +1049,16 @@ pub trait RPCConn<T: Future = Self>: Future {
 
     // a call specific to RDMA
     fn get_pending_reqs(&self) -> usize;
+
+    // the number of the signaled requests posted to the underlying QP so far
+    fn get_signaled_reqs(&self) -> u64;
+
+    // the number of the signaled requests of the QP completed so far, in the order of posting,
+    // so the ones posted before the last completed are done as well
+    fn get_completed_reqs(&self) -> u64;
+
+    // the max number of the outstanding requests in the send queue
+    fn get_send_depth(&self) -> usize;
 }
 
 /// This is a simple wrapper over crate::conn::Factory
@@ -292,6 +1116,18 @@ enum CallKind<T> {
 
     ConnectError,
 
+    /// No reply after retransmitting the request for several times
+    Lost,
+
+    /// The message exceeds the max message size, or the reply exceeds the hook's reply buffer
+    TooLarge,
+
+    /// The hook cannot parse the request
+    Malformed,
+
+    /// The session is removed before the reply arrives, e.g., its peer is down
+    PeerDown,
+
     Fatal,
 }
 
@@ -322,4 +1158,37 @@ impl<T> CallError<T> {
     pub fn connect_error() -> CallError<T> {
         CallError(CallKind::ConnectError)
     }
+
+    /// Create a new `Error` representing the call is lost after the retransmissions
+    pub fn lost() -> CallError<T> {
+        CallError(CallKind::Lost)
+    }
+
+    pub fn too_large() -> CallError<T> {
+        CallError(CallKind::TooLarge)
+    }
+
+    /// Create a new `Error` representing the request is rejected as malformed by the hook
+    pub fn malformed() -> CallError<T> {
+        CallError(CallKind::Malformed)
+    }
+
+    /// Create a new `Error` representing the session of the call is removed
+    pub fn peer_down() -> CallError<T> {
+        CallError(CallKind::PeerDown)
+    }
+
+    pub fn is_peer_down(&self) -> bool {
+        match self.0 {
+            CallKind::PeerDown => true,
+            _ => false,
+        }
+    }
+
+    pub fn is_lost(&self) -> bool {
+        match self.0 {
+            CallKind::Lost => true,
+            _ => false,
+        }
+    }
 }
@@ -1,16 +1,167 @@
 use hashbrown::HashMap;
 
-#[derive(Debug)]
+/// Number of the buckets of a `LatencyHistogram`
+pub const LATENCY_BUCKETS: usize = 16;
+
+/// The latencies in log2 buckets of microseconds:
+/// bucket 0 counts the ones less than 1us, bucket i counts the ones in [2^(i-1), 2^i) us,
+/// and the last bucket also counts all the larger ones.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct LatencyHistogram {
+    buckets: [u64; LATENCY_BUCKETS],
+    count: u64,
+    sum_usec: u64,
+    max_usec: u64,
+}
+
+impl LatencyHistogram {
+    #[inline]
+    pub fn record(&mut self, usec: i64) {
+        let usec = core::cmp::max(usec, 0) as u64;
+        let idx = (64 - usec.leading_zeros()) as usize;
+        self.buckets[core::cmp::min(idx, LATENCY_BUCKETS - 1)] += 1;
+        self.count += 1;
+        self.sum_usec += usec;
+        self.max_usec = core::cmp::max(self.max_usec, usec);
+    }
+
+    pub fn merge(&mut self, other: &Self) {
+        for (b, o) in self.buckets.iter_mut().zip(other.buckets.iter()) {
+            *b += o;
+        }
+        self.count += other.count;
+        self.sum_usec += other.sum_usec;
+        self.max_usec = core::cmp::max(self.max_usec, other.max_usec);
+    }
+
+    #[inline]
+    pub fn get_buckets(&self) -> &[u64; LATENCY_BUCKETS] {
+        &self.buckets
+    }
+
+    #[inline]
+    pub fn get_count(&self) -> u64 {
+        self.count
+    }
+
+    #[inline]
+    pub fn get_max_usec(&self) -> u64 {
+        self.max_usec
+    }
+
+    #[inline]
+    pub fn get_avg_usec(&self) -> u64 {
+        if self.count == 0 {
+            return 0;
+        }
+        self.sum_usec / self.count
+    }
+
+    /// The upper bound (in us) of the bucket where the `p`-th (0-100) percentile falls in
+    pub fn percentile_usec(&self, p: u64) -> u64 {
+        let target = (self.count * core::cmp::min(p, 100) + 99) / 100;
+        let mut seen = 0;
+        for (i, b) in self.buckets.iter().enumerate() {
+            seen += b;
+            if seen >= target && seen > 0 {
+                if i == LATENCY_BUCKETS - 1 {
+                    return self.max_usec;
+                }
+                return 1 << i;
+            }
+        }
+        0
+    }
+}
+
+/// The kinds of the failed calls (at the caller) or requests (at the hook)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// The call is lost after the retransmissions, or the session expires at the hook
+    Timeout,
+    /// The RPC ID is not registered at the hook
+    NoID,
+    /// The message header (or the request of a typed RPC) is corrupted
+    Corrupted,
+    Other,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ErrorCounts {
+    pub timeout: usize,
+    pub no_id: usize,
+    pub corrupted: usize,
+    pub other: usize,
+}
+
+impl ErrorCounts {
+    pub fn merge(&mut self, other: &Self) {
+        self.timeout += other.timeout;
+        self.no_id += other.no_id;
+        self.corrupted += other.corrupted;
+        self.other += other.other;
+    }
+}
+
+/// The statistics of one session, indexed by its ID at the hook
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SessionStats {
+    pub ncalls: usize,
+    // the payload bytes of the requests and the replies
+    pub bytes_in: usize,
+    pub bytes_out: usize,
+}
+
+impl SessionStats {
+    pub fn merge(&mut self, other: &Self) {
+        self.ncalls += other.ncalls;
+        self.bytes_in += other.bytes_in;
+        self.bytes_out += other.bytes_out;
+    }
+}
+
+/// The statistics of a caller or a hook.
+///
+/// The latencies are measured per RPC ID: at the caller from the call to its reply,
+/// and at the hook the execution of the callback.
+/// The queue depth is the number of the in-flight calls at the caller, sampled on each call
+/// and each completion, and the pending replies in the send queues of all the sessions
+/// at the hook, sampled on each reply.
+#[derive(Debug, Clone)]
 pub struct RPCAnalysis {
     ncalls: usize,
-    pub(crate) session_counts: HashMap<usize, usize>,
+    // duplicate requests replied from the cache, or dropped
+    nduplicates: usize,
+    // connect (and dis-connect) requests without the token of the hook
+    nrejected: usize,
+    errors: ErrorCounts,
+    bytes_in: usize,
+    bytes_out: usize,
+    queue_depth: usize,
+    max_queue_depth: usize,
+    pub(crate) sessions: HashMap<usize, SessionStats>,
+    latencies: HashMap<usize, LatencyHistogram>,
+}
+
+impl Default for RPCAnalysis {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl RPCAnalysis {
     pub fn new() -> Self {
         Self {
             ncalls: 0,
-            session_counts: Default::default(),
+            nduplicates: 0,
+            nrejected: 0,
+            errors: Default::default(),
+            bytes_in: 0,
+            bytes_out: 0,
+            queue_depth: 0,
+            max_queue_depth: 0,
+            sessions: Default::default(),
+            latencies: Default::default(),
         }
     }
 
@@ -18,6 +169,86 @@ impl RPCAnalysis {
         self.ncalls
     }
 
+    pub fn get_nduplicates(&self) -> usize {
+        self.nduplicates
+    }
+
+    pub fn get_nrejected(&self) -> usize {
+        self.nrejected
+    }
+
+    pub fn get_errors(&self) -> &ErrorCounts {
+        &self.errors
+    }
+
+    pub fn get_bytes_in(&self) -> usize {
+        self.bytes_in
+    }
+
+    pub fn get_bytes_out(&self) -> usize {
+        self.bytes_out
+    }
+
+    /// The queue depth when it was last sampled
+    pub fn get_queue_depth(&self) -> usize {
+        self.queue_depth
+    }
+
+    pub fn get_max_queue_depth(&self) -> usize {
+        self.max_queue_depth
+    }
+
+    pub fn get_session(&self, id: usize) -> core::option::Option<&SessionStats> {
+        self.sessions.get(&id)
+    }
+
+    pub fn get_sessions(&self) -> &HashMap<usize, SessionStats> {
+        &self.sessions
+    }
+
+    pub fn get_latency(&self, rpc_id: usize) -> core::option::Option<&LatencyHistogram> {
+        self.latencies.get(&rpc_id)
+    }
+
+    /// The latency histograms indexed by the RPC ID
+    pub fn get_latencies(&self) -> &HashMap<usize, LatencyHistogram> {
+        &self.latencies
+    }
+
+    /// Accumulate the statistics of another caller or hook, e.g., of other threads.
+    /// The queue depths are summed, while the maximum is the largest one of them,
+    /// since they are not reached at the same time.
+    pub fn merge(&mut self, other: &Self) {
+        self.ncalls += other.ncalls;
+        self.nduplicates += other.nduplicates;
+        self.nrejected += other.nrejected;
+        self.errors.merge(&other.errors);
+        self.bytes_in += other.bytes_in;
+        self.bytes_out += other.bytes_out;
+        self.queue_depth += other.queue_depth;
+        self.max_queue_depth = core::cmp::max(self.max_queue_depth, other.max_queue_depth);
+        for (id, s) in other.sessions.iter() {
+            self.sessions.entry(*id).or_default().merge(s);
+        }
+        for (id, h) in other.latencies.iter() {
+            self.latencies.entry(*id).or_default().merge(h);
+        }
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self::new();
+    }
+
+    #[inline]
+    pub fn handle_duplicate(&mut self) {
+        self.nduplicates += 1;
+    }
+
+    #[inline]
+    pub fn handle_rejected(&mut self) {
+        self.nrejected += 1;
+    }
+
     #[inline]
     pub fn handle_one(&mut self) {
         self.ncalls += 1;
@@ -25,10 +256,39 @@ impl RPCAnalysis {
 
     #[inline]
     pub fn handle_session_call(&mut self, id: usize) {
-        if self.session_counts.contains_key(&id) {
-            self.session_counts.insert(id, self.session_counts[&id] + 1);
-        } else {
-            self.session_counts.insert(id, 1);
+        self.sessions.entry(id).or_default().ncalls += 1;
+    }
+
+    #[inline]
+    pub fn handle_error(&mut self, kind: ErrorKind) {
+        match kind {
+            ErrorKind::Timeout => self.errors.timeout += 1,
+            ErrorKind::NoID => self.errors.no_id += 1,
+            ErrorKind::Corrupted => self.errors.corrupted += 1,
+            ErrorKind::Other => self.errors.other += 1,
         }
     }
+
+    #[inline]
+    pub fn handle_latency(&mut self, rpc_id: usize, usec: i64) {
+        self.latencies.entry(rpc_id).or_default().record(usec);
+    }
+
+    #[inline]
+    pub fn handle_bytes_in(&mut self, session_id: usize, sz: usize) {
+        self.bytes_in += sz;
+        self.sessions.entry(session_id).or_default().bytes_in += sz;
+    }
+
+    #[inline]
+    pub fn handle_bytes_out(&mut self, session_id: usize, sz: usize) {
+        self.bytes_out += sz;
+        self.sessions.entry(session_id).or_default().bytes_out += sz;
+    }
+
+    #[inline]
+    pub fn handle_queue_depth(&mut self, depth: usize) {
+        self.queue_depth = depth;
+        self.max_queue_depth = core::cmp::max(self.max_queue_depth, depth);
+    }
 }
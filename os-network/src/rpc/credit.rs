@@ -0,0 +1,162 @@
+//! Credit-based flow control of the RPC sessions.
+//!
+//! A caller sends at most as many messages to a session as the credits it holds,
+//! so it never overruns the receive buffers of the hook.
+//! The credits are negotiated on connect: the caller asks for the depth of its send queue,
+//! and the hook grants at most its share of the posted receive buffers.
+//! The shares are rebalanced as the sessions come and go. A shrunk grant takes effect
+//! as the caller uses up the credits granted before, since a granted credit is never revoked.
+//!
+//! Each message to the hook carries its sequence number in the session (`CreditStub`).
+//! After the hook has re-posted the receive buffer of a message, the caller can send one more:
+//! the messages to the caller carry the credit limit, i.e., the last sequence number it can send.
+//! The limit is piggybacked on the replies (and other messages to the caller),
+//! or sent alone (`ReqType::Credit`) if half of the grant is returned without any of them.
+//! Since the limit is cumulative, a lost or duplicated message never leaks the credits.
+//!
+//! The calls without credits are blocked in the caller, and resumed when the credits are returned.
+//! The control messages (acks and heartbeats) may exceed the limit by `CONTROL_CREDITS`,
+//! which the hook reserves for each session besides its grant,
+//! so a session never stalls, e.g., when the messages holding the credits are lost.
+//!
+//! The replies need no credits, since each of them answers a request,
+//! and the fragments of a large reply are bounded by the fragment window.
+
+use super::header::CreditStub;
+
+/// Number of the control messages a caller can send beyond its credit limit
+pub const CONTROL_CREDITS: usize = 2;
+
+/// The credits held by the caller of a session
+#[derive(Debug, Default)]
+pub(crate) struct Credits {
+    // the sequence number of the last sent message
+    sent: u64,
+    // the last sequence number allowed by the hook
+    limit: u64,
+    // some calls are blocked for the credits
+    starved: bool,
+}
+
+impl Credits {
+    #[inline]
+    pub(crate) fn available(&self) -> usize {
+        self.limit.saturating_sub(self.sent) as usize
+    }
+
+    #[inline]
+    pub(crate) fn is_starved(&self) -> bool {
+        self.starved
+    }
+
+    /// Take a credit to send a message, return the stamp of the message
+    pub(crate) fn take(&mut self) -> core::option::Option<CreditStub> {
+        if self.sent >= self.limit {
+            self.starved = true;
+            return None;
+        }
+        self.sent += 1;
+        Some(CreditStub::new(0, self.sent))
+    }
+
+    /// Take a credit to send a control message, which may use the reserved ones.
+    ///
+    /// Return None if the reserved credits are used up as well, so the message is dropped
+    /// (and sent again later, e.g., by the next heartbeat).
+    pub(crate) fn take_control(&mut self) -> core::option::Option<CreditStub> {
+        if self.sent >= self.limit + CONTROL_CREDITS as u64 {
+            return None;
+        }
+        self.sent += 1;
+        Some(CreditStub::new(0, self.sent))
+    }
+
+    /// The hook returns the credits.
+    ///
+    /// Return true if the blocked calls can be resumed.
+    pub(crate) fn on_limit(&mut self, limit: u64) -> bool {
+        self.limit = core::cmp::max(self.limit, limit);
+        if self.starved && self.sent < self.limit {
+            self.starved = false;
+            return true;
+        }
+        false
+    }
+}
+
+/// The credits granted by the hook to a session
+#[derive(Debug)]
+pub(crate) struct Grant {
+    // the caller's ID of the session
+    token: usize,
+    // the credits asked by the caller, 0 if it does not ask
+    asked: u64,
+    size: u64,
+    // the sequence number of the last message whose receive buffer is re-posted
+    freed: u64,
+    // the last credit limit sent to the caller
+    stamped: u64,
+}
+
+impl Grant {
+    pub(crate) fn new(token: usize, asked: usize) -> Self {
+        Self {
+            token: token,
+            asked: asked as u64,
+            size: 0,
+            freed: 0,
+            stamped: 0,
+        }
+    }
+
+    /// Grant as many credits as the caller asks, bounded by `share`
+    #[inline]
+    pub(crate) fn resize(&mut self, share: usize) {
+        let mut size = share as u64;
+        if self.asked > 0 {
+            size = core::cmp::min(size, self.asked);
+        }
+        self.size = core::cmp::max(size, 1);
+    }
+
+    /// The receive buffer of a message is re-posted.
+    /// The earlier messages not received are lost, so their credits are returned as well.
+    #[inline]
+    pub(crate) fn on_freed(&mut self, seq: u64) {
+        self.freed = core::cmp::max(self.freed, seq);
+    }
+
+    /// The stamp of a message to the caller, carrying the credit limit.
+    /// The limit never goes back, even if the grant shrinks.
+    #[inline]
+    pub(crate) fn stamp(&mut self) -> CreditStub {
+        self.stamped = core::cmp::max(self.stamped, self.freed + self.size);
+        CreditStub::new(self.token, self.stamped)
+    }
+
+    /// Whether the credits should be returned without waiting for a reply to piggyback
+    #[inline]
+    pub(crate) fn should_return(&self) -> bool {
+        (self.freed + self.size).saturating_sub(self.stamped) >= core::cmp::max(self.size / 2, 1)
+    }
+}
+
+/// The source of the stamps of the messages sent in a row, e.g., the fragments
+pub(crate) trait Stamp {
+    /// Return None if the message cannot be sent for now
+    fn next_stamp(&mut self) -> core::option::Option<CreditStub>;
+}
+
+impl Stamp for Credits {
+    #[inline]
+    fn next_stamp(&mut self) -> core::option::Option<CreditStub> {
+        self.take()
+    }
+}
+
+impl Stamp for Grant {
+    #[inline]
+    fn next_stamp(&mut self) -> core::option::Option<CreditStub> {
+        Some(self.stamp())
+    }
+}
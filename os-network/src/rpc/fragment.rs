@@ -0,0 +1,238 @@
+//! Fragmentation and reassembly of the RPC messages larger than one datagram.
+//!
+//! A large message is sent as fragments, each carrying the message header
+//! with its position (`FragStub`) in the message.
+//! The receiver reassembles the fragments in order, and acknowledges the received bytes
+//! (`ReqType::FragAck`) every half window. The sender keeps at most a window of
+//! unacknowledged fragments, so it never overruns the receive buffers of the peer.
+//!
+//! A lost fragment is recovered by the caller's retransmission:
+//! the message is sent again from the first fragment, which restarts the reassembly.
+
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+use super::credit::Stamp;
+use super::header::{CreditStub, MsgHeader};
+use super::send_buf::SendBufs;
+use super::RPCConn;
+use crate::bytes::{BytesMut, ToBytes};
+use crate::datagram::Receiver;
+
+/// Configuration of the fragmentation
+#[derive(Debug, Clone, Copy)]
+pub struct FragmentConfig {
+    /// The max size of a request or reply payload
+    pub max_msg_size: usize,
+    /// The max number of unacknowledged fragments of a message
+    pub window: usize,
+}
+
+impl Default for FragmentConfig {
+    fn default() -> Self {
+        // so that a session buffer of the hook (with the header) fits in 64KB
+        Self {
+            max_msg_size: 60 * 1024,
+            window: 8,
+        }
+    }
+}
+
+impl FragmentConfig {
+    /// Receive an acknowledgement after so many fragments
+    #[inline]
+    pub(crate) fn ack_every(&self) -> usize {
+        core::cmp::max(self.window / 2, 1)
+    }
+}
+
+/// The max payload of one fragment, received by R
+#[inline]
+pub fn frag_payload_size<R: Receiver>() -> usize {
+    R::MTU - R::HEADER - core::mem::size_of::<MsgHeader>()
+}
+
+/// A message being sent
+pub(crate) struct OutgoingMsg {
+    // the header of the message, copied to each fragment
+    header: MsgHeader,
+    payload: Arc<[u8]>,
+    sent: usize,
+    acked: usize,
+}
+
+impl OutgoingMsg {
+    pub(crate) fn new(header: MsgHeader, payload: Arc<[u8]>) -> Self {
+        Self {
+            header: header,
+            payload: payload,
+            sent: 0,
+            acked: 0,
+        }
+    }
+
+    #[inline]
+    pub(crate) fn len(&self) -> usize {
+        self.payload.len()
+    }
+
+    /// The message fits in one datagram, so it is sent without fragmentation
+    #[inline]
+    pub(crate) fn is_single<R: Receiver>(&self) -> bool {
+        self.len() <= frag_payload_size::<R>()
+    }
+
+    #[inline]
+    pub(crate) fn is_acked(&self) -> bool {
+        self.acked >= self.len()
+    }
+
+    /// The ack may be a stale one before the rewind, so it never exceeds the sent bytes
+    pub(crate) fn on_ack(&mut self, received: usize) {
+        self.acked = core::cmp::min(core::cmp::max(self.acked, received), self.sent);
+    }
+
+    /// Send the message again from the first fragment
+    pub(crate) fn rewind(&mut self) {
+        self.sent = 0;
+        self.acked = 0;
+    }
+
+    /// Write the whole message (header + payload) to the buffer, return its size
+    pub(crate) fn write_single(&mut self, buf: &mut BytesMut, credit: CreditStub) -> core::option::Option<usize> {
+        let header = self.header.clone_with_frag(0, 0, self.len()).clone_with_credit(credit);
+        let sz = write_msg(buf, &header, &self.payload)?;
+        self.sent = self.len();
+        Some(sz)
+    }
+
+    /// Whether the window allows sending the next fragment
+    #[inline]
+    fn can_send<R: Receiver>(&self, window: usize) -> bool {
+        self.sent < self.len() && self.sent < self.acked + window * frag_payload_size::<R>()
+    }
+
+    /// Write the next fragment to the buffer, return its size
+    fn write_next<R: Receiver>(&mut self, buf: &mut BytesMut, credit: CreditStub) -> core::option::Option<usize> {
+        let end = core::cmp::min(self.sent + frag_payload_size::<R>(), self.len());
+        let header = self
+            .header
+            .clone_with_frag(self.len(), self.sent, end - self.sent)
+            .clone_with_credit(credit);
+        let sz = write_msg(buf, &header, &self.payload[self.sent..end])?;
+        self.sent = end;
+        Some(sz)
+    }
+
+    /// Send the fragments allowed by the window, each stamped by the credits.
+    /// Each fragment is staged in its own send buffer of the session.
+    ///
+    /// It never waits for the completions: it stops if no send buffer is free,
+    /// and the rest of the window is sent by a later call.
+    /// Return false if it stops for running out of the credits or the buffers.
+    pub(crate) fn send_window<R: Receiver, S: RPCConn, C: Stamp>(
+        &mut self,
+        window: usize,
+        session: &mut S,
+        bufs: &mut SendBufs<S::ReqPayload>,
+        credits: &mut C,
+    ) -> Result<bool, S::IOResult>
+    where
+        S::ReqPayload: ToBytes,
+    {
+        while self.can_send::<R>(window) {
+            let credit = match credits.next_stamp() {
+                Some(c) => c,
+                None => return Ok(false),
+            };
+            let buf = match bufs.try_next_buf(session) {
+                Some(b) => b,
+                None => return Ok(false),
+            };
+            let sz = match self.write_next::<R>(buf.get_bytes_mut(), credit) {
+                Some(sz) => sz,
+                None => break,
+            };
+            bufs.post(session, sz)?;
+        }
+        Ok(true)
+    }
+}
+
+/// Write the header and then the payload to the buffer, return the total size
+pub(crate) fn write_msg(buf: &mut BytesMut, header: &MsgHeader, payload: &[u8]) -> core::option::Option<usize> {
+    let header_sz = core::mem::size_of::<MsgHeader>();
+    if buf.len() < header_sz + payload.len() {
+        return None;
+    }
+    unsafe {
+        buf.memcpy_serialize_at(0, header)?;
+        core::ptr::copy_nonoverlapping(
+            payload.as_ptr(),
+            buf.get_ptr().add(header_sz),
+            payload.len(),
+        );
+    }
+    Some(header_sz + payload.len())
+}
+
+/// What the receiver should do after a fragment is reassembled
+pub(crate) enum Assemble {
+    /// The fragment is out-of-order (or a duplicate), and is dropped
+    Ignored,
+    /// Send an acknowledgement of the received bytes if Some
+    Partial(Option<usize>),
+    /// The message is complete
+    Complete,
+}
+
+/// A message being reassembled
+pub(crate) struct Reassembly {
+    buf: Vec<u8>,
+    received: usize,
+    unacked: usize,
+}
+
+impl Reassembly {
+    pub(crate) fn new(total: usize) -> Self {
+        Self {
+            buf: alloc::vec![0; total],
+            received: 0,
+            unacked: 0,
+        }
+    }
+
+    /// Whether the fragment starts the message
+    #[inline]
+    pub(crate) fn is_first(header: &MsgHeader) -> bool {
+        header.get_frag().get_offset() == 0
+    }
+
+    pub(crate) fn on_fragment(&mut self, header: &MsgHeader, payload: &BytesMut, ack_every: usize) -> Assemble {
+        let offset = header.get_frag().get_offset();
+        let sz = header.get_payload();
+        if offset != self.received || offset + sz > self.buf.len() || payload.len() < sz {
+            return Assemble::Ignored;
+        }
+
+        unsafe {
+            core::ptr::copy_nonoverlapping(payload.get_ptr(), self.buf.as_mut_ptr().add(offset), sz)
+        };
+        self.received += sz;
+        self.unacked += 1;
+
+        if self.received == self.buf.len() {
+            return Assemble::Complete;
+        }
+        if self.unacked >= ack_every {
+            self.unacked = 0;
+            return Assemble::Partial(Some(self.received));
+        }
+        Assemble::Partial(None)
+    }
+
+    #[inline]
+    pub(crate) fn into_inner(self) -> Vec<u8> {
+        self.buf
+    }
+}
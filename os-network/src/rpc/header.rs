@@ -3,6 +3,8 @@
 pub struct CallStub {
     session_id: usize,
     rpc_id: usize,
+    // echoed by the reply, so the caller can match it
+    req_id: u64,
 }
 
 impl CallStub { 
@@ -13,15 +15,27 @@ impl CallStub {
     pub fn get_rpc_id(&self) -> usize { 
         self.rpc_id
     }
+
+    pub fn get_req_id(&self) -> u64 {
+        self.req_id
+    }
 }
 
 /// Data structures used to generate the RPC connect struct 
 #[derive(Debug, Default, Copy, Clone)]
-pub struct ConnectStub(usize);
+pub struct ConnectStub {
+    session_id: usize,
+    // the token shared by the cluster, see `RPCHook::set_auth_token`
+    auth: u64,
+}
 
 impl ConnectStub {
     pub fn get_session_id(&self) -> usize {
-        self.0
+        self.session_id
+    }
+
+    pub fn get_auth(&self) -> u64 {
+        self.auth
     }
 }
 
@@ -32,14 +46,124 @@ pub enum ReplyStatus {
     #[default]
     Ok = 1, // a success call
     NotExist = 3, // function is not registered in the service
+    Malformed = 4, // the request cannot be parsed by the function
+    TooLarge = 5, // the reply exceeds the reply buffer
+}
+
+/// The reply status, with the request ID of the call it replies.
+/// The reply of a connect request carries the request ID 0.
+#[derive(Debug, Default, Copy, Clone)]
+pub struct ReplyStub {
+    status: ReplyStatus,
+    req_id: u64,
+}
+
+impl ReplyStub {
+    pub fn get_status(&self) -> ReplyStatus {
+        self.status
+    }
+
+    pub fn get_req_id(&self) -> u64 {
+        self.req_id
+    }
 }
 
-#[derive(Debug, Default)]
+/// Acknowledge the received bytes of a fragmented message, see `rpc::fragment`
+#[derive(Debug, Default, Copy, Clone)]
+pub struct FragAckStub {
+    // the session ID of the sender of the ack, used by the hook to find the reply
+    session_id: usize,
+    req_id: u64,
+    received: usize,
+}
+
+impl FragAckStub {
+    pub fn get_session_id(&self) -> usize {
+        self.session_id
+    }
+
+    pub fn get_req_id(&self) -> u64 {
+        self.req_id
+    }
+
+    pub fn get_received(&self) -> usize {
+        self.received
+    }
+}
+
+/// A heartbeat of the caller, echoed by the hook, see `rpc::heartbeat`
+#[derive(Debug, Default, Copy, Clone)]
+pub struct HeartbeatStub {
+    // the session ID of the caller at the hook
+    session_id: usize,
+    // the caller's ID of the session, echoed back so the caller can find it
+    token: usize,
+}
+
+impl HeartbeatStub {
+    pub fn get_session_id(&self) -> usize {
+        self.session_id
+    }
+
+    pub fn get_token(&self) -> usize {
+        self.token
+    }
+}
+
+/// The flow control of a session, see `rpc::credit`.
+///
+/// In a message to the hook, `seq` is the sequence number of the message in its session,
+/// or the credits asked for in a connect request.
+/// In a message to the caller, `seq` is the credit limit, i.e., the last sequence number
+/// the caller can send, and `token` is the caller's ID of the session.
+#[derive(Debug, Default, Copy, Clone)]
+pub struct CreditStub {
+    token: usize,
+    seq: u64,
+}
+
+impl CreditStub {
+    pub fn new(token: usize, seq: u64) -> Self {
+        Self { token, seq }
+    }
+
+    pub fn get_token(&self) -> usize {
+        self.token
+    }
+
+    pub fn get_seq(&self) -> u64 {
+        self.seq
+    }
+}
+
+/// The position of a fragment in its message.
+/// A message not fragmented has a zero `total`.
+#[derive(Debug, Default, Copy, Clone)]
+pub struct FragStub {
+    total: u32,
+    offset: u32,
+}
+
+impl FragStub {
+    /// The payload size of the whole message
+    pub fn get_total(&self) -> usize {
+        self.total as usize
+    }
+
+    pub fn get_offset(&self) -> usize {
+        self.offset as usize
+    }
+}
+
+#[derive(Debug, Default, Copy, Clone)]
 #[repr(u64)]
 enum RPCMeta {
     Request(CallStub),
-    Reply(ReplyStatus),
+    Reply(ReplyStub),
     Connect(ConnectStub),
+    FragAck(FragAckStub),
+    DisConnect(ConnectStub),
+    Heartbeat(HeartbeatStub),
     #[default]
     None,
 }
@@ -53,13 +177,21 @@ pub enum ReqType {
     Request = 1,
     Reply = 2,
     DisConnect = 3,
+    FragAck = 5,
+    Heartbeat = 6,
+    Credit = 7,
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Copy, Clone)]
 pub struct MsgHeader {
     marker: ReqType,
     payload: usize,
     meta: RPCMeta,
+    frag: FragStub,
+    credit: CreditStub,
+    // the nonce of the session given on connect, which authenticates the messages to the hook,
+    // see `RPCHook::set_auth_token`
+    nonce: u64,
 }
 
 impl MsgHeader {
@@ -67,26 +199,124 @@ impl MsgHeader {
         Self {
             marker: ReqType::Connect,
             payload: payload,
-            meta: RPCMeta::Connect(ConnectStub(session_id)),
+            meta: RPCMeta::Connect(ConnectStub { session_id, auth: 0 }),
+            frag: Default::default(),
+            credit: Default::default(),
+            nonce: 0,
+        }
+    }
+
+    /// The session of the caller (session_id at the hook) is closed
+    pub fn gen_disconnect_stub(session_id: usize) -> Self {
+        Self {
+            marker: ReqType::DisConnect,
+            payload: 0,
+            meta: RPCMeta::DisConnect(ConnectStub { session_id, auth: 0 }),
+            frag: Default::default(),
+            credit: Default::default(),
+            nonce: 0,
+        }
+    }
+
+    pub fn gen_heartbeat_stub(session_id: usize, token: usize) -> Self {
+        Self {
+            marker: ReqType::Heartbeat,
+            payload: 0,
+            meta: RPCMeta::Heartbeat(HeartbeatStub { session_id, token }),
+            frag: Default::default(),
+            credit: Default::default(),
+            nonce: 0,
+        }
+    }
+
+    /// The credits returned to the caller alone, see `rpc::credit`
+    pub fn gen_credit_stub() -> Self {
+        Self {
+            marker: ReqType::Credit,
+            payload: 0,
+            meta: RPCMeta::None,
+            frag: Default::default(),
+            credit: Default::default(),
+            nonce: 0,
+        }
+    }
+
+    pub fn gen_frag_ack_stub(session_id: usize, req_id: u64, received: usize) -> Self {
+        Self {
+            marker: ReqType::FragAck,
+            payload: 0,
+            meta: RPCMeta::FragAck(FragAckStub {
+                session_id,
+                req_id,
+                received,
+            }),
+            frag: Default::default(),
+            credit: Default::default(),
+            nonce: 0,
+        }
+    }
+
+    /// The header of a fragment (or a whole message if total is 0) of this message
+    pub fn clone_with_frag(&self, total: usize, offset: usize, payload: usize) -> Self {
+        Self {
+            payload: payload,
+            frag: FragStub {
+                total: total as u32,
+                offset: offset as u32,
+            },
+            ..*self
+        }
+    }
+
+    /// The header stamped with the credit of the session
+    pub fn clone_with_credit(&self, credit: CreditStub) -> Self {
+        Self {
+            credit: credit,
+            ..*self
         }
     }
 
-    pub fn gen_call_stub(session_id: usize, rpc_id: usize, payload: usize) -> Self {
+    pub fn clone_with_nonce(&self, nonce: u64) -> Self {
+        Self {
+            nonce: nonce,
+            ..*self
+        }
+    }
+
+    /// The (dis-)connect header carrying the token of the cluster.
+    /// Other headers carry no token, so they are returned as is.
+    pub fn clone_with_auth(&self, auth: u64) -> Self {
+        let meta = match self.meta {
+            RPCMeta::Connect(s) => RPCMeta::Connect(ConnectStub { auth, ..s }),
+            RPCMeta::DisConnect(s) => RPCMeta::DisConnect(ConnectStub { auth, ..s }),
+            meta => meta,
+        };
+        Self { meta: meta, ..*self }
+    }
+
+    pub fn gen_call_stub(session_id: usize, rpc_id: usize, req_id: u64, payload: usize) -> Self {
         Self {
             marker: ReqType::Request,
             payload: payload,
             meta: RPCMeta::Request(CallStub {
                 session_id: session_id,
                 rpc_id: rpc_id,
+                req_id: req_id,
             }),
+            frag: Default::default(),
+            credit: Default::default(),
+            nonce: 0,
         }
     }
 
-    pub fn gen_reply_stub(status : ReplyStatus, sz : usize) -> Self { 
+    pub fn gen_reply_stub(status : ReplyStatus, req_id : u64, sz : usize) -> Self { 
         Self { 
             marker : ReqType::Reply,
             payload : sz, 
-            meta : RPCMeta::Reply(status)
+            meta : RPCMeta::Reply(ReplyStub { status, req_id }),
+            frag : Default::default(),
+            credit : Default::default(),
+            nonce : 0,
         }
     }
 
@@ -107,13 +337,57 @@ impl MsgHeader {
     }
 
     #[inline]
-    pub fn get_reply_stub(&self) -> core::option::Option<&ReplyStatus> {
+    pub fn get_reply_stub(&self) -> core::option::Option<&ReplyStub> {
         match &self.meta {
             RPCMeta::Reply(s) => Some(s),
             _ => None,
         }
     }
 
+    #[inline]
+    pub fn get_frag_ack_stub(&self) -> core::option::Option<&FragAckStub> {
+        match &self.meta {
+            RPCMeta::FragAck(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    #[inline]
+    pub fn get_disconnect_stub(&self) -> core::option::Option<&ConnectStub> {
+        match &self.meta {
+            RPCMeta::DisConnect(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    #[inline]
+    pub fn get_heartbeat_stub(&self) -> core::option::Option<&HeartbeatStub> {
+        match &self.meta {
+            RPCMeta::Heartbeat(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    #[inline]
+    pub fn get_frag(&self) -> &FragStub {
+        &self.frag
+    }
+
+    #[inline]
+    pub fn get_credit(&self) -> &CreditStub {
+        &self.credit
+    }
+
+    pub fn get_nonce(&self) -> u64 {
+        self.nonce
+    }
+
+    /// Whether the message is a fragment of a larger one
+    #[inline]
+    pub fn is_fragment(&self) -> bool {
+        self.frag.get_total() > 0
+    }
+
     #[inline]
     pub fn get_marker(&self) -> ReqType {
         self.marker
@@ -139,4 +413,8 @@ impl MsgHeader {
     pub fn is_disconnect(&self) -> bool {
         self.marker == ReqType::DisConnect
     }
+
+    pub fn is_heartbeat(&self) -> bool {
+        self.marker == ReqType::Heartbeat
+    }
 }
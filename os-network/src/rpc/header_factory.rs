@@ -1,12 +1,36 @@
 use super::header::*;
 use crate::bytes::BytesMut;
+use crate::serialize::Serialize;
 
-pub struct ConnectStubFactory(usize);
+pub struct ConnectStubFactory {
+    session_id: usize,
+    credit: CreditStub,
+    auth: u64,
+}
 
 impl ConnectStubFactory {
     #[inline]
     pub fn new(id: usize) -> Self {
-        Self(id)
+        Self {
+            session_id: id,
+            credit: Default::default(),
+            auth: 0,
+        }
+    }
+
+    /// Ask for the credits of the session, see `rpc::credit`
+    #[inline]
+    pub fn set_credit(mut self, credit: CreditStub) -> Self {
+        self.credit = credit;
+        self
+    }
+
+    /// Tag the connection with the token shared by the cluster.
+    /// It is sent in plaintext, see `RPCHook::set_auth_token`.
+    #[inline]
+    pub fn set_auth(mut self, auth: u64) -> Self {
+        self.auth = auth;
+        self
     }
 
     /// Generate the connect stub using a connect meta data (T)
@@ -15,15 +39,21 @@ impl ConnectStubFactory {
     /// * if succeed, return the real message size
     ///
     #[inline]
-    pub fn generate<T: Sized>(self, meta: &T, msg: &mut BytesMut) -> core::option::Option<usize> {
-        unsafe {
-            Some(
-                msg.memcpy_serialize_at(
-                    0,
-                    &MsgHeader::gen_connect_stub(self.0, core::mem::size_of_val(meta)),
-                )? + msg.memcpy_serialize_at(core::mem::size_of::<MsgHeader>(), meta)?,
-            )
+    pub fn generate<T: Serialize>(self, meta: &T, msg: &mut BytesMut) -> core::option::Option<usize> {
+        let meta_sz = meta.serialization_buf_len();
+        let header_sz = unsafe {
+            msg.memcpy_serialize_at(
+                0,
+                &MsgHeader::gen_connect_stub(self.session_id, meta_sz)
+                    .clone_with_credit(self.credit)
+                    .clone_with_auth(self.auth),
+            )?
+        };
+        let mut payload = unsafe { msg.truncate_header(header_sz)? };
+        if !meta.serialize(&mut payload) {
+            return None;
         }
+        Some(header_sz + meta_sz)
     }
 }
 
@@ -31,6 +61,7 @@ impl ConnectStubFactory {
 pub struct CallStubFactory {
     session_id: usize,
     rpc_id: usize,
+    req_id: u64,
 }
 
 impl CallStubFactory {
@@ -39,6 +70,7 @@ impl CallStubFactory {
         Self {
             session_id: id,
             rpc_id: rpc_id,
+            req_id: 0,
         }
     }
 
@@ -48,6 +80,12 @@ impl CallStubFactory {
         self
     }
 
+    #[inline]
+    pub fn set_req_id(mut self, id: u64) -> Self {
+        self.req_id = id;
+        self
+    }
+
     /// Generate the call stub using a connect meta data (T)
     ///
     /// Return
@@ -62,6 +100,7 @@ impl CallStubFactory {
                     &MsgHeader::gen_call_stub(
                         self.session_id,
                         self.rpc_id,
+                        self.req_id,
                         core::mem::size_of::<T>(),
                     ),
                 )? + msg.memcpy_serialize_at(core::mem::size_of::<MsgHeader>(), meta)?,
@@ -72,7 +111,9 @@ impl CallStubFactory {
 
 pub struct ReplyStubFactory {
     status: ReplyStatus,
+    req_id: u64,
     payload: usize,
+    credit: CreditStub,
 }
 
 impl ReplyStubFactory {
@@ -80,10 +121,26 @@ impl ReplyStubFactory {
     pub fn new(status: ReplyStatus, sz: usize) -> Self {
         Self {
             status: status,
+            req_id: 0,
             payload: sz,
+            credit: Default::default(),
         }
     }
 
+    /// Set the request ID of the call to reply
+    #[inline]
+    pub fn set_req_id(mut self, id: u64) -> Self {
+        self.req_id = id;
+        self
+    }
+
+    /// Return the credits of the session with the reply, see `rpc::credit`
+    #[inline]
+    pub fn set_credit(mut self, credit: CreditStub) -> Self {
+        self.credit = credit;
+        self
+    }
+
     #[inline]
     pub fn get_payload(&self) -> usize {
         self.payload
@@ -91,6 +148,8 @@ impl ReplyStubFactory {
 
     #[inline]
     pub fn generate(self, msg: &mut BytesMut) -> core::option::Option<usize> {
-        unsafe { msg.memcpy_serialize_at(0, &MsgHeader::gen_reply_stub(self.status, self.payload)) }
+        let header = MsgHeader::gen_reply_stub(self.status, self.req_id, self.payload)
+            .clone_with_credit(self.credit);
+        unsafe { msg.memcpy_serialize_at(0, &header) }
     }
 }
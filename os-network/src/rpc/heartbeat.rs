@@ -0,0 +1,82 @@
+//! Liveness of the connected peers.
+//!
+//! The caller sends a heartbeat over each of its sessions periodically, which is echoed by the hook.
+//! A peer is down if nothing has been received from it for `max_missed` intervals,
+//! e.g., it has crashed, or rebooted: a rebooted hook has lost the session, so it ignores the heartbeats.
+//!
+//! On the other side, the hook can expire the sessions whose callers have been silent for long,
+//! see `RPCHook::expire_sessions`.
+
+use rust_kernel_linux_util::timer::KTimer;
+
+/// Configuration of the heartbeats
+#[derive(Debug, Clone, Copy)]
+pub struct HeartbeatConfig {
+    /// Send a heartbeat over each session every so often
+    pub interval_usec: i64,
+    /// The peer is down after so many intervals without hearing from it
+    pub max_missed: usize,
+}
+
+impl Default for HeartbeatConfig {
+    /// A peer is down after 3 seconds of silence
+    fn default() -> Self {
+        Self {
+            interval_usec: 500_000,
+            max_missed: 6,
+        }
+    }
+}
+
+impl HeartbeatConfig {
+    #[inline]
+    pub fn down_timeout_usec(&self) -> i64 {
+        self.interval_usec * self.max_missed as i64
+    }
+}
+
+/// The liveness of the peer of a caller's session
+pub(crate) struct Liveness {
+    my_session_id: usize,
+    // time since anything is received from the peer
+    last_seen: KTimer,
+    // time since the last heartbeat is sent
+    last_ping: KTimer,
+}
+
+impl Liveness {
+    pub(crate) fn new(my_session_id: usize) -> Self {
+        Self {
+            my_session_id: my_session_id,
+            last_seen: KTimer::new(),
+            last_ping: KTimer::new(),
+        }
+    }
+
+    /// The ID of my session at the remote hook
+    #[inline]
+    pub(crate) fn get_my_session_id(&self) -> usize {
+        self.my_session_id
+    }
+
+    /// Something is received from the peer
+    #[inline]
+    pub(crate) fn touch(&mut self) {
+        self.last_seen.reset();
+    }
+
+    #[inline]
+    pub(crate) fn should_ping(&self, config: &HeartbeatConfig) -> bool {
+        self.last_ping.get_passed_usec() >= config.interval_usec
+    }
+
+    #[inline]
+    pub(crate) fn on_ping(&mut self) {
+        self.last_ping.reset();
+    }
+
+    #[inline]
+    pub(crate) fn is_down(&self, config: &HeartbeatConfig) -> bool {
+        self.last_seen.get_passed_usec() >= config.down_timeout_usec()
+    }
+}
@@ -16,7 +16,47 @@ where
     session_factory: F,
     meta_factory: Arc<MF>,
     transport: R,
-    connected_sessions: HashMap<usize, (F::ConnType, R::MsgBuf)>,
+    connected_sessions: HashMap<usize, (F::ConnType, SendBufs<R::MsgBuf>)>,
+    // the callbacks write their replies here, which are copied to the sessions' send buffers
+    reply_buf: core::option::Option<R::MsgBuf>,
+
+    // the recent replies of each session, to filter out the duplicate requests
+    reply_caches: HashMap<usize, super::reliable::ReplyCache>,
+    reply_cache_size: usize,
+
+    frag: super::fragment::FragmentConfig,
+    // the large requests being reassembled, indexed by (session ID, request ID)
+    reassemblies: HashMap<(usize, u64), Reassembly>,
+    // the large replies waiting for the acknowledgements, since the last one
+    outgoing: HashMap<(usize, u64), (OutgoingMsg, KTimer)>,
+
+    // time since each session's caller is heard
+    last_seen: HashMap<usize, KTimer>,
+    // 0 if the silent sessions never expire
+    session_timeout_usec: i64,
+
+    // the credits granted to each session, see `super::credit`
+    credits: HashMap<usize, Grant>,
+    max_session_credits: usize,
+    // number of the receive buffers shared by the sessions
+    recv_bufs: usize,
+
+    // 0 if any caller can connect
+    auth_token: u64,
+    // the nonce of each connected session, carried by all its messages
+    nonces: HashMap<usize, u64>,
+    // mixed into the nonces, see `gen_nonce`
+    nonce_seq: u64,
+    epoch: KTimer,
+
+    // the sessions are created outside the hook, see `set_deferred_connect`
+    defer_connect: bool,
+    // the sessions to create, and the credits asked by the callers waiting for them
+    connect_reqs: VecDeque<(usize, F::ConnMeta)>,
+    pending_connects: HashMap<usize, CreditStub>,
+
+    // called with the released sessions, see `set_release_callback`
+    on_release: core::option::Option<alloc::boxed::Box<dyn FnMut(usize) + 'a>>,
 
     // counting data
     analysis: super::analysis::RPCAnalysis,
@@ -30,9 +70,256 @@ where
     pub fn get_mut_service(&mut self) -> &mut Service<'a> {
         &mut self.service
     }
+
+    /// Set the number of replies cached per session, 0 to disable the duplicate detection.
+    /// It should be larger than the number of in-flight calls of a caller.
+    pub fn set_reply_cache_size(&mut self, size: usize) {
+        self.reply_cache_size = size;
+    }
+
+    /// Set the max message size and the window of fragments.
+    /// Since the reply buffer is allocated on the first connect, it should be set before any connection.
+    pub fn set_fragment_config(&mut self, config: super::fragment::FragmentConfig) {
+        self.frag = config;
+    }
+
+    /// Set the max number of credits granted to a session.
+    /// A session is granted at most its share of the posted receive buffers.
+    pub fn set_max_session_credits(&mut self, credits: usize) {
+        self.max_session_credits = credits;
+    }
+
+    /// Only accept the (dis-)connect requests carrying this token, 0 to accept any.
+    ///
+    /// Each connected session is given a nonce (derived from the token) in the connect reply,
+    /// and the other messages of the session are only accepted with its nonce,
+    /// so others cannot issue the calls of a session by forging its session ID.
+    ///
+    /// This only guards against accidental misconfiguration, e.g., a machine of another
+    /// cluster or a stale session ID; it is NOT authentication. The token and the nonces
+    /// travel in plaintext and are compared with `==`, so anyone who can observe the fabric
+    /// can replay them. The RPCs must be kept on a trusted network.
+    pub fn set_auth_token(&mut self, token: u64) {
+        self.auth_token = token;
+    }
+
+    /// Expire the sessions whose callers have been silent for so long, 0 to disable.
+    /// It should be larger than the down timeout of the callers' `HeartbeatConfig`,
+    /// otherwise the sessions of the live (yet idle) callers are expired.
+    pub fn set_session_timeout(&mut self, timeout_usec: i64) {
+        self.session_timeout_usec = timeout_usec;
+    }
+
+    /// Create the sessions of the connect requests outside the hook,
+    /// since creating one may block the poll for long (e.g., the RC handshake).
+    /// The requests are taken by `pop_connect`, and the created sessions are
+    /// handed back by `complete_connect`, which replies to their callers.
+    pub fn set_deferred_connect(&mut self, defer: bool) {
+        self.defer_connect = defer;
+    }
+
+    /// Call f with the ID of each released session, whether it is disconnected or expired.
+    /// The session replaced by a re-connect of its caller is not counted.
+    pub fn set_release_callback(&mut self, f: impl FnMut(usize) + 'a) {
+        self.on_release = Some(alloc::boxed::Box::new(f));
+    }
+
+    /// Take the next session to create, with the deferred connect
+    pub fn pop_connect(&mut self) -> core::option::Option<(usize, F::ConnMeta)> {
+        self.connect_reqs.pop_front()
+    }
+
+    /// Give up the session, e.g., it cannot be created.
+    /// The connect request of its caller times out.
+    pub fn abort_connect(&mut self, session_id: usize) {
+        self.pending_connects.remove(&session_id);
+    }
+
+    /// Release a session, and all the states of its calls
+    ///
+    /// Return false if the session is not connected
+    pub fn release_session(&mut self, session_id: usize) -> bool {
+        let released = self.remove_session(session_id);
+        if released {
+            if let Some(f) = self.on_release.as_mut() {
+                f(session_id);
+            }
+        }
+        released
+    }
+
+    /// Release the session without notifying the release callback
+    fn remove_session(&mut self, session_id: usize) -> bool {
+        // a session being created is released as well
+        let pending = self.pending_connects.remove(&session_id).is_some();
+        let released = match self.connected_sessions.remove(&session_id) {
+            Some((mut session, bufs)) => {
+                // the buffers are freed with the session
+                bufs.wait_all(&mut session);
+                true
+            }
+            None => false,
+        };
+        self.reply_caches.remove(&session_id);
+        self.last_seen.remove(&session_id);
+        self.nonces.remove(&session_id);
+        if self.credits.remove(&session_id).is_some() {
+            self.rebalance_credits();
+        }
+        self.reassemblies.retain(|(s, _), _| *s != session_id);
+        self.outgoing.retain(|(s, _), _| *s != session_id);
+        pending || released
+    }
+
+    /// Release the sessions whose callers are silent for longer than the session timeout,
+    /// which should be called periodically.
+    ///
+    /// Return the number of the released sessions.
+    pub fn expire_sessions(&mut self) -> usize {
+        self.expire_outgoing();
+        if self.session_timeout_usec <= 0 {
+            return 0;
+        }
+        let timeout = self.session_timeout_usec;
+        let expired: alloc::vec::Vec<usize> = self
+            .last_seen
+            .iter()
+            .filter(|(_, t)| t.get_passed_usec() >= timeout)
+            .map(|(id, _)| *id)
+            .collect();
+        for session_id in expired.iter() {
+            crate::log::info!("expire the silent session {}", session_id);
+            self.release_session(*session_id);
+            self.analysis.handle_error(super::analysis::ErrorKind::Timeout);
+        }
+        expired.len()
+    }
+
+    /// Drop the fragmented replies whose callers acknowledge nothing for long.
+    /// By then the callers have retransmitted the calls, which send the replies again.
+    fn expire_outgoing(&mut self) {
+        if !self.outgoing.is_empty() {
+            self.outgoing
+                .retain(|_, (_, timer)| timer.get_passed_usec() < OUTGOING_TIMEOUT_USEC);
+        }
+    }
+
+    /// The caller of the session is heard
+    #[inline]
+    fn touch(&mut self, session_id: usize) {
+        if let Some(t) = self.last_seen.get_mut(&session_id) {
+            t.reset();
+        }
+    }
+
+    /// Whether the (dis-)connect request carries the token of the hook,
+    /// or the other message carries the nonce of its session.
+    /// The rejected ones are counted in the analysis.
+    ///
+    /// A plain comparison of the values in the message, see `set_auth_token` for what it guards.
+    fn authenticate(&mut self, msg_header: &MsgHeader) -> bool {
+        let (session_id, ok) = match msg_header.get_marker() {
+            ReqType::Connect | ReqType::DisConnect => match msg_header
+                .get_connect_stub()
+                .or(msg_header.get_disconnect_stub())
+            {
+                Some(s) => (
+                    s.get_session_id(),
+                    self.auth_token == 0 || s.get_auth() == self.auth_token,
+                ),
+                // left to the corrupted header check
+                None => return true,
+            },
+            _ => {
+                let session_id = match Self::session_of(msg_header) {
+                    Some(id) => id,
+                    None => return true,
+                };
+                let nonce = self.nonces.get(&session_id).copied();
+                (session_id, nonce == Some(msg_header.get_nonce()))
+            }
+        };
+        if ok {
+            return true;
+        }
+        crate::log::warn!(
+            "reject the {:?} message of session {}: bad auth token or nonce",
+            msg_header.get_marker(),
+            session_id
+        );
+        self.analysis.handle_rejected();
+        false
+    }
+
+    /// The session of a message from the caller, None for the (dis-)connect requests
+    #[inline]
+    fn session_of(msg_header: &MsgHeader) -> core::option::Option<usize> {
+        match msg_header.get_marker() {
+            ReqType::Request => msg_header.get_call_stub().map(|s| s.get_session_id()),
+            ReqType::FragAck => msg_header.get_frag_ack_stub().map(|s| s.get_session_id()),
+            ReqType::Heartbeat => msg_header.get_heartbeat_stub().map(|s| s.get_session_id()),
+            _ => None,
+        }
+    }
+
+    /// A nonce that cannot be guessed without the auth token, yet is readable on the wire.
+    /// It mixes the token with the session, a sequence and the time (splitmix64).
+    fn gen_nonce(&mut self, session_id: usize) -> u64 {
+        self.nonce_seq += 1;
+        let mut z = self.auth_token
+            ^ (session_id as u64).rotate_left(32)
+            ^ self.nonce_seq.wrapping_mul(0x9E3779B97F4A7C15)
+            ^ (self.epoch.get_passed_usec() as u64);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+        // 0 is carried by the messages of no session
+        core::cmp::max(z ^ (z >> 31), 1)
+    }
+
+    /// The stamp of a message to the session, which returns its credits
+    #[inline]
+    fn stamp(&mut self, session_id: usize) -> CreditStub {
+        self.credits
+            .get_mut(&session_id)
+            .map(|g| g.stamp())
+            .unwrap_or_default()
+    }
+
+    /// Divide the posted receive buffers among the sessions, after one connects or leaves.
+    /// Each session is granted as many credits as its caller asks for, bounded by its share,
+    /// which excludes the buffers reserved for its control messages.
+    fn rebalance_credits(&mut self) {
+        let nsessions = core::cmp::max(self.credits.len(), 1);
+        let share = (self.recv_bufs / nsessions).saturating_sub(CONTROL_CREDITS);
+        let share = core::cmp::min(share, self.max_session_credits);
+        for grant in self.credits.values_mut() {
+            grant.resize(share);
+        }
+    }
+
+    /// The reply buffer holds the largest reply (written by the callback)
+    #[inline]
+    fn reply_buf_size(&self) -> usize {
+        core::cmp::max(R::MTU, self.frag.max_msg_size)
+    }
 }
 
-use super::header_factory::*;
+/// Default number of replies cached per session
+pub const DEFAULT_REPLY_CACHE_SIZE: usize = 32;
+
+/// Default max number of credits granted to a session
+pub const DEFAULT_SESSION_CREDITS: usize = 32;
+
+/// A fragmented reply is dropped if none of it is acknowledged for so long,
+/// i.e., the max wait of the callers' default `RetransmitConfig`
+pub const OUTGOING_TIMEOUT_USEC: i64 = 1000_000;
+
+use super::credit::{Grant, CONTROL_CREDITS};
+use super::fragment::{frag_payload_size, Assemble, OutgoingMsg, Reassembly};
+use rust_kernel_linux_util::timer::KTimer;
+use super::reliable::{Lookup, ReplyCache};
+use super::send_buf::{num_send_bufs, SendBufs};
+use super::service::{MALFORMED_REQ, REPLY_TOO_LARGE};
 
 impl<'a, F, R, MF> RPCHook<'a, F, R, MF>
 where
@@ -52,58 +339,368 @@ where
             meta_factory: meta_f,
             session_factory: factory,
             connected_sessions: HashMap::new(),
+            reply_buf: None,
+            reply_caches: HashMap::new(),
+            reply_cache_size: DEFAULT_REPLY_CACHE_SIZE,
+            frag: Default::default(),
+            reassemblies: HashMap::new(),
+            outgoing: HashMap::new(),
+            last_seen: HashMap::new(),
+            session_timeout_usec: 0,
+            credits: HashMap::new(),
+            max_session_credits: DEFAULT_SESSION_CREDITS,
+            recv_bufs: 0,
+            auth_token: 0,
+            nonces: HashMap::new(),
+            nonce_seq: 0,
+            epoch: KTimer::new(),
+            defer_connect: false,
+            connect_reqs: VecDeque::new(),
+            pending_connects: HashMap::new(),
+            on_release: None,
             transport: transport,
             analysis: super::analysis::RPCAnalysis::new(),
         }
     }
 
+    /// Post a receive buffer, shared by the sessions connected later
     pub fn post_msg_buf(&mut self, msg: R::MsgBuf) -> Result<(), R::IOResult> {
-        self.transport.post_recv_buf(msg)
+        self.transport.post_recv_buf(msg)?;
+        self.recv_bufs += 1;
+        Ok(())
     }
 
     pub fn get_analysis(&self) -> &super::analysis::RPCAnalysis {
         &self.analysis
     }
 
+    /// Send the reply whose payload fits in a datagram
     fn send_reply(
         &mut self,
         session_id: usize,
-        reply: ReplyStubFactory,
+        status: ReplyStatus,
+        req_id: u64,
+        payload: &[u8],
+    ) -> Result<(), Error<R::Error>> {
+        let header = MsgHeader::gen_reply_stub(status, req_id, payload.len())
+            .clone_with_credit(self.stamp(session_id));
+        self.post_msg(session_id, &header, payload)
+    }
+
+    /// Send the reply payload, as fragments if it is larger than a datagram
+    fn send_reply_payload(
+        &mut self,
+        session_id: usize,
+        req_id: u64,
+        payload: Arc<[u8]>,
     ) -> Result<(), Error<R::Error>> {
-        let (session, buf) = self
+        if payload.len() <= frag_payload_size::<R>() {
+            return self.send_reply(session_id, ReplyStatus::Ok, req_id, &payload);
+        }
+
+        let header = MsgHeader::gen_reply_stub(ReplyStatus::Ok, req_id, payload.len());
+        let mut reply = OutgoingMsg::new(header, payload);
+
+        let (session, bufs) = self
             .connected_sessions
             .get_mut(&session_id)
-            .ok_or(Error::session_creation_error())?;
+            .ok_or(Error::not_connected())?;
+        let grant = self
+            .credits
+            .get_mut(&session_id)
+            .ok_or(Error::not_connected())?;
+
+        reply
+            .send_window::<R, F::ConnType, _>(self.frag.window, session, bufs, grant)
+            .map_err(|_| Error::fatal())?;
+        // kept until all the fragments are acknowledged by the caller
+        self.outgoing.insert((session_id, req_id), (reply, KTimer::new()));
+        Ok(())
+    }
+
+    /// Continue the windows of the fragmented replies that stopped for the busy send buffers
+    fn resume_outgoing(&mut self) -> Result<(), Error<R::Error>> {
+        for ((session_id, _), (reply, _)) in self.outgoing.iter_mut() {
+            let (session, bufs) = match self.connected_sessions.get_mut(session_id) {
+                Some(s) => s,
+                None => continue,
+            };
+            let grant = match self.credits.get_mut(session_id) {
+                Some(g) => g,
+                None => continue,
+            };
+            reply
+                .send_window::<R, F::ConnType, _>(self.frag.window, session, bufs, grant)
+                .map_err(|_| Error::fatal())?;
+        }
+        Ok(())
+    }
+
+    /// Acknowledge the received bytes of a fragmented request
+    fn send_frag_ack(
+        &mut self,
+        session_id: usize,
+        req_id: u64,
+        received: usize,
+    ) -> Result<(), Error<R::Error>> {
+        if !self.connected_sessions.contains_key(&session_id) {
+            return Err(Error::not_connected());
+        }
+        // the caller finds the request only by its ID
+        let ack = MsgHeader::gen_frag_ack_stub(0, req_id, received)
+            .clone_with_credit(self.stamp(session_id));
+        self.post_msg(session_id, &ack, &[])
+    }
+
+    /// Reassemble a fragment of a request, return the request if it is complete
+    fn reassemble(
+        &mut self,
+        session_id: usize,
+        msg_header: &MsgHeader,
+        payload: &BytesMut,
+    ) -> Result<core::option::Option<alloc::vec::Vec<u8>>, Error<R::Error>> {
+        let req_id = msg_header.get_call_stub().ok_or(Error::corrupted())?.get_req_id();
+        let key = (session_id, req_id);
+
+        let total = msg_header.get_frag().get_total();
+        if total > self.frag.max_msg_size {
+            return Err(Error::invalid_sz());
+        }
+
+        if Reassembly::is_first(msg_header) {
+            self.reassemblies.insert(key, Reassembly::new(total));
+        }
+        let res = match self.reassemblies.get_mut(&key) {
+            Some(r) => r.on_fragment(msg_header, payload, self.frag.ack_every()),
+            None => Assemble::Ignored,
+        };
+
+        match res {
+            Assemble::Ignored => Ok(None),
+            Assemble::Partial(ack) => {
+                if let Some(received) = ack {
+                    self.send_frag_ack(session_id, req_id, received)?;
+                }
+                Ok(None)
+            }
+            Assemble::Complete => Ok(self.reassemblies.remove(&key).map(|r| r.into_inner())),
+        }
+    }
+
+    /// Handle a RPC request, or a fragment of it
+    fn handle_request(&mut self, msg_header: &MsgHeader, rpc_args: BytesMut) -> Result<(), Error<R::Error>> {
+        let meta = *msg_header.get_call_stub().ok_or(Error::corrupted())?;
+        let session_id = meta.get_session_id();
+        let req_id = meta.get_req_id();
+
+        if !self.connected_sessions.contains_key(&session_id) {
+            return Err(Error::not_connected());
+        }
+        self.touch(session_id);
+
+        // a large request is handled after all its fragments are received
+        let mut assembled = None;
+        if msg_header.is_fragment() {
+            match self.reassemble(session_id, msg_header, &rpc_args)? {
+                Some(req) => assembled = Some(req),
+                None => return Ok(()),
+            }
+        }
+        let rpc_args = match assembled.as_mut() {
+            Some(req) => unsafe { BytesMut::from_raw(req.as_mut_ptr(), req.len()) },
+            None => rpc_args,
+        };
+
+        // requests without an ID (0) cannot be deduplicated
+        let dedup = req_id != 0
+            && self.reply_cache_size > 0
+            && !self.service.is_idempotent(meta.get_rpc_id());
+
+        let lookup = if dedup {
+            self.reply_caches
+                .get(&session_id)
+                .map(|c| c.lookup(req_id))
+                .unwrap_or(Lookup::New)
+        } else {
+            Lookup::New
+        };
+
+        match lookup {
+            Lookup::New => {}
+            Lookup::Cached(reply) => {
+                // the reply is lost, send it again without re-executing
+                let reply = reply.clone();
+                self.analysis.handle_duplicate();
+                return self.send_reply_payload(session_id, req_id, reply);
+            }
+            Lookup::Stale => {
+                crate::log::debug!(
+                    "drop stale request {} of session {}",
+                    req_id,
+                    session_id
+                );
+                self.analysis.handle_duplicate();
+                return Ok(());
+            }
+        }
+
+        // allocated on connect
+        let mut out_buf = unsafe { self.reply_buf.as_ref().ok_or(Error::fatal())?.get_bytes().clone() };
 
-        let signal = session.get_pending_reqs() == 0;
+        // handle the RPC request
+        let timer = KTimer::new();
+        let reply_payload = self
+            .service
+            .execute_for(session_id, meta.get_rpc_id(), &rpc_args, &mut out_buf);
+
+        self.analysis.handle_one();
+        self.analysis.handle_session_call(session_id);
+        self.analysis.handle_bytes_in(session_id, rpc_args.len());
+
+        let size = match reply_payload {
+            Some(MALFORMED_REQ) => {
+                self.analysis.handle_error(super::analysis::ErrorKind::Corrupted);
+                return self.send_reply(session_id, ReplyStatus::Malformed, req_id, &[]);
+            }
+            Some(REPLY_TOO_LARGE) => {
+                self.analysis.handle_error(super::analysis::ErrorKind::Other);
+                return self.send_reply(session_id, ReplyStatus::TooLarge, req_id, &[]);
+            }
+            Some(size) if size > out_buf.len() => return Err(Error::invalid_sz()),
+            Some(size) => size,
+            None => {
+                self.analysis.handle_error(super::analysis::ErrorKind::NoID);
+                return self.send_reply(session_id, ReplyStatus::NotExist, req_id, &[])
+            }
+        };
+
+        self.analysis
+            .handle_latency(meta.get_rpc_id(), timer.get_passed_usec());
+        self.analysis.handle_bytes_out(session_id, size);
+
+        let reply = unsafe { core::slice::from_raw_parts(out_buf.get_ptr(), size) };
+        let cached = dedup.then(|| Arc::<[u8]>::from(reply));
+        if let Some(reply) = cached.as_ref() {
+            let capacity = self.reply_cache_size;
+            self.reply_caches
+                .entry(session_id)
+                .or_insert_with(|| ReplyCache::new(capacity))
+                .insert(req_id, reply.clone());
+        }
+
+        if size <= frag_payload_size::<R>() {
+            return self.send_reply(session_id, ReplyStatus::Ok, req_id, reply);
+        }
+        let reply = cached.unwrap_or_else(|| Arc::from(reply));
+        self.send_reply_payload(session_id, req_id, reply)
+    }
+
+    /// The caller has received part of a fragmented reply, so send more
+    fn handle_frag_ack(&mut self, stub: &FragAckStub) -> Result<(), Error<R::Error>> {
+        let key = (stub.get_session_id(), stub.get_req_id());
+        self.touch(key.0);
+        let reply = match self.outgoing.get_mut(&key) {
+            Some((r, timer)) => {
+                timer.reset();
+                r
+            }
+            None => return Ok(()),
+        };
+
+        reply.on_ack(stub.get_received());
+        if reply.is_acked() {
+            self.outgoing.remove(&key);
+            return Ok(());
+        }
+
+        let (session, bufs) = self
+            .connected_sessions
+            .get_mut(&key.0)
+            .ok_or(Error::not_connected())?;
+        let grant = self.credits.get_mut(&key.0).ok_or(Error::not_connected())?;
+        reply
+            .send_window::<R, F::ConnType, _>(self.frag.window, session, bufs, grant)
+            .map(|_| ())
+            .map_err(|_| Error::fatal())
+    }
+
+    /// Echo the heartbeat of a connected session
+    fn handle_heartbeat(&mut self, stub: &HeartbeatStub) -> Result<(), Error<R::Error>> {
+        let session_id = stub.get_session_id();
+        if !self.connected_sessions.contains_key(&session_id) {
+            // e.g., we have rebooted, so the caller should find us down
+            crate::log::debug!("ignore the heartbeat of unknown session {}", session_id);
+            return Ok(());
+        }
+
+        let echo = MsgHeader::gen_heartbeat_stub(0, stub.get_token())
+            .clone_with_credit(self.stamp(session_id));
+        self.touch(session_id);
+        self.post_msg(session_id, &echo, &[])
+    }
+
+    /// Stage the message in a send buffer of the session, and post it
+    fn post_msg(
+        &mut self,
+        session_id: usize,
+        header: &MsgHeader,
+        payload: &[u8],
+    ) -> Result<(), Error<R::Error>> {
+        let (session, bufs) = self
+            .connected_sessions
+            .get_mut(&session_id)
+            .ok_or(Error::not_connected())?;
 
         crate::log::debug!(
-            "send a request with signal {} to session {}, pending: {}",
-            signal,
+            "send a reply to session {}, pending: {}",
             session_id,
             session.get_pending_reqs()
         );
 
-        let msg_sz = reply.get_payload() + reply.generate(buf.get_bytes_mut()).unwrap(); // should succeed
-        let ret = session
-            .post(&buf, msg_sz, signal)
-            .map_err(|_| Error::fatal());
+        // the poll loop only stalls if all the send buffers are outstanding
+        let buf = bufs.next_buf(session);
+        let msg_sz = super::fragment::write_msg(buf.get_bytes_mut(), header, payload)
+            .ok_or(Error::invalid_sz())?;
+        bufs.post(session, msg_sz).map_err(|_| Error::fatal())?;
 
-        // FIXME: 4 is a magic number I used here
-        // the outstanding request threshold should not be greater
-        // to prevent ib_post_send failure
-        if session.get_pending_reqs() > 4 {
-            crate::block_on(session).map_err(|_| Error::fatal())?;
-            assert!(session.get_pending_reqs() == 0);
-        } 
-        return ret;           
+        let depth = self.pending_replies();
+        self.analysis.handle_queue_depth(depth);
+        Ok(())
+    }
+
+    /// The replies not yet completed in the send queues of all the sessions
+    fn pending_replies(&self) -> usize {
+        self.connected_sessions
+            .values()
+            .map(|(session, _)| session.get_pending_reqs())
+            .sum()
+    }
+
+    /// The receive buffer of the message is re-posted, so its credit is returned to the caller.
+    /// The credits are sent alone if no reply returns them for long.
+    fn free_credit(&mut self, msg_header: &MsgHeader) -> Result<(), Error<R::Error>> {
+        let session_id = match Self::session_of(msg_header) {
+            Some(id) => id,
+            None => return Ok(()),
+        };
+        let grant = match self.credits.get_mut(&session_id) {
+            Some(g) => g,
+            None => return Ok(()),
+        };
+
+        grant.on_freed(msg_header.get_credit().get_seq());
+        if !grant.should_return() {
+            return Ok(());
+        }
+        let header = MsgHeader::gen_credit_stub().clone_with_credit(grant.stamp());
+        self.post_msg(session_id, &header, &[])
     }
 }
 
 #[allow(unused_imports)]
 use super::header::*;
 
-impl<'a, F, R, MF> Future for RPCHook<'a, F, R, MF>
+impl<'a, F, R, MF> RPCHook<'a, F, R, MF>
 where
     F: RPCFactory,
     // we need to ensure that the polled result can be sent back to
@@ -117,10 +714,155 @@ where
     <<F as RPCFactory>::ConnType as RPCConn>::ReqPayload: ToBytes,
     MF: MetaFactory<Meta = F::ConnMeta>,
 {
-    type Output = (); // msg, session ID
-    type Error = Error<R::Error>;
+    /// Accept the session created for a deferred connect, and reply to its caller.
+    ///
+    /// Return false if its caller no longer waits for it, e.g., the session is released meanwhile.
+    pub fn complete_connect(
+        &mut self,
+        session_id: usize,
+        session: F::ConnType,
+    ) -> Result<bool, Error<R::Error>> {
+        let credit = match self.pending_connects.remove(&session_id) {
+            Some(c) => c,
+            None => return Ok(false),
+        };
+        self.accept_session(session_id, session, credit)?;
+        Ok(true)
+    }
 
-    fn poll<'r>(&'r mut self) -> Poll<Self::Output, Self::Error> {
+    /// Add the created session, and reply to its caller with the credits and the nonce
+    fn accept_session(
+        &mut self,
+        session_id: usize,
+        session: F::ConnType,
+        credit: CreditStub,
+    ) -> Result<(), Error<R::Error>> {
+        let nbufs = num_send_bufs(&session, self.frag.window);
+        let session_bufs = SendBufs::new(
+            (0..nbufs)
+                .map(|_| R::MsgBuf::create(R::MTU, 0, self.transport.get_context()))
+                .collect(),
+        );
+        if self.reply_buf.is_none() {
+            self.reply_buf = Some(R::MsgBuf::create(
+                self.reply_buf_size(),
+                0,
+                self.transport.get_context(),
+            ));
+        }
+
+        // the credits asked by the caller, with its ID of the session
+        self.credits.insert(
+            session_id,
+            Grant::new(credit.get_token(), credit.get_seq() as usize),
+        );
+        self.rebalance_credits();
+
+        // add to my connected session
+        self.connected_sessions
+            .insert(session_id, (session, session_bufs));
+        self.last_seen.insert(session_id, KTimer::new());
+        let nonce = self.gen_nonce(session_id);
+        self.nonces.insert(session_id, nonce);
+
+        // send the reply, which gives the nonce of the session
+        let header = MsgHeader::gen_reply_stub(ReplyStatus::Ok, 0, 0)
+            .clone_with_credit(self.stamp(session_id))
+            .clone_with_nonce(nonce);
+        self.post_msg(session_id, &header, &[])
+    }
+
+    /// Dispatch a received message by its type.
+    /// The receive buffer of the message is re-posted by the caller, whether it fails or not.
+    fn dispatch_msg(
+        &mut self,
+        msg_header: &MsgHeader,
+        accepted: bool,
+        rpc_args: BytesMut,
+    ) -> Result<(), Error<R::Error>> {
+        match msg_header.get_marker() {
+            // no session is created (or released) for the unauthenticated callers,
+            // and their connect requests time out.
+            // The forged messages of a session are dropped.
+            _ if !accepted => {}
+
+            super::header::ReqType::Connect => {
+                let meta = msg_header.get_connect_stub().ok_or(Error::corrupted())?;
+                let session_id = meta.get_session_id();
+                //crate::log::debug!("handle in-coming connect req {:?}",meta);
+
+                // a connected session means that the caller is re-created
+                // (e.g., its machine has rebooted), so the stale one is replaced
+                if self.remove_session(session_id) {
+                    crate::log::info!("re-connect session ID: {}", session_id);
+                }
+
+                let mut session_meta: <MF as MetaFactory>::HyperMeta = Default::default();
+                unsafe {
+                    rpc_args
+                        .memcpy_deserialize(&mut session_meta)
+                        .ok_or(Error::corrupted())?
+                };
+                let connect_meta = self
+                    .meta_factory
+                    .create_meta(session_meta)
+                    .map_err(|_| Error::session_creation_error())?;
+
+                // the credits asked by the caller, with its ID of the session
+                let credit = *msg_header.get_credit();
+                if self.defer_connect {
+                    self.pending_connects.insert(session_id, credit);
+                    self.connect_reqs.push_back((session_id, connect_meta));
+                } else {
+                    let session = self
+                        .session_factory
+                        .create(connect_meta)
+                        .map_err(|_| Error::session_creation_error())?;
+                    self.accept_session(session_id, session, credit)?;
+                }
+
+                // handle connect message done
+            }
+
+            // handle the RPC request
+            super::header::ReqType::Request => {
+                self.handle_request(msg_header, rpc_args)?;
+            }
+
+            // the caller has received part of a large reply
+            super::header::ReqType::FragAck => {
+                let stub = *msg_header.get_frag_ack_stub().ok_or(Error::corrupted())?;
+                self.handle_frag_ack(&stub)?;
+            }
+
+            // the caller keeps its sessions alive
+            super::header::ReqType::Heartbeat => {
+                let stub = *msg_header.get_heartbeat_stub().ok_or(Error::corrupted())?;
+                self.handle_heartbeat(&stub)?;
+            }
+
+            // handle the session dis-connect
+            super::header::ReqType::DisConnect => {
+                let meta = msg_header.get_disconnect_stub().ok_or(Error::corrupted())?;
+                if !self.release_session(meta.get_session_id()) {
+                    crate::log::debug!(
+                        "dis-connect unknown session ID: {}",
+                        meta.get_session_id()
+                    );
+                }
+            }
+
+            // Error case
+            _ => {
+                // should never happen at the hooker if no error happens !
+                crate::log::error!("unknown message header {:?}", msg_header);
+            }
+        }
+        Ok(())
+    }
+
+    /// Receive and handle one message
+    fn poll_msg(&mut self) -> Poll<(), Error<R::Error>> {
         match self.transport.poll() {
             // not receiving any request, just move on
             Ok(Async::NotReady) => Ok(Async::NotReady),
@@ -133,128 +875,62 @@ where
 
                 // the datagram may have a extra header (e.g., GRH_HEADER in UD)
                 // so we must truncate it first
-                let msg_header_bytes =
-                    unsafe { bytes.truncate_header(R::HEADER).ok_or(Error::corrupted()) }?;
-                unsafe { msg_header_bytes.memcpy_deserialize(&mut msg_header) };
+                let msg_header_bytes = unsafe { bytes.truncate_header(R::HEADER) }
+                    .filter(|b| unsafe { b.memcpy_deserialize(&mut msg_header) }.is_some());
+                let accepted = msg_header_bytes.is_some() && self.authenticate(&msg_header);
 
-                let rpc_args = unsafe {
-                    msg_header_bytes
-                        .truncate_header(core::mem::size_of::<super::header::MsgHeader>())
+                let rpc_args = msg_header_bytes.and_then(|b| unsafe {
+                    b.truncate_header(core::mem::size_of::<super::header::MsgHeader>())
                         .and_then(|msg| msg.clone_and_resize(msg_header.get_payload()))
-                        .ok_or(Error::corrupted())?
+                });
+                let res = match rpc_args {
+                    Some(rpc_args) => self.dispatch_msg(&msg_header, accepted, rpc_args),
+                    None => Err(Error::corrupted()),
                 };
 
-                // parse and dispatch the message
-                match msg_header.get_marker() {
-                    super::header::ReqType::Connect => {
-                        let meta = msg_header.get_connect_stub().ok_or(Error::corrupted())?;
-                        //crate::log::debug!("handle in-coming connect req {:?}",meta);
-
-                        // insert the session if necessary
-                        if !self.connected_sessions.contains_key(&meta.get_session_id()) {
-                            let mut session_meta: <MF as MetaFactory>::HyperMeta =
-                                Default::default();
-                            unsafe {
-                                rpc_args
-                                    .memcpy_deserialize(&mut session_meta)
-                                    .ok_or(Error::corrupted())?
-                            };
-                            let connect_meta = self
-                                .meta_factory
-                                .create_meta(session_meta)
-                                .map_err(|_| Error::session_creation_error())?;
-
-                            let session = self
-                                .session_factory
-                                .create(connect_meta)
-                                .map_err(|_| Error::session_creation_error())?;
-
-                            let session_buf = R::MsgBuf::create(R::MTU, 0, self.transport.get_context());
-
-                            // add to my connected session
-                            self.connected_sessions
-                                .insert(meta.get_session_id(), (session, session_buf));
-                            // send the reply
-                            self.send_reply(
-                                meta.get_session_id(),
-                                ReplyStubFactory::new(ReplyStatus::Ok, 0),
-                            )?;
-                            // crate::log::info!("send reply done");
-                        } else {
-                            crate::log::debug!(
-                                "duplicate connect session ID: {}",
-                                meta.get_session_id()
-                            );
-                            return Err(Error::session_creation_error());
-                        }
-
-                        // handle connect message done
-                    }
-
-                    // handle the RPC request
-                    super::header::ReqType::Request => {
-                        let meta = msg_header.get_call_stub().ok_or(Error::corrupted())?;
-
-                        let (_, out_buf) = self
-                            .connected_sessions
-                            .get_mut(&meta.get_session_id())
-                            .ok_or(Error::not_connected())?;
-
-                        let mut out_buf = unsafe {
-                            out_buf
-                                .get_bytes_mut()
-                                .truncate_header(core::mem::size_of::<super::header::MsgHeader>())
-                                .unwrap()
-                        };
-
-                        // handle the RPC request
-                        let reply_payload =
-                            self.service
-                                .execute(meta.get_rpc_id(), &rpc_args, &mut out_buf);
-
-                        self.analysis.handle_one();
-                        self.analysis.handle_session_call(meta.get_session_id());
-
-                        match reply_payload {
-                            Some(size) => self.send_reply(
-                                meta.get_session_id(),
-                                ReplyStubFactory::new(ReplyStatus::Ok, size),
-                            )?,
-                            None => self.send_reply(
-                                meta.get_session_id(),
-                                ReplyStubFactory::new(ReplyStatus::NotExist, 0),
-                            )?,
-                        }
-                        // handle RPC request done
-                    }
-
-                    // handle the session dis-connect
-                    super::header::ReqType::DisConnect => {
-                        // TODO @Yuhan: handle the dis connect part
-                        todo!();
-                    }
-
-                    // Error case
-                    _ => {
-                        // should never happen at the hooker if no error happens !
-                        crate::log::error!("unknown message header {:?}", msg_header);
-                    }
-                }
-
-                // FIXME: Is the post_recv on the last ok? No.
-                // Currently, if some error happens during the above process,
-                // we may fail to post_recv_buf.
-                // Will handle it later
+                // The buffer is re-posted and the credit is released even if the message
+                // fails, otherwise each malformed message leaks a receive buffer,
+                // and a credit of its session.
                 self.transport
                     .post_recv_buf(msg)
                     .map_err(|e| Error::inner(e))?;
-                Ok(Async::Ready(()))
+                // the forged messages take no credits of the session
+                if accepted {
+                    self.free_credit(&msg_header)?;
+                }
+                res.map(|_| Async::Ready(()))
             }
             Err(e) => Err(Error::inner(e)),
         }
     }
 }
 
+impl<'a, F, R, MF> Future for RPCHook<'a, F, R, MF>
+where
+    F: RPCFactory,
+    // we need to ensure that the polled result can be sent back to
+    R: Receiver<
+        Output = <<F as RPCFactory>::ConnType as RPCConn>::ReqPayload,
+        MsgBuf = <<F as RPCFactory>::ConnType as RPCConn>::ReqPayload,
+        IOResult = <R as Future>::Error,
+    > + GetContext<
+        Context = <<<F as RPCFactory>::ConnType as RPCConn>::ReqPayload as AllocMsgBuf>::Context,
+    >,
+    <<F as RPCFactory>::ConnType as RPCConn>::ReqPayload: ToBytes,
+    MF: MetaFactory<Meta = F::ConnMeta>,
+{
+    type Output = (); // msg, session ID
+    type Error = Error<R::Error>;
+
+    fn poll<'r>(&'r mut self) -> Poll<Self::Output, Self::Error> {
+        let res = self.resume_outgoing().and_then(|_| self.poll_msg());
+        if let Err(e) = res.as_ref() {
+            self.analysis.handle_error(e.kind());
+        }
+        res
+    }
+}
+
 use core::fmt::{Debug, Display, Formatter};
 
 impl<'a, F, R, MF> Debug for RPCHook<'a, F, R, MF>
@@ -265,11 +941,14 @@ where
     fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
         write!(
             f,
-            "RPCHook\n  \t service: {}\n\t connected_sessions: {}, ncalls handled {}, other: {:?}",
+            "RPCHook\n  \t service: {}\n\t connected_sessions: {}, ncalls handled {}, duplicates {}, rejected {}, errors {:?}, other: {:?}",
             self.service,
             self.connected_sessions.len(),
             self.analysis.get_ncalls(),
-            self.analysis.session_counts
+            self.analysis.get_nduplicates(),
+            self.analysis.get_nrejected(),
+            self.analysis.get_errors(),
+            self.analysis.sessions
         )
     }
 }
@@ -351,4 +1030,13 @@ impl<T> Error<T> {
     pub fn not_connected() -> Error<T> {
         Error(Kind::NotConnected)
     }
+
+    /// The kind counted in the analysis
+    pub fn kind(&self) -> super::analysis::ErrorKind {
+        match self.0 {
+            Kind::NoID => super::analysis::ErrorKind::NoID,
+            Kind::CorruptedHeader => super::analysis::ErrorKind::Corrupted,
+            _ => super::analysis::ErrorKind::Other,
+        }
+    }
 }
@@ -1 +1,3 @@
-pub mod ud;
\ No newline at end of file
+pub mod rc;
+pub mod transport;
+pub mod ud;
@@ -0,0 +1,596 @@
+//! RPC over RDMA's reliable connection (RC).
+//!
+//! The messages are written with one-sided RDMA WRITEs into the mailbox of the receiver,
+//! so the receiver needs no receive requests posted to the (server-created) RC QPs.
+//!
+//! The mailbox consists of lanes (memory regions), and each lane has exactly one sender (session).
+//! A lane is a ring of `depth` message slots, with a stamp per slot and a `consumed` counter:
+//!
+//! ```text
+//! | consumed (u64) | stamps (depth * u64) | slots (depth * MTU) |
+//! ```
+//!
+//! The n-th message (counting from 1) of a lane is written into slot `n % depth`,
+//! followed by a second WRITE of its stamp `(n << 16) | len`.
+//! Since the WRITEs of a QP are placed in order, the message is complete once its stamp is seen.
+//! The receiver polls the stamp of the next message of each lane, copies the message into a
+//! posted receive buffer (after a gap as large as the UD header, so the buffers are laid out
+//! as over UD), and then advances `consumed`.
+//! The sender reads `consumed` back (RDMA READ) only when it runs out of the slots.
+
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+use core::sync::atomic::{fence, Ordering};
+
+use crate::conn::Conn;
+use crate::datagram::msg::UDMsg;
+use crate::datagram::ud_receiver::UDReceiver;
+use crate::datagram::Receiver;
+use crate::future::*;
+use crate::rdma::payload::rc::RCReqPayload;
+use crate::rdma::payload::RDMAOp;
+use crate::rdma::rc::{RCConn, RCFactory, RCFactoryError};
+use crate::rpc::GetContext;
+
+use rust_kernel_linux_util::timer::KTimer;
+use KRdmaKit::context::Context;
+use KRdmaKit::memory_region::MemoryRegion;
+use KRdmaKit::DatapathError;
+
+/// The size of a message slot, i.e., the MTU of the RC RPC
+pub const MAX_MSG_SZ: usize = 4096;
+
+/// Default number of message slots in a lane
+pub const DEFAULT_LANE_DEPTH: usize = 32;
+
+/// The max number of the outstanding messages of a session.
+/// Each message takes two WRs: the message and its stamp.
+pub const MAX_SEND_MSGS: usize = 32;
+
+// a full lane is an error if the receiver consumes nothing in it for so long
+const LANE_FULL_TIMEOUT_USEC: i64 = 1000_000; // 1s
+
+const STAMP_LEN_BITS: u64 = 16;
+
+#[inline]
+fn lane_size(depth: usize) -> usize {
+    core::mem::size_of::<u64>() * (1 + depth) + MAX_MSG_SZ * depth
+}
+
+/// The lane of a remote mailbox that a session writes its messages to
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct RCMailboxMeta {
+    /// physical address of the lane
+    pub addr: u64,
+    pub rkey: u32,
+    /// number of message slots in the lane
+    pub depth: u32,
+}
+
+impl RCMailboxMeta {
+    #[inline]
+    fn consumed_addr(&self) -> u64 {
+        self.addr
+    }
+
+    #[inline]
+    fn stamp_addr(&self, slot: usize) -> u64 {
+        self.addr + (core::mem::size_of::<u64>() * (1 + slot)) as u64
+    }
+
+    #[inline]
+    fn slot_addr(&self, slot: usize) -> u64 {
+        self.addr
+            + (core::mem::size_of::<u64>() * (1 + self.depth as usize) + MAX_MSG_SZ * slot) as u64
+    }
+}
+
+/// The meta data sent by the caller on connect, so that the hook can create the session back.
+/// * gid, service_id, port: the RC connection service of the caller
+/// * mailbox: the lane of the caller's receiver assigned to the session
+pub struct RCHyperMeta {
+    pub gid: KRdmaKit::rdma_shim::bindings::ib_gid,
+    pub service_id: u64,
+    pub port: u8,
+    pub mailbox: RCMailboxMeta,
+}
+
+impl Default for RCHyperMeta {
+    fn default() -> Self {
+        // We use 1 as the default local port number
+        Self {
+            gid: Default::default(),
+            service_id: Default::default(),
+            port: 1 as u8,
+            mailbox: Default::default(),
+        }
+    }
+}
+
+use core::fmt::{Debug, Formatter};
+
+impl Debug for RCHyperMeta {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "RCHyperMeta {{ gid : {:?}, service_id : {}, mailbox : {:?} }}",
+            self.gid, self.service_id, self.mailbox
+        )
+    }
+}
+
+// sent by memory copy in the connect request
+impl crate::serialize::Serialize for RCHyperMeta {}
+
+/// The meta data to create a RC session
+pub struct RCSessionMeta {
+    pub conn: crate::rdma::ConnMeta,
+    pub mailbox: RCMailboxMeta,
+}
+
+/// RCSession writes the messages into a lane of the remote mailbox.
+///
+/// Note:
+/// - we assume that the session is only used by one **thread**
+pub struct RCSession {
+    inner: RCConn,
+    mailbox: RCMailboxMeta,
+
+    // the stamps written after the messages, one per slot
+    stamps: Arc<MemoryRegion>,
+    // where the `consumed` of the remote lane is read back
+    consumed_buf: Arc<MemoryRegion>,
+
+    // the sequence number of the last message written
+    sent: u64,
+    // the last known sequence number consumed by the receiver
+    consumed: u64,
+    // the lane may be used by a former session, so we start after what it has consumed
+    synced: bool,
+
+    pending: usize,
+    // the signaled requests posted and completed, including the reads of `consumed`
+    signaled: u64,
+    completed: u64,
+}
+
+impl RCSession {
+    pub fn new(conn: RCConn, mailbox: RCMailboxMeta) -> Self {
+        let ctx = conn.get_qp().ctx().clone();
+        let depth = mailbox.depth as usize;
+        Self {
+            inner: conn,
+            mailbox: mailbox,
+            stamps: Arc::new(
+                MemoryRegion::new(ctx.clone(), core::mem::size_of::<u64>() * depth)
+                    .expect("Memory allocation should succeed."),
+            ),
+            consumed_buf: Arc::new(
+                MemoryRegion::new(ctx, core::mem::size_of::<u64>())
+                    .expect("Memory allocation should succeed."),
+            ),
+            sent: 0,
+            consumed: 0,
+            synced: false,
+            pending: 0,
+            signaled: 0,
+            completed: 0,
+        }
+    }
+
+    #[inline]
+    pub fn get_ss_meta(&self) -> &RCMailboxMeta {
+        &self.mailbox
+    }
+
+    #[inline]
+    pub fn get_inner(&self) -> &RCConn {
+        &self.inner
+    }
+
+    #[inline]
+    fn free_slots(&self) -> usize {
+        self.mailbox.depth as usize - (self.sent - self.consumed) as usize
+    }
+
+    /// Read the `consumed` of the remote lane, and wait for the completion
+    fn read_consumed(&mut self) -> Result<u64, crate::rdma::Err> {
+        // the completions are in order, so the next one is the read after all the signaled ones
+        while self.completed < self.signaled {
+            crate::block_on(self)?;
+        }
+        let payload = RCReqPayload::new(
+            self.consumed_buf.clone(),
+            0..core::mem::size_of::<u64>() as u64,
+            true,
+            RDMAOp::READ,
+            self.mailbox.rkey,
+            self.mailbox.consumed_addr(),
+        );
+        self.inner.post(&payload)?;
+        self.signaled += 1;
+        let wc = crate::block_on(self)?;
+        if wc.status != rust_kernel_rdma_base::ib_wc_status::IB_WC_SUCCESS {
+            return Err(crate::rdma::Err::WCErr(unsafe { core::mem::transmute(wc.status) }));
+        }
+        Ok(unsafe { core::ptr::read_volatile(self.consumed_buf.get_virt_addr() as *const u64) })
+    }
+
+    /// Wait until the receiver consumes a slot of the lane
+    fn wait_free_slot(&mut self) -> Result<(), crate::rdma::Err> {
+        let timer = KTimer::new();
+        loop {
+            let consumed = self.read_consumed()?;
+            if !self.synced {
+                self.sent = consumed;
+                self.synced = true;
+            }
+            self.consumed = core::cmp::max(self.consumed, consumed);
+            if self.free_slots() > 0 {
+                return Ok(());
+            }
+            if timer.get_passed_usec() > LANE_FULL_TIMEOUT_USEC {
+                return Err(crate::rdma::Err::DatapathError(
+                    DatapathError::TimeoutError,
+                ));
+            }
+        }
+    }
+}
+
+impl crate::future::Future for RCSession {
+    type Output = <RCConn as Future>::Output;
+    type Error = crate::rdma::Err;
+
+    /// Poll the completions of the signaled requests
+    #[inline]
+    fn poll(&mut self) -> Poll<Self::Output, Self::Error> {
+        match self.inner.poll()? {
+            Async::Ready(wc) => {
+                self.completed += 1;
+                self.pending = 0;
+                Ok(Async::Ready(wc))
+            }
+            Async::NotReady => Ok(Async::NotReady),
+        }
+    }
+}
+
+impl super::super::RPCConn for RCSession {
+    /// #Argument
+    /// * UDMsg: the message to send, its memory only holds the message
+    /// * bool : whether to signal the request
+    type ReqPayload = UDMsg;
+    type HyperMeta = RCHyperMeta;
+
+    fn post(
+        &mut self,
+        req: &Self::ReqPayload,
+        sz: usize,
+        signaled: bool,
+    ) -> Result<(), Self::IOResult> {
+        // the callers split the messages by the MTU of the receiver
+        debug_assert!(sz <= MAX_MSG_SZ);
+        if !self.synced || self.free_slots() == 0 {
+            self.wait_free_slot()?;
+        }
+
+        let seq = self.sent + 1;
+        let slot = (seq % self.mailbox.depth as u64) as usize;
+
+        // the message is unsignaled, since its stamp is posted right after it,
+        // whose completion means that both are written
+        let msg = RCReqPayload::new(
+            req.get_inner(),
+            0..sz as u64,
+            false,
+            RDMAOp::WRITE,
+            self.mailbox.rkey,
+            self.mailbox.slot_addr(slot),
+        );
+        self.inner.post(&msg)?;
+
+        // the previous stamp of the slot has been written, since its message is consumed
+        let stamp_off = core::mem::size_of::<u64>() * slot;
+        unsafe {
+            core::ptr::write_volatile(
+                (self.stamps.get_virt_addr() as usize + stamp_off) as *mut u64,
+                (seq << STAMP_LEN_BITS) | sz as u64,
+            )
+        };
+        let stamp = RCReqPayload::new(
+            self.stamps.clone(),
+            stamp_off as u64..(stamp_off + core::mem::size_of::<u64>()) as u64,
+            signaled,
+            RDMAOp::WRITE,
+            self.mailbox.rkey,
+            self.mailbox.stamp_addr(slot),
+        );
+        self.inner.post(&stamp)?;
+
+        self.sent = seq;
+        self.pending += 1;
+        if signaled {
+            self.signaled += 1;
+        }
+        Ok(())
+    }
+
+    #[inline]
+    fn get_pending_reqs(&self) -> usize {
+        self.pending
+    }
+
+    #[inline]
+    fn get_signaled_reqs(&self) -> u64 {
+        self.signaled
+    }
+
+    #[inline]
+    fn get_completed_reqs(&self) -> u64 {
+        self.completed
+    }
+
+    #[inline]
+    fn get_send_depth(&self) -> usize {
+        core::cmp::min(MAX_SEND_MSGS, self.mailbox.depth as usize)
+    }
+}
+
+impl super::super::RPCFactory for RCFactory {
+    type ConnMeta = RCSessionMeta;
+    type ConnType = RCSession;
+
+    type ConnResult = RCFactoryError;
+
+    /// Connect to the RC service of the remote, and write to the given lane of its mailbox
+    fn create(&self, meta: Self::ConnMeta) -> Result<Self::ConnType, Self::ConnResult> {
+        let conn = crate::conn::Factory::create(self, meta.conn)?;
+        Ok(RCSession::new(conn, meta.mailbox))
+    }
+}
+
+impl crate::conn::MetaFactory for RCFactory {
+    type HyperMeta = RCHyperMeta;
+    type Meta = RCSessionMeta;
+    type MetaResult = RCFactoryError;
+
+    /// The path to the remote is resolved when the session is created
+    fn create_meta(&self, meta: Self::HyperMeta) -> Result<Self::Meta, Self::MetaResult> {
+        Ok(RCSessionMeta {
+            conn: crate::rdma::ConnMeta {
+                gid: meta.gid,
+                service_id: meta.service_id,
+                port: meta.port,
+            },
+            mailbox: meta.mailbox,
+        })
+    }
+}
+
+// a lane of the mailbox, see the module document
+struct Lane {
+    mr: MemoryRegion,
+    // the sequence number of the next message
+    expected: u64,
+}
+
+impl Lane {
+    fn new(ctx: &Arc<Context>, depth: usize) -> core::option::Option<Self> {
+        let mr = MemoryRegion::new(ctx.clone(), lane_size(depth)).ok()?;
+        // no stamp is valid
+        unsafe { core::ptr::write_bytes(mr.get_virt_addr() as *mut u8, 0, lane_size(depth)) };
+        Some(Self { mr: mr, expected: 1 })
+    }
+
+    #[inline]
+    fn ptr(&self) -> *mut u64 {
+        self.mr.get_virt_addr() as *mut u64
+    }
+}
+
+/// RCReceiver owns a mailbox and serves as the message receiver of RC sessions.
+/// It is also the transport to create the sessions of its caller.
+pub struct RCReceiver {
+    factory: RCFactory,
+    depth: usize,
+    lanes: Vec<Lane>,
+    // the lane polled first, so that a busy lane cannot starve the others
+    cursor: usize,
+
+    msg_queues: VecDeque<UDMsg>,
+}
+
+/// A wrapper to simplify creating RCReceiver
+///
+/// Example:
+/// ```
+/// let receiver = RCReceiverFactory::new()
+///                .set_lanes(12)
+///                .create(&ctx);
+/// ```
+///
+/// Arguments
+/// * lanes: number of the lanes allocated at first, more can be added later
+/// * depth: number of message slots of each lane
+pub struct RCReceiverFactory {
+    lanes: usize,
+    depth: usize,
+}
+
+impl Default for RCReceiverFactory {
+    fn default() -> Self {
+        Self {
+            lanes: 1,
+            depth: DEFAULT_LANE_DEPTH,
+        }
+    }
+}
+
+impl RCReceiverFactory {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn set_lanes(mut self, lanes: usize) -> Self {
+        self.lanes = lanes;
+        self
+    }
+
+    pub fn set_depth(mut self, depth: usize) -> Self {
+        self.depth = depth;
+        self
+    }
+
+    pub fn create(self, ctx: &Arc<Context>) -> RCReceiver {
+        RCReceiver::new(ctx, self.lanes, self.depth)
+    }
+}
+
+impl RCReceiver {
+    pub fn new(ctx: &Arc<Context>, lanes: usize, depth: usize) -> Self {
+        assert!(depth > 0);
+        let mut res = Self {
+            factory: RCFactory::new(ctx),
+            depth: depth,
+            lanes: Vec::new(),
+            cursor: 0,
+            msg_queues: VecDeque::new(),
+        };
+        for _ in 0..lanes {
+            res.add_lane().expect("Memory allocation should succeed.");
+        }
+        res
+    }
+
+    #[inline]
+    pub fn get_lanes(&self) -> usize {
+        self.lanes.len()
+    }
+
+    /// Allocate a new lane, return its index
+    pub fn add_lane(&mut self) -> core::option::Option<usize> {
+        self.lanes.push(Lane::new(&self.factory.get_context(), self.depth)?);
+        Some(self.lanes.len() - 1)
+    }
+
+    /// The meta data for a remote session to write to the lane.
+    /// Each lane should be used by at most one session at a time.
+    pub fn get_lane_meta(&self, lane: usize) -> core::option::Option<RCMailboxMeta> {
+        self.lanes.get(lane).map(|l| RCMailboxMeta {
+            addr: unsafe { l.mr.get_rdma_addr() },
+            rkey: self.factory.get_context().rkey(),
+            depth: self.depth as u32,
+        })
+    }
+
+    /// Take the next message of the lane, if it has arrived
+    fn poll_lane(&mut self, idx: usize) -> core::option::Option<UDMsg> {
+        let depth = self.depth;
+        let lane = &mut self.lanes[idx];
+        let seq = lane.expected;
+        let slot = (seq % depth as u64) as usize;
+
+        let stamp = unsafe { core::ptr::read_volatile(lane.ptr().add(1 + slot)) };
+        if stamp >> STAMP_LEN_BITS != seq {
+            return None;
+        }
+        // read the message after its stamp
+        fence(Ordering::Acquire);
+
+        let msg = self.msg_queues.pop_front()?;
+        let header = <Self as Receiver>::HEADER;
+        let len = core::cmp::min(
+            (stamp & ((1 << STAMP_LEN_BITS) - 1)) as usize,
+            core::cmp::min(msg.len().saturating_sub(header), MAX_MSG_SZ),
+        );
+        unsafe {
+            let src = (lane.ptr() as *const u8)
+                .add(core::mem::size_of::<u64>() * (1 + depth) + MAX_MSG_SZ * slot);
+            core::ptr::copy_nonoverlapping(src, msg.get_bytes().get_ptr().add(header), len);
+        }
+
+        // the sender can reuse the slot
+        fence(Ordering::Release);
+        unsafe { core::ptr::write_volatile(lane.ptr(), seq) };
+        lane.expected = seq + 1;
+        Some(msg)
+    }
+}
+
+impl Receiver for RCReceiver {
+    type MsgBuf = <Self as Future>::Output;
+    type IOResult = DatapathError;
+
+    // the messages are copied after a gap as large as the UD header,
+    // so that a caller can use either transport with the same buffers, see `super::transport`
+    const HEADER: usize = <UDReceiver as Receiver>::HEADER;
+
+    const MTU: usize = MAX_MSG_SZ;
+
+    /// Post the buffer that an in-coming message is copied to
+    ///
+    /// #Arguments
+    /// * `buf` - the memory buffer used for receiving future requests
+    fn post_recv_buf(&mut self, buf: Self::MsgBuf) -> Result<(), Self::IOResult> {
+        self.msg_queues.push_back(buf);
+        Ok(())
+    }
+}
+
+use crate::bytes::ToBytes;
+
+impl Future for RCReceiver {
+    type Output = UDMsg;
+    type Error = DatapathError;
+
+    /// Poll the lanes of the mailbox in turn
+    ///
+    /// Return
+    /// * If succeed, return the UDMsg poped from internal queue, filled with the message
+    /// * If no message arrives, or no buffer is posted, return NotReady
+    fn poll(&mut self) -> Poll<Self::Output, Self::Error> {
+        // the messages stay in the mailbox until the buffers are posted
+        if self.msg_queues.is_empty() {
+            return Ok(Async::NotReady);
+        }
+        let lanes = self.get_lanes();
+        for i in 0..lanes {
+            let lane = (self.cursor + i) % lanes;
+            if let Some(msg) = self.poll_lane(lane) {
+                self.cursor = (lane + 1) % lanes;
+                return Ok(Async::Ready(msg));
+            }
+        }
+        Ok(Async::NotReady)
+    }
+}
+
+impl super::super::RPCFactory for RCReceiver {
+    type ConnMeta = RCSessionMeta;
+    type ConnType = RCSession;
+
+    type ConnResult = RCFactoryError;
+
+    fn create(&self, meta: Self::ConnMeta) -> Result<Self::ConnType, Self::ConnResult> {
+        super::super::RPCFactory::create(&self.factory, meta)
+    }
+}
+
+impl crate::rpc::GetTransport for RCReceiver {
+    type Transport = Self;
+
+    fn get_transport_mut(&mut self) -> &mut Self::Transport {
+        self
+    }
+}
+
+impl GetContext for RCReceiver {
+    type Context = Arc<Context>;
+
+    fn get_context(&self) -> Self::Context {
+        self.factory.get_context()
+    }
+}
@@ -0,0 +1,216 @@
+//! RPC over a transport chosen at runtime, UD or RC.
+//!
+//! Both transports receive the messages into `UDMsg`s with the same header and MTU,
+//! so a caller of `Caller<RPCReceiver, RPCSession>` works over either of them.
+//! The hooks are transport-specific, since each one polls its own receiver.
+
+use alloc::sync::Arc;
+
+use crate::datagram::msg::UDMsg;
+use crate::datagram::ud::UDHyperMeta;
+use crate::datagram::ud_receiver::UDReceiver;
+use crate::datagram::Receiver;
+use crate::future::*;
+use crate::rpc::{GetContext, GetTransport, RPCConn};
+use crate::serialize::Serialize;
+use crate::bytes::BytesMut;
+
+use super::rc::{RCHyperMeta, RCReceiver, RCSession};
+use super::ud::UDSession;
+
+use KRdmaKit::context::Context;
+use KRdmaKit::DatapathError;
+
+/// The transport of the RPC sessions
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RPCTransport {
+    /// Datagrams, see `super::ud`
+    UD,
+    /// One-sided writes into the mailboxes, see `super::rc`
+    RC,
+}
+
+impl Default for RPCTransport {
+    fn default() -> Self {
+        RPCTransport::UD
+    }
+}
+
+/// The receiver of a caller over either transport
+pub enum RPCReceiver {
+    UD(UDReceiver),
+    RC(RCReceiver),
+}
+
+impl RPCReceiver {
+    #[inline]
+    pub fn get_kind(&self) -> RPCTransport {
+        match self {
+            Self::UD(_) => RPCTransport::UD,
+            Self::RC(_) => RPCTransport::RC,
+        }
+    }
+
+    #[inline]
+    pub fn as_ud_mut(&mut self) -> core::option::Option<&mut UDReceiver> {
+        match self {
+            Self::UD(r) => Some(r),
+            _ => None,
+        }
+    }
+
+    #[inline]
+    pub fn as_rc_mut(&mut self) -> core::option::Option<&mut RCReceiver> {
+        match self {
+            Self::RC(r) => Some(r),
+            _ => None,
+        }
+    }
+}
+
+impl Future for RPCReceiver {
+    type Output = UDMsg;
+    type Error = DatapathError;
+
+    #[inline]
+    fn poll(&mut self) -> Poll<Self::Output, Self::Error> {
+        match self {
+            Self::UD(r) => r.poll(),
+            Self::RC(r) => r.poll(),
+        }
+    }
+}
+
+impl Receiver for RPCReceiver {
+    type MsgBuf = UDMsg;
+    type IOResult = DatapathError;
+
+    // the RC receiver keeps the messages at the same offset, see `super::rc`
+    const HEADER: usize = <UDReceiver as Receiver>::HEADER;
+    const MTU: usize = <UDReceiver as Receiver>::MTU;
+
+    #[inline]
+    fn post_recv_buf(&mut self, buf: Self::MsgBuf) -> Result<(), Self::IOResult> {
+        match self {
+            Self::UD(r) => r.post_recv_buf(buf),
+            Self::RC(r) => r.post_recv_buf(buf),
+        }
+    }
+}
+
+impl GetTransport for RPCReceiver {
+    type Transport = Self;
+
+    /// The sessions are created by the transport of each variant, see `as_ud_mut` and `as_rc_mut`
+    fn get_transport_mut(&mut self) -> &mut Self::Transport {
+        self
+    }
+}
+
+impl GetContext for RPCReceiver {
+    type Context = Arc<Context>;
+
+    fn get_context(&self) -> Self::Context {
+        match self {
+            Self::UD(r) => r.get_context(),
+            Self::RC(r) => r.get_context(),
+        }
+    }
+}
+
+/// A session over either transport, whose caller must use the receiver of the same one
+pub enum RPCSession {
+    UD(UDSession),
+    RC(RCSession),
+}
+
+impl Future for RPCSession {
+    type Output = KRdmaKit::rdma_shim::bindings::ib_wc;
+    type Error = crate::rdma::Err;
+
+    #[inline]
+    fn poll(&mut self) -> Poll<Self::Output, Self::Error> {
+        match self {
+            Self::UD(s) => s.poll().map_err(|e| e.into()),
+            Self::RC(s) => s.poll(),
+        }
+    }
+}
+
+impl RPCConn for RPCSession {
+    type ReqPayload = UDMsg;
+    type HyperMeta = RPCHyperMeta;
+
+    #[inline]
+    fn post(
+        &mut self,
+        req: &Self::ReqPayload,
+        sz: usize,
+        signaled: bool,
+    ) -> Result<(), Self::IOResult> {
+        match self {
+            Self::UD(s) => s.post(req, sz, signaled).map_err(|e| e.into()),
+            Self::RC(s) => s.post(req, sz, signaled),
+        }
+    }
+
+    #[inline]
+    fn get_pending_reqs(&self) -> usize {
+        match self {
+            Self::UD(s) => s.get_pending_reqs(),
+            Self::RC(s) => s.get_pending_reqs(),
+        }
+    }
+
+    #[inline]
+    fn get_signaled_reqs(&self) -> u64 {
+        match self {
+            Self::UD(s) => s.get_signaled_reqs(),
+            Self::RC(s) => s.get_signaled_reqs(),
+        }
+    }
+
+    #[inline]
+    fn get_completed_reqs(&self) -> u64 {
+        match self {
+            Self::UD(s) => s.get_completed_reqs(),
+            Self::RC(s) => s.get_completed_reqs(),
+        }
+    }
+
+    #[inline]
+    fn get_send_depth(&self) -> usize {
+        match self {
+            Self::UD(s) => s.get_send_depth(),
+            Self::RC(s) => s.get_send_depth(),
+        }
+    }
+}
+
+/// The meta data of the session sent on connect.
+/// Only the meta of the transport is sent, which is what its hook expects.
+pub enum RPCHyperMeta {
+    UD(UDHyperMeta),
+    RC(RCHyperMeta),
+}
+
+impl Serialize for RPCHyperMeta {
+    fn serialize(&self, bytes: &mut BytesMut) -> bool {
+        match self {
+            Self::UD(m) => m.serialize(bytes),
+            Self::RC(m) => m.serialize(bytes),
+        }
+    }
+
+    /// The hooks deserialize the meta of their own transport
+    fn deserialize(_bytes: &BytesMut) -> core::option::Option<Self> {
+        None
+    }
+
+    fn serialization_buf_len(&self) -> usize {
+        match self {
+            Self::UD(m) => m.serialization_buf_len(),
+            Self::RC(m) => m.serialization_buf_len(),
+        }
+    }
+}
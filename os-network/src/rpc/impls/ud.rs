@@ -60,6 +60,21 @@ impl super::super::RPCConn for UDSession {
     fn get_pending_reqs(&self) -> usize {
         self.inner.get_pending()
     }
+
+    #[inline]
+    fn get_signaled_reqs(&self) -> u64 {
+        self.inner.get_signaled()
+    }
+
+    #[inline]
+    fn get_completed_reqs(&self) -> u64 {
+        self.inner.get_completed()
+    }
+
+    #[inline]
+    fn get_send_depth(&self) -> usize {
+        crate::datagram::ud::MAX_SEND_WR as usize
+    }
 }
 
 impl super::super::RPCFactory for UDDatagram {
@@ -0,0 +1,209 @@
+//! Reliable RPC over unreliable datagrams (e.g., UD).
+//!
+//! The caller retransmits a request if its reply does not arrive in time,
+//! with an exponential backoff. Since a retransmitted request may be a duplicate
+//! (only the reply is lost), the hook caches the recent replies of each session,
+//! and replies the duplicates from the cache without executing them again.
+
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+use rust_kernel_linux_util::timer::KTimer;
+
+use super::fragment::OutgoingMsg;
+
+/// Configuration of the caller's retransmission
+#[derive(Debug, Clone, Copy)]
+pub struct RetransmitConfig {
+    /// Time to wait for the reply before the first retransmission
+    pub init_timeout_usec: i64,
+    /// The wait time doubles after each retransmission, up to this value
+    pub max_timeout_usec: i64,
+    /// The call fails after being retransmitted so many times
+    pub max_retries: usize,
+}
+
+impl Default for RetransmitConfig {
+    /// In total, the caller waits for about 6.5 seconds before a call fails
+    fn default() -> Self {
+        Self {
+            init_timeout_usec: 100_000,
+            max_timeout_usec: 1000_000,
+            max_retries: 8,
+        }
+    }
+}
+
+/// A request waiting for its reply, which is kept for the retransmission
+pub(crate) struct InflightCall {
+    session_id: usize,
+    my_session_id: usize,
+    rpc_id: usize,
+    pub(crate) req: OutgoingMsg,
+    // since the call is issued, regardless of the retransmissions
+    issued: KTimer,
+    timer: KTimer,
+    timeout_usec: i64,
+    retries: usize,
+}
+
+impl InflightCall {
+    pub(crate) fn new(
+        session_id: usize,
+        my_session_id: usize,
+        rpc_id: usize,
+        req: OutgoingMsg,
+        config: &RetransmitConfig,
+    ) -> Self {
+        Self {
+            session_id: session_id,
+            my_session_id: my_session_id,
+            rpc_id: rpc_id,
+            req: req,
+            issued: KTimer::new(),
+            timer: KTimer::new(),
+            timeout_usec: config.init_timeout_usec,
+            retries: 0,
+        }
+    }
+
+    #[inline]
+    pub(crate) fn get_session_id(&self) -> usize {
+        self.session_id
+    }
+
+    /// The ID of my session at the remote hook
+    #[inline]
+    pub(crate) fn get_my_session_id(&self) -> usize {
+        self.my_session_id
+    }
+
+    #[inline]
+    pub(crate) fn get_rpc_id(&self) -> usize {
+        self.rpc_id
+    }
+
+    #[inline]
+    pub(crate) fn get_latency_usec(&self) -> i64 {
+        self.issued.get_passed_usec()
+    }
+
+    #[inline]
+    pub(crate) fn is_expired(&self) -> bool {
+        self.timer.get_passed_usec() >= self.timeout_usec
+    }
+
+    #[inline]
+    pub(crate) fn is_exhausted(&self, config: &RetransmitConfig) -> bool {
+        self.retries >= config.max_retries
+    }
+
+    /// Wait for the reply from now on, e.g., the call was blocked before being sent
+    #[inline]
+    pub(crate) fn restart(&mut self) {
+        self.timer.reset();
+    }
+
+    /// Record a retransmission, and double the wait time
+    pub(crate) fn backoff(&mut self, config: &RetransmitConfig) {
+        self.retries += 1;
+        self.timeout_usec = core::cmp::min(self.timeout_usec * 2, config.max_timeout_usec);
+        self.timer.reset();
+    }
+}
+
+/// How the hook should handle an in-coming request
+pub enum Lookup<'a> {
+    /// Not executed before
+    New,
+    /// A duplicate whose reply payload is still cached
+    Cached(&'a Arc<[u8]>),
+    /// A duplicate whose reply has been evicted, which must not be executed again
+    Stale,
+}
+
+/// Number of the recent request IDs of a session whose execution is tracked,
+/// whether their replies are still cached or not
+pub const DEDUP_WINDOW: u64 = 1024;
+
+/// The recent replies of one session, indexed by the request ID.
+///
+/// The requests may arrive out of order, e.g., a retransmission overtakes a fragmented request,
+/// so the executed ones are tracked in a sliding window (a bitmap) below the highest executed ID.
+/// A request older than the window is too old to tell, and is taken as a duplicate.
+pub struct ReplyCache {
+    replies: VecDeque<(u64, Arc<[u8]>)>,
+    capacity: usize,
+    // the highest request ID executed
+    highest: u64,
+    // one bit per ID in (highest - DEDUP_WINDOW, highest], set if executed
+    executed: Vec<u64>,
+}
+
+impl ReplyCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            replies: VecDeque::with_capacity(capacity),
+            capacity: capacity,
+            highest: 0,
+            executed: alloc::vec![0; (DEDUP_WINDOW / 64) as usize],
+        }
+    }
+
+    pub fn lookup(&self, req_id: u64) -> Lookup<'_> {
+        if let Some((_, reply)) = self.replies.iter().find(|(id, _)| *id == req_id) {
+            return Lookup::Cached(reply);
+        }
+        if req_id > self.highest {
+            return Lookup::New;
+        }
+        if self.highest - req_id >= DEDUP_WINDOW || self.is_executed(req_id) {
+            return Lookup::Stale;
+        }
+        Lookup::New
+    }
+
+    /// The request is executed, and its reply payload is cached
+    pub fn insert(&mut self, req_id: u64, reply: Arc<[u8]>) {
+        self.mark_executed(req_id);
+        if self.capacity == 0 {
+            return;
+        }
+        if self.replies.len() >= self.capacity {
+            self.replies.pop_front();
+        }
+        self.replies.push_back((req_id, reply));
+    }
+
+    #[inline]
+    fn bit(req_id: u64) -> (usize, u64) {
+        let pos = req_id % DEDUP_WINDOW;
+        ((pos / 64) as usize, 1 << (pos % 64))
+    }
+
+    #[inline]
+    fn is_executed(&self, req_id: u64) -> bool {
+        let (word, mask) = Self::bit(req_id);
+        self.executed[word] & mask != 0
+    }
+
+    fn mark_executed(&mut self, req_id: u64) {
+        if req_id > self.highest {
+            // the IDs sliding into the window are not executed yet
+            if req_id - self.highest >= DEDUP_WINDOW {
+                self.executed.iter_mut().for_each(|w| *w = 0);
+            } else {
+                for id in self.highest + 1..req_id {
+                    let (word, mask) = Self::bit(id);
+                    self.executed[word] &= !mask;
+                }
+            }
+            self.highest = req_id;
+        } else if self.highest - req_id >= DEDUP_WINDOW {
+            return;
+        }
+        let (word, mask) = Self::bit(req_id);
+        self.executed[word] |= mask;
+    }
+}
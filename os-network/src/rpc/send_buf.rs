@@ -0,0 +1,100 @@
+//! The send buffers of the RPC sessions.
+//!
+//! A message is sent (or written, in RC) directly from the buffer it is staged in,
+//! so the buffer must not be rewritten until the send completes.
+//! Each session owns a ring of buffers, one per outstanding message.
+//!
+//! Every message is signaled, and remembers the number of the signaled requests
+//! posted to its QP so far (its ticket). Since the completions of a QP are in the order of
+//! posting, the buffer is free once the QP has completed as many signaled requests.
+//! The counts are per QP rather than per session, so the sessions sharing a QP (e.g., UD)
+//! can reap the completions of each other.
+//!
+//! The sender only waits if all the buffers of the session are outstanding.
+
+use alloc::vec::Vec;
+
+use super::RPCConn;
+use crate::future::{Async, Future};
+
+/// Default number of the send buffers of a session
+pub const DEFAULT_SEND_BUFS: usize = 16;
+
+/// Number of the send buffers of the session, so that a window of fragments
+/// (see `FragmentConfig`) is sent without waiting for the completions
+#[inline]
+pub(crate) fn num_send_bufs<S: RPCConn>(session: &S, window: usize) -> usize {
+    core::cmp::min(
+        core::cmp::max(DEFAULT_SEND_BUFS, window),
+        session.get_send_depth(),
+    )
+}
+
+pub(crate) struct SendBufs<M> {
+    // the buffers, each with the ticket of its last message (0 if never sent)
+    bufs: Vec<(M, u64)>,
+    // the buffer of the next message
+    next: usize,
+}
+
+impl<M> SendBufs<M> {
+    pub(crate) fn new(bufs: Vec<M>) -> Self {
+        assert!(!bufs.is_empty());
+        Self {
+            bufs: bufs.into_iter().map(|b| (b, 0)).collect(),
+            next: 0,
+        }
+    }
+
+    /// The buffer to stage the next message, after its last message has been sent
+    pub(crate) fn next_buf<S: RPCConn<ReqPayload = M>>(&mut self, session: &mut S) -> &mut M {
+        wait_ticket(session, self.bufs[self.next].1);
+        &mut self.bufs[self.next].0
+    }
+
+    /// Like `next_buf`, but return None instead of waiting if its last message is outstanding
+    pub(crate) fn try_next_buf<S: RPCConn<ReqPayload = M>>(
+        &mut self,
+        session: &mut S,
+    ) -> Option<&mut M> {
+        let ticket = self.bufs[self.next].1;
+        if session.get_completed_reqs() < ticket {
+            while let Ok(Async::Ready(_)) = session.poll() {}
+            if session.get_completed_reqs() < ticket {
+                return None;
+            }
+        }
+        Some(&mut self.bufs[self.next].0)
+    }
+
+    /// Post the message staged in the buffer returned by `next_buf` (or `try_next_buf`),
+    /// and reap the completions of the QP without blocking.
+    pub(crate) fn post<S: RPCConn<ReqPayload = M>>(
+        &mut self,
+        session: &mut S,
+        sz: usize,
+    ) -> Result<(), S::IOResult> {
+        let (buf, ticket) = &mut self.bufs[self.next];
+        session.post(buf, sz, true)?;
+        *ticket = session.get_signaled_reqs();
+        self.next = (self.next + 1) % self.bufs.len();
+
+        // so the completion queue never overflows
+        while let Ok(Async::Ready(_)) = session.poll() {}
+        Ok(())
+    }
+
+    /// Wait until all the messages are sent, e.g., before the buffers are freed
+    pub(crate) fn wait_all<S: RPCConn>(&self, session: &mut S) {
+        let last = self.bufs.iter().map(|(_, t)| *t).max().unwrap_or(0);
+        wait_ticket(session, last);
+    }
+}
+
+#[inline]
+fn wait_ticket<S: RPCConn>(session: &mut S, ticket: u64) {
+    while session.get_completed_reqs() < ticket {
+        let res = session.poll(); // should never fail
+        assert!(res.is_ok());
+    }
+}
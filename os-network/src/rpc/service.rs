@@ -1,10 +1,20 @@
 use crate::bytes::BytesMut;
 use alloc::boxed::Box;
-use hashbrown::HashMap;
+use hashbrown::{HashMap, HashSet};
+
+/// Returned by a callback if it cannot parse the request,
+/// which is replied with `ReplyStatus::Malformed`
+pub const MALFORMED_REQ: usize = usize::MAX;
+
+/// Returned by a callback if its reply cannot fit in the output buffer,
+/// which is replied with `ReplyStatus::TooLarge`
+pub const REPLY_TOO_LARGE: usize = usize::MAX - 1;
 
 pub struct Service<'a> {
-    // input, output
-    callbacks: HashMap<usize, Box<dyn FnMut(&BytesMut, &mut BytesMut) -> usize + 'a>>,
+    // session ID of the caller, input, output
+    callbacks: HashMap<usize, Box<dyn FnMut(usize, &BytesMut, &mut BytesMut) -> usize + 'a>>,
+    // callbacks that are safe to re-execute on duplicate requests
+    idempotent: HashSet<usize>,
 }
 
 impl<'a> Service<'a> {}
@@ -13,10 +23,23 @@ impl<'a> Service<'a> {
     pub fn new() -> Self {
         Self {
             callbacks: Default::default(),
+            idempotent: Default::default(),
         }
     }
 
-    pub fn register(&mut self, id: usize, callback: impl FnMut(&BytesMut, &mut BytesMut) -> usize + 'a) -> bool {
+    pub fn register(&mut self, id: usize, mut callback: impl FnMut(&BytesMut, &mut BytesMut) -> usize + 'a) -> bool {
+        self.register_with_session(id, move |_, input, output| callback(input, output))
+    }
+
+    /// Register a callback that is also given the session ID of the caller.
+    /// The ID is checked against the nonce of its session by the hook, so unlike the one
+    /// in the request, it is not mistaken for another session's. It is not proof against
+    /// a forging peer on the fabric, see `RPCHook::set_auth_token`.
+    pub fn register_with_session(
+        &mut self,
+        id: usize,
+        callback: impl FnMut(usize, &BytesMut, &mut BytesMut) -> usize + 'a,
+    ) -> bool {
         if self.callbacks.contains_key(&id) {
             return false;
         }
@@ -24,10 +47,49 @@ impl<'a> Service<'a> {
         true
     }
 
+    /// Register a callback whose re-execution has no side effects (e.g., a lookup).
+    /// The hook does not cache its replies, but simply re-executes the duplicate requests.
+    pub fn register_idempotent(&mut self, id: usize, callback: impl FnMut(&BytesMut, &mut BytesMut) -> usize + 'a) -> bool {
+        if !self.register(id, callback) {
+            return false;
+        }
+        self.idempotent.insert(id);
+        true
+    }
+
+    /// The idempotent version of `register_with_session`
+    pub fn register_idempotent_with_session(
+        &mut self,
+        id: usize,
+        callback: impl FnMut(usize, &BytesMut, &mut BytesMut) -> usize + 'a,
+    ) -> bool {
+        if !self.register_with_session(id, callback) {
+            return false;
+        }
+        self.idempotent.insert(id);
+        true
+    }
+
+    #[inline]
+    pub fn is_idempotent(&self, id: usize) -> bool {
+        self.idempotent.contains(&id)
+    }
+
     pub fn execute(&mut self, id: usize, input: &BytesMut, output: &mut BytesMut) -> core::option::Option<usize> {
+        self.execute_for(0, id, input, output)
+    }
+
+    /// Execute the callback for the request of the session
+    pub fn execute_for(
+        &mut self,
+        session_id: usize,
+        id: usize,
+        input: &BytesMut,
+        output: &mut BytesMut,
+    ) -> core::option::Option<usize> {
         self.callbacks
             .get_mut(&id)
-            .map(|func| func(input, output))
+            .map(|func| func(session_id, input, output))
     }
 }
 
@@ -32,6 +32,12 @@ fn test_callback(input: &BytesMut, output: &mut BytesMut) -> usize {
 }
 
 // a local test
+// echo the input, which may be larger than a datagram
+fn test_large_callback(input: &BytesMut, output: &mut BytesMut) -> usize {
+    unsafe { core::ptr::copy_nonoverlapping(input.get_ptr(), output.get_ptr(), input.len()) };
+    input.len()
+}
+
 fn test_service() -> Result<(), TestError> {
     let mut service = Service::new();
     assert_eq!(true, service.register(TEST_RPC_ID, test_callback));
@@ -51,6 +57,83 @@ fn test_service() -> Result<(), TestError> {
         log::error!("Service callback output mismatch.");
         TestError::Error("Callback output error")
     })?;
+
+    // a callback can be given the session ID of the caller
+    assert_eq!(true, service.register_with_session(TEST_LARGE_RPC_ID, |session_id, _, _| session_id));
+    if service.execute_for(DEFAULT_SESSION_ID, TEST_LARGE_RPC_ID, &msg, &mut out_msg) != Some(DEFAULT_SESSION_ID) {
+        log::error!("Service callback is not given the session ID.");
+        return Err(TestError::Error("Callback session error"));
+    }
+    Ok(())
+}
+
+use mitosis_macros::rpc_service;
+use os_network::rpc::service::{MALFORMED_REQ, REPLY_TOO_LARGE};
+use os_network::serialize::Serialize;
+
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct TypedReply {
+    sum: u64,
+    key: usize,
+}
+
+impl Serialize for TypedReply {}
+
+#[rpc_service]
+pub trait TestTyped {
+    #[rpc(id = 76, idempotent)]
+    fn add(key: usize, a: u64, b: u64) -> TypedReply;
+    #[rpc(id = 77)]
+    fn nil();
+}
+
+struct TestTypedImpl;
+
+impl TestTyped for TestTypedImpl {
+    fn add(key: usize, a: u64, b: u64) -> TypedReply {
+        TypedReply { sum: a + b, key: key }
+    }
+
+    fn nil() {}
+}
+
+fn test_typed_service() -> Result<(), TestError> {
+    let mut service = Service::new();
+    assert_eq!(true, test_typed::register::<TestTypedImpl>(&mut service));
+    assert_eq!(false, test_typed::register::<TestTypedImpl>(&mut service));
+    assert_eq!(true, service.is_idempotent(test_typed::ADD));
+
+    let mut buf = vec![0; 64];
+    let mut msg = unsafe { BytesMut::from_raw(buf.as_mut_ptr(), buf.len()) };
+    let req = test_typed::AddRequest { key: 12, a: 73, b: 1 };
+    assert_eq!(true, req.serialize(&mut msg));
+
+    let mut out_buf = vec![0; 64];
+    let mut out_msg = unsafe { BytesMut::from_raw(out_buf.as_mut_ptr(), out_buf.len()) };
+    let sz = service
+        .execute(test_typed::ADD, &msg, &mut out_msg)
+        .ok_or(TestError::Error("Typed callback not registered"))?;
+
+    let reply = test_typed::decode_add(&out_msg).ok_or(TestError::Error("Typed reply decode error"))?;
+    if sz != core::mem::size_of::<TypedReply>() || reply != (TypedReply { sum: 74, key: 12 }) {
+        log::error!("Wrong typed reply {:?} of size {}", reply, sz);
+        return Err(TestError::Error("Typed reply mismatch"));
+    }
+
+    // a truncated request is rejected, which is distinct from an empty reply
+    let short = unsafe { BytesMut::from_raw(buf.as_mut_ptr(), 4) };
+    if service.execute(test_typed::ADD, &short, &mut out_msg) != Some(MALFORMED_REQ) {
+        return Err(TestError::Error("Typed request size check error"));
+    }
+
+    // the reply buffer is checked before the call
+    let mut small = unsafe { BytesMut::from_raw(out_buf.as_mut_ptr(), 4) };
+    if service.execute(test_typed::ADD, &msg, &mut small) != Some(REPLY_TOO_LARGE) {
+        return Err(TestError::Error("Typed reply size check error"));
+    }
+    if service.execute(test_typed::NIL, &msg, &mut small) != Some(0) {
+        return Err(TestError::Error("Typed empty reply error"));
+    }
     Ok(())
 }
 
@@ -68,10 +151,12 @@ const DEFAULT_SERVICE_ID: u64 = 37;
 const DEFAULT_QD_HINT: u64 = 73;
 const CLIENT_QD_HINT: u64 = 12;
 const TEST_RPC_ID: usize = 73;
+const TEST_LARGE_RPC_ID: usize = 75;
 const DEFAULT_PORT: u8 = 1;
 const DEFAULT_RECV_BUF_SIZE: usize = 4096;
 const DEFAULT_SEND_BUF_SIZE: usize = 1024;
 const DEFAULT_SESSION_ID: usize = 74;
+const TEST_AUTH_TOKEN: u64 = 0xdead_beef;
 
 // test generation of rpc headers
 fn test_rpc_headers() -> Result<(), TestError> {
@@ -85,14 +170,103 @@ fn test_rpc_headers() -> Result<(), TestError> {
         })?;
     if stub.get_session_id() != my_session_id {
         log::error!("Failed to gen correct session id, expected {}, got: {}.", my_session_id, stub.get_session_id());
-        Err(TestError::Error("Session id error."))
-    } else {
-        Ok(())
+        return Err(TestError::Error("Session id error."));
+    }
+
+    // the reply must carry the request ID of the call
+    let req_id = 42;
+    let call_header = MsgHeader::gen_call_stub(my_session_id, TEST_RPC_ID, req_id, payload);
+    let reply_header = MsgHeader::gen_reply_stub(ReplyStatus::Ok, req_id, payload);
+    let call_id = call_header.get_call_stub().map(|s| s.get_req_id());
+    let reply_id = reply_header.get_reply_stub().map(|s| s.get_req_id());
+    if call_id != Some(req_id) || reply_id != Some(req_id) {
+        log::error!("Failed to gen correct request id, expected {}, got: {:?} {:?}.", req_id, call_id, reply_id);
+        return Err(TestError::Error("Request id error."));
+    }
+
+    // the credits are stamped on the reply, without changing the rest
+    let stamped = reply_header.clone_with_credit(CreditStub::new(my_session_id, 7));
+    let credit = stamped.get_credit();
+    if credit.get_token() != my_session_id
+        || credit.get_seq() != 7
+        || stamped.get_reply_stub().map(|s| s.get_req_id()) != Some(req_id)
+    {
+        log::error!("Failed to stamp the credit, got: {:?}.", stamped);
+        return Err(TestError::Error("Credit stamp error."));
+    }
+
+    // only the (dis-)connect headers carry the auth token
+    let auth = connect_header
+        .clone_with_auth(TEST_AUTH_TOKEN)
+        .get_connect_stub()
+        .map(|s| (s.get_session_id(), s.get_auth()));
+    let unchanged = reply_header.clone_with_auth(TEST_AUTH_TOKEN);
+    if auth != Some((my_session_id, TEST_AUTH_TOKEN))
+        || unchanged.get_reply_stub().map(|s| s.get_req_id()) != Some(req_id)
+    {
+        log::error!("Failed to set the auth token, got: {:?}.", auth);
+        return Err(TestError::Error("Auth token error."));
     }
+
+    // the nonce of the session is stamped on any header, without changing the rest
+    let nonce = 0x1234_5678;
+    let stamped = call_header.clone_with_nonce(nonce);
+    if call_header.get_nonce() != 0
+        || stamped.get_nonce() != nonce
+        || stamped.get_call_stub().map(|s| s.get_req_id()) != Some(req_id)
+    {
+        log::error!("Failed to stamp the nonce, got: {:?}.", stamped);
+        return Err(TestError::Error("Nonce stamp error."));
+    }
+    Ok(())
+}
+
+use os_network::rpc::reliable::{Lookup, ReplyCache, DEDUP_WINDOW};
+
+// the duplicate detection of the hook, with the requests arriving out of order
+fn test_reply_cache() -> Result<(), TestError> {
+    let reply: Arc<[u8]> = Arc::from(&[1u8, 2, 3][..]);
+    let new = |c: &ReplyCache, id| matches!(c.lookup(id), Lookup::New);
+    let cached = |c: &ReplyCache, id| matches!(c.lookup(id), Lookup::Cached(_));
+    let stale = |c: &ReplyCache, id| matches!(c.lookup(id), Lookup::Stale);
+
+    // the earlier requests arrive later, which must still be executed
+    let mut cache = ReplyCache::new(2);
+    cache.insert(5, reply.clone());
+    if !new(&cache, 3) || !new(&cache, 4) || !new(&cache, 6) {
+        return Err(TestError::Error("Out-of-order request taken as a duplicate."));
+    }
+    cache.insert(3, reply.clone());
+    if !cached(&cache, 5) || !cached(&cache, 3) {
+        return Err(TestError::Error("Reply not cached."));
+    }
+
+    // the evicted reply is stale, while the ones never executed are new
+    cache.insert(4, reply.clone());
+    if !stale(&cache, 5) || !cached(&cache, 3) || !cached(&cache, 4) {
+        return Err(TestError::Error("Wrong eviction."));
+    }
+    if !new(&cache, 1) || !new(&cache, 2) {
+        return Err(TestError::Error("Unexecuted request taken as a duplicate."));
+    }
+
+    // the window slides with the highest ID, the older ones are taken as duplicates
+    cache.insert(5 + DEDUP_WINDOW, reply.clone());
+    if !stale(&cache, 5) || !stale(&cache, 2) || !new(&cache, 6) || !new(&cache, 4 + DEDUP_WINDOW) {
+        return Err(TestError::Error("Wrong window."));
+    }
+    // the bits of the IDs slid out are cleared for the ones slid in
+    cache.insert(3 + DEDUP_WINDOW, reply.clone());
+    cache.insert(6 + DEDUP_WINDOW, reply.clone());
+    cache.insert(4 + 2 * DEDUP_WINDOW, reply.clone());
+    if !new(&cache, 3 + 2 * DEDUP_WINDOW) || !stale(&cache, 6 + DEDUP_WINDOW) {
+        return Err(TestError::Error("Stale bits in the window."));
+    }
+    Ok(())
 }
 
 use os_network::block_on;
-use os_network::timeout::Timeout;
+use os_network::timeout::{Delay, Timeout, TimeoutWRef};
 
 // a test RPC with RDMA
 fn test_ud_rpc() -> Result<(), TestError> {
@@ -326,6 +500,19 @@ fn test_ud_rpc_elegant() -> Result<(), TestError> {
 
     // create client-side session and receiver
     let client_session = client_ud.create(endpoint).unwrap();
+    // the session connected without the auth token
+    let rejected_session = factory
+        .create_meta(UDHyperMeta {
+            gid,
+            service_id,
+            qd_hint: DEFAULT_QD_HINT as usize,
+            local_port: client_port,
+        })
+        .map(|endpoint| client_ud.create(endpoint).unwrap())
+        .map_err(|_| {
+            log::error!("Create endpoint error.");
+            TestError::Error("Endpoint error.")
+        })?;
     let mut client_receiver = UDReceiverFactory::new()
         .set_qd_hint(CLIENT_QD_HINT as _)
         .create(client_ud);
@@ -343,6 +530,9 @@ fn test_ud_rpc_elegant() -> Result<(), TestError> {
     rpc_server
         .get_mut_service()
         .register(TEST_RPC_ID, test_callback);
+    rpc_server
+        .get_mut_service()
+        .register(TEST_LARGE_RPC_ID, test_large_callback);
 
     log::info!("check RPCHook: {:?}", rpc_server);
 
@@ -358,6 +548,37 @@ fn test_ud_rpc_elegant() -> Result<(), TestError> {
     // client
     let my_session_id = DEFAULT_SESSION_ID;
     let mut caller = UDCaller::new(client_receiver);
+
+    // the connect request with a wrong token is dropped by the server
+    rpc_server.set_auth_token(TEST_AUTH_TOKEN);
+    caller.set_auth_token(TEST_AUTH_TOKEN + 1);
+    caller
+        .connect(
+            my_session_id,
+            my_session_id,
+            rejected_session,
+            UDHyperMeta {
+                gid,
+                service_id: service_id,
+                qd_hint: CLIENT_QD_HINT as usize,
+                local_port: client_port,
+            },
+        ).map_err(|_| {
+            log::error!("Client caller connect error.");
+            TestError::Error("Caller connect error.")
+        })?;
+    block_on(&mut TimeoutWRef::new(&mut rpc_server, timeout_usec))
+        .map_err(|e| {
+            log::error!("Server receiver process err {:?}", e);
+            TestError::Error("Server receiver error.")
+        })?;
+    if rpc_server.get_analysis().get_nrejected() != 1 || rpc_server.release_session(my_session_id) {
+        log::error!("Failed to reject the connect request, status: {:?}", rpc_server);
+        return Err(TestError::Error("Auth error."));
+    }
+    caller.remove_session(my_session_id);
+
+    caller.set_auth_token(TEST_AUTH_TOKEN);
     caller
         .connect(
             my_session_id,
@@ -421,16 +642,547 @@ fn test_ud_rpc_elegant() -> Result<(), TestError> {
         })?;
     log::debug!("sanity check client rpc result: {:?}", res.1);
 
-    let rpc_server = rpc_server.into_inner();
+    // client pipelines two calls, and waits for them in the reverse order
+    let mut caller = caller_timeout.into_inner();
+    let mut req_ids = [0; 2];
+    for id in req_ids.iter_mut() {
+        *id = caller
+            .call_async(my_session_id, my_session_id, TEST_RPC_ID, 666 as u64)
+            .map_err(|_| {
+                log::error!("Client caller call rpc error.");
+                TestError::Error("Caller caller error.")
+            })?;
+    }
+
+    for _ in 0..req_ids.len() {
+        rpc_server.reset_timer(timeout_usec);
+        block_on(&mut rpc_server)
+            .map_err(|e| {
+                log::error!("Server receiver process err {:?}", e);
+                TestError::Error("Server receiver error.")
+            })?;
+    }
+
+    for id in req_ids.iter().rev() {
+        let (msg, reply) = block_on(&mut Timeout::new(caller.get_reply(*id), timeout_usec))
+            .map_err(|e| {
+                log::error!("Client receiver process err {:?} of request {}", e, id);
+                TestError::Error("Client receiver error.")
+            })?;
+        log::debug!("sanity check client rpc result of request {}: {:?}", id, reply);
+        caller.register_recv_buf(msg).unwrap();
+    }
+    if caller.get_completed_num() != 0 {
+        return Err(TestError::Error("Unclaimed replies."));
+    }
+
+    // the calls beyond the credits are blocked, until the replies return the credits
+    let credits = caller.get_credits(my_session_id).unwrap_or(0);
+    if credits == 0 {
+        return Err(TestError::Error("No credits granted."));
+    }
+    // more calls than the send buffers, each echoed with its own argument,
+    // so an argument overwritten before being sent is caught
+    let ncalls = core::cmp::max(credits, os_network::rpc::send_buf::DEFAULT_SEND_BUFS) + 2;
+    let mut req_ids = vec::Vec::new();
+    for i in 0..ncalls {
+        let id = caller
+            .call_async(my_session_id, my_session_id, TEST_LARGE_RPC_ID, i as u64)
+            .map_err(|_| {
+                log::error!("Client caller call rpc error.");
+                TestError::Error("Caller caller error.")
+            })?;
+        req_ids.push(id);
+    }
+    if caller.get_credits(my_session_id) != Some(0) {
+        return Err(TestError::Error("Calls beyond the credits."));
+    }
+
+    for i in 0..req_ids.len() {
+        // the blocked calls are sent once the earlier replies return the credits
+        if i % credits == 0 {
+            for _ in 0..core::cmp::min(credits, req_ids.len() - i) {
+                rpc_server.reset_timer(timeout_usec);
+                block_on(&mut rpc_server)
+                    .map_err(|e| {
+                        log::error!("Server receiver process err {:?}", e);
+                        TestError::Error("Server receiver error.")
+                    })?;
+            }
+        }
+        let (msg, reply) = block_on(&mut Timeout::new(caller.get_reply(req_ids[i]), timeout_usec))
+            .map_err(|e| {
+                log::error!("Client receiver process err {:?} of request {}", e, req_ids[i]);
+                TestError::Error("Client receiver error.")
+            })?;
+        let mut echo: u64 = 0;
+        unsafe { reply.memcpy_deserialize(&mut echo) };
+        caller.register_recv_buf(msg).unwrap();
+        if echo != i as u64 {
+            log::error!("Wrong echo {} of call {}.", echo, i);
+            return Err(TestError::Error("Send buffer reused before sent."));
+        }
+    }
+
+    // a cancelled call is forgotten, and its late reply is dropped with the buffer re-posted
+    let cancelled = caller
+        .call_async(my_session_id, my_session_id, TEST_RPC_ID, 666 as u64)
+        .map_err(|_| {
+            log::error!("Client caller call rpc error.");
+            TestError::Error("Caller caller error.")
+        })?;
+    let timeouts = caller.get_analysis().get_errors().timeout;
+    caller.cancel(cancelled);
+    if caller.get_inflight_num() != 0 {
+        return Err(TestError::Error("Cancelled call in flight."));
+    }
+    // the caller gives up the call on its timeout
+    if caller.get_analysis().get_errors().timeout != timeouts + 1
+        || caller.get_analysis().get_queue_depth() != 0
+    {
+        return Err(TestError::Error("Cancelled call not counted as timed out."));
+    }
+    let req_id = caller
+        .call_async(my_session_id, my_session_id, TEST_RPC_ID, 666 as u64)
+        .map_err(|_| {
+            log::error!("Client caller call rpc error.");
+            TestError::Error("Caller caller error.")
+        })?;
+    for _ in 0..2 {
+        rpc_server.reset_timer(timeout_usec);
+        block_on(&mut rpc_server)
+            .map_err(|e| {
+                log::error!("Server receiver process err {:?}", e);
+                TestError::Error("Server receiver error.")
+            })?;
+    }
+    let (msg, _) = block_on(&mut Timeout::new(caller.get_reply(req_id), timeout_usec))
+        .map_err(|e| {
+            log::error!("Client receiver process err {:?} of request {}", e, req_id);
+            TestError::Error("Client receiver error.")
+        })?;
+    caller.register_recv_buf(msg).unwrap();
+    if caller.get_completed_num() != 0 {
+        return Err(TestError::Error("Cancelled reply is kept."));
+    }
+
+    // the server does not poll, so the client retransmits the request once
+    caller.set_retransmit_config(RetransmitConfig {
+        init_timeout_usec: 1000,
+        max_timeout_usec: 2000,
+        max_retries: 4,
+    });
+    let req_id = caller
+        .call_async(my_session_id, my_session_id, TEST_RPC_ID, 666 as u64)
+        .map_err(|_| {
+            log::error!("Client caller call rpc error.");
+            TestError::Error("Caller caller error.")
+        })?;
+    if block_on(&mut Timeout::new(caller.get_reply(req_id), 1500)).is_ok() {
+        return Err(TestError::Error("Reply without the server's polling."));
+    }
+
+    // the duplicated request must be replied from the cache, instead of executed again
+    let ncalls = rpc_server.get_ref().get_analysis().get_ncalls();
+    for _ in 0..2 {
+        rpc_server.reset_timer(timeout_usec);
+        block_on(&mut rpc_server)
+            .map_err(|e| {
+                log::error!("Server receiver process err {:?}", e);
+                TestError::Error("Server receiver error.")
+            })?;
+    }
+    let analysis = rpc_server.get_ref().get_analysis();
+    if analysis.get_ncalls() != ncalls + 1 || analysis.get_nduplicates() != 1 {
+        log::error!(
+            "Wrong duplicate detection: ncalls {} -> {}, duplicates {}.",
+            ncalls,
+            analysis.get_ncalls(),
+            analysis.get_nduplicates()
+        );
+        return Err(TestError::Error("Duplicate detection error."));
+    }
+
+    let (msg, _) = block_on(&mut Timeout::new(caller.get_reply(req_id), timeout_usec))
+        .map_err(|e| {
+            log::error!("Client receiver process err {:?} of request {}", e, req_id);
+            TestError::Error("Client receiver error.")
+        })?;
+    caller.register_recv_buf(msg).unwrap();
+    if caller.get_inflight_num() != 0 {
+        return Err(TestError::Error("Unfinished calls."));
+    }
+
+    // a request and its reply both span 3 fragments
+    caller.set_retransmit_config(Default::default());
+    let frag_sz = os_network::rpc::fragment::frag_payload_size::<UDReceiver>();
+    let large_req: vec::Vec<u8> = (0..2 * frag_sz + 100).map(|i| i as u8).collect();
+    let req_id = caller
+        .call_async_bytes(my_session_id, my_session_id, TEST_LARGE_RPC_ID, &large_req)
+        .map_err(|_| {
+            log::error!("Client caller call large rpc error.");
+            TestError::Error("Caller caller error.")
+        })?;
+
+    for _ in 0..3 {
+        rpc_server.reset_timer(timeout_usec);
+        block_on(&mut rpc_server)
+            .map_err(|e| {
+                log::error!("Server receiver process err {:?}", e);
+                TestError::Error("Server receiver error.")
+            })?;
+    }
+
+    let (msg, reply) = block_on(&mut Timeout::new(caller.get_reply(req_id), timeout_usec))
+        .map_err(|e| {
+            log::error!("Client receiver process err {:?} of request {}", e, req_id);
+            TestError::Error("Client receiver error.")
+        })?;
+    let reply_bytes = unsafe { core::slice::from_raw_parts(reply.get_ptr(), reply.len()) };
+    if reply_bytes != &large_req[..] {
+        log::error!("Wrong large reply of size {}, expected {}.", reply.len(), large_req.len());
+        return Err(TestError::Error("Reassembly error."));
+    }
+    caller.register_recv_buf(msg).unwrap();
+
+    // the server receives the ack of the whole reply
+    rpc_server.reset_timer(timeout_usec);
+    block_on(&mut rpc_server)
+        .map_err(|e| {
+            log::error!("Server receiver process err {:?}", e);
+            TestError::Error("Server receiver error.")
+        })?;
+
+    // both sides count the large call once, with the reassembled payloads
+    let (client, server) = (caller.get_analysis(), rpc_server.get_ref().get_analysis());
+    let counted = |a: &os_network::rpc::analysis::RPCAnalysis| {
+        a.get_latency(TEST_LARGE_RPC_ID).map(|h| h.get_count()) == Some(1)
+    };
+    if !counted(client)
+        || !counted(server)
+        || client.get_bytes_in() < large_req.len()
+        || client.get_bytes_out() < large_req.len()
+        || server.get_bytes_in() != client.get_bytes_out()
+        || server.get_bytes_out() != client.get_bytes_in()
+    {
+        log::error!("Wrong analysis, client {:?}, server {:?}.", client, server);
+        return Err(TestError::Error("Analysis error."));
+    }
+
+    // the fragments of a request larger than the server's max size are rejected,
+    // while their buffers are re-posted and their credits are returned
+    rpc_server
+        .get_mut()
+        .set_fragment_config(os_network::rpc::fragment::FragmentConfig {
+            max_msg_size: frag_sz,
+            ..Default::default()
+        });
+    // more fragments than the 12 posted buffers, so a leaked buffer fails the later calls
+    for _ in 0..6 {
+        let req_id = caller
+            .call_async_bytes(my_session_id, my_session_id, TEST_LARGE_RPC_ID, &large_req)
+            .map_err(|_| {
+                log::error!("Client caller call large rpc error.");
+                TestError::Error("Caller caller error.")
+            })?;
+        let mut nrejected = 0;
+        loop {
+            rpc_server.reset_timer(100_000);
+            match block_on(&mut rpc_server) {
+                Ok(_) => {}
+                Err(e) if e.is_elapsed() => break,
+                Err(_) => nrejected += 1,
+            }
+        }
+        if nrejected == 0 {
+            return Err(TestError::Error("Malformed fragment accepted."));
+        }
+        caller.cancel(req_id);
+
+        // a normal call still succeeds
+        let req_id = caller
+            .call_async(my_session_id, my_session_id, TEST_RPC_ID, 666 as u64)
+            .map_err(|_| {
+                log::error!("Client caller call rpc error.");
+                TestError::Error("Caller caller error.")
+            })?;
+        rpc_server.reset_timer(timeout_usec);
+        block_on(&mut rpc_server)
+            .map_err(|e| {
+                log::error!("Server receiver process err {:?}", e);
+                TestError::Error("Server receiver error.")
+            })?;
+        let (msg, _) = block_on(&mut Timeout::new(caller.get_reply(req_id), timeout_usec))
+            .map_err(|e| {
+                log::error!("Client receiver process err {:?} after a malformed fragment", e);
+                TestError::Error("Call failed after a malformed fragment.")
+            })?;
+        caller.register_recv_buf(msg).unwrap();
+    }
+    rpc_server.get_mut().set_fragment_config(Default::default());
+
+    // the heartbeat is echoed by the server, so the peer stays alive
+    caller.set_heartbeat_config(HeartbeatConfig {
+        interval_usec: 1000,
+        max_missed: 1000,
+    });
+    let _ = block_on(&mut Delay::new(1000));
+    let down = caller.check_peers().map_err(|e| {
+        log::error!("Client check peers err {:?}", e);
+        TestError::Error("Heartbeat error.")
+    })?;
+    rpc_server.reset_timer(timeout_usec);
+    block_on(&mut rpc_server)
+        .map_err(|e| {
+            log::error!("Server receiver process err {:?}", e);
+            TestError::Error("Server receiver error.")
+        })?;
+    if !down.is_empty() || !caller.session_connected(my_session_id) {
+        return Err(TestError::Error("Alive peer is down."));
+    }
+
+    // disconnect tears down the session at both sides
+    caller.disconnect(my_session_id).map_err(|_| {
+        log::error!("Client caller disconnect error.");
+        TestError::Error("Caller disconnect error.")
+    })?;
+    rpc_server.reset_timer(timeout_usec);
+    block_on(&mut rpc_server)
+        .map_err(|e| {
+            log::error!("Server receiver process err {:?}", e);
+            TestError::Error("Server receiver error.")
+        })?;
+
+    let mut rpc_server = rpc_server.into_inner();
+    if caller.session_connected(my_session_id) || rpc_server.release_session(my_session_id) {
+        return Err(TestError::Error("Session is not released."));
+    }
     log::debug!("final check hook status {:?}", rpc_server);
     Ok(())    
 }
 
+use os_network::rdma::rc::RCFactory;
+use os_network::rpc::impls::rc::{RCHyperMeta, RCReceiver, RCReceiverFactory, RCSessionMeta};
+use os_network::rpc::impls::transport::{RPCHyperMeta, RPCReceiver, RPCSession};
+use KRdmaKit::services::ReliableConnectionServer;
+
+const RC_SERVICE_ID: u64 = 39;
+// small enough that the calls below wrap around the lane
+const TEST_LANE_DEPTH: usize = 4;
+
+fn test_rc_rpc() -> Result<(), TestError> {
+    log::info!("Test RPC backed by RDMA's RC.");
+    let timeout_usec = 5000_000;
+    type RCRPCHook<'a> = hook::RPCHook<'a, RCFactory, RCReceiver, RCFactory>;
+    // the caller whose transport is chosen at runtime
+    type RCCaller = Caller<RPCReceiver, RPCSession>;
+
+    // the sessions released by the hook
+    let released = core::cell::Cell::new(0);
+
+    let driver = unsafe { KDriver::create().unwrap() };
+    let ctx = driver
+        .devices()
+        .get(0)
+        .ok_or(TestError::Error("Not valid device"))?
+        .open_context()
+        .map_err(|_| {
+            log::error!("Open server ctx error.");
+            TestError::Error("Server context error.")
+        })?;
+    let port = DEFAULT_PORT;
+
+    // both the caller and the hook accept RC connections at the service
+    let rc_server = ReliableConnectionServer::create(&ctx, port);
+    let _server_cm = CMServer::new(RC_SERVICE_ID, &rc_server, ctx.get_dev_ref())
+        .map_err(|_| {
+            log::error!("Open server cm error.");
+            TestError::Error("Server cm error.")
+        })?;
+    let gid = ctx.get_dev_ref().query_gid(port, 0).unwrap();
+
+    let server_receiver = RCReceiverFactory::new()
+        .set_lanes(2)
+        .set_depth(TEST_LANE_DEPTH)
+        .create(&ctx);
+    let mut client_receiver = RCReceiverFactory::new().create(&ctx);
+
+    // the session writes to the lane 1 of the server
+    let server_lane = server_receiver.get_lane_meta(1).unwrap();
+    let client_lane = client_receiver.get_lane_meta(0).unwrap();
+    let client_session = RPCFactory::create(
+        client_receiver.get_transport_mut(),
+        RCSessionMeta {
+            conn: os_network::rdma::ConnMeta {
+                gid,
+                service_id: RC_SERVICE_ID,
+                port,
+            },
+            mailbox: server_lane,
+        },
+    )
+    .map_err(|_| {
+        log::error!("Create RC session error.");
+        TestError::Error("Session error.")
+    })?;
+
+    let mut rpc_server = RCRPCHook::new(
+        Arc::new(RCFactory::new(&ctx)),
+        RCFactory::new(&ctx),
+        server_receiver,
+    );
+    // the hook connects back to the caller outside its poll
+    rpc_server.set_deferred_connect(true);
+    rpc_server.set_release_callback(|_| released.set(released.get() + 1));
+    rpc_server
+        .get_mut_service()
+        .register(TEST_RPC_ID, test_callback);
+    rpc_server
+        .get_mut_service()
+        .register(TEST_LARGE_RPC_ID, test_large_callback);
+
+    for _ in 0..12 {
+        rpc_server
+            .post_msg_buf(UDMsg::new(DEFAULT_RECV_BUF_SIZE, 0, ctx.clone()))
+            .unwrap();
+        client_receiver
+            .post_recv_buf(UDMsg::new(DEFAULT_RECV_BUF_SIZE, 0, ctx.clone()))
+            .unwrap();
+    }
+
+    let my_session_id = DEFAULT_SESSION_ID;
+    let mut caller = RCCaller::new(RPCReceiver::RC(client_receiver));
+    caller
+        .connect(
+            my_session_id,
+            my_session_id,
+            RPCSession::RC(client_session),
+            RPCHyperMeta::RC(RCHyperMeta {
+                gid,
+                service_id: RC_SERVICE_ID,
+                port,
+                mailbox: client_lane,
+            }),
+        )
+        .map_err(|_| {
+            log::error!("Client caller connect error.");
+            TestError::Error("Caller connect error.")
+        })?;
+
+    let mut rpc_server = Timeout::new(rpc_server, timeout_usec);
+    block_on(&mut rpc_server)
+        .map_err(|e| {
+            log::error!("Server receiver process err {:?}", e);
+            TestError::Error("Server receiver error.")
+        })?;
+    // the connect is replied after its session is created
+    let (session_id, meta) = rpc_server.get_mut().pop_connect().ok_or_else(|| {
+        log::error!("No deferred connect request.");
+        TestError::Error("Deferred connect error.")
+    })?;
+    let session = RPCFactory::create(&RCFactory::new(&ctx), meta).map_err(|_| {
+        log::error!("Create the RC session back error.");
+        TestError::Error("Session error.")
+    })?;
+    if session_id != my_session_id || !rpc_server.get_mut().complete_connect(session_id, session).unwrap_or(false) {
+        log::error!("Failed to complete the connect of session {}.", session_id);
+        return Err(TestError::Error("Deferred connect error."));
+    }
+    let (msg, _) = block_on(&mut TimeoutWRef::new(&mut caller, timeout_usec))
+        .map_err(|e| {
+            log::error!("Client receiver process err {:?}", e);
+            TestError::Error("Client receiver error.")
+        })?;
+    caller.register_recv_buf(msg).unwrap();
+
+    // more calls than the slots of the lane, so the session waits for the slots to be consumed
+    for _ in 0..3 * TEST_LANE_DEPTH {
+        let req_id = caller
+            .call_async(my_session_id, my_session_id, TEST_RPC_ID, 666 as u64)
+            .map_err(|_| {
+                log::error!("Client caller call rpc error.");
+                TestError::Error("Caller caller error.")
+            })?;
+        rpc_server.reset_timer(timeout_usec);
+        block_on(&mut rpc_server)
+            .map_err(|e| {
+                log::error!("Server receiver process err {:?}", e);
+                TestError::Error("Server receiver error.")
+            })?;
+        let (msg, _) = block_on(&mut Timeout::new(caller.get_reply(req_id), timeout_usec))
+            .map_err(|e| {
+                log::error!("Client receiver process err {:?} of request {}", e, req_id);
+                TestError::Error("Client receiver error.")
+            })?;
+        caller.register_recv_buf(msg).unwrap();
+    }
+
+    // a request and its reply both span 3 fragments
+    let frag_sz = os_network::rpc::fragment::frag_payload_size::<RPCReceiver>();
+    let large_req: vec::Vec<u8> = (0..2 * frag_sz + 100).map(|i| i as u8).collect();
+    let req_id = caller
+        .call_async_bytes(my_session_id, my_session_id, TEST_LARGE_RPC_ID, &large_req)
+        .map_err(|_| {
+            log::error!("Client caller call large rpc error.");
+            TestError::Error("Caller caller error.")
+        })?;
+    for _ in 0..3 {
+        rpc_server.reset_timer(timeout_usec);
+        block_on(&mut rpc_server)
+            .map_err(|e| {
+                log::error!("Server receiver process err {:?}", e);
+                TestError::Error("Server receiver error.")
+            })?;
+    }
+    let (msg, reply) = block_on(&mut Timeout::new(caller.get_reply(req_id), timeout_usec))
+        .map_err(|e| {
+            log::error!("Client receiver process err {:?} of request {}", e, req_id);
+            TestError::Error("Client receiver error.")
+        })?;
+    let reply_bytes = unsafe { core::slice::from_raw_parts(reply.get_ptr(), reply.len()) };
+    if reply_bytes != &large_req[..] {
+        log::error!("Wrong large reply of size {}, expected {}.", reply.len(), large_req.len());
+        return Err(TestError::Error("Reassembly error."));
+    }
+    caller.register_recv_buf(msg).unwrap();
+    rpc_server.reset_timer(timeout_usec);
+    block_on(&mut rpc_server)
+        .map_err(|e| {
+            log::error!("Server receiver process err {:?}", e);
+            TestError::Error("Server receiver error.")
+        })?;
+
+    caller.disconnect(my_session_id).map_err(|_| {
+        log::error!("Client caller disconnect error.");
+        TestError::Error("Caller disconnect error.")
+    })?;
+    rpc_server.reset_timer(timeout_usec);
+    block_on(&mut rpc_server)
+        .map_err(|e| {
+            log::error!("Server receiver process err {:?}", e);
+            TestError::Error("Server receiver error.")
+        })?;
+
+    let mut rpc_server = rpc_server.into_inner();
+    if caller.session_connected(my_session_id) || rpc_server.release_session(my_session_id) {
+        return Err(TestError::Error("Session is not released."));
+    }
+    if released.get() != 1 {
+        log::error!("The release callback is called {} times, expected 1.", released.get());
+        return Err(TestError::Error("Release callback error."));
+    }
+    log::debug!("final check RC hook status {:?}", rpc_server);
+    Ok(())
+}
+
 fn test_wrapper() -> Result<(), TestError> {
     test_service()?;
+    test_typed_service()?;
     test_rpc_headers()?;
+    test_reply_cache()?;
     test_ud_rpc()?;
     test_ud_rpc_elegant()?;
+    test_rc_rpc()?;
     Ok(())
 }
 